@@ -0,0 +1,310 @@
+//! Cyclic arbitrage over loaded pools.
+//!
+//! A [`TokenGraph`] has an edge per pool and direction, weighted by the pool's spot price less
+//! its fee. Cycles from a start token whose edges multiply to more than one are candidates;
+//! [`find_arbitrage`] then sizes each with the pools' exact quotes, since spot prices say
+//! nothing about how far a trade moves them, and prices the transaction it would take.
+
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::dex::{apply_slippage_bps, DexError, Pool, PoolQuote};
+
+/// Base fee of the transaction's one signature.
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// How far short of its quote an intermediate swap may fall, to absorb rounding in the pools'
+/// on-chain math; at least one unit.
+pub const HOP_ROUNDING_MARGIN_BPS: u16 = 1;
+
+#[derive(Clone, Debug)]
+pub struct ArbitrageConfig {
+    /// Most swaps in a cycle, 2 or more.
+    pub max_hops: usize,
+    /// Largest input tried, in the start token.
+    pub max_amount_in: u64,
+    /// Least net profit, in the start token, an atomic transaction accepts.
+    pub min_profit: u64,
+    pub compute_unit_limit: u32,
+    /// Priority fee, in micro-lamports per compute unit.
+    pub compute_unit_price: u64,
+}
+
+impl Default for ArbitrageConfig {
+    fn default() -> Self {
+        ArbitrageConfig { max_hops: 3, max_amount_in: u64::MAX, min_profit: 0, compute_unit_limit: 600_000, compute_unit_price: 0 }
+    }
+}
+
+impl ArbitrageConfig {
+    /// Signature and priority fee of the transaction, in lamports.
+    pub fn network_fee_lamports(&self) -> u64 {
+        LAMPORTS_PER_SIGNATURE + (self.compute_unit_limit as u128 * self.compute_unit_price as u128).div_ceil(1_000_000) as u64
+    }
+}
+
+/// Swapping `mint_in` for `mint_out` on pool `pool`, at `rate` raw `mint_out` per raw `mint_in`
+/// after fees.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edge {
+    pub pool: usize,
+    pub a_to_b: bool,
+    pub mint_in: Pubkey,
+    pub mint_out: Pubkey,
+    pub rate: f64,
+}
+
+/// Edges in trading order from the start token back to it, and the product of their rates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cycle {
+    pub edges: Vec<Edge>,
+    pub rate: f64,
+}
+
+/// Tokens joined by the pools trading them, at current prices.
+pub struct TokenGraph {
+    edges: Vec<Edge>,
+}
+
+impl TokenGraph {
+    /// Edges of every pool that is loaded and priced; the others are left out.
+    pub fn new(pools: &[Box<dyn Pool>]) -> Self {
+        let mut edges = Vec::new();
+        for (index, pool) in pools.iter().enumerate() {
+            let (Some((mint_a, mint_b)), Ok(price), Ok(fee_rate)) = (pool.mints(), pool.price(), pool.fee_rate()) else {
+                continue;
+            };
+            if price.is_zero() {
+                continue;
+            }
+            let after_fee = Decimal::ONE - fee_rate;
+            for (a_to_b, rate) in [(true, price * after_fee), (false, after_fee / price)] {
+                let (mint_in, mint_out) = if a_to_b { (mint_a, mint_b) } else { (mint_b, mint_a) };
+                if let Some(rate) = rate.to_f64() {
+                    edges.push(Edge { pool: index, a_to_b, mint_in, mint_out, rate });
+                }
+            }
+        }
+        TokenGraph { edges }
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// The best rate of any one pool from `mint_in` to `mint_out`.
+    pub fn rate(&self, mint_in: &Pubkey, mint_out: &Pubkey) -> Option<f64> {
+        self.edges.iter().filter(|edge| edge.mint_in == *mint_in && edge.mint_out == *mint_out).map(|edge| edge.rate).reduce(f64::max)
+    }
+
+    /// Cycles from `start` of up to `max_hops` swaps that gain at spot prices, best first. A
+    /// cycle passes each token and pool once.
+    pub fn profitable_cycles(&self, start: &Pubkey, max_hops: usize) -> Vec<Cycle> {
+        let mut cycles = Vec::new();
+        self.extend_cycles(start, &mut Vec::new(), max_hops, &mut cycles);
+        cycles.sort_by(|a, b| b.rate.total_cmp(&a.rate));
+        cycles
+    }
+
+    fn extend_cycles(&self, start: &Pubkey, path: &mut Vec<usize>, max_hops: usize, cycles: &mut Vec<Cycle>) {
+        let mint = path.last().map_or(*start, |&edge| self.edges[edge].mint_out);
+        for (index, edge) in self.edges.iter().enumerate() {
+            if edge.mint_in != mint || path.iter().any(|&used| self.edges[used].pool == edge.pool) {
+                continue;
+            }
+            if edge.mint_out == *start {
+                let edges: Vec<Edge> = path.iter().chain([&index]).map(|&used| self.edges[used].clone()).collect();
+                let rate = edges.iter().map(|edge| edge.rate).product::<f64>();
+                if edges.len() >= 2 && rate > 1.0 {
+                    cycles.push(Cycle { edges, rate });
+                }
+            } else if path.len() + 2 <= max_hops && path.iter().all(|&used| self.edges[used].mint_in != edge.mint_out) {
+                path.push(index);
+                self.extend_cycles(start, path, max_hops, cycles);
+                path.pop();
+            }
+        }
+    }
+}
+
+/// A cycle sized to its most profitable input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Opportunity {
+    pub cycle: Cycle,
+    pub hops: Vec<PoolQuote>,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// What comes back above `amount_in`, pool fees already paid.
+    pub gross_profit: u64,
+    pub network_fee_lamports: u64,
+    /// The network fee in the start token, `None` when no pool prices SOL in it.
+    pub network_fee: Option<u64>,
+    /// `gross_profit` less `network_fee`.
+    pub net_profit: Option<i64>,
+}
+
+impl Opportunity {
+    /// The swaps of the cycle, all in one transaction for `user`, whose token accounts have to
+    /// exist.
+    ///
+    /// Every swap spends its quoted input rather than the balance the previous one actually
+    /// left. Swaps but the last have to deliver what the next one spends, less
+    /// [`HOP_ROUNDING_MARGIN_BPS`] that the user's own balance of the token covers; only the
+    /// last is held strictly to the input plus the network fee, where known, and `min_profit`,
+    /// so the transaction fails as a whole rather than trade at a loss.
+    pub fn swap_instructions(&self, pools: &[Box<dyn Pool>], user: &Pubkey, funder: &Pubkey, config: &ArbitrageConfig) -> Result<Vec<Instruction>, DexError> {
+        let min_amount_out = self.amount_in.saturating_add(self.network_fee.unwrap_or(0)).saturating_add(config.min_profit);
+        if self.amount_out < min_amount_out {
+            return Err(format!("cycle returns {}, below the {} it must", self.amount_out, min_amount_out).into());
+        }
+
+        let mut instructions = vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(config.compute_unit_limit),
+            ComputeBudgetInstruction::set_compute_unit_price(config.compute_unit_price),
+        ];
+        for (index, (hop, edge)) in self.hops.iter().zip(&self.cycle.edges).enumerate() {
+            let min_amount_out = self.hops.get(index + 1).map_or(min_amount_out, |next| apply_slippage_bps(next.amount_in, HOP_ROUNDING_MARGIN_BPS));
+            instructions.extend(pools[edge.pool].swap_instructions(user, funder, hop, min_amount_out)?);
+        }
+        Ok(instructions)
+    }
+}
+
+/// Cycles from `start` that gain after pool fees at their best size, most net profit first.
+pub fn find_arbitrage(pools: &[Box<dyn Pool>], start: &Pubkey, config: &ArbitrageConfig) -> Vec<Opportunity> {
+    let graph = TokenGraph::new(pools);
+    let network_fee_lamports = config.network_fee_lamports();
+    let native_mint = spl_token::native_mint::id();
+    let network_fee = if *start == native_mint {
+        Some(network_fee_lamports)
+    } else {
+        graph.rate(&native_mint, start).map(|rate| (network_fee_lamports as f64 * rate).ceil() as u64)
+    };
+
+    let mut opportunities: Vec<Opportunity> = graph
+        .profitable_cycles(start, config.max_hops)
+        .into_iter()
+        .filter_map(|cycle| {
+            let hops = size_cycle(pools, &cycle, config.max_amount_in)?;
+            let (amount_in, amount_out) = (hops[0].amount_in, hops[hops.len() - 1].amount_out);
+            let gross_profit = amount_out.checked_sub(amount_in).filter(|&profit| profit > 0)?;
+            let net_profit = network_fee.map(|fee| gross_profit as i64 - fee as i64);
+            Some(Opportunity { cycle, hops, amount_in, amount_out, gross_profit, network_fee_lamports, network_fee, net_profit })
+        })
+        .collect();
+    opportunities.sort_by_key(|opportunity| std::cmp::Reverse(opportunity.net_profit.unwrap_or(opportunity.gross_profit as i64)));
+    opportunities
+}
+
+// the quotes of every hop for all of `amount`, or `None` where a pool can't take it all
+fn quote_cycle(pools: &[Box<dyn Pool>], cycle: &Cycle, amount: u64) -> Option<Vec<PoolQuote>> {
+    let mut hops = Vec::with_capacity(cycle.edges.len());
+    let mut amount = amount;
+    for edge in &cycle.edges {
+        let quote = pools[edge.pool].quote(amount, edge.a_to_b).ok()?;
+        if quote.amount_in < amount || quote.amount_out == 0 {
+            return None;
+        }
+        amount = quote.amount_out;
+        hops.push(quote);
+    }
+    Some(hops)
+}
+
+// the input up to `max_amount_in` returning the most: the best power of two, then a ternary
+// search between its neighbours, as profit rises with size until price impact eats it
+fn size_cycle(pools: &[Box<dyn Pool>], cycle: &Cycle, max_amount_in: u64) -> Option<Vec<PoolQuote>> {
+    let profit = |amount: u64| -> (i128, Option<Vec<PoolQuote>>) {
+        match quote_cycle(pools, cycle, amount) {
+            Some(hops) => (hops[hops.len() - 1].amount_out as i128 - amount as i128, Some(hops)),
+            None => (i128::MIN, None),
+        }
+    };
+
+    let (mut best_amount, mut best) = (0, (i128::MIN, None));
+    for shift in 0..64 {
+        let amount = 1u64 << shift;
+        if amount > max_amount_in {
+            break;
+        }
+        let candidate = profit(amount);
+        if candidate.0 > best.0 {
+            (best_amount, best) = (amount, candidate);
+        }
+    }
+    best.1.as_ref()?;
+
+    let (mut low, mut high) = (best_amount / 2, best_amount.saturating_mul(2).min(max_amount_in));
+    while high - low >= 3 {
+        let (left, right) = (low + (high - low) / 3, high - (high - low) / 3);
+        if profit(left).0 < profit(right).0 {
+            low = left;
+        } else {
+            high = right;
+        }
+    }
+    for amount in low.max(1)..=high {
+        let candidate = profit(amount);
+        if candidate.0 > best.0 {
+            best = candidate;
+        }
+    }
+    best.1
+}
+
+#[test]
+fn test_find_arbitrage() {
+    use crate::dex::constant_product_pool as pool;
+
+    let sol = spl_token::native_mint::id();
+    let [usdc, bonk] = [2u8, 3].map(|byte| Pubkey::new_from_array([byte; 32]));
+    // SOL is worth 100 USDC in the first pool and 105 in the second; the third is in line
+    let pools = vec![
+        pool(10, (sol, usdc), (1_000_000_000_000, 100_000_000_000)),
+        pool(11, (usdc, sol), (105_000_000_000, 1_000_000_000_000)),
+        pool(12, (sol, bonk), (1_000_000_000_000, 1_000_000_000_000)),
+    ];
+
+    let graph = TokenGraph::new(&pools);
+    assert_eq!(graph.edges().len(), 6);
+    let cycles = graph.profitable_cycles(&sol, 3);
+    assert_eq!(cycles.len(), 1);
+    let pools_of = |cycle: &Cycle| cycle.edges.iter().map(|edge| edge.pool).collect::<Vec<_>>();
+    assert_eq!(pools_of(&cycles[0]), vec![1, 0]);
+    assert!((cycles[0].rate - 1.05 * 0.997 * 0.997).abs() < 1e-9);
+    assert!(graph.profitable_cycles(&bonk, 3).is_empty());
+
+    let config = ArbitrageConfig { compute_unit_price: 10_000, ..ArbitrageConfig::default() };
+    assert_eq!(config.network_fee_lamports(), 5_000 + 6_000);
+    let opportunities = find_arbitrage(&pools, &sol, &config);
+    assert_eq!(opportunities.len(), 1);
+    let opportunity = &opportunities[0];
+    assert_eq!(opportunity.gross_profit, opportunity.amount_out - opportunity.amount_in);
+    assert_eq!(opportunity.net_profit, Some(opportunity.gross_profit as i64 - 11_000));
+
+    // the size is the best there is, a little more or less returns less
+    let cycle_profit = |amount: u64| quote_cycle(&pools, &opportunity.cycle, amount).map(|hops| hops[1].amount_out as i64 - amount as i64).unwrap();
+    assert_eq!(cycle_profit(opportunity.amount_in), opportunity.gross_profit as i64);
+    for amount in [opportunity.amount_in * 99 / 100, opportunity.amount_in * 101 / 100] {
+        assert!(cycle_profit(amount) < opportunity.gross_profit as i64);
+    }
+    // capped, the cycle trades up to the cap, give or take rounding
+    let capped = find_arbitrage(&pools, &sol, &ArbitrageConfig { max_amount_in: 1_000_000_000, ..config.clone() });
+    assert!((999_000_000..=1_000_000_000).contains(&capped[0].amount_in));
+
+    // the first swap must deliver what the second spends but for rounding, the second the
+    // input, fee and profit exactly
+    let user = Pubkey::new_unique();
+    let instructions = opportunity.swap_instructions(&pools, &user, &user, &ArbitrageConfig { min_profit: 1_000, ..config.clone() }).unwrap();
+    assert_eq!(instructions.len(), 4);
+    assert_eq!(instructions[0].program_id, solana_sdk::compute_budget::id());
+    let amounts = |instruction: &Instruction| (u64::from_le_bytes(instruction.data[..8].try_into().unwrap()), u64::from_le_bytes(instruction.data[8..].try_into().unwrap()));
+    assert_eq!(amounts(&instructions[2]), (opportunity.amount_in, apply_slippage_bps(opportunity.hops[1].amount_in, HOP_ROUNDING_MARGIN_BPS)));
+    assert!(amounts(&instructions[2]).1 < opportunity.hops[1].amount_in);
+    assert_eq!(amounts(&instructions[3]), (opportunity.hops[1].amount_in, opportunity.amount_in + 11_000 + 1_000));
+    let too_greedy = ArbitrageConfig { min_profit: opportunity.gross_profit, ..config };
+    assert!(opportunity.swap_instructions(&pools, &user, &user, &too_greedy).is_err());
+}
@@ -0,0 +1,35 @@
+// Records a whirlpool fixture for offline tests, against the configured cluster profile:
+//   cargo run --bin record_fixture -- <whirlpool address or pool name> <output.json>
+
+use dotenv::dotenv;
+use solana_client::nonblocking::rpc_client::RpcClient as AsyncRpcClient;
+use solana_dex::config::Config;
+use solana_dex::fixture::PoolFixture;
+use solana_sdk::pubkey::Pubkey;
+use std::env;
+use std::str::FromStr;
+
+#[tokio::main]
+async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let profile = Config::load_default()?.profile(None)?;
+
+    let mut args = env::args().skip(1);
+    let (Some(whirlpool), Some(output)) = (args.next(), args.next()) else {
+        return Err("usage: record_fixture <whirlpool address or pool name> <output.json>".into());
+    };
+
+    let whirlpool = match profile.pool(&whirlpool) {
+        Ok(pool) => pool.address()?,
+        Err(_) => Pubkey::from_str(&whirlpool)?,
+    };
+    let program_id = profile.whirlpool_program_id()?;
+    let rpc_client = AsyncRpcClient::new_with_commitment(profile.rpc_url.clone(), profile.commitment_config()?);
+
+    let fixture = PoolFixture::record(&rpc_client, &program_id, &whirlpool).await?;
+    fixture.save(&output)?;
+    println!("recorded {} accounts at slot {} to {}", fixture.accounts.len(), fixture.slot, output);
+
+    Ok(())
+}
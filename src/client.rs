@@ -0,0 +1,905 @@
+use anchor_lang::prelude::*;
+use solana_client::nonblocking::rpc_client::RpcClient as AsyncRpcClient;
+use solana_sdk::{
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    message::Message,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Signature, Signer},
+    transaction::Transaction,
+};
+use spl_associated_token_account::get_associated_token_address;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Instant;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::arbitrage::{ArbitrageConfig, Opportunity};
+use crate::config::ClusterConfig;
+use crate::confirmation::{ConfirmationEvent, ConfirmationSource, SignatureSubscribeSource};
+use crate::constant::{ORCA_WHIRLPOOL_PROGRAM_ID, WHIRLPOOL_SWAP_DISCRIMINATOR, WHIRLPOOL_TICK_SPACINGS};
+use crate::data_source::{decode_whirlpool_account, WhirlpoolAccount};
+use crate::dex::{Pool, PoolQuote};
+use crate::keystore::Keystore;
+use crate::nonce::{create_nonce_account_instructions, durable_nonce_account, NonceInfo};
+use crate::offline::OfflineTransaction;
+use crate::router::{RoutePlan, Router};
+use crate::rpc::SolanaRpc;
+use crate::rpc_pool::{RpcPool, RpcPoolOptions};
+use crate::sender::{SendConfig, TransactionSender};
+use crate::settlement::SwapSettlement;
+#[cfg(feature = "jito")]
+use crate::jito::{tip_instruction, BundleConfig, BundleError, BundleSender, JitoClient, LandedBundle};
+use crate::tick_array::{pdautil_get_tick_array, tickutil_get_start_tick_indexes_for_swap, TickArray, Whirlpool};
+use crate::whirlpool::{build_initialize_tick_array_instruction, get_whirlpool_address, quote_whirlpool_swap, whirlpool_swap_instructions, OrcaSwapQuote, SwapData, MAX_TICK_ARRAYS_PER_TRADE};
+
+/// Tries to fetch a swap's confirmed transaction, half a second apart.
+const SETTLEMENT_ATTEMPTS: usize = 10;
+
+pub struct DEXClient<R: SolanaRpc = RpcPool> {
+    rpc_client: R,
+    fee_payer: Option<Box<dyn Signer + Send + Sync>>,
+    send_config: SendConfig,
+    confirmation_source: Option<Box<dyn ConfirmationSource>>,
+    #[cfg(feature = "jito")]
+    jito: Option<(JitoClient, BundleConfig)>,
+}
+
+#[derive(Default)]
+pub struct SwapTokenData {
+    pub amount: u64,
+    pub other_amount_threshold: u64,
+    pub sqrt_price_limit: u128,
+    pub amount_specified_is_input: bool,
+    pub token_in: solana_sdk::pubkey::Pubkey, 
+    pub token_out: solana_sdk::pubkey::Pubkey, 
+    pub token_in_decimals: i8, 
+    pub token_out_decimals: i8, 
+}
+
+impl SwapTokenData {
+    pub fn new(amount: u64, token_in: solana_sdk::pubkey::Pubkey, token_out: solana_sdk::pubkey::Pubkey, token_in_decimals: i8, token_out_decimals: i8) -> Self {
+        SwapTokenData {
+            amount,
+            other_amount_threshold: 0,
+            sqrt_price_limit: 0,
+            amount_specified_is_input: true,
+            token_in,
+            token_out,
+            token_in_decimals,
+            token_out_decimals,
+        }
+    }
+}
+
+
+
+
+impl DEXClient {
+    pub fn new(rpc_url: &str) -> Self {
+        let rpc_client = AsyncRpcClient::new_with_commitment(rpc_url.to_string(), CommitmentConfig::confirmed());
+        DEXClient::with_rpc(RpcPool::new(RpcPoolOptions::default()).with_endpoint(rpc_url, rpc_client, None, true))
+    }
+
+    /// Connects to the profile's RPC endpoints at the profile's commitment.
+    pub fn from_config(config: &ClusterConfig) -> std::result::Result<Self, Box<dyn std::error::Error>> {
+        let commitment = config.commitment_config()?;
+        let client = DEXClient::with_rpc(RpcPool::from_config(config)?)
+            .with_send_config(SendConfig { commitment: commitment.commitment, ..SendConfig::default() })
+            .with_confirmation_source(SignatureSubscribeSource::new(&config.ws_url));
+        #[cfg(feature = "jito")]
+        let client = match &config.jito {
+            Some(jito) => client.with_jito(JitoClient::from_config(jito)?, BundleConfig::from_config(jito)),
+            None => client,
+        };
+        Ok(client)
+    }
+}
+
+impl<R: SolanaRpc> DEXClient<R> {
+    pub fn with_rpc(rpc_client: R) -> Self {
+        DEXClient {
+            rpc_client,
+            fee_payer: None,
+            send_config: SendConfig::default(),
+            confirmation_source: None,
+            #[cfg(feature = "jito")]
+            jito: None,
+        }
+    }
+
+    /// Commitment [`DEXClient::send_instructions`] waits for.
+    pub fn commitment(&self) -> CommitmentLevel {
+        self.send_config.commitment
+    }
+
+    /// Where [`DEXClient::send_instructions_tracked`] gets confirmations from.
+    pub fn with_confirmation_source(mut self, confirmation_source: impl ConfirmationSource + 'static) -> Self {
+        self.confirmation_source = Some(Box::new(confirmation_source));
+        self
+    }
+
+    /// How [`DEXClient::send_instructions`] rebroadcasts and re-signs.
+    pub fn with_send_config(mut self, send_config: SendConfig) -> Self {
+        self.send_config = send_config;
+        self
+    }
+
+    /// Pays transaction fees and rent for everything this client sends, so the user signing
+    /// a trade only acts as its token authority.
+    pub fn with_fee_payer(mut self, fee_payer: impl Signer + Send + Sync + 'static) -> Self {
+        self.fee_payer = Some(Box::new(fee_payer));
+        self
+    }
+
+    /// Like [`DEXClient::with_fee_payer`] with the key `name` unlocked from `keystore`.
+    pub fn with_keystore_fee_payer(self, keystore: &Keystore, name: &str, passphrase: &str) -> std::result::Result<Self, Box<dyn std::error::Error>> {
+        Ok(self.with_fee_payer(keystore.unlock(name, passphrase)?))
+    }
+
+    /// The account paying for transactions signed by `user`.
+    pub fn fee_payer_pubkey(&self, user: &Pubkey) -> Pubkey {
+        self.fee_payer.as_ref().map_or(*user, |fee_payer| fee_payer.pubkey())
+    }
+
+    /// Signs `instructions` by the fee payer and by `user` if any instruction needs it.
+    pub fn sign_transaction(&self, user: &dyn Signer, instructions: &[Instruction], recent_blockhash: Hash) -> std::result::Result<Transaction, Box<dyn std::error::Error>> {
+        self.sign_transaction_with(user, &[], instructions, recent_blockhash)
+    }
+
+    /// Like [`DEXClient::sign_transaction`], with `signers_too` signing as well, e.g. accounts
+    /// being created.
+    pub fn sign_transaction_with(
+        &self,
+        user: &dyn Signer,
+        signers_too: &[&dyn Signer],
+        instructions: &[Instruction],
+        recent_blockhash: Hash,
+    ) -> std::result::Result<Transaction, Box<dyn std::error::Error>> {
+        let fee_payer: &dyn Signer = match &self.fee_payer {
+            Some(fee_payer) => fee_payer.as_ref(),
+            None => user,
+        };
+        let message = Message::new(instructions, Some(&fee_payer.pubkey()));
+        let required_signers = &message.account_keys[..message.header.num_required_signatures as usize];
+
+        let mut signers = vec![fee_payer];
+        if user.pubkey() != fee_payer.pubkey() && required_signers.contains(&user.pubkey()) {
+            signers.push(user);
+        }
+        signers.extend(signers_too);
+
+        let mut transaction = Transaction::new_unsigned(message);
+        transaction.try_sign(&signers, recent_blockhash)?;
+        Ok(transaction)
+    }
+
+    pub fn rpc(&self) -> &R {
+        &self.rpc_client
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn orca_swap(
+        &self,
+        user: &dyn Signer,
+        whirlpool: &Pubkey,
+        token_authority: &Pubkey,
+        token_owner_account_a: &Pubkey,
+        token_vault_a: &Pubkey,
+        token_owner_account_b: &Pubkey,
+        token_vault_b: &Pubkey,
+        tick_array_0: &Pubkey,
+        tick_array_1: &Pubkey,
+        tick_array_2: &Pubkey,
+        oracle: &Pubkey,
+        amount: u64,
+        other_amount_threshold: u64,
+        sqrt_price_limit: u128,
+        amount_specified_is_input: bool,
+        a_to_b: bool,
+    ) -> std::result::Result<String, Box<dyn std::error::Error>> {
+        let orca_swap_program_id = Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID)?;
+        let token_program = spl_token::id();
+
+        let accounts = vec![
+            AccountMeta::new(token_program, false),
+            AccountMeta::new(*token_authority, true),
+            AccountMeta::new(*whirlpool, false),
+            AccountMeta::new(*token_owner_account_a, false),
+            AccountMeta::new(*token_vault_a, false),
+            AccountMeta::new(*token_owner_account_b, false),
+            AccountMeta::new(*token_vault_b, false),
+            AccountMeta::new(*tick_array_0, false),
+            AccountMeta::new(*tick_array_1, false),
+            AccountMeta::new(*tick_array_2, false),
+            AccountMeta::new(*oracle, false),
+        ];
+
+        let swap_data = SwapData {
+            amount,
+            other_amount_threshold,
+            sqrt_price_limit,
+            amount_specified_is_input,
+            a_to_b,
+        };
+
+        let data = {
+            let mut prefix = WHIRLPOOL_SWAP_DISCRIMINATOR.to_vec();
+            prefix.extend(swap_data.try_to_vec()?);
+            prefix
+        };
+
+        let instruction = Instruction {
+            program_id: orca_swap_program_id,
+            accounts,
+            data,
+        };
+
+        self.send_instructions(user, vec![instruction]).await
+    }
+
+
+    /// Signs and sends `instructions`, rebroadcasting until they land, see
+    /// [`TransactionSender`]. A [`crate::sender::SendError`] tells whether trying again is safe.
+    pub async fn send_instructions(
+        &self,
+        user: &dyn Signer,
+        instructions: Vec<Instruction>,
+    ) -> std::result::Result<String, Box<dyn std::error::Error>> {
+        self.send_instructions_with_commitment(user, instructions, self.send_config.commitment).await
+    }
+
+    /// Like [`DEXClient::send_instructions`], waiting for `commitment` instead of the
+    /// configured one.
+    pub async fn send_instructions_with_commitment(
+        &self,
+        user: &dyn Signer,
+        instructions: Vec<Instruction>,
+        commitment: CommitmentLevel,
+    ) -> std::result::Result<String, Box<dyn std::error::Error>> {
+        let sender = TransactionSender::with_config(&self.rpc_client, SendConfig { commitment, ..self.send_config.clone() });
+        let sent = sender.send(|recent_blockhash| self.sign_transaction(user, &instructions, recent_blockhash)).await?;
+        Ok(sent.signature.to_string())
+    }
+
+    /// Signs and sends `instructions` once, and streams their confirmation events up to
+    /// `commitment` from the confirmation source. Nothing is rebroadcast: a stream that ends
+    /// before `commitment` means the transaction didn't land in time.
+    pub async fn send_instructions_tracked(
+        &self,
+        user: &dyn Signer,
+        instructions: Vec<Instruction>,
+        commitment: CommitmentLevel,
+    ) -> std::result::Result<(Signature, UnboundedReceiver<ConfirmationEvent>), Box<dyn std::error::Error>> {
+        let confirmation_source = self.confirmation_source.as_ref().ok_or("no confirmation source configured")?;
+        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
+        let transaction = self.sign_transaction(user, &instructions, recent_blockhash)?;
+        let signature = transaction.signatures[0];
+
+        let sent_at = Instant::now();
+        let events = confirmation_source.track(&signature, commitment, sent_at).await.map_err(|err| err.to_string())?;
+        self.rpc_client.send_transaction(&transaction, false).await?;
+        Ok((signature, events))
+    }
+
+    /// Block engine [`DEXClient::send_bundle`] goes through.
+    #[cfg(feature = "jito")]
+    pub fn with_jito(mut self, jito: JitoClient, bundle_config: BundleConfig) -> Self {
+        self.jito = Some((jito, bundle_config));
+        self
+    }
+
+    /// Sends each of `transactions` as a transaction of one Jito bundle, the last one paying
+    /// the tip, and waits for `commitment`. If the bundle is dropped and fallback is on, they
+    /// are sent one by one the normal way, without the tip and no longer atomically.
+    #[cfg(feature = "jito")]
+    pub async fn send_bundle(
+        &self,
+        user: &dyn Signer,
+        transactions: Vec<Vec<Instruction>>,
+        commitment: CommitmentLevel,
+    ) -> std::result::Result<LandedBundle, Box<dyn std::error::Error>> {
+        let (jito, bundle_config) = self.jito.as_ref().ok_or("no block engine configured")?;
+        let bundle_config = BundleConfig { commitment, ..bundle_config.clone() };
+        let tip_payer = self.fee_payer_pubkey(&user.pubkey());
+
+        let sender = BundleSender::new(&self.rpc_client, jito, bundle_config.clone());
+        let sent = sender
+            .send(|recent_blockhash, tip_account| {
+                let mut signed = Vec::new();
+                for (index, instructions) in transactions.iter().enumerate() {
+                    let mut instructions = instructions.clone();
+                    if index + 1 == transactions.len() {
+                        instructions.push(tip_instruction(&tip_payer, tip_account, bundle_config.tip_lamports));
+                    }
+                    signed.push(self.sign_transaction(user, &instructions, recent_blockhash)?);
+                }
+                Ok(signed)
+            })
+            .await;
+
+        match sent {
+            Err(BundleError::Dropped { reason, .. }) if bundle_config.fallback => {
+                let sender = TransactionSender::with_config(&self.rpc_client, SendConfig { commitment, ..self.send_config.clone() });
+                let mut signatures = Vec::new();
+                let mut slot = 0;
+                for instructions in &transactions {
+                    let sent = sender.send(|recent_blockhash| self.sign_transaction(user, instructions, recent_blockhash)).await?;
+                    signatures.push(sent.signature);
+                    slot = sent.slot;
+                }
+                Ok(LandedBundle { bundle_id: None, signatures, slot, fell_back: true, drop_reason: Some(reason) })
+            }
+            sent => Ok(sent?),
+        }
+    }
+
+    /// What the swap `signature` quoted by `quote` did for `owner`, once its transaction is
+    /// confirmed. Waits a few seconds for the node to serve it.
+    pub async fn get_swap_settlement(&self, signature: &Signature, owner: &Pubkey, quote: &PoolQuote) -> std::result::Result<SwapSettlement, Box<dyn std::error::Error>> {
+        for attempt in 0..SETTLEMENT_ATTEMPTS {
+            if let Some(meta) = self.rpc_client.get_transaction_meta(signature).await? {
+                return SwapSettlement::from_meta(*signature, &meta, owner, &quote.mint_in, &quote.mint_out, quote.amount_in, quote.amount_out);
+            }
+            if attempt + 1 < SETTLEMENT_ATTEMPTS {
+                tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            }
+        }
+        Err(format!("transaction {} is not confirmed yet", signature).into())
+    }
+
+    /// Builds a transaction `user` signs elsewhere, see [`crate::offline`]. The client's fee
+    /// payer, if any, signs it right away.
+    pub async fn build_offline_transaction(&self, user: &Pubkey, instructions: &[Instruction]) -> std::result::Result<OfflineTransaction, Box<dyn std::error::Error>> {
+        let mut message = Message::new(instructions, Some(&self.fee_payer_pubkey(user)));
+        message.recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
+
+        let mut transaction = OfflineTransaction::new(&message);
+        if let Some(fee_payer) = &self.fee_payer {
+            transaction.sign(fee_payer.as_ref())?;
+        }
+        Ok(transaction)
+    }
+
+    /// Like [`DEXClient::build_offline_transaction`], signed over the durable nonce in
+    /// `nonce_account` so it stays valid until sent. Its authority has to be `user` or the
+    /// client's fee payer.
+    pub async fn build_offline_transaction_with_nonce(
+        &self,
+        user: &Pubkey,
+        instructions: &[Instruction],
+        nonce_account: &Pubkey,
+    ) -> std::result::Result<OfflineTransaction, Box<dyn std::error::Error>> {
+        let nonce = self.get_nonce_info(nonce_account).await?;
+        let fee_payer = self.fee_payer_pubkey(user);
+        if nonce.authority != *user && nonce.authority != fee_payer {
+            return Err(format!("nonce {} is advanced by {}, neither the signer nor the fee payer", nonce_account, nonce.authority).into());
+        }
+
+        let mut transaction = OfflineTransaction::new(&nonce.message(instructions, &fee_payer));
+        if let Some(fee_payer) = &self.fee_payer {
+            transaction.sign(fee_payer.as_ref())?;
+        }
+        Ok(transaction)
+    }
+
+    /// Sends a transaction from [`DEXClient::build_offline_transaction`] once every signature
+    /// is in. One on a durable nonce is rebroadcast until it lands or the nonce moves on.
+    pub async fn send_offline_transaction(&self, transaction: OfflineTransaction) -> std::result::Result<String, Box<dyn std::error::Error>> {
+        let transaction = transaction.into_transaction()?;
+        if let Some(nonce_account) = durable_nonce_account(&transaction.message) {
+            let sent = TransactionSender::with_config(&self.rpc_client, self.send_config.clone()).send_durable(&transaction, &nonce_account).await?;
+            return Ok(sent.signature.to_string());
+        }
+        let signature = self.rpc_client.send_and_confirm_transaction(&transaction).await?;
+        Ok(signature.to_string())
+    }
+
+    /// The nonce account at `address`, with its current value.
+    pub async fn get_nonce_info(&self, address: &Pubkey) -> std::result::Result<NonceInfo, Box<dyn std::error::Error>> {
+        let account = self.rpc_client.get_account(address).await?;
+        NonceInfo::from_account(address, &account)
+    }
+
+    /// Creates `nonce_account`, paid for by the fee payer, with `authority` to advance it.
+    pub async fn create_nonce_account(&self, user: &dyn Signer, nonce_account: &dyn Signer, authority: &Pubkey) -> std::result::Result<NonceInfo, Box<dyn std::error::Error>> {
+        let instructions = create_nonce_account_instructions(&self.fee_payer_pubkey(&user.pubkey()), &nonce_account.pubkey(), authority);
+        let sender = TransactionSender::with_config(&self.rpc_client, self.send_config.clone());
+        sender.send(|recent_blockhash| self.sign_transaction_with(user, &[nonce_account], &instructions, recent_blockhash)).await?;
+        self.get_nonce_info(&nonce_account.pubkey()).await
+    }
+
+    /// Moves `nonce_account` to a new value, which voids every transaction signed over the
+    /// current one. `user` or the fee payer has to be its authority.
+    pub async fn advance_nonce_account(&self, user: &dyn Signer, nonce_account: &Pubkey) -> std::result::Result<NonceInfo, Box<dyn std::error::Error>> {
+        let nonce = self.get_nonce_info(nonce_account).await?;
+        let instruction = solana_sdk::system_instruction::advance_nonce_account(nonce_account, &nonce.authority);
+        self.send_instructions(user, vec![instruction]).await?;
+        self.get_nonce_info(nonce_account).await
+    }
+
+
+
+    /// The associated token account of `user` for `mint`, created first if it doesn't exist.
+    pub async fn get_or_create_associated_token_account(&self, user: &dyn Signer, mint: &Pubkey) -> std::result::Result<Pubkey, Box<dyn std::error::Error>> {
+        let associated_token_address = get_associated_token_address(&user.pubkey(), mint);
+
+        let (_, accounts) = self.rpc_client.get_multiple_accounts(&[associated_token_address]).await?;
+        match accounts.first() {
+            Some(Some(_)) => Ok(associated_token_address),
+            _ => self.create_associated_token_account(user, mint).await,
+        }
+    }
+
+    async fn create_associated_token_account(&self, user: &dyn Signer, mint: &Pubkey) -> std::result::Result<Pubkey, Box<dyn std::error::Error>> {
+        let associated_token_address = get_associated_token_address(&user.pubkey(), mint);
+        
+        let instruction = spl_associated_token_account::instruction::create_associated_token_account(
+            &self.fee_payer_pubkey(&user.pubkey()),
+            &user.pubkey(),
+            mint,
+            &spl_token::id(),
+        );
+
+        self.send_instructions(user, vec![instruction]).await?;
+
+        Ok(associated_token_address)
+    }
+
+    /// Fetches every account `pool` watches into it, again for accounts that only turn up
+    /// once others are loaded.
+    pub async fn load_pool(&self, pool: &mut dyn Pool) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let mut loaded = HashSet::new();
+        loop {
+            let pubkeys: Vec<Pubkey> = pool.accounts_to_watch().into_iter().filter(|pubkey| !loaded.contains(pubkey)).collect();
+            if pubkeys.is_empty() {
+                return Ok(());
+            }
+            let (_, accounts) = self.rpc_client.get_multiple_accounts(&pubkeys).await?;
+            for (pubkey, account) in pubkeys.into_iter().zip(accounts) {
+                pool.update(&pubkey, account.as_ref())?;
+                loaded.insert(pubkey);
+            }
+        }
+    }
+
+    /// Instructions executing `quote` on `pool` for `user`, creating their missing token
+    /// accounts first. Account rent is paid by the client's fee payer, `user` unless one is set.
+    pub async fn build_swap_instructions(
+        &self,
+        user: &Pubkey,
+        pool: &dyn Pool,
+        quote: &PoolQuote,
+        min_amount_out: u64,
+    ) -> std::result::Result<Vec<Instruction>, Box<dyn std::error::Error>> {
+        let mut instructions = self.create_token_account_instructions(user, &[quote.mint_in, quote.mint_out]).await?;
+        instructions.extend(pool.swap_instructions(user, &self.fee_payer_pubkey(user), quote, min_amount_out)?);
+        Ok(instructions)
+    }
+
+    /// Every whirlpool of `whirlpools_config` trading `mint_a` against `mint_b`, in either order,
+    /// one per tick spacing in use.
+    pub async fn find_whirlpools(
+        &self,
+        program_id: &Pubkey,
+        whirlpools_config: &Pubkey,
+        mint_a: &Pubkey,
+        mint_b: &Pubkey,
+    ) -> std::result::Result<Vec<Pubkey>, Box<dyn std::error::Error>> {
+        let candidates: Vec<Pubkey> = [(mint_a, mint_b), (mint_b, mint_a)]
+            .into_iter()
+            .flat_map(|(mint_a, mint_b)| WHIRLPOOL_TICK_SPACINGS.map(|tick_spacing| get_whirlpool_address(program_id, whirlpools_config, mint_a, mint_b, tick_spacing)))
+            .collect();
+        let (_, accounts) = self.rpc_client.get_multiple_accounts(&candidates).await?;
+        Ok(candidates
+            .into_iter()
+            .zip(accounts)
+            .filter(|(_, account)| account.as_ref().is_some_and(|account| account.owner == *program_id && matches!(decode_whirlpool_account(&account.data), Some(WhirlpoolAccount::Whirlpool(_)))))
+            .map(|(address, _)| address)
+            .collect())
+    }
+
+    /// Instructions executing every route of `plan` for `user`, creating their missing token
+    /// accounts first, intermediate tokens included.
+    pub async fn build_route_instructions(
+        &self,
+        user: &Pubkey,
+        router: &Router,
+        plan: &RoutePlan,
+        slippage_bps: u16,
+    ) -> std::result::Result<Vec<Instruction>, Box<dyn std::error::Error>> {
+        let mut instructions = self.create_token_account_instructions(user, &plan.mints()).await?;
+        instructions.extend(plan.swap_instructions(router, user, &self.fee_payer_pubkey(user), slippage_bps)?);
+        Ok(instructions)
+    }
+
+    /// Instructions executing `opportunity` found among `pools` for `user` atomically, creating
+    /// their missing token accounts first.
+    pub async fn build_arbitrage_instructions(
+        &self,
+        user: &Pubkey,
+        pools: &[Box<dyn Pool>],
+        opportunity: &Opportunity,
+        config: &ArbitrageConfig,
+    ) -> std::result::Result<Vec<Instruction>, Box<dyn std::error::Error>> {
+        let mints: Vec<Pubkey> = opportunity.hops.iter().map(|hop| hop.mint_in).collect();
+        let mut instructions = self.create_token_account_instructions(user, &mints).await?;
+        instructions.extend(opportunity.swap_instructions(pools, user, &self.fee_payer_pubkey(user), config)?);
+        Ok(instructions)
+    }
+
+    /// Creates the associated token accounts of `user` for `mints` that don't exist yet, paid
+    /// by the fee payer.
+    async fn create_token_account_instructions(&self, user: &Pubkey, mints: &[Pubkey]) -> std::result::Result<Vec<Instruction>, Box<dyn std::error::Error>> {
+        let funder = self.fee_payer_pubkey(user);
+        let token_accounts: Vec<Pubkey> = mints.iter().map(|mint| get_associated_token_address(user, mint)).collect();
+        let (_, accounts) = self.rpc_client.get_multiple_accounts(&token_accounts).await?;
+        Ok(mints
+            .iter()
+            .zip(accounts)
+            .filter(|(_, account)| account.is_none())
+            .map(|(mint, _)| spl_associated_token_account::instruction::create_associated_token_account_idempotent(&funder, user, mint, &spl_token::id()))
+            .collect())
+    }
+
+    pub async fn get_token_balance(&self, token_account: &Pubkey) -> std::result::Result<u64, Box<dyn std::error::Error>> {
+        self.rpc_client.get_token_account_balance(token_account).await
+    }
+
+    /// Balances of `token_accounts` in one request, `None` for accounts that don't exist.
+    pub async fn get_token_balances(&self, token_accounts: &[Pubkey]) -> std::result::Result<Vec<Option<u64>>, Box<dyn std::error::Error>> {
+        let (_, accounts) = self.rpc_client.get_multiple_accounts(token_accounts).await?;
+        token_accounts
+            .iter()
+            .zip(accounts)
+            .map(|(token_account, account)| {
+                account
+                    .map(|account| {
+                        spl_token::state::Account::unpack(&account.data)
+                            .map(|token_account| token_account.amount)
+                            .map_err(|_| format!("{} is not a token account", token_account).into())
+                    })
+                    .transpose()
+            })
+            .collect()
+    }
+
+    pub async fn get_whirlpool(&self, whirlpool_pubkey: &Pubkey) -> std::result::Result<Whirlpool, Box<dyn std::error::Error>> {
+        match decode_whirlpool_account(&self.rpc_client.get_account_data(whirlpool_pubkey).await?) {
+            Some(WhirlpoolAccount::Whirlpool(whirlpool)) => Ok(*whirlpool),
+            _ => Err(format!("{} is not a whirlpool", whirlpool_pubkey).into()),
+        }
+    }
+
+    /// Up to `count` tick arrays a swap would cross, in swap direction, stopping at the first
+    /// one that is not initialized. Fails when even the array holding the current tick is missing.
+    pub async fn get_tick_arrays_for_swap(
+        &self,
+        program_id: &Pubkey,
+        whirlpool_pubkey: &Pubkey,
+        whirlpool: &Whirlpool,
+        a_to_b: bool,
+        count: usize,
+    ) -> std::result::Result<Vec<(Pubkey, TickArray)>, Box<dyn std::error::Error>> {
+        let start_tick_indexes = tickutil_get_start_tick_indexes_for_swap(whirlpool.tick_current_index, whirlpool.tick_spacing, a_to_b, count);
+        let fetched = self.get_tick_arrays(program_id, whirlpool_pubkey, &start_tick_indexes).await?;
+
+        let tick_arrays: Vec<(Pubkey, TickArray)> = fetched
+            .iter()
+            .map_while(|(pubkey, tick_array)| tick_array.map(|tick_array| (*pubkey, tick_array)))
+            .collect();
+        if tick_arrays.is_empty() {
+            return Err(format!("tick array {} holding the current tick is not initialized", fetched[0].0).into());
+        }
+        Ok(tick_arrays)
+    }
+
+    /// The tick arrays of `whirlpool_pubkey` starting at each of `start_tick_indexes`, `None`
+    /// where the array is not initialized.
+    pub async fn get_tick_arrays(
+        &self,
+        program_id: &Pubkey,
+        whirlpool_pubkey: &Pubkey,
+        start_tick_indexes: &[i32],
+    ) -> std::result::Result<Vec<(Pubkey, Option<TickArray>)>, Box<dyn std::error::Error>> {
+        let pubkeys: Vec<Pubkey> = start_tick_indexes
+            .iter()
+            .map(|start_tick_index| pdautil_get_tick_array(program_id, whirlpool_pubkey, *start_tick_index))
+            .collect();
+        let (_, accounts) = self.rpc_client.get_multiple_accounts(&pubkeys).await?;
+
+        Ok(pubkeys
+            .into_iter()
+            .zip(accounts)
+            .map(|(pubkey, account)| match account.and_then(|account| decode_whirlpool_account(&account.data)) {
+                Some(WhirlpoolAccount::TickArray(tick_array)) => (pubkey, Some(*tick_array)),
+                _ => (pubkey, None),
+            })
+            .collect())
+    }
+
+    /// Prepends an `initialize_tick_array`, paid by `funder`, for each of `start_tick_indexes`
+    /// whose tick array does not exist yet.
+    pub async fn prepend_initialize_tick_array_instructions(
+        &self,
+        program_id: &Pubkey,
+        whirlpool_pubkey: &Pubkey,
+        funder: &Pubkey,
+        start_tick_indexes: &[i32],
+        instructions: Vec<Instruction>,
+    ) -> std::result::Result<Vec<Instruction>, Box<dyn std::error::Error>> {
+        let mut unique_start_tick_indexes = Vec::with_capacity(start_tick_indexes.len());
+        for start_tick_index in start_tick_indexes {
+            if !unique_start_tick_indexes.contains(start_tick_index) {
+                unique_start_tick_indexes.push(*start_tick_index);
+            }
+        }
+        let start_tick_indexes = unique_start_tick_indexes;
+        let tick_arrays = self.get_tick_arrays(program_id, whirlpool_pubkey, &start_tick_indexes).await?;
+
+        let mut prepended = Vec::with_capacity(tick_arrays.len() + instructions.len());
+        for (start_tick_index, (_, tick_array)) in start_tick_indexes.iter().zip(tick_arrays) {
+            if tick_array.is_none() {
+                prepended.push(build_initialize_tick_array_instruction(program_id, whirlpool_pubkey, funder, *start_tick_index)?);
+            }
+        }
+        prepended.extend(instructions);
+        Ok(prepended)
+    }
+}
+
+
+/// One swap of `swap_token_data.amount` on `pool_address`, quoted with `quote_orca_pool_swap`
+/// and accepting `slippage_bps` less than the simulated output. The user's missing token
+/// accounts are created first. Trades crossing more than three tick arrays need
+/// `build_orca_pool_swap_instructions`, which splits them.
+pub async fn build_orca_pool_swap_instruction<R: SolanaRpc>(orca_client: &DEXClient<R>, user: &dyn Signer, swap_token_data: &SwapTokenData, pool_address: &Pubkey, dex_address: &Pubkey, slippage_bps: u16) -> std::result::Result<Instruction, Box<dyn std::error::Error>> {
+    let quote = quote_orca_pool_swap(orca_client, pool_address, dex_address, &swap_token_data.token_in, swap_token_data.amount, slippage_bps, false).await?;
+    if quote.legs.len() != 1 {
+        return Err(format!("swap takes {} legs, use build_orca_pool_swap_instructions to split it", quote.legs.len()).into());
+    }
+
+    for mint in [&quote.whirlpool.token_mint_a, &quote.whirlpool.token_mint_b] {
+        orca_client.get_or_create_associated_token_account(user, &Pubkey::new_from_array(mint.to_bytes())).await?;
+    }
+
+    let user = user.pubkey();
+    let mut instructions = whirlpool_swap_instructions(dex_address, pool_address, &user, &user, &quote)?;
+    instructions.pop().ok_or_else(|| "quote has no swap".into())
+}
+
+
+/// Quotes swapping `amount` of `token_in` on `pool_address`, split into legs of at most three
+/// tick arrays. `slippage_bps` sets each leg's minimum output.
+///
+/// With `initialize_tick_arrays`, missing tick arrays are treated as empty and the ones the
+/// trade reaches are listed in `tick_arrays_to_initialize`. Otherwise the trade must fit in
+/// the arrays that already exist.
+pub async fn quote_orca_pool_swap<R: SolanaRpc>(
+    orca_client: &DEXClient<R>,
+    pool_address: &Pubkey,
+    dex_address: &Pubkey,
+    token_in: &Pubkey,
+    amount: u64,
+    slippage_bps: u16,
+    initialize_tick_arrays: bool,
+) -> std::result::Result<OrcaSwapQuote, Box<dyn std::error::Error>> {
+    let whirlpool = orca_client.get_whirlpool(pool_address).await?;
+    let a_to_b = if *token_in == Pubkey::new_from_array(whirlpool.token_mint_a.to_bytes()) {
+        true
+    } else if *token_in == Pubkey::new_from_array(whirlpool.token_mint_b.to_bytes()) {
+        false
+    } else {
+        return Err(format!("{} is not traded in pool {}", token_in, pool_address).into());
+    };
+
+    let start_tick_indexes = tickutil_get_start_tick_indexes_for_swap(whirlpool.tick_current_index, whirlpool.tick_spacing, a_to_b, MAX_TICK_ARRAYS_PER_TRADE);
+    let fetched = orca_client.get_tick_arrays(dex_address, pool_address, &start_tick_indexes).await?;
+    let tick_arrays: Vec<(i32, Pubkey, Option<TickArray>)> = start_tick_indexes
+        .into_iter()
+        .zip(fetched)
+        .map(|(start_tick_index, (pubkey, tick_array))| (start_tick_index, pubkey, tick_array))
+        .collect();
+    quote_whirlpool_swap(pool_address, &whirlpool, &tick_arrays, amount, a_to_b, slippage_bps, initialize_tick_arrays)
+}
+
+/// Like `build_orca_pool_swap_instruction`, but quotes the trade against the pool's tick arrays
+/// with `quote_orca_pool_swap` and splits it into several swaps when it would cross more than
+/// three of them. Missing token accounts and, with `initialize_tick_arrays`, tick arrays are
+/// created by instructions ahead of the swaps, paid by the client's fee payer (`user` unless
+/// one is set); nothing is sent.
+pub async fn build_orca_pool_swap_instructions<R: SolanaRpc>(orca_client: &DEXClient<R>, user: &Pubkey, swap_token_data: &SwapTokenData, pool_address: &Pubkey, dex_address: &Pubkey, slippage_bps: u16, initialize_tick_arrays: bool) -> std::result::Result<Vec<Instruction>, Box<dyn std::error::Error>> {
+    let quote = quote_orca_pool_swap(orca_client, pool_address, dex_address, &swap_token_data.token_in, swap_token_data.amount, slippage_bps, initialize_tick_arrays).await?;
+    build_orca_pool_swap_instructions_from_quote(orca_client, user, &quote, pool_address, dex_address).await
+}
+
+/// The instructions executing `quote`, see `build_orca_pool_swap_instructions`.
+pub async fn build_orca_pool_swap_instructions_from_quote<R: SolanaRpc>(orca_client: &DEXClient<R>, user: &Pubkey, quote: &OrcaSwapQuote, pool_address: &Pubkey, dex_address: &Pubkey) -> std::result::Result<Vec<Instruction>, Box<dyn std::error::Error>> {
+    let funder = orca_client.fee_payer_pubkey(user);
+    let mints = [&quote.whirlpool.token_mint_a, &quote.whirlpool.token_mint_b].map(|mint| Pubkey::new_from_array(mint.to_bytes()));
+    let mut instructions = orca_client.create_token_account_instructions(user, &mints).await?;
+    instructions.extend(whirlpool_swap_instructions(dex_address, pool_address, user, &funder, quote)?);
+    Ok(instructions)
+}
+
+#[tokio::test]
+async fn test_build_orca_pool_swap_instruction_offline() {
+    use rust_decimal::Decimal;
+    use solana_sdk::account::Account;
+    use solana_sdk::program_pack::Pack;
+    use solana_sdk::signer::keypair::keypair_from_seed;
+    use spl_token::state::{Account as TokenAccount, AccountState};
+
+    use crate::config::Config;
+    use crate::dex::apply_slippage_bps;
+    use crate::rpc::InMemoryRpc;
+    use crate::tick_array::{generate_oracle_pda, pricemath_sqrt_price_x64_to_price};
+
+    // synthetic whirlpool state for WSOL_USDC_3000 priced at 140 USDC/SOL, tick -19662
+    let rpc = InMemoryRpc::from_fixture_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/wsol_usdc_3000")).unwrap();
+    let user = keypair_from_seed(&[7; 32]).unwrap();
+    let mainnet = &Config::builtin().profiles["mainnet"];
+    let (sol, usdc_token) = (mainnet.token("SOL").unwrap(), mainnet.token("USDC").unwrap());
+    let (wsol, usdc) = (sol.mint().unwrap(), usdc_token.mint().unwrap());
+    let (wsol_decimals, usdc_decimals) = (sol.decimals, usdc_token.decimals);
+
+    for (mint, amount) in [(wsol, 2_000_000_000), (usdc, 500_000_000)] {
+        let token_account = TokenAccount {
+            mint,
+            owner: user.pubkey(),
+            amount,
+            state: AccountState::Initialized,
+            ..Default::default()
+        };
+        let mut data = vec![0; TokenAccount::LEN];
+        token_account.pack_into_slice(&mut data);
+        rpc.set_account(
+            get_associated_token_address(&user.pubkey(), &mint),
+            Account { lamports: 2_039_280, data, owner: spl_token::id(), executable: false, rent_epoch: 0 },
+        );
+    }
+
+    let client = DEXClient::with_rpc(rpc);
+    let pool_address = mainnet.pool("WSOL_USDC_3000").unwrap().address().unwrap();
+    let dex_address = mainnet.whirlpool_program_id().unwrap();
+
+    let price = pricemath_sqrt_price_x64_to_price(6_902_236_513_748_122_214, wsol_decimals, usdc_decimals);
+    assert_eq!(Decimal::from_str(&price).unwrap().round_dp(2), Decimal::from(140));
+
+    let swap_token_data = SwapTokenData::new(300_000, usdc, wsol, usdc_decimals, wsol_decimals);
+    let instruction = build_orca_pool_swap_instruction(&client, &user, &swap_token_data, &pool_address, &dex_address, 100).await.unwrap();
+
+    // the array after -16896 is not initialized, so the last one is repeated
+    let tick_arrays = [-22528, -16896, -16896].map(|start_tick_index| pdautil_get_tick_array(&dex_address, &pool_address, start_tick_index));
+    let accounts: Vec<Pubkey> = instruction.accounts.iter().map(|meta| meta.pubkey).collect();
+    assert_eq!(instruction.program_id, dex_address);
+    assert_eq!(accounts[1], user.pubkey());
+    assert_eq!(accounts[3], get_associated_token_address(&user.pubkey(), &wsol));
+    assert_eq!(accounts[5], get_associated_token_address(&user.pubkey(), &usdc));
+    assert_eq!(accounts[7..10], tick_arrays);
+    assert_eq!(accounts[10], generate_oracle_pda(&pool_address, &dex_address).0);
+
+    // 0.3 USDC buys about 2_142_857 lamports at 140 USDC/SOL, less the 0.3% fee and 1% slippage
+    let swap_data = SwapData::try_from_slice(&instruction.data[8..]).unwrap();
+    let quote = quote_orca_pool_swap(&client, &pool_address, &dex_address, &usdc, 300_000, 100, false).await.unwrap();
+    assert!(!swap_data.a_to_b);
+    assert_eq!(swap_data.amount, 300_000);
+    assert_eq!(swap_data.other_amount_threshold, apply_slippage_bps(quote.amount_out, 100));
+    assert!((2_100_000..2_120_000).contains(&swap_data.other_amount_threshold));
+
+    client.send_instructions(&user, vec![instruction]).await.unwrap();
+    assert_eq!(client.rpc().sent_transactions().len(), 1);
+}
+
+#[tokio::test]
+async fn test_initialize_tick_array_instructions() {
+    use solana_sdk::signer::keypair::keypair_from_seed;
+
+    use crate::whirlpool::InitializeTickArrayData;
+
+    use crate::config::Config;
+    use crate::rpc::InMemoryRpc;
+
+    let fixture_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/wsol_usdc_3000");
+    let user = keypair_from_seed(&[7; 32]).unwrap();
+    let mainnet = &Config::builtin().profiles["mainnet"];
+    let pool_address = mainnet.pool("WSOL_USDC_3000").unwrap().address().unwrap();
+    let dex_address = mainnet.whirlpool_program_id().unwrap();
+    let (sol, usdc) = (mainnet.token("SOL").unwrap(), mainnet.token("USDC").unwrap());
+    let swap_token_data = SwapTokenData::new(300_000, usdc.mint().unwrap(), sol.mint().unwrap(), usdc.decimals, sol.decimals);
+
+    // -22528 and -16896 exist in the fixture, -11264 does not
+    let client = DEXClient::with_rpc(InMemoryRpc::from_fixture_dir(fixture_dir).unwrap());
+    let instructions = client
+        .prepend_initialize_tick_array_instructions(&dex_address, &pool_address, &user.pubkey(), &[-22528, -16896, -11264, -11264], vec![])
+        .await
+        .unwrap();
+    assert_eq!(instructions.len(), 1);
+    let instruction = &instructions[0];
+    assert_eq!(instruction.program_id, dex_address);
+    assert_eq!(instruction.accounts[0].pubkey, pool_address);
+    assert_eq!(instruction.accounts[1].pubkey, user.pubkey());
+    assert!(instruction.accounts[1].is_signer && instruction.accounts[1].is_writable);
+    assert_eq!(instruction.accounts[2].pubkey, pdautil_get_tick_array(&dex_address, &pool_address, -11264));
+    assert_eq!(instruction.data[..8], [11, 188, 193, 214, 141, 91, 149, 184]);
+    assert_eq!(InitializeTickArrayData::try_from_slice(&instruction.data[8..]).unwrap().start_tick_index, -11264);
+
+    // a small trade never reaches -11264, so only the user's two token accounts are created
+    let instructions = build_orca_pool_swap_instructions(&client, &user.pubkey(), &swap_token_data, &pool_address, &dex_address, 1000, true).await.unwrap();
+    assert_eq!(instructions.len(), 3);
+    assert!(instructions[..2].iter().all(|instruction| instruction.program_id == spl_associated_token_account::id()));
+    assert!(client.rpc().sent_transactions().is_empty());
+
+    // without any tick array the swap only goes through if the current one is created first
+    let rpc = InMemoryRpc::new();
+    rpc.load_account_file(format!("{}/{}.json", fixture_dir, pool_address)).unwrap();
+    let client = DEXClient::with_rpc(rpc);
+    assert!(build_orca_pool_swap_instructions(&client, &user.pubkey(), &swap_token_data, &pool_address, &dex_address, 1000, false).await.is_err());
+
+    let instructions = build_orca_pool_swap_instructions(&client, &user.pubkey(), &swap_token_data, &pool_address, &dex_address, 1000, true).await.unwrap();
+    assert_eq!(instructions.len(), 4);
+    let current_tick_array = pdautil_get_tick_array(&dex_address, &pool_address, -22528);
+    assert_eq!(instructions[2].accounts[2].pubkey, current_tick_array);
+    let accounts: Vec<Pubkey> = instructions[3].accounts.iter().map(|meta| meta.pubkey).collect();
+    assert_eq!(accounts[7..10], [current_tick_array; 3]);
+}
+
+#[tokio::test]
+async fn test_separate_fee_payer() {
+    use solana_sdk::signer::keypair::keypair_from_seed;
+
+    use crate::config::Config;
+    use crate::rpc::InMemoryRpc;
+
+    let rpc = InMemoryRpc::from_fixture_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/wsol_usdc_3000")).unwrap();
+    let authority = keypair_from_seed(&[7; 32]).unwrap();
+    let fee_payer = keypair_from_seed(&[8; 32]).unwrap();
+    let fee_payer_pubkey = fee_payer.pubkey();
+    let client = DEXClient::with_rpc(rpc).with_fee_payer(fee_payer);
+    assert_eq!(client.fee_payer_pubkey(&authority.pubkey()), fee_payer_pubkey);
+
+    let mainnet = &Config::builtin().profiles["mainnet"];
+    let pool_address = mainnet.pool("WSOL_USDC_3000").unwrap().address().unwrap();
+    let dex_address = mainnet.whirlpool_program_id().unwrap();
+    let (sol, usdc) = (mainnet.token("SOL").unwrap(), mainnet.token("USDC").unwrap());
+    let swap_token_data = SwapTokenData::new(300_000, usdc.mint().unwrap(), sol.mint().unwrap(), usdc.decimals, sol.decimals);
+
+    // token account rent comes from the fee payer, the authority still owns and signs the swap
+    let instructions = build_orca_pool_swap_instructions(&client, &authority.pubkey(), &swap_token_data, &pool_address, &dex_address, 50, false).await.unwrap();
+    assert_eq!(instructions[0].accounts[0].pubkey, fee_payer_pubkey);
+    assert_eq!(instructions[0].accounts[2].pubkey, authority.pubkey());
+    client.send_instructions(&authority, instructions).await.unwrap();
+
+    // creating a token account doesn't need the authority's signature at all
+    client.get_or_create_associated_token_account(&authority, &sol.mint().unwrap()).await.unwrap();
+
+    let sent = client.rpc().sent_transactions();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0].message.account_keys[0], fee_payer_pubkey);
+    assert_eq!(sent[0].signatures.len(), 2);
+    assert_eq!(sent[1].message.account_keys[0], fee_payer_pubkey);
+    assert_eq!(sent[1].signatures.len(), 1);
+}
+
+#[tokio::test]
+async fn test_get_token_balances() {
+    use spl_token::state::{Account as TokenAccount, AccountState};
+
+    use crate::rpc::InMemoryRpc;
+
+    let [token_account, missing, not_a_token_account] = [1u8, 2, 3].map(|byte| Pubkey::new_from_array([byte; 32]));
+    let mut data = vec![0; TokenAccount::LEN];
+    TokenAccount { mint: Pubkey::new_unique(), owner: Pubkey::new_unique(), amount: 42, state: AccountState::Initialized, ..Default::default() }.pack_into_slice(&mut data);
+    let rpc = InMemoryRpc::new();
+    rpc.set_program_account(token_account, spl_token::id(), &[], &data);
+    rpc.set_program_account(not_a_token_account, Pubkey::new_unique(), &[], &[0; 8]);
+    let client = DEXClient::with_rpc(rpc);
+
+    assert_eq!(client.get_token_balances(&[token_account, missing]).await.unwrap(), vec![Some(42), None]);
+    assert!(client.get_token_balances(&[not_a_token_account]).await.is_err());
+    // a node that is down is not a missing account
+    client.rpc().set_unavailable(true);
+    assert!(client.get_token_balances(&[token_account, missing]).await.is_err());
+}
@@ -0,0 +1,393 @@
+//! Per-cluster settings: endpoints, commitment, program ids, known tokens and pools.
+//!
+//! Profiles are read from a TOML file, see `config/solana_dex.toml` which is also compiled in
+//! as the fallback, and the selected profile can be overridden from the environment.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::constant::{JUPITER_API_URL, METEORA_DLMM_PROGRAM_ID, RAYDIUM_AMM_PROGRAM_ID, RAYDIUM_CLMM_PROGRAM_ID};
+
+pub type ConfigError = Box<dyn std::error::Error>;
+
+/// The profiles shipped with the crate.
+pub const DEFAULT_CONFIG: &str = include_str!("../config/solana_dex.toml");
+
+/// Read when neither `SOLANA_DEX_CONFIG` nor an explicit path is given.
+pub const DEFAULT_CONFIG_PATH: &str = "solana_dex.toml";
+
+pub const CONFIG_PATH_ENV: &str = "SOLANA_DEX_CONFIG";
+pub const PROFILE_ENV: &str = "SOLANA_DEX_PROFILE";
+pub const RPC_URL_ENV: &str = "SOLANA_DEX_RPC_URL";
+pub const WS_URL_ENV: &str = "SOLANA_DEX_WS_URL";
+pub const COMMITMENT_ENV: &str = "SOLANA_DEX_COMMITMENT";
+pub const WHIRLPOOL_PROGRAM_ID_ENV: &str = "SOLANA_DEX_WHIRLPOOL_PROGRAM_ID";
+pub const KEYPAIR_ENV: &str = "SOLANA_DEX_KEYPAIR";
+pub const FEE_PAYER_ENV: &str = "SOLANA_DEX_FEE_PAYER";
+pub const JUPITER_URL_ENV: &str = "SOLANA_DEX_JUPITER_URL";
+
+// still honored so existing .env files keep working
+const LEGACY_RPC_URL_ENV: &str = "RPC_URL";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Config {
+    pub default_profile: String,
+    pub profiles: BTreeMap<String, ClusterConfig>,
+}
+
+/// Everything that differs between mainnet, devnet and a local validator.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClusterConfig {
+    pub rpc_url: String,
+    pub ws_url: String,
+    /// More RPC endpoints to fail over to, `rpc_url` comes first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rpc_endpoints: Vec<RpcEndpointConfig>,
+    /// Send every transaction through all sending endpoints at once.
+    #[serde(default)]
+    pub fan_out_sends: bool,
+    /// Block engine swaps can be sent to as bundles, with the `jito` feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jito: Option<JitoConfig>,
+    /// `processed`, `confirmed` or `finalized`.
+    pub commitment: String,
+    pub whirlpool_program_id: String,
+    /// Raydium's CLMM program, mainnet's when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raydium_clmm_program_id: Option<String>,
+    /// Raydium's AMM v4 program, mainnet's when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raydium_amm_program_id: Option<String>,
+    /// Meteora's DLMM program, mainnet's when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meteora_dlmm_program_id: Option<String>,
+    /// Phoenix's order book program; Phoenix markets are only traded when it is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phoenix_program_id: Option<String>,
+    /// Jupiter's v6 swap API, compared against with the `jupiter` feature, the public one when
+    /// unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jupiter_url: Option<String>,
+    /// Keypair file, base58 secret key, `keystore:NAME` or remote signer trades are signed with, see
+    /// `keypair::load_authority`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keypair: Option<String>,
+    /// Keypair file or remote signer paying fees and rent instead of the signer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_payer: Option<String>,
+    /// Keyed by symbol.
+    #[serde(default)]
+    pub tokens: BTreeMap<String, TokenConfig>,
+    /// Keyed by name.
+    #[serde(default)]
+    pub pools: BTreeMap<String, PoolConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RpcEndpointConfig {
+    pub url: String,
+    /// Requests this endpoint may get per second, unlimited if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_second: Option<f64>,
+    /// Whether transactions are sent through this endpoint, or it is only read from.
+    #[serde(default = "default_true")]
+    pub send: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JitoConfig {
+    /// e.g. `https://mainnet.block-engine.jito.wtf`.
+    pub block_engine_url: String,
+    /// Paid to a tip account by the last transaction of every bundle.
+    pub tip_lamports: u64,
+    /// Asked from the block engine when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tip_accounts: Vec<String>,
+    /// Send normally, without the tip, once a bundle provably didn't land.
+    #[serde(default = "default_true")]
+    pub fallback: bool,
+}
+
+impl JitoConfig {
+    pub fn tip_accounts(&self) -> Result<Vec<Pubkey>, ConfigError> {
+        self.tip_accounts
+            .iter()
+            .map(|tip_account| Pubkey::from_str(tip_account).map_err(|err| format!("invalid tip account {}: {}", tip_account, err).into()))
+            .collect()
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TokenConfig {
+    pub mint: String,
+    pub decimals: i8,
+}
+
+/// A whirlpool and the symbols of its two tokens.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PoolConfig {
+    pub address: String,
+    pub token_a: String,
+    pub token_b: String,
+}
+
+impl Config {
+    pub fn from_toml_str(toml_str: &str) -> Result<Self, ConfigError> {
+        let config: Config = toml::from_str(toml_str)?;
+        for (name, profile) in &config.profiles {
+            profile.validate().map_err(|err| format!("profile {}: {}", name, err))?;
+        }
+        if !config.profiles.contains_key(&config.default_profile) {
+            return Err(format!("default_profile {} is not defined", config.default_profile).into());
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        Self::from_toml_str(&fs::read_to_string(path)?).map_err(|err| format!("{}: {}", path.display(), err).into())
+    }
+
+    /// The profiles compiled into the crate.
+    pub fn builtin() -> Self {
+        Self::from_toml_str(DEFAULT_CONFIG).expect("bundled config is valid")
+    }
+
+    /// `$SOLANA_DEX_CONFIG`, else `./solana_dex.toml` if present, else [`Config::builtin`].
+    pub fn load_default() -> Result<Self, ConfigError> {
+        if let Ok(path) = env::var(CONFIG_PATH_ENV) {
+            return Self::load(path);
+        }
+        if Path::new(DEFAULT_CONFIG_PATH).exists() {
+            return Self::load(DEFAULT_CONFIG_PATH);
+        }
+        Ok(Self::builtin())
+    }
+
+    /// The profile called `name`, else `$SOLANA_DEX_PROFILE`, else `default_profile`, with
+    /// environment overrides applied.
+    pub fn profile(&self, name: Option<&str>) -> Result<ClusterConfig, ConfigError> {
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => env::var(PROFILE_ENV).unwrap_or_else(|_| self.default_profile.clone()),
+        };
+        let mut profile = self
+            .profiles
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| format!("unknown profile {}, expected one of {:?}", name, self.profiles.keys().collect::<Vec<_>>()))?;
+        profile.apply_overrides(|key| env::var(key).ok())?;
+        Ok(profile)
+    }
+}
+
+impl ClusterConfig {
+    /// Replaces endpoints, commitment, program id and keys with whatever `lookup` returns for
+    /// the `SOLANA_DEX_*` variables.
+    pub fn apply_overrides(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        if let Some(rpc_url) = lookup(RPC_URL_ENV).or_else(|| lookup(LEGACY_RPC_URL_ENV)) {
+            self.rpc_url = rpc_url;
+        }
+        if let Some(ws_url) = lookup(WS_URL_ENV) {
+            self.ws_url = ws_url;
+        }
+        if let Some(commitment) = lookup(COMMITMENT_ENV) {
+            self.commitment = commitment;
+        }
+        if let Some(program_id) = lookup(WHIRLPOOL_PROGRAM_ID_ENV) {
+            self.whirlpool_program_id = program_id;
+        }
+        if let Some(keypair) = lookup(KEYPAIR_ENV) {
+            self.keypair = Some(keypair);
+        }
+        if let Some(fee_payer) = lookup(FEE_PAYER_ENV) {
+            self.fee_payer = Some(fee_payer);
+        }
+        if let Some(jupiter_url) = lookup(JUPITER_URL_ENV) {
+            self.jupiter_url = Some(jupiter_url);
+        }
+        self.validate()
+    }
+
+    /// `rpc_url` followed by `rpc_endpoints`.
+    pub fn all_rpc_endpoints(&self) -> Vec<RpcEndpointConfig> {
+        let primary = RpcEndpointConfig { url: self.rpc_url.clone(), requests_per_second: None, send: true };
+        std::iter::once(primary).chain(self.rpc_endpoints.iter().cloned()).collect()
+    }
+
+    pub fn commitment_config(&self) -> Result<CommitmentConfig, ConfigError> {
+        match self.commitment.as_str() {
+            "processed" | "confirmed" | "finalized" => Ok(CommitmentConfig::from_str(&self.commitment)?),
+            other => Err(format!("unsupported commitment {}", other).into()),
+        }
+    }
+
+    pub fn whirlpool_program_id(&self) -> Result<Pubkey, ConfigError> {
+        parse_pubkey("whirlpool_program_id", &self.whirlpool_program_id)
+    }
+
+    pub fn raydium_clmm_program_id(&self) -> Result<Pubkey, ConfigError> {
+        parse_pubkey("raydium_clmm_program_id", self.raydium_clmm_program_id.as_deref().unwrap_or(RAYDIUM_CLMM_PROGRAM_ID))
+    }
+
+    pub fn raydium_amm_program_id(&self) -> Result<Pubkey, ConfigError> {
+        parse_pubkey("raydium_amm_program_id", self.raydium_amm_program_id.as_deref().unwrap_or(RAYDIUM_AMM_PROGRAM_ID))
+    }
+
+    pub fn meteora_dlmm_program_id(&self) -> Result<Pubkey, ConfigError> {
+        parse_pubkey("meteora_dlmm_program_id", self.meteora_dlmm_program_id.as_deref().unwrap_or(METEORA_DLMM_PROGRAM_ID))
+    }
+
+    pub fn phoenix_program_id(&self) -> Result<Option<Pubkey>, ConfigError> {
+        self.phoenix_program_id.as_deref().map(|program_id| parse_pubkey("phoenix_program_id", program_id)).transpose()
+    }
+
+    pub fn jupiter_url(&self) -> &str {
+        self.jupiter_url.as_deref().unwrap_or(JUPITER_API_URL)
+    }
+
+    pub fn token(&self, symbol: &str) -> Result<&TokenConfig, ConfigError> {
+        self.tokens.get(symbol).ok_or_else(|| format!("unknown token {}", symbol).into())
+    }
+
+    /// Symbol and config of the token with `mint`, if it is a known one.
+    pub fn token_by_mint(&self, mint: &Pubkey) -> Option<(&str, &TokenConfig)> {
+        self.tokens
+            .iter()
+            .find(|(_, token)| token.mint().is_ok_and(|token_mint| token_mint == *mint))
+            .map(|(symbol, token)| (symbol.as_str(), token))
+    }
+
+    pub fn pool(&self, name: &str) -> Result<&PoolConfig, ConfigError> {
+        self.pools.get(name).ok_or_else(|| format!("unknown pool {}", name).into())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.commitment_config()?;
+        for endpoint in &self.rpc_endpoints {
+            if !endpoint.url.starts_with("http://") && !endpoint.url.starts_with("https://") {
+                return Err(format!("rpc endpoint {} is not an http(s) url", endpoint.url).into());
+            }
+            if endpoint.requests_per_second.is_some_and(|requests_per_second| requests_per_second <= 0.0) {
+                return Err(format!("rpc endpoint {} needs a positive requests_per_second", endpoint.url).into());
+            }
+        }
+        if let Some(jito) = &self.jito {
+            if !jito.block_engine_url.starts_with("http://") && !jito.block_engine_url.starts_with("https://") {
+                return Err(format!("block engine {} is not an http(s) url", jito.block_engine_url).into());
+            }
+            jito.tip_accounts()?;
+        }
+        if !self.jupiter_url().starts_with("http://") && !self.jupiter_url().starts_with("https://") {
+            return Err(format!("jupiter url {} is not an http(s) url", self.jupiter_url()).into());
+        }
+        self.whirlpool_program_id()?;
+        self.raydium_clmm_program_id()?;
+        self.raydium_amm_program_id()?;
+        self.meteora_dlmm_program_id()?;
+        self.phoenix_program_id()?;
+        for token in self.tokens.values() {
+            token.mint()?;
+        }
+        for (name, pool) in &self.pools {
+            pool.address()?;
+            for symbol in [&pool.token_a, &pool.token_b] {
+                if !self.tokens.contains_key(symbol) {
+                    return Err(format!("pool {} uses unknown token {}", name, symbol).into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TokenConfig {
+    pub fn mint(&self) -> Result<Pubkey, ConfigError> {
+        parse_pubkey("mint", &self.mint)
+    }
+}
+
+impl PoolConfig {
+    pub fn address(&self) -> Result<Pubkey, ConfigError> {
+        parse_pubkey("address", &self.address)
+    }
+}
+
+fn parse_pubkey(field: &str, value: &str) -> Result<Pubkey, ConfigError> {
+    Pubkey::from_str(value).map_err(|err| format!("{} {}: {}", field, value, err).into())
+}
+
+#[test]
+fn test_config_profiles() {
+    use std::collections::HashMap;
+
+    let config = Config::builtin();
+    assert_eq!(config.default_profile, "mainnet");
+    for name in ["mainnet", "devnet", "localnet"] {
+        assert!(config.profiles.contains_key(name));
+    }
+
+    let mainnet = &config.profiles["mainnet"];
+    assert_eq!(mainnet.whirlpool_program_id().unwrap().to_string(), "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc");
+    assert_eq!(mainnet.raydium_clmm_program_id().unwrap().to_string(), "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK");
+    assert_eq!(mainnet.raydium_amm_program_id().unwrap().to_string(), "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
+    assert_eq!(mainnet.meteora_dlmm_program_id().unwrap().to_string(), "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo");
+    assert_eq!(mainnet.phoenix_program_id().unwrap(), None);
+    assert_eq!(mainnet.jupiter_url(), "https://quote-api.jup.ag/v6");
+    assert_eq!(mainnet.token("USDC").unwrap().decimals, 6);
+    let sol_mint = mainnet.token("SOL").unwrap().mint().unwrap();
+    assert_eq!(mainnet.token_by_mint(&sol_mint).unwrap().0, "SOL");
+    assert_eq!(mainnet.pool("WSOL_USDC_3000").unwrap().address, "HJPjoWUrhoZzkNfRpHuieeFk9WcZWjwy6PBjZ81ngndJ");
+    assert!(mainnet.pool("BONK_USDC").is_err());
+
+    // overrides, the prefixed variable wins over the legacy one
+    let env = HashMap::from([
+        ("RPC_URL", "http://legacy:8899"),
+        ("SOLANA_DEX_RPC_URL", "http://override:8899"),
+        ("SOLANA_DEX_COMMITMENT", "finalized"),
+        ("SOLANA_DEX_FEE_PAYER", "~/.config/solana/fee_payer.json"),
+        ("SOLANA_DEX_JUPITER_URL", "http://127.0.0.1:8080"),
+    ]);
+    let mut localnet = config.profiles["localnet"].clone();
+    localnet.apply_overrides(|key| env.get(key).map(|value| value.to_string())).unwrap();
+    assert_eq!(localnet.rpc_url, "http://override:8899");
+    assert_eq!(localnet.ws_url, "ws://127.0.0.1:8900");
+    assert_eq!(localnet.commitment_config().unwrap(), CommitmentConfig::finalized());
+    assert_eq!(localnet.keypair, None);
+    assert_eq!(localnet.fee_payer.as_deref(), Some("~/.config/solana/fee_payer.json"));
+    assert_eq!(localnet.jupiter_url(), "http://127.0.0.1:8080");
+    assert!(localnet.apply_overrides(|key| (key == COMMITMENT_ENV).then(|| "eventually".to_string())).is_err());
+
+    let with_endpoints = DEFAULT_CONFIG.replace(
+        "[profiles.localnet.tokens]",
+        "[[profiles.localnet.rpc_endpoints]]\nurl = \"http://127.0.0.1:8999\"\nrequests_per_second = 5\nsend = false\n\n[profiles.localnet.tokens]",
+    );
+    let endpoints = Config::from_toml_str(&with_endpoints).unwrap().profiles["localnet"].all_rpc_endpoints();
+    assert_eq!(endpoints.len(), 2);
+    assert_eq!((endpoints[0].send, endpoints[1].send, endpoints[1].requests_per_second), (true, false, Some(5.0)));
+    assert!(Config::from_toml_str(&with_endpoints.replace("http://127.0.0.1:8999", "127.0.0.1:8999")).is_err());
+
+    let with_jito = DEFAULT_CONFIG.replace(
+        "[profiles.localnet.tokens]",
+        "[profiles.localnet.jito]\nblock_engine_url = \"http://127.0.0.1:1234\"\ntip_lamports = 10000\n\n[profiles.localnet.tokens]",
+    );
+    let jito = Config::from_toml_str(&with_jito).unwrap().profiles["localnet"].jito.clone().unwrap();
+    assert_eq!((jito.tip_lamports, jito.fallback, jito.tip_accounts().unwrap()), (10000, true, vec![]));
+    assert!(Config::from_toml_str(&with_jito.replace("tip_lamports = 10000", "tip_lamports = 10000\ntip_accounts = [\"nope\"]")).is_err());
+
+    // pools must reference known tokens
+    let broken = DEFAULT_CONFIG.replace("token_b = \"USDC\" }", "token_b = \"USDT\" }");
+    assert!(Config::from_toml_str(&broken).is_err());
+    assert!(Config::builtin().profile(Some("testnet")).is_err());
+}
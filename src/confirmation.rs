@@ -0,0 +1,218 @@
+//! Confirmation tracking over websocket `signatureSubscribe`.
+//!
+//! A notification only fires once per subscription and commitment, so one subscription is
+//! opened for every level up to the one asked for, and their notifications are merged into a
+//! single stream of [`ConfirmationEvent`]s.
+
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::RpcSignatureSubscribeConfig;
+use solana_client::rpc_response::RpcSignatureResult;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::TransactionError;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::sync::oneshot;
+
+use crate::data_source::DataSourceError;
+
+/// About a minute and a half, when the blockhash of a transaction sent now has expired.
+pub const DEFAULT_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// A transaction reaching one commitment level.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfirmationEvent {
+    pub signature: Signature,
+    pub commitment: CommitmentLevel,
+    /// Slot the notification was sent at.
+    pub slot: u64,
+    /// Time from sending to this event.
+    pub latency: Duration,
+    /// Set when the transaction executed and failed.
+    pub err: Option<TransactionError>,
+}
+
+/// Streams confirmation events of a sent transaction.
+///
+/// Implemented over websocket `signatureSubscribe` ([`SignatureSubscribeSource`]).
+#[async_trait]
+pub trait ConfirmationSource: Send + Sync {
+    /// Events for `signature` at each level up to `commitment`, with latencies counted from
+    /// `sent_at`. Returns once the source is listening, so sending after it misses nothing,
+    /// and fails if it can't. The stream ends after the event at `commitment`, or empty handed
+    /// when the source gives up waiting.
+    async fn track(&self, signature: &Signature, commitment: CommitmentLevel, sent_at: Instant) -> Result<UnboundedReceiver<ConfirmationEvent>, DataSourceError>;
+}
+
+pub struct SignatureSubscribeSource {
+    ws_url: String,
+    timeout: Duration,
+}
+
+impl SignatureSubscribeSource {
+    pub fn new(ws_url: &str) -> Self {
+        SignatureSubscribeSource {
+            ws_url: ws_url.to_string(),
+            timeout: DEFAULT_CONFIRMATION_TIMEOUT,
+        }
+    }
+
+    /// How long to wait for the last event before closing the stream.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// `processed` up to `commitment`, lowest first.
+pub fn commitment_levels_up_to(commitment: CommitmentLevel) -> Vec<CommitmentLevel> {
+    match commitment {
+        CommitmentLevel::Processed => vec![CommitmentLevel::Processed],
+        CommitmentLevel::Confirmed => vec![CommitmentLevel::Processed, CommitmentLevel::Confirmed],
+        CommitmentLevel::Finalized => vec![CommitmentLevel::Processed, CommitmentLevel::Confirmed, CommitmentLevel::Finalized],
+    }
+}
+
+#[async_trait]
+impl ConfirmationSource for SignatureSubscribeSource {
+    async fn track(&self, signature: &Signature, commitment: CommitmentLevel, sent_at: Instant) -> Result<UnboundedReceiver<ConfirmationEvent>, DataSourceError> {
+        let pubsub_client = PubsubClient::new(&self.ws_url).await?;
+        let (sender, receiver) = unbounded_channel();
+        let (subscribed_sender, subscribed) = oneshot::channel();
+
+        let signature = *signature;
+        let deadline = tokio::time::Instant::from_std(sent_at) + self.timeout;
+
+        // the streams borrow the client, so they are opened in the task that owns it, and
+        // `track` only returns once the server has acknowledged every one of them
+        tokio::spawn(async move {
+            let mut subscriptions = Vec::new();
+            for level in commitment_levels_up_to(commitment) {
+                let config = RpcSignatureSubscribeConfig {
+                    commitment: Some(CommitmentConfig { commitment: level }),
+                    enable_received_notification: Some(false),
+                };
+                match pubsub_client.signature_subscribe(&signature, Some(config)).await {
+                    Ok((stream, _unsubscribe)) => subscriptions.push(stream.map(move |response| (level, response))),
+                    Err(err) => {
+                        let _ = subscribed_sender.send(Err(format!("signatureSubscribe failed: {}", err)));
+                        return;
+                    }
+                }
+            }
+            if subscribed_sender.send(Ok(())).is_err() {
+                return;
+            }
+
+            let mut notifications = futures::stream::select_all(subscriptions);
+            while let Ok(Some((level, response))) = tokio::time::timeout_at(deadline, notifications.next()).await {
+                let RpcSignatureResult::ProcessedSignature(result) = response.value else {
+                    continue;
+                };
+
+                let event = ConfirmationEvent {
+                    signature,
+                    commitment: level,
+                    slot: response.context.slot,
+                    latency: sent_at.elapsed(),
+                    err: result.err,
+                };
+                if sender.send(event).is_err() || level == commitment {
+                    break;
+                }
+            }
+        });
+
+        subscribed.await.map_err(|_| "signatureSubscribe task stopped")??;
+        Ok(receiver)
+    }
+}
+
+#[tokio::test]
+async fn test_signature_subscribe_source() {
+    use futures::SinkExt;
+    use serde_json::{json, Value};
+    use solana_sdk::signature::Signer;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use crate::client::DEXClient;
+    use crate::rpc::InMemoryRpc;
+
+    // answers every subscription and notifies right away, at slot 100 + its level, with
+    // signature [2; 64] as failed on chain
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut websocket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                let mut subscription = 0;
+                while let Some(Ok(Message::Text(text))) = websocket.next().await {
+                    let request: Value = serde_json::from_str(&text).unwrap();
+                    if request["method"] != "signatureSubscribe" {
+                        let _ = websocket.send(Message::Text(json!({ "jsonrpc": "2.0", "result": true, "id": request["id"] }).to_string())).await;
+                        continue;
+                    }
+                    if request["params"][0] == Signature::from([3; 64]).to_string() {
+                        let error = json!({ "jsonrpc": "2.0", "error": { "code": -32602, "message": "Invalid params" }, "id": request["id"] });
+                        let _ = websocket.send(Message::Text(error.to_string())).await;
+                        continue;
+                    }
+                    subscription += 1;
+                    let slot = match request["params"][1]["commitment"].as_str().unwrap() {
+                        "processed" => 100,
+                        "confirmed" => 101,
+                        _ => 102,
+                    };
+                    let err = if request["params"][0] == Signature::from([2; 64]).to_string() { json!({ "InstructionError": [0, { "Custom": 6001 }] }) } else { Value::Null };
+                    for message in [
+                        json!({ "jsonrpc": "2.0", "result": subscription, "id": request["id"] }),
+                        json!({ "jsonrpc": "2.0", "method": "signatureNotification", "params": { "result": { "context": { "slot": slot }, "value": { "err": err } }, "subscription": subscription } }),
+                    ] {
+                        let _ = websocket.send(Message::Text(message.to_string())).await;
+                    }
+                }
+            });
+        }
+    });
+
+    let source = SignatureSubscribeSource::new(&ws_url).with_timeout(Duration::from_secs(5));
+    let signature = Signature::from([1; 64]);
+    let mut events = source.track(&signature, CommitmentLevel::Finalized, Instant::now()).await.unwrap();
+    let mut received = Vec::new();
+    while let Some(event) = events.recv().await {
+        received.push((event.commitment, event.slot, event.err));
+    }
+    assert_eq!(
+        received,
+        vec![(CommitmentLevel::Processed, 100, None), (CommitmentLevel::Confirmed, 101, None), (CommitmentLevel::Finalized, 102, None)]
+    );
+
+    // failed transactions are reported at every level too, and the stream stops at the one asked for
+    let failed = Signature::from([2; 64]);
+    let mut events = source.track(&failed, CommitmentLevel::Confirmed, Instant::now()).await.unwrap();
+    let mut received = Vec::new();
+    while let Some(event) = events.recv().await {
+        assert!(matches!(event.err, Some(TransactionError::InstructionError(0, _))));
+        received.push(event.commitment);
+    }
+    assert_eq!(received, vec![CommitmentLevel::Processed, CommitmentLevel::Confirmed]);
+
+    // a rejected subscription fails the call rather than yielding a stream that never fires
+    assert!(source.track(&Signature::from([3; 64]), CommitmentLevel::Confirmed, Instant::now()).await.is_err());
+
+    // sent by the client, which subscribes first
+    let payer = solana_sdk::signer::keypair::keypair_from_seed(&[4; 32]).unwrap();
+    let client = DEXClient::with_rpc(InMemoryRpc::new()).with_confirmation_source(source);
+    let instruction = solana_sdk::system_instruction::transfer(&payer.pubkey(), &payer.pubkey(), 1);
+    let (signature, mut events) = client.send_instructions_tracked(&payer, vec![instruction], CommitmentLevel::Processed).await.unwrap();
+    let event = events.recv().await.unwrap();
+    assert_eq!((event.signature, event.commitment, event.slot), (signature, CommitmentLevel::Processed, 100));
+    assert!(events.recv().await.is_none());
+    assert_eq!(client.rpc().sent_transactions().len(), 1);
+
+    assert_eq!(commitment_levels_up_to(CommitmentLevel::Processed), vec![CommitmentLevel::Processed]);
+}
@@ -7,18 +7,49 @@ pub const TICK_ARRAY_SIZE_USIZE: usize = 88;
 
 
 pub const ORCA_WHIRLPOOL_PROGRAM_ID: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
-
-
-// Token Decimals 
-pub const WSOL_DECIMALS: i8 = 9;
-pub const USDC_DECIMALS: i8 = 6;
-
-
-// Token Address
-pub const WSOL_ADDRESS: &str = "So11111111111111111111111111111111111111112"; 
-pub const USDC_ADDRESS: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
-
-
-
-// Pool Address
-pub const WSOL_USDC_3000: &str = "HJPjoWUrhoZzkNfRpHuieeFk9WcZWjwy6PBjZ81ngndJ";
\ No newline at end of file
+// a whirlpools config holds at most one pool per pair and tick spacing, one of these
+pub const WHIRLPOOL_TICK_SPACINGS: [u16; 10] = [1, 2, 4, 8, 16, 64, 96, 128, 256, 32896];
+pub const RAYDIUM_CLMM_PROGRAM_ID: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+pub const RAYDIUM_AMM_PROGRAM_ID: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+pub const METEORA_DLMM_PROGRAM_ID: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+pub const TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+pub const MEMO_PROGRAM_ID: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+pub const JUPITER_API_URL: &str = "https://quote-api.jup.ag/v6";
+
+// Raydium CLMM: ticks per tick array, and tick arrays per bitmap (each side of 0 in the pool)
+pub const RAYDIUM_TICK_ARRAY_SIZE: i32 = 60;
+pub const RAYDIUM_TICK_ARRAY_SIZE_USIZE: usize = 60;
+pub const RAYDIUM_TICK_ARRAY_BITMAP_SIZE: i32 = 512;
+
+// Raydium AMM v4: `AmmInfo` and serum/OpenBook `MarketState` sizes, instruction tags
+pub const RAYDIUM_AMM_INFO_SIZE: usize = 752;
+pub const SERUM_MARKET_STATE_SIZE: usize = 388;
+pub const RAYDIUM_AMM_SWAP_BASE_IN_TAG: u8 = 9;
+pub const RAYDIUM_AMM_SWAP_BASE_OUT_TAG: u8 = 11;
+
+// Meteora DLMM: bins per bin array, and bin arrays in the pair's own bitmap (half each side of 0)
+pub const DLMM_BINS_PER_ARRAY: i32 = 70;
+pub const DLMM_BINS_PER_ARRAY_USIZE: usize = 70;
+pub const DLMM_BIN_ARRAY_BITMAP_SIZE: i32 = 512;
+
+// Phoenix: `MarketHeader` size, and the `Swap` instruction tag
+pub const PHOENIX_MARKET_HEADER_SIZE: usize = 576;
+pub const PHOENIX_SWAP_TAG: u8 = 0;
+
+
+// Anchor Account Discriminators
+pub const WHIRLPOOL_DISCRIMINATOR: [u8; 8] = [63, 149, 209, 12, 225, 128, 99, 9];
+pub const TICK_ARRAY_DISCRIMINATOR: [u8; 8] = [69, 97, 189, 190, 110, 7, 66, 187];
+pub const RAYDIUM_POOL_STATE_DISCRIMINATOR: [u8; 8] = [247, 237, 227, 245, 215, 195, 222, 70];
+pub const RAYDIUM_AMM_CONFIG_DISCRIMINATOR: [u8; 8] = [218, 244, 33, 104, 203, 203, 43, 111];
+pub const RAYDIUM_TICK_ARRAY_STATE_DISCRIMINATOR: [u8; 8] = [192, 155, 85, 205, 49, 249, 129, 42];
+pub const RAYDIUM_TICK_ARRAY_BITMAP_EXTENSION_DISCRIMINATOR: [u8; 8] = [60, 150, 36, 219, 97, 128, 139, 153];
+pub const LB_PAIR_DISCRIMINATOR: [u8; 8] = [33, 11, 49, 98, 181, 101, 177, 13];
+pub const BIN_ARRAY_DISCRIMINATOR: [u8; 8] = [92, 142, 92, 220, 5, 148, 70, 181];
+
+// Anchor Instruction Discriminators
+pub const WHIRLPOOL_SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+pub const INITIALIZE_TICK_ARRAY_DISCRIMINATOR: [u8; 8] = [11, 188, 193, 214, 141, 91, 149, 184];
+pub const RAYDIUM_SWAP_V2_DISCRIMINATOR: [u8; 8] = [43, 4, 237, 11, 26, 201, 30, 98];
+// also named `swap`, so the same as Whirlpool's
+pub const METEORA_DLMM_SWAP_DISCRIMINATOR: [u8; 8] = WHIRLPOOL_SWAP_DISCRIMINATOR;
@@ -0,0 +1,125 @@
+use anchor_lang::AnchorDeserialize;
+use async_trait::async_trait;
+use futures::StreamExt;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use crate::constant::{TICK_ARRAY_DISCRIMINATOR, WHIRLPOOL_DISCRIMINATOR};
+use crate::tick_array::{TickArray, Whirlpool};
+
+pub type DataSourceError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A decoded account owned by the Whirlpool program.
+/// Both variants are boxed, a `TickArray` alone is ~10KB.
+pub enum WhirlpoolAccount {
+    Whirlpool(Box<Whirlpool>),
+    TickArray(Box<TickArray>),
+}
+
+/// One account write observed by a data source.
+pub struct AccountUpdate {
+    pub pubkey: Pubkey,
+    pub slot: u64,
+    pub account: WhirlpoolAccount,
+}
+
+/// Decodes raw account data (discriminator included) into a `Whirlpool` or `TickArray`.
+/// Returns `None` for any other account type of the program (positions, configs, ...).
+pub fn decode_whirlpool_account(data: &[u8]) -> Option<WhirlpoolAccount> {
+    if data.len() < 8 {
+        return None;
+    }
+    let (discriminator, mut body) = data.split_at(8);
+
+    if discriminator == WHIRLPOOL_DISCRIMINATOR {
+        Whirlpool::deserialize(&mut body).ok().map(|whirlpool| WhirlpoolAccount::Whirlpool(Box::new(whirlpool)))
+    } else if discriminator == TICK_ARRAY_DISCRIMINATOR {
+        TickArray::deserialize(&mut body).ok().map(|tick_array| WhirlpoolAccount::TickArray(Box::new(tick_array)))
+    } else {
+        None
+    }
+}
+
+/// Updates from a subscription. A failed or dropped subscription ends it with an `Err`, after
+/// which the caller has to subscribe again.
+pub type AccountUpdates = UnboundedReceiver<Result<AccountUpdate, DataSourceError>>;
+
+/// A stream of decoded account updates for every account owned by `program_id`.
+///
+/// Implemented over websocket `programSubscribe` ([`RpcPubsubSource`]) and, with the
+/// `geyser` feature, over a Yellowstone gRPC stream (`geyser::GeyserSource`).
+#[async_trait]
+pub trait AccountDataSource: Send + Sync {
+    async fn subscribe(&self, program_id: &Pubkey) -> Result<AccountUpdates, DataSourceError>;
+}
+
+pub struct RpcPubsubSource {
+    ws_url: String,
+    commitment: CommitmentConfig,
+}
+
+impl RpcPubsubSource {
+    pub fn new(ws_url: &str) -> Self {
+        RpcPubsubSource {
+            ws_url: ws_url.to_string(),
+            commitment: CommitmentConfig::confirmed(),
+        }
+    }
+}
+
+#[async_trait]
+impl AccountDataSource for RpcPubsubSource {
+    async fn subscribe(&self, program_id: &Pubkey) -> Result<AccountUpdates, DataSourceError> {
+        let pubsub_client = PubsubClient::new(&self.ws_url).await?;
+        let (sender, receiver) = unbounded_channel();
+
+        let program_id = *program_id;
+        let config = RpcProgramAccountsConfig {
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(self.commitment),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        tokio::spawn(async move {
+            let (mut stream, _unsubscribe) = match pubsub_client.program_subscribe(&program_id, Some(config)).await {
+                Ok(subscription) => subscription,
+                Err(err) => {
+                    let _ = sender.send(Err(format!("programSubscribe failed: {}", err).into()));
+                    return;
+                }
+            };
+
+            while let Some(response) = stream.next().await {
+                let Ok(pubkey) = response.value.pubkey.parse::<Pubkey>() else {
+                    continue;
+                };
+                let Some(data) = response.value.account.data.decode() else {
+                    continue;
+                };
+                let Some(account) = decode_whirlpool_account(&data) else {
+                    continue;
+                };
+
+                let update = AccountUpdate {
+                    pubkey,
+                    slot: response.context.slot,
+                    account,
+                };
+                if sender.send(Ok(update)).is_err() {
+                    return;
+                }
+            }
+            // the websocket only ends the stream when the connection is lost
+            let _ = sender.send(Err("programSubscribe stream closed".into()));
+        });
+
+        Ok(receiver)
+    }
+}
@@ -0,0 +1,155 @@
+//! Venue-independent view of a liquidity pool.
+//!
+//! A [`Pool`] is a local copy of a pool's on-chain state: it lists the accounts it is built
+//! from, is fed their data through [`Pool::update`], and quotes and builds swaps from that
+//! state alone, without any RPC calls. `DEXClient::load_pool` fetches the accounts, and the
+//! same `update` takes writes from a data source to keep the copy current.
+
+use rust_decimal::Decimal;
+use solana_sdk::account::Account;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+
+pub type DexError = Box<dyn std::error::Error>;
+
+/// An exact-input swap simulated against a pool's current state.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolQuote {
+    pub pool: Pubkey,
+    pub a_to_b: bool,
+    pub mint_in: Pubkey,
+    pub mint_out: Pubkey,
+    /// What the pool takes, fee included. Order books can take less than asked for, in whole
+    /// lots and no more than the book holds; other venues fail to quote what they can't take.
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// Part of `amount_in` kept as fee; order books charge it in the quote token instead.
+    pub fee_amount: u64,
+    /// Swap instructions the trade is split into.
+    pub legs: usize,
+    /// Accounts the pool needs created before swapping, paid by the funder.
+    pub accounts_to_initialize: Vec<Pubkey>,
+}
+
+impl PoolQuote {
+    /// Least output accepted with `slippage_bps` of slippage.
+    pub fn min_amount_out(&self, slippage_bps: u16) -> u64 {
+        apply_slippage_bps(self.amount_out, slippage_bps)
+    }
+}
+
+pub trait Pool: Send + Sync {
+    /// Short name of the venue, e.g. `whirlpool`.
+    fn dex(&self) -> &'static str;
+
+    fn address(&self) -> Pubkey;
+
+    fn program_id(&self) -> Pubkey;
+
+    /// Token mints A and B. Empty until the pool account is loaded.
+    fn mints(&self) -> Option<(Pubkey, Pubkey)>;
+
+    /// Accounts the pool's state is read from. Can grow as accounts are loaded, e.g. once the
+    /// current price tells which tick arrays matter.
+    fn accounts_to_watch(&self) -> Vec<Pubkey>;
+
+    /// Takes the current data of one of `accounts_to_watch`, `None` if it does not exist.
+    fn update(&mut self, pubkey: &Pubkey, account: Option<&Account>) -> Result<(), DexError>;
+
+    /// Spot price in raw token B per raw token A.
+    fn price(&self) -> Result<Decimal, DexError>;
+
+    /// Fee a swap pays as a fraction of its input, e.g. 0.003, at the current state.
+    fn fee_rate(&self) -> Result<Decimal, DexError>;
+
+    /// Simulates swapping `amount_in` of token A for B, or of B for A; see
+    /// [`PoolQuote::amount_in`] for amounts the pool can't take whole.
+    fn quote(&self, amount_in: u64, a_to_b: bool) -> Result<PoolQuote, DexError>;
+
+    /// Instructions executing `quote` for `user` out of and into their associated token
+    /// accounts, which have to exist. `funder` pays for `quote.accounts_to_initialize`.
+    fn swap_instructions(&self, user: &Pubkey, funder: &Pubkey, quote: &PoolQuote, min_amount_out: u64) -> Result<Vec<Instruction>, DexError>;
+
+    /// Whether a swap of `mint_in` goes from A to B.
+    fn a_to_b(&self, mint_in: &Pubkey) -> Result<bool, DexError> {
+        match self.mints() {
+            Some((mint_a, _)) if mint_a == *mint_in => Ok(true),
+            Some((_, mint_b)) if mint_b == *mint_in => Ok(false),
+            Some(_) => Err(format!("{} is not traded in pool {}", mint_in, self.address()).into()),
+            None => Err(format!("pool {} is not loaded", self.address()).into()),
+        }
+    }
+}
+
+/// `amount` less `slippage_bps` basis points, rounded down.
+pub fn apply_slippage_bps(amount: u64, slippage_bps: u16) -> u64 {
+    (amount as u128 * 10_000u128.saturating_sub(slippage_bps as u128) / 10_000) as u64
+}
+
+/// `pubkey` as the Anchor `Pubkey` the venues' account structs hold, for tests building them.
+#[cfg(test)]
+pub(crate) fn anchor_pubkey(pubkey: &Pubkey) -> anchor_lang::prelude::Pubkey {
+    anchor_lang::prelude::Pubkey::new_from_array(pubkey.to_bytes())
+}
+
+/// An x * y = k pool with a 0.3% fee off the input, at address `[byte; 32]`, for tests; its
+/// swap instructions carry the input and minimum output amounts.
+#[cfg(test)]
+pub(crate) fn constant_product_pool(byte: u8, mints: (Pubkey, Pubkey), reserves: (u64, u64)) -> Box<dyn Pool> {
+    Box::new(ConstantProduct { address: Pubkey::new_from_array([byte; 32]), mints, reserves })
+}
+
+#[cfg(test)]
+struct ConstantProduct {
+    address: Pubkey,
+    mints: (Pubkey, Pubkey),
+    reserves: (u64, u64),
+}
+
+#[cfg(test)]
+impl Pool for ConstantProduct {
+    fn dex(&self) -> &'static str {
+        "constant_product"
+    }
+
+    fn address(&self) -> Pubkey {
+        self.address
+    }
+
+    fn program_id(&self) -> Pubkey {
+        Pubkey::default()
+    }
+
+    fn mints(&self) -> Option<(Pubkey, Pubkey)> {
+        Some(self.mints)
+    }
+
+    fn accounts_to_watch(&self) -> Vec<Pubkey> {
+        Vec::new()
+    }
+
+    fn update(&mut self, _: &Pubkey, _: Option<&Account>) -> Result<(), DexError> {
+        Ok(())
+    }
+
+    fn price(&self) -> Result<Decimal, DexError> {
+        Ok(Decimal::from(self.reserves.1) / Decimal::from(self.reserves.0))
+    }
+
+    fn fee_rate(&self) -> Result<Decimal, DexError> {
+        Ok(Decimal::new(3, 3))
+    }
+
+    fn quote(&self, amount_in: u64, a_to_b: bool) -> Result<PoolQuote, DexError> {
+        let (reserve_in, reserve_out) = if a_to_b { self.reserves } else { (self.reserves.1, self.reserves.0) };
+        let fee_amount = (amount_in as u128 * 3).div_ceil(1000) as u64;
+        let amount_out = reserve_out as u128 * (amount_in - fee_amount) as u128 / (reserve_in as u128 + (amount_in - fee_amount) as u128);
+        let (mint_in, mint_out) = if a_to_b { self.mints } else { (self.mints.1, self.mints.0) };
+        Ok(PoolQuote { pool: self.address, a_to_b, mint_in, mint_out, amount_in, amount_out: amount_out as u64, fee_amount, legs: 1, accounts_to_initialize: Vec::new() })
+    }
+
+    fn swap_instructions(&self, _: &Pubkey, _: &Pubkey, quote: &PoolQuote, min_amount_out: u64) -> Result<Vec<Instruction>, DexError> {
+        let data = [quote.amount_in.to_le_bytes(), min_amount_out.to_le_bytes()].concat();
+        Ok(vec![Instruction { program_id: self.address, accounts: Vec::new(), data }])
+    }
+}
@@ -0,0 +1,210 @@
+use std::fs;
+use std::path::Path;
+
+use anchor_lang::AnchorDeserialize;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::data_source::{decode_whirlpool_account, WhirlpoolAccount};
+use crate::rpc::{InMemoryRpc, RpcError, SolanaRpc};
+use crate::tick_array::{generate_oracle_pda, pdautil_get_tick_array, tickutil_get_start_tick_index, TickArray, Whirlpool};
+
+/// Bumped whenever the layout of [`PoolFixture`] changes; `load` rejects other versions.
+pub const FIXTURE_VERSION: u32 = 1;
+
+/// Tick arrays recorded around the current one, enough for a three-array swap either way.
+pub const RECORDED_TICK_ARRAY_OFFSETS: [i32; 5] = [-2, -1, 0, 1, 2];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FixtureAccountKind {
+    Whirlpool,
+    TickArray,
+    Oracle,
+    Mint,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FixtureAccount {
+    pub pubkey: String,
+    pub kind: FixtureAccountKind,
+    pub lamports: u64,
+    pub owner: String,
+    pub executable: bool,
+    pub rent_epoch: u64,
+    /// Base64 encoded account data.
+    pub data: String,
+}
+
+/// A whirlpool and the accounts needed to quote and build swaps against it, as read at `slot`.
+///
+/// Accounts that did not exist when recorded (uninitialized tick arrays, the oracle of most
+/// pools) are simply absent.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PoolFixture {
+    pub version: u32,
+    pub slot: u64,
+    pub program_id: String,
+    pub whirlpool: String,
+    pub accounts: Vec<FixtureAccount>,
+}
+
+impl FixtureAccount {
+    fn new(pubkey: &Pubkey, kind: FixtureAccountKind, account: &Account) -> Self {
+        FixtureAccount {
+            pubkey: pubkey.to_string(),
+            kind,
+            lamports: account.lamports,
+            owner: account.owner.to_string(),
+            executable: account.executable,
+            rent_epoch: account.rent_epoch,
+            data: BASE64_STANDARD.encode(&account.data),
+        }
+    }
+
+    pub fn to_account(&self) -> Result<(Pubkey, Account), RpcError> {
+        let account = Account {
+            lamports: self.lamports,
+            data: BASE64_STANDARD.decode(&self.data)?,
+            owner: self.owner.parse()?,
+            executable: self.executable,
+            rent_epoch: self.rent_epoch,
+        };
+        Ok((self.pubkey.parse()?, account))
+    }
+}
+
+impl PoolFixture {
+    /// Snapshots `whirlpool`, its surrounding tick arrays, its oracle and both mints.
+    pub async fn record<R: SolanaRpc>(rpc: &R, program_id: &Pubkey, whirlpool: &Pubkey) -> Result<Self, RpcError> {
+        let whirlpool_data = rpc.get_account_data(whirlpool).await?;
+        let whirlpool_state = Whirlpool::deserialize(&mut &whirlpool_data[8..])?;
+
+        let mut pubkeys = vec![(*whirlpool, FixtureAccountKind::Whirlpool)];
+        for offset in RECORDED_TICK_ARRAY_OFFSETS {
+            let start_tick_index = tickutil_get_start_tick_index(whirlpool_state.tick_current_index, whirlpool_state.tick_spacing, offset);
+            pubkeys.push((pdautil_get_tick_array(program_id, whirlpool, start_tick_index), FixtureAccountKind::TickArray));
+        }
+        pubkeys.push((generate_oracle_pda(whirlpool, program_id).0, FixtureAccountKind::Oracle));
+        pubkeys.push((Pubkey::new_from_array(whirlpool_state.token_mint_a.to_bytes()), FixtureAccountKind::Mint));
+        pubkeys.push((Pubkey::new_from_array(whirlpool_state.token_mint_b.to_bytes()), FixtureAccountKind::Mint));
+
+        // everything is re-read in one call so the snapshot is consistent at a single slot
+        let keys: Vec<Pubkey> = pubkeys.iter().map(|(pubkey, _)| *pubkey).collect();
+        let (slot, accounts) = rpc.get_multiple_accounts(&keys).await?;
+
+        let accounts = pubkeys
+            .iter()
+            .zip(accounts)
+            .filter_map(|((pubkey, kind), account)| account.map(|account| FixtureAccount::new(pubkey, *kind, &account)))
+            .collect();
+
+        Ok(PoolFixture {
+            version: FIXTURE_VERSION,
+            slot,
+            program_id: program_id.to_string(),
+            whirlpool: whirlpool.to_string(),
+            accounts,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RpcError> {
+        let fixture: PoolFixture = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if fixture.version != FIXTURE_VERSION {
+            return Err(format!("unsupported fixture version {}, expected {}", fixture.version, FIXTURE_VERSION).into());
+        }
+        Ok(fixture)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RpcError> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    /// An offline RPC holding every recorded account, reporting the recorded slot.
+    pub fn to_rpc(&self) -> Result<InMemoryRpc, RpcError> {
+        let rpc = InMemoryRpc::new().with_slot(self.slot);
+        for fixture_account in &self.accounts {
+            let (pubkey, account) = fixture_account.to_account()?;
+            rpc.set_account(pubkey, account);
+        }
+        Ok(rpc)
+    }
+
+    pub fn whirlpool(&self) -> Result<Whirlpool, RpcError> {
+        self.decoded(FixtureAccountKind::Whirlpool)
+            .find_map(|account| match account {
+                WhirlpoolAccount::Whirlpool(whirlpool) => Some(*whirlpool),
+                _ => None,
+            })
+            .ok_or_else(|| "fixture has no whirlpool account".into())
+    }
+
+    /// Recorded tick arrays, ordered by start tick index.
+    pub fn tick_arrays(&self) -> Vec<TickArray> {
+        let mut tick_arrays: Vec<TickArray> = self
+            .decoded(FixtureAccountKind::TickArray)
+            .filter_map(|account| match account {
+                WhirlpoolAccount::TickArray(tick_array) => Some(*tick_array),
+                _ => None,
+            })
+            .collect();
+        tick_arrays.sort_by_key(|tick_array| tick_array.start_tick_index);
+        tick_arrays
+    }
+
+    fn decoded(&self, kind: FixtureAccountKind) -> impl Iterator<Item = WhirlpoolAccount> + '_ {
+        self.accounts
+            .iter()
+            .filter(move |account| account.kind == kind)
+            .filter_map(|account| BASE64_STANDARD.decode(&account.data).ok())
+            .filter_map(|data| decode_whirlpool_account(&data))
+    }
+}
+
+#[tokio::test]
+async fn test_pool_fixture_roundtrip() {
+    use std::str::FromStr;
+
+    use crate::constant::{ORCA_WHIRLPOOL_PROGRAM_ID, TICK_ARRAY_DISCRIMINATOR};
+
+    let program_id = Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
+    let pool_address = Pubkey::from_str("HJPjoWUrhoZzkNfRpHuieeFk9WcZWjwy6PBjZ81ngndJ").unwrap();
+    let rpc = InMemoryRpc::from_fixture_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/wsol_usdc_3000"))
+        .unwrap()
+        .with_slot(290_000_000);
+
+    // overwrite the current tick array with an empty one; the fixture dir also has the next one up
+    let start_tick_index = tickutil_get_start_tick_index(-19662, 64, 0);
+    let mut tick_array_data = TICK_ARRAY_DISCRIMINATOR.to_vec();
+    tick_array_data.extend(start_tick_index.to_le_bytes());
+    tick_array_data.extend(vec![0; 88 * 113]);
+    tick_array_data.extend(pool_address.to_bytes());
+    rpc.set_account(
+        pdautil_get_tick_array(&program_id, &pool_address, start_tick_index),
+        Account { lamports: 70_407_360, data: tick_array_data, owner: program_id, executable: false, rent_epoch: u64::MAX },
+    );
+
+    let fixture = PoolFixture::record(&rpc, &program_id, &pool_address).await.unwrap();
+    assert_eq!(fixture.slot, 290_000_000);
+    assert_eq!(fixture.accounts.len(), 3);
+
+    let path = std::env::temp_dir().join(format!("pool_fixture_{}.json", std::process::id()));
+    fixture.save(&path).unwrap();
+    let loaded = PoolFixture::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, fixture);
+
+    assert_eq!(loaded.whirlpool().unwrap().tick_spacing, 64);
+    let tick_arrays = loaded.tick_arrays();
+    assert_eq!(tick_arrays.len(), 2);
+    assert_eq!({ tick_arrays[0].start_tick_index }, start_tick_index);
+    assert!(tick_arrays[0].ticks.iter().all(|tick| !tick.initialized));
+
+    let offline_rpc = loaded.to_rpc().unwrap();
+    let whirlpool_data = offline_rpc.get_account_data(&pool_address).await.unwrap();
+    assert_eq!(whirlpool_data, rpc.get_account_data(&pool_address).await.unwrap());
+}
@@ -0,0 +1,393 @@
+//! Yellowstone (Dragon's Mouth) gRPC account stream.
+//!
+//! Only the subset of `geyser.proto` needed to follow program-owned accounts is declared
+//! here; field tags match upstream so any Yellowstone-compatible endpoint can be used.
+
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::mpsc::unbounded_channel;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{ClientTlsConfig, Endpoint};
+
+use crate::data_source::{decode_whirlpool_account, AccountDataSource, AccountUpdate, AccountUpdates, DataSourceError};
+use proto::{CommitmentLevel, SubscribeRequest, SubscribeRequestFilterAccounts, SubscribeRequestPing};
+
+pub const GEYSER_SUBSCRIBE_PATH: &str = "/geyser.Geyser/Subscribe";
+
+pub mod proto {
+    use std::collections::HashMap;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequest {
+        #[prost(map = "string, message", tag = "1")]
+        pub accounts: HashMap<String, SubscribeRequestFilterAccounts>,
+        #[prost(enumeration = "CommitmentLevel", optional, tag = "6")]
+        pub commitment: Option<i32>,
+        #[prost(message, optional, tag = "9")]
+        pub ping: Option<SubscribeRequestPing>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequestFilterAccounts {
+        #[prost(string, repeated, tag = "2")]
+        pub account: Vec<String>,
+        #[prost(string, repeated, tag = "3")]
+        pub owner: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequestPing {
+        #[prost(int32, tag = "1")]
+        pub id: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdate {
+        #[prost(string, repeated, tag = "1")]
+        pub filters: Vec<String>,
+        #[prost(oneof = "UpdateOneof", tags = "2, 3, 6, 9")]
+        pub update_oneof: Option<UpdateOneof>,
+    }
+
+    /// Transaction, block and entry updates are not subscribed to and decode as `None`.
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum UpdateOneof {
+        #[prost(message, tag = "2")]
+        Account(SubscribeUpdateAccount),
+        #[prost(message, tag = "3")]
+        Slot(SubscribeUpdateSlot),
+        #[prost(message, tag = "6")]
+        Ping(SubscribeUpdatePing),
+        #[prost(message, tag = "9")]
+        Pong(SubscribeUpdatePong),
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdateAccount {
+        #[prost(message, optional, tag = "1")]
+        pub account: Option<SubscribeUpdateAccountInfo>,
+        #[prost(uint64, tag = "2")]
+        pub slot: u64,
+        #[prost(bool, tag = "3")]
+        pub is_startup: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdateAccountInfo {
+        #[prost(bytes = "vec", tag = "1")]
+        pub pubkey: Vec<u8>,
+        #[prost(uint64, tag = "2")]
+        pub lamports: u64,
+        #[prost(bytes = "vec", tag = "3")]
+        pub owner: Vec<u8>,
+        #[prost(bool, tag = "4")]
+        pub executable: bool,
+        #[prost(uint64, tag = "5")]
+        pub rent_epoch: u64,
+        #[prost(bytes = "vec", tag = "6")]
+        pub data: Vec<u8>,
+        #[prost(uint64, tag = "7")]
+        pub write_version: u64,
+        #[prost(bytes = "vec", optional, tag = "8")]
+        pub txn_signature: Option<Vec<u8>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdateSlot {
+        #[prost(uint64, tag = "1")]
+        pub slot: u64,
+        #[prost(uint64, optional, tag = "2")]
+        pub parent: Option<u64>,
+        #[prost(enumeration = "CommitmentLevel", tag = "3")]
+        pub status: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdatePing {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdatePong {
+        #[prost(int32, tag = "1")]
+        pub id: i32,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum CommitmentLevel {
+        Processed = 0,
+        Confirmed = 1,
+        Finalized = 2,
+    }
+}
+
+pub struct GeyserSource {
+    endpoint: String,
+    x_token: Option<String>,
+    commitment: CommitmentLevel,
+}
+
+impl GeyserSource {
+    pub fn new(endpoint: &str, x_token: Option<String>) -> Self {
+        GeyserSource {
+            endpoint: endpoint.to_string(),
+            x_token,
+            commitment: CommitmentLevel::Confirmed,
+        }
+    }
+
+    pub fn with_commitment(mut self, commitment: CommitmentLevel) -> Self {
+        self.commitment = commitment;
+        self
+    }
+
+    fn subscribe_request(&self, program_id: &Pubkey) -> SubscribeRequest {
+        let mut request = SubscribeRequest {
+            commitment: Some(self.commitment as i32),
+            ..Default::default()
+        };
+        request.accounts.insert(
+            "whirlpool".to_string(),
+            SubscribeRequestFilterAccounts {
+                owner: vec![program_id.to_string()],
+                ..Default::default()
+            },
+        );
+        request
+    }
+}
+
+#[async_trait]
+impl AccountDataSource for GeyserSource {
+    async fn subscribe(&self, program_id: &Pubkey) -> Result<AccountUpdates, DataSourceError> {
+        let mut endpoint = Endpoint::from_shared(self.endpoint.clone())?;
+        if self.endpoint.starts_with("https") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+        }
+        let channel = endpoint.connect().await?;
+
+        // the request stream stays open for the lifetime of the subscription, it is also
+        // used to answer server pings so load balancers don't drop the connection
+        let (request_sender, request_receiver) = unbounded_channel();
+        request_sender.send(self.subscribe_request(program_id))?;
+
+        let mut request = tonic::Request::new(UnboundedReceiverStream::new(request_receiver));
+        if let Some(x_token) = &self.x_token {
+            request.metadata_mut().insert("x-token", x_token.parse()?);
+        }
+
+        let mut grpc = tonic::client::Grpc::new(channel);
+        grpc.ready().await?;
+        let mut stream: tonic::Streaming<proto::SubscribeUpdate> = grpc
+            .streaming(request, PathAndQuery::from_static(GEYSER_SUBSCRIBE_PATH), ProstCodec::default())
+            .await?
+            .into_inner();
+
+        let (sender, receiver) = unbounded_channel();
+
+        tokio::spawn(async move {
+            loop {
+                let update = match stream.message().await {
+                    Ok(Some(update)) => update,
+                    Ok(None) => {
+                        let _ = sender.send(Err("geyser stream closed by server".into()));
+                        break;
+                    }
+                    Err(status) => {
+                        let _ = sender.send(Err(format!("geyser stream closed: {}", status).into()));
+                        break;
+                    }
+                };
+
+                match update.update_oneof {
+                    Some(proto::UpdateOneof::Account(account_update)) => {
+                        let Some(account_info) = account_update.account else {
+                            continue;
+                        };
+                        let Ok(pubkey) = Pubkey::try_from(account_info.pubkey.as_slice()) else {
+                            continue;
+                        };
+                        let Some(account) = decode_whirlpool_account(&account_info.data) else {
+                            continue;
+                        };
+
+                        let update = AccountUpdate {
+                            pubkey,
+                            slot: account_update.slot,
+                            account,
+                        };
+                        if sender.send(Ok(update)).is_err() {
+                            break;
+                        }
+                    }
+                    Some(proto::UpdateOneof::Ping(_)) => {
+                        let ping = SubscribeRequest {
+                            ping: Some(SubscribeRequestPing { id: 1 }),
+                            ..Default::default()
+                        };
+                        if request_sender.send(ping).is_err() {
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        Ok(receiver)
+    }
+}
+
+#[tokio::test]
+async fn test_geyser_source_stub_server() {
+    use std::convert::Infallible;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use anchor_lang::AnchorSerialize;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tokio_stream::Stream;
+    use tonic::codegen::{http, Body, BoxFuture, StdError};
+    use tonic::server::{Grpc, NamedService, StreamingService};
+    use tonic::{Request, Response, Status, Streaming};
+
+    use crate::constant::{ORCA_WHIRLPOOL_PROGRAM_ID, WHIRLPOOL_DISCRIMINATOR};
+    use crate::data_source::WhirlpoolAccount;
+    use crate::tick_array::Whirlpool;
+    use proto::{SubscribeUpdate, SubscribeUpdateAccount, SubscribeUpdateAccountInfo, SubscribeUpdatePing, UpdateOneof};
+
+    type UpdateStream = Pin<Box<dyn Stream<Item = Result<SubscribeUpdate, Status>> + Send>>;
+
+    // Replays `updates` to any subscriber whose first request filters on `owner`, then ends
+    // the stream with `ending`, or cleanly without one
+    #[derive(Clone)]
+    struct StubGeyser {
+        owner: String,
+        updates: Vec<SubscribeUpdate>,
+        ending: Option<Status>,
+    }
+
+    impl StreamingService<SubscribeRequest> for StubGeyser {
+        type Response = SubscribeUpdate;
+        type ResponseStream = UpdateStream;
+        type Future = BoxFuture<Response<UpdateStream>, Status>;
+
+        fn call(&mut self, request: Request<Streaming<SubscribeRequest>>) -> Self::Future {
+            let stub = self.clone();
+            Box::pin(async move {
+                let mut requests = request.into_inner();
+                let subscribe = requests.message().await?.ok_or_else(|| Status::invalid_argument("no subscribe request"))?;
+                let filter = subscribe.accounts.values().next().ok_or_else(|| Status::invalid_argument("no accounts filter"))?;
+                if filter.owner != vec![stub.owner] {
+                    return Err(Status::invalid_argument("unexpected owner filter"));
+                }
+                // the server goes away once the updates have gone out
+                let shutdown = futures::StreamExt::filter_map(
+                    futures::stream::once(async move {
+                        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                        stub.ending
+                    }),
+                    |ending| futures::future::ready(ending.map(Err)),
+                );
+                let stream: UpdateStream = Box::pin(futures::StreamExt::chain(tokio_stream::iter(stub.updates.into_iter().map(Ok)), shutdown));
+                Ok(Response::new(stream))
+            })
+        }
+    }
+
+    impl<B> tonic::codegen::Service<http::Request<B>> for StubGeyser
+    where
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<B>) -> Self::Future {
+            assert_eq!(request.uri().path(), GEYSER_SUBSCRIBE_PATH);
+            let stub = self.clone();
+            Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.streaming(stub, request).await)
+            })
+        }
+    }
+
+    impl NamedService for StubGeyser {
+        const NAME: &'static str = "geyser.Geyser";
+    }
+
+    let program_id = ORCA_WHIRLPOOL_PROGRAM_ID.parse::<Pubkey>().unwrap();
+    let whirlpool_pubkey = Pubkey::new_unique();
+    let whirlpool = Whirlpool {
+        tick_spacing: 64,
+        tick_current_index: -18_000,
+        ..Default::default()
+    };
+    let mut whirlpool_data = WHIRLPOOL_DISCRIMINATOR.to_vec();
+    whirlpool_data.extend(whirlpool.try_to_vec().unwrap());
+
+    let account_update = |data: Vec<u8>, slot: u64| SubscribeUpdate {
+        filters: vec!["whirlpool".to_string()],
+        update_oneof: Some(UpdateOneof::Account(SubscribeUpdateAccount {
+            account: Some(SubscribeUpdateAccountInfo {
+                pubkey: whirlpool_pubkey.to_bytes().to_vec(),
+                owner: program_id.to_bytes().to_vec(),
+                data,
+                ..Default::default()
+            }),
+            slot,
+            is_startup: false,
+        })),
+    };
+    let stub = StubGeyser {
+        owner: program_id.to_string(),
+        updates: vec![
+            account_update(whirlpool_data, 100),
+            SubscribeUpdate {
+                filters: vec![],
+                update_oneof: Some(UpdateOneof::Ping(SubscribeUpdatePing {})),
+            },
+            // position accounts and the like are skipped by the decoder
+            account_update(vec![0; 216], 101),
+        ],
+        ending: Some(Status::unavailable("shutting down")),
+    };
+    let closing = StubGeyser { owner: program_id.to_string(), updates: Vec::new(), ending: None };
+
+    let serve = |stub: StubGeyser| async move {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(stub)
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        GeyserSource::new(&format!("http://{}", address), Some("token".to_string()))
+    };
+
+    let source = serve(stub).await;
+    let mut updates = source.subscribe(&program_id).await.unwrap();
+
+    let update = updates.recv().await.unwrap().unwrap();
+    assert_eq!(update.pubkey, whirlpool_pubkey);
+    assert_eq!(update.slot, 100);
+    match update.account {
+        WhirlpoolAccount::Whirlpool(decoded) => assert_eq!(*decoded, whirlpool),
+        WhirlpoolAccount::TickArray(_) => panic!("expected a whirlpool"),
+    }
+    // the error is passed on so the caller can reconnect
+    assert!(matches!(updates.recv().await, Some(Err(err)) if err.to_string().contains("shutting down")));
+    assert!(updates.recv().await.is_none());
+
+    // so is a clean close by the server
+    let mut updates = serve(closing).await.subscribe(&program_id).await.unwrap();
+    assert!(matches!(updates.recv().await, Some(Err(err)) if err.to_string().contains("closed by server")));
+    assert!(updates.recv().await.is_none());
+}
@@ -0,0 +1,512 @@
+//! Jito bundles: up to five transactions executed atomically and in order by a Jito block
+//! engine, paid for by a tip transfer in the last one.
+//!
+//! A bundle either lands whole or not at all, and never reaches the public mempool, so a swap
+//! sent this way can't be sandwiched. When the block engine drops a bundle the same
+//! instructions can be sent the normal way, see [`BundleError::Dropped`].
+
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::commitment_config::CommitmentLevel;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+
+use crate::config::JitoConfig;
+use crate::rpc::SolanaRpc;
+use crate::sender::{classify_error, commitment_rank, ErrorClass, SendError};
+
+pub type JitoError = Box<dyn Error + Send + Sync>;
+
+/// Transactions a block engine accepts in one bundle.
+pub const MAX_BUNDLE_TRANSACTIONS: usize = 5;
+
+/// The smallest tip block engines accept.
+pub const MIN_TIP_LAMPORTS: u64 = 1000;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A JSON-RPC error answered by the block engine, so the request was not accepted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockEngineError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl fmt::Display for BlockEngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "block engine error {}: {}", self.code, self.message)
+    }
+}
+
+impl Error for BlockEngineError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum InflightBundleStatus {
+    /// Unknown to the block engine, or older than its five minute look back.
+    Invalid,
+    Pending,
+    /// Rejected by every region that got it, it won't be forwarded.
+    Failed,
+    Landed,
+}
+
+/// A landed bundle as reported by `getBundleStatuses`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct BundleStatus {
+    pub bundle_id: String,
+    pub transactions: Vec<String>,
+    pub slot: u64,
+    pub confirmation_status: CommitmentLevel,
+}
+
+/// JSON-RPC client of a block engine's bundle API.
+pub struct JitoClient {
+    url: String,
+    http: reqwest::Client,
+    tip_accounts: Mutex<Vec<Pubkey>>,
+    next_id: AtomicU64,
+}
+
+impl JitoClient {
+    /// `block_engine_url` without a path, e.g. `https://mainnet.block-engine.jito.wtf`.
+    pub fn new(block_engine_url: &str) -> Self {
+        JitoClient {
+            url: block_engine_url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build().expect("default http client"),
+            tip_accounts: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Tips go to one of `tip_accounts` instead of the ones the block engine lists.
+    pub fn with_tip_accounts(self, tip_accounts: Vec<Pubkey>) -> Self {
+        *self.tip_accounts.lock().unwrap() = tip_accounts;
+        self
+    }
+
+    pub fn from_config(config: &JitoConfig) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(&config.block_engine_url).with_tip_accounts(config.tip_accounts()?))
+    }
+
+    async fn request(&self, path: &str, method: &str, params: Value) -> Result<Value, JitoError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let response: Value = self.http.post(format!("{}{}", self.url, path)).json(&request).send().await?.json().await?;
+        if let Some(error) = response.get("error") {
+            return Err(Box::new(BlockEngineError {
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+            }));
+        }
+        Ok(response["result"].clone())
+    }
+
+    /// Submits `transactions` as one bundle and returns its id.
+    pub async fn send_bundle(&self, transactions: &[Transaction]) -> Result<String, JitoError> {
+        let encoded = transactions
+            .iter()
+            .map(|transaction| Ok(BASE64_STANDARD.encode(bincode::serialize(transaction)?)))
+            .collect::<Result<Vec<_>, JitoError>>()?;
+        let result = self.request("/api/v1/bundles", "sendBundle", json!([encoded, { "encoding": "base64" }])).await?;
+        Ok(result.as_str().ok_or("sendBundle returned no bundle id")?.to_string())
+    }
+
+    /// Landed bundles among `bundle_ids`, `None` for the others.
+    pub async fn get_bundle_statuses(&self, bundle_ids: &[String]) -> Result<Vec<Option<BundleStatus>>, JitoError> {
+        let result = self.request("/api/v1/getBundleStatuses", "getBundleStatuses", json!([bundle_ids])).await?;
+        Ok(serde_json::from_value(result["value"].clone())?)
+    }
+
+    /// Where `bundle_ids` stand, landed or not, over the last five minutes.
+    pub async fn get_inflight_bundle_statuses(&self, bundle_ids: &[String]) -> Result<Vec<Option<InflightBundleStatus>>, JitoError> {
+        #[derive(Deserialize)]
+        struct InflightBundle {
+            status: InflightBundleStatus,
+        }
+
+        let result = self.request("/api/v1/getInflightBundleStatuses", "getInflightBundleStatuses", json!([bundle_ids])).await?;
+        let bundles: Vec<Option<InflightBundle>> = serde_json::from_value(result["value"].clone())?;
+        Ok(bundles.into_iter().map(|bundle| bundle.map(|bundle| bundle.status)).collect())
+    }
+
+    /// The configured tip accounts, or the block engine's, asked for once.
+    pub async fn get_tip_accounts(&self) -> Result<Vec<Pubkey>, JitoError> {
+        let known = self.tip_accounts.lock().unwrap().clone();
+        if !known.is_empty() {
+            return Ok(known);
+        }
+        let result = self.request("/api/v1/getTipAccounts", "getTipAccounts", json!([])).await?;
+        let tip_accounts = serde_json::from_value::<Vec<String>>(result)?
+            .iter()
+            .map(|tip_account| tip_account.parse())
+            .collect::<Result<Vec<Pubkey>, _>>()?;
+        if tip_accounts.is_empty() {
+            return Err("block engine lists no tip accounts".into());
+        }
+        *self.tip_accounts.lock().unwrap() = tip_accounts.clone();
+        Ok(tip_accounts)
+    }
+}
+
+/// Pays `lamports` from `payer` to a block engine tip account.
+pub fn tip_instruction(payer: &Pubkey, tip_account: &Pubkey, lamports: u64) -> Instruction {
+    system_instruction::transfer(payer, tip_account, lamports)
+}
+
+#[derive(Clone, Debug)]
+pub struct BundleConfig {
+    pub tip_lamports: u64,
+    /// Send the instructions the normal way once the bundle was dropped.
+    pub fallback: bool,
+    /// Commitment the bundle has to reach.
+    pub commitment: CommitmentLevel,
+    /// How often the bundle's status is checked.
+    pub poll_interval: Duration,
+    /// RPC failures in a row tolerated before giving up.
+    pub max_rpc_errors: usize,
+}
+
+impl BundleConfig {
+    pub fn from_config(config: &JitoConfig) -> Self {
+        BundleConfig { tip_lamports: config.tip_lamports, fallback: config.fallback, ..BundleConfig::default() }
+    }
+}
+
+impl Default for BundleConfig {
+    fn default() -> Self {
+        BundleConfig {
+            tip_lamports: 10_000,
+            fallback: true,
+            commitment: CommitmentLevel::Confirmed,
+            poll_interval: Duration::from_secs(1),
+            max_rpc_errors: 10,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LandedBundle {
+    /// `None` when the block engine didn't answer `sendBundle`, or the transactions were
+    /// sent without a bundle.
+    pub bundle_id: Option<String>,
+    /// In bundle order.
+    pub signatures: Vec<Signature>,
+    pub slot: u64,
+    /// Sent one by one without the tip after the bundle was dropped.
+    pub fell_back: bool,
+    /// Why the bundle was dropped, when it fell back.
+    pub drop_reason: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum BundleError {
+    /// None of the bundle's transactions landed or can land anymore: the block engine turned
+    /// it down, or its blockhash expired. Sending again is safe.
+    Dropped { bundle_id: Option<String>, reason: String },
+    /// A transaction failed on chain or couldn't be signed, or the RPC node kept failing.
+    Send(SendError),
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BundleError::Dropped { bundle_id: Some(bundle_id), reason } => write!(f, "bundle {} dropped: {}", bundle_id, reason),
+            BundleError::Dropped { reason, .. } => write!(f, "bundle dropped: {}", reason),
+            BundleError::Send(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for BundleError {}
+
+/// Sends one bundle and follows it until it lands or provably can't anymore. Unlike
+/// [`crate::sender::TransactionSender`] nothing is rebroadcast or re-signed, the block engine
+/// keeps forwarding a bundle until its blockhash expires.
+pub struct BundleSender<'a, R: SolanaRpc> {
+    rpc: &'a R,
+    jito: &'a JitoClient,
+    config: BundleConfig,
+}
+
+impl<'a, R: SolanaRpc> BundleSender<'a, R> {
+    pub fn new(rpc: &'a R, jito: &'a JitoClient, config: BundleConfig) -> Self {
+        BundleSender { rpc, jito, config }
+    }
+
+    /// Sends the transactions `sign` builds for a blockhash and a tip account. The last one
+    /// has to pay the tip.
+    pub async fn send(&self, sign: impl FnOnce(Hash, &Pubkey) -> Result<Vec<Transaction>, Box<dyn Error>>) -> Result<LandedBundle, BundleError> {
+        let fatal = |message: String| BundleError::Send(SendError::Fatal { message, transaction_error: None, signature: None });
+        if self.config.tip_lamports < MIN_TIP_LAMPORTS {
+            return Err(fatal(format!("a tip of {} lamports is below the minimum of {}", self.config.tip_lamports, MIN_TIP_LAMPORTS)));
+        }
+        let tip_accounts = self.jito.get_tip_accounts().await.map_err(|err| BundleError::Dropped { bundle_id: None, reason: err.to_string() })?;
+        let (blockhash, last_valid_block_height) = self
+            .rpc
+            .get_latest_blockhash_with_last_valid_block_height()
+            .await
+            .map_err(|err| BundleError::Send(SendError::Unavailable(err.to_string())))?;
+
+        // spread over the tip accounts to contend less for their write locks
+        let tip_account = tip_accounts[blockhash.as_ref()[0] as usize % tip_accounts.len()];
+        let transactions = sign(blockhash, &tip_account).map_err(|err| fatal(err.to_string()))?;
+        if transactions.is_empty() || transactions.len() > MAX_BUNDLE_TRANSACTIONS {
+            return Err(fatal(format!("a bundle holds 1 to {} transactions, not {}", MAX_BUNDLE_TRANSACTIONS, transactions.len())));
+        }
+        let signatures: Vec<Signature> = transactions.iter().map(|transaction| transaction.signatures[0]).collect();
+
+        let bundle_id = match self.jito.send_bundle(&transactions).await {
+            Ok(bundle_id) => Some(bundle_id),
+            Err(err) if err.is::<BlockEngineError>() => return Err(BundleError::Dropped { bundle_id: None, reason: err.to_string() }),
+            // it may have arrived all the same, so watch the chain until the blockhash expires
+            Err(_) => None,
+        };
+        let dropped = |reason: &str| BundleError::Dropped { bundle_id: bundle_id.clone(), reason: reason.to_string() };
+
+        let mut rpc_errors = 0;
+        loop {
+            tokio::time::sleep(self.config.poll_interval).await;
+
+            // the height is read before the statuses on one node, as in TransactionSender
+            let (block_height, statuses) = match self.rpc.poll_signature_statuses(&signatures).await {
+                Ok(polled) => {
+                    rpc_errors = 0;
+                    polled
+                }
+                Err(err) => {
+                    rpc_errors += 1;
+                    if classify_error(err.as_ref()) == ErrorClass::Fatal || rpc_errors > self.config.max_rpc_errors {
+                        return Err(BundleError::Send(SendError::Unavailable(err.to_string())));
+                    }
+                    continue;
+                }
+            };
+
+            // bundles land whole, so any transaction tells about all of them
+            if let Some((signature, status)) = signatures.iter().zip(&statuses).find_map(|(signature, status)| status.as_ref().map(|status| (*signature, status))) {
+                if let Some(err) = &status.err {
+                    return Err(BundleError::Send(SendError::Fatal { message: err.to_string(), transaction_error: Some(err.clone()), signature: Some(signature) }));
+                }
+                if commitment_rank(status.commitment) >= commitment_rank(self.config.commitment) {
+                    return Ok(LandedBundle { bundle_id, signatures, slot: status.slot, fell_back: false, drop_reason: None });
+                }
+                continue;
+            }
+
+            // an unreachable block engine is no reason to stop, the rpc still tells when the
+            // blockhash expired
+            let inflight = match &bundle_id {
+                Some(bundle_id) => self.jito.get_inflight_bundle_statuses(std::slice::from_ref(bundle_id)).await.ok().and_then(|inflight| inflight.into_iter().next().flatten()),
+                None => None,
+            };
+            match (inflight, &bundle_id) {
+                (Some(InflightBundleStatus::Failed), _) => return Err(dropped("rejected by the block engine")),
+                (Some(InflightBundleStatus::Landed), Some(bundle_id)) => {
+                    let landed = self.jito.get_bundle_statuses(std::slice::from_ref(bundle_id)).await.ok().and_then(|landed| landed.into_iter().next().flatten());
+                    if let Some(status) = landed.filter(|status| commitment_rank(status.confirmation_status) >= commitment_rank(self.config.commitment)) {
+                        return Ok(LandedBundle { bundle_id: Some(status.bundle_id), signatures, slot: status.slot, fell_back: false, drop_reason: None });
+                    }
+                }
+                // Invalid right after sending can mean it isn't indexed yet
+                _ => {}
+            }
+
+            if block_height > last_valid_block_height {
+                return Err(dropped("blockhash expired"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod mock {
+    use std::io::{self, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+    use base64::Engine;
+    use serde_json::{json, Value};
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::transaction::Transaction;
+
+    use crate::jito::{InflightBundleStatus, MAX_BUNDLE_TRANSACTIONS};
+    use crate::remote_signer::read_http_message;
+
+    /// A block engine answering on an ephemeral port of 127.0.0.1 from a background thread, for
+    /// tests. Every bundle reports `Pending` once, then the outcome set with
+    /// [`MockBlockEngine::set_outcome`].
+    pub struct MockBlockEngine {
+        url: String,
+        state: Arc<Mutex<MockState>>,
+    }
+
+    struct MockState {
+        tip_accounts: Vec<Pubkey>,
+        outcome: InflightBundleStatus,
+        slot: u64,
+        bundles: Vec<Vec<Transaction>>,
+        polls: Vec<usize>,
+    }
+
+    impl MockBlockEngine {
+        pub fn spawn(tip_accounts: Vec<Pubkey>) -> io::Result<Self> {
+            let listener = TcpListener::bind("127.0.0.1:0")?;
+            let url = format!("http://{}", listener.local_addr()?);
+            let state = Arc::new(Mutex::new(MockState { tip_accounts, outcome: InflightBundleStatus::Landed, slot: 1, bundles: Vec::new(), polls: Vec::new() }));
+            let served = state.clone();
+            thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    let Ok((_, body)) = read_http_message(&mut BufReader::new(&mut stream)) else {
+                        continue;
+                    };
+                    let response = serde_json::from_slice(&body).map_or_else(
+                        |err| json!({ "jsonrpc": "2.0", "id": null, "error": { "code": -32700, "message": err.to_string() } }),
+                        |request: Value| served.lock().unwrap().answer(&request),
+                    );
+                    let body = response.to_string();
+                    let _ = write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+                }
+            });
+            Ok(MockBlockEngine { url, state })
+        }
+
+        /// What to pass to [`JitoClient::new`].
+        pub fn url(&self) -> &str {
+            &self.url
+        }
+
+        /// How bundles end up, and the slot they land at.
+        pub fn set_outcome(&self, outcome: InflightBundleStatus, slot: u64) {
+            let mut state = self.state.lock().unwrap();
+            state.outcome = outcome;
+            state.slot = slot;
+        }
+
+        /// Bundles received so far.
+        pub fn bundles(&self) -> Vec<Vec<Transaction>> {
+            self.state.lock().unwrap().bundles.clone()
+        }
+    }
+
+    impl MockState {
+        fn answer(&mut self, request: &Value) -> Value {
+            let result = match request["method"].as_str().unwrap_or_default() {
+                "getTipAccounts" => Ok(json!(self.tip_accounts.iter().map(Pubkey::to_string).collect::<Vec<_>>())),
+                "sendBundle" => self.receive(&request["params"][0]).map(|bundle_id| json!(bundle_id)),
+                "getInflightBundleStatuses" => Ok(self.statuses(&request["params"][0], |state, index, bundle_id| {
+                    state.polls[index] += 1;
+                    let status = if state.polls[index] == 1 { InflightBundleStatus::Pending } else { state.outcome };
+                    json!({ "bundle_id": bundle_id, "status": format!("{:?}", status), "landed_slot": (status == InflightBundleStatus::Landed).then_some(state.slot) })
+                })),
+                "getBundleStatuses" => Ok(self.statuses(&request["params"][0], |state, index, bundle_id| {
+                    if state.polls[index] < 2 || state.outcome != InflightBundleStatus::Landed {
+                        return Value::Null;
+                    }
+                    let signatures: Vec<String> = state.bundles[index].iter().map(|transaction| transaction.signatures[0].to_string()).collect();
+                    json!({ "bundle_id": bundle_id, "transactions": signatures, "slot": state.slot, "confirmation_status": "finalized", "err": { "Ok": null } })
+                })),
+                method => Err(format!("method {} not found", method)),
+            };
+            match result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+                Err(message) => json!({ "jsonrpc": "2.0", "id": request["id"], "error": { "code": -32602, "message": message } }),
+            }
+        }
+
+        fn receive(&mut self, encoded: &Value) -> Result<String, String> {
+            let bundle = encoded
+                .as_array()
+                .ok_or("expected a list of transactions")?
+                .iter()
+                .map(|transaction| {
+                    let bytes = BASE64_STANDARD.decode(transaction.as_str().unwrap_or_default()).map_err(|err| err.to_string())?;
+                    let transaction: Transaction = bincode::deserialize(&bytes).map_err(|err| err.to_string())?;
+                    transaction.verify().map_err(|err| err.to_string())?;
+                    Ok(transaction)
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            if bundle.is_empty() || bundle.len() > MAX_BUNDLE_TRANSACTIONS {
+                return Err(format!("bundle of {} transactions", bundle.len()));
+            }
+            self.bundles.push(bundle);
+            self.polls.push(0);
+            Ok(format!("bundle-{}", self.bundles.len()))
+        }
+
+        fn statuses(&mut self, bundle_ids: &Value, mut status: impl FnMut(&mut Self, usize, &str) -> Value) -> Value {
+            let value: Vec<Value> = bundle_ids
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .map(|bundle_id| {
+                    let bundle_id = bundle_id.as_str().unwrap_or_default();
+                    match bundle_id.strip_prefix("bundle-").and_then(|index| index.parse::<usize>().ok()).filter(|index| (1..=self.bundles.len()).contains(index)) {
+                        Some(index) => status(self, index - 1, bundle_id),
+                        None => Value::Null,
+                    }
+                })
+                .collect();
+            json!({ "context": { "slot": self.slot }, "value": value })
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_bundle_sender() {
+    use solana_sdk::signature::Signer;
+    use solana_sdk::signer::keypair::keypair_from_seed;
+
+    use crate::client::DEXClient;
+    use crate::jito::mock::MockBlockEngine;
+    use crate::rpc::InMemoryRpc;
+
+    let payer = keypair_from_seed(&[5; 32]).unwrap();
+    let tip_accounts = vec![Pubkey::new_from_array([7; 32]), Pubkey::new_from_array([8; 32])];
+    let engine = MockBlockEngine::spawn(tip_accounts.clone()).unwrap();
+    let config = BundleConfig { tip_lamports: 5000, poll_interval: Duration::from_millis(5), ..BundleConfig::default() };
+    let client = DEXClient::with_rpc(InMemoryRpc::new().with_block_height_step(10)).with_jito(JitoClient::new(engine.url()), config.clone());
+    let transfer = |lamports| vec![system_instruction::transfer(&payer.pubkey(), &Pubkey::new_from_array([9; 32]), lamports)];
+
+    // lands as one bundle, the last transaction tips one of the engine's accounts
+    engine.set_outcome(InflightBundleStatus::Landed, 42);
+    let landed = client.send_bundle(&payer, vec![transfer(1), transfer(2)], CommitmentLevel::Confirmed).await.unwrap();
+    assert_eq!((landed.bundle_id.as_deref(), landed.slot, landed.fell_back), (Some("bundle-1"), 42, false));
+    let bundle = &engine.bundles()[0];
+    assert_eq!(bundle.iter().map(|transaction| transaction.signatures[0]).collect::<Vec<_>>(), landed.signatures);
+    assert_eq!(bundle[0].message.instructions.len(), 1);
+    let tip = bundle[1].message.instructions.last().unwrap();
+    assert!(tip_accounts.contains(&bundle[1].message.account_keys[tip.accounts[1] as usize]));
+    assert_eq!(tip.data, bincode::serialize(&solana_sdk::system_instruction::SystemInstruction::Transfer { lamports: 5000 }).unwrap());
+    assert!(client.rpc().sent_transactions().is_empty());
+
+    // dropped by the engine, then sent the normal way without the tip
+    engine.set_outcome(InflightBundleStatus::Failed, 0);
+    let landed = client.send_bundle(&payer, vec![transfer(3)], CommitmentLevel::Confirmed).await.unwrap();
+    assert!(landed.fell_back && landed.drop_reason.is_some());
+    let sent = client.rpc().sent_transactions();
+    assert_eq!(sent.len(), 1);
+    assert_eq!((sent[0].signatures[0], sent[0].message.instructions.len()), (landed.signatures[0], 1));
+
+    // or not, and never with a tip too small to be accepted
+    let client = client.with_jito(JitoClient::new(engine.url()), BundleConfig { fallback: false, ..config });
+    let err = client.send_bundle(&payer, vec![transfer(4)], CommitmentLevel::Confirmed).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<BundleError>(), Some(BundleError::Dropped { .. })));
+    let client = client.with_jito(JitoClient::new(engine.url()), BundleConfig { tip_lamports: 10, ..BundleConfig::default() });
+    assert!(client.send_bundle(&payer, vec![transfer(5)], CommitmentLevel::Confirmed).await.is_err());
+    assert_eq!(engine.bundles().len(), 3);
+}
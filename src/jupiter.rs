@@ -0,0 +1,423 @@
+//! Client of Jupiter's v6 swap API, `/quote` and `/swap-instructions`, to hold our own quotes
+//! against an aggregator's.
+//!
+//! Routes are asked for as legacy transactions, so the instructions Jupiter returns need no
+//! address lookup tables and go through `DEXClient::send_instructions` like any others.
+
+use std::error::Error;
+use std::str::FromStr;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+
+pub type JupiterError = Box<dyn Error + Send + Sync>;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One swap of a Jupiter route.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JupiterRouteStep {
+    pub amm_key: Pubkey,
+    /// Venue, e.g. `Whirlpool`.
+    pub label: String,
+    pub mint_in: Pubkey,
+    pub mint_out: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// Share of the amount routed through this step.
+    pub percent: u8,
+}
+
+/// An exact-input quote of Jupiter, with the response it came in to ask for its instructions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JupiterQuote {
+    pub mint_in: Pubkey,
+    pub mint_out: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// `amount_out` less the slippage asked for.
+    pub min_amount_out: u64,
+    pub slippage_bps: u16,
+    pub price_impact_pct: Decimal,
+    pub route: Vec<JupiterRouteStep>,
+    pub response: Value,
+}
+
+impl JupiterQuote {
+    /// Venues of the route, each once.
+    pub fn labels(&self) -> Vec<&str> {
+        let mut labels: Vec<&str> = Vec::new();
+        for step in &self.route {
+            if !labels.contains(&step.label.as_str()) {
+                labels.push(&step.label);
+            }
+        }
+        labels
+    }
+}
+
+/// What `/swap-instructions` returns, in the order they run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JupiterSwapInstructions {
+    pub compute_budget: Vec<Instruction>,
+    /// Token accounts created and SOL wrapped.
+    pub setup: Vec<Instruction>,
+    pub swap: Instruction,
+    /// Wrapped SOL unwrapped.
+    pub cleanup: Option<Instruction>,
+}
+
+impl JupiterSwapInstructions {
+    pub fn instructions(&self) -> Vec<Instruction> {
+        let mut instructions = self.compute_budget.clone();
+        instructions.extend(self.setup.iter().cloned());
+        instructions.push(self.swap.clone());
+        instructions.extend(self.cleanup.iter().cloned());
+        instructions
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuoteResponse {
+    input_mint: String,
+    in_amount: String,
+    output_mint: String,
+    out_amount: String,
+    other_amount_threshold: String,
+    slippage_bps: u16,
+    price_impact_pct: String,
+    route_plan: Vec<RoutePlanStep>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RoutePlanStep {
+    swap_info: SwapInfo,
+    percent: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SwapInfo {
+    amm_key: String,
+    label: String,
+    input_mint: String,
+    output_mint: String,
+    in_amount: String,
+    out_amount: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SwapInstructionsResponse {
+    #[serde(default)]
+    compute_budget_instructions: Vec<JupiterInstruction>,
+    #[serde(default)]
+    setup_instructions: Vec<JupiterInstruction>,
+    swap_instruction: JupiterInstruction,
+    cleanup_instruction: Option<JupiterInstruction>,
+    #[serde(default)]
+    address_lookup_table_addresses: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JupiterInstruction {
+    program_id: String,
+    accounts: Vec<JupiterAccountMeta>,
+    data: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JupiterAccountMeta {
+    pubkey: String,
+    is_signer: bool,
+    is_writable: bool,
+}
+
+impl JupiterInstruction {
+    fn decode(&self) -> Result<Instruction, JupiterError> {
+        let accounts = self
+            .accounts
+            .iter()
+            .map(|account| Ok(AccountMeta { pubkey: Pubkey::from_str(&account.pubkey)?, is_signer: account.is_signer, is_writable: account.is_writable }))
+            .collect::<Result<Vec<_>, JupiterError>>()?;
+        Ok(Instruction { program_id: Pubkey::from_str(&self.program_id)?, accounts, data: BASE64_STANDARD.decode(&self.data)? })
+    }
+}
+
+/// HTTP client of a Jupiter v6 swap API.
+pub struct JupiterClient {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl JupiterClient {
+    /// `base_url` up to the API version, e.g. `https://quote-api.jup.ag/v6`.
+    pub fn new(base_url: &str) -> Self {
+        JupiterClient {
+            url: base_url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build().expect("default http client"),
+        }
+    }
+
+    async fn read(response: reqwest::Response) -> Result<Value, JupiterError> {
+        let status = response.status();
+        let body: Value = response.json().await?;
+        if !status.is_success() {
+            let message = body["error"].as_str().map_or_else(|| body.to_string(), str::to_string);
+            return Err(format!("jupiter answered {}: {}", status, message).into());
+        }
+        Ok(body)
+    }
+
+    /// The best route Jupiter knows for `amount` of `mint_in`, fitting a legacy transaction.
+    pub async fn quote(&self, mint_in: &Pubkey, mint_out: &Pubkey, amount: u64, slippage_bps: u16) -> Result<JupiterQuote, JupiterError> {
+        let query = [
+            ("inputMint", mint_in.to_string()),
+            ("outputMint", mint_out.to_string()),
+            ("amount", amount.to_string()),
+            ("slippageBps", slippage_bps.to_string()),
+            ("swapMode", "ExactIn".to_string()),
+            ("asLegacyTransaction", "true".to_string()),
+        ];
+        let response = Self::read(self.http.get(format!("{}/quote", self.url)).query(&query).send().await?).await?;
+        let quote: QuoteResponse = serde_json::from_value(response.clone())?;
+
+        let route = quote
+            .route_plan
+            .iter()
+            .map(|step| {
+                Ok(JupiterRouteStep {
+                    amm_key: Pubkey::from_str(&step.swap_info.amm_key)?,
+                    label: step.swap_info.label.clone(),
+                    mint_in: Pubkey::from_str(&step.swap_info.input_mint)?,
+                    mint_out: Pubkey::from_str(&step.swap_info.output_mint)?,
+                    amount_in: step.swap_info.in_amount.parse()?,
+                    amount_out: step.swap_info.out_amount.parse()?,
+                    percent: step.percent,
+                })
+            })
+            .collect::<Result<Vec<_>, JupiterError>>()?;
+        Ok(JupiterQuote {
+            mint_in: Pubkey::from_str(&quote.input_mint)?,
+            mint_out: Pubkey::from_str(&quote.output_mint)?,
+            amount_in: quote.in_amount.parse()?,
+            amount_out: quote.out_amount.parse()?,
+            min_amount_out: quote.other_amount_threshold.parse()?,
+            slippage_bps: quote.slippage_bps,
+            price_impact_pct: Decimal::from_str(&quote.price_impact_pct)?,
+            route,
+            response,
+        })
+    }
+
+    /// Instructions trading `quote` for `user`, who also pays for the token accounts Jupiter
+    /// creates. SOL is wrapped and unwrapped as needed.
+    pub async fn swap_instructions(&self, quote: &JupiterQuote, user: &Pubkey) -> Result<JupiterSwapInstructions, JupiterError> {
+        let request = json!({
+            "quoteResponse": quote.response,
+            "userPublicKey": user.to_string(),
+            "wrapAndUnwrapSol": true,
+            "asLegacyTransaction": true,
+        });
+        let response = Self::read(self.http.post(format!("{}/swap-instructions", self.url)).json(&request).send().await?).await?;
+        let response: SwapInstructionsResponse = serde_json::from_value(response)?;
+        if !response.address_lookup_table_addresses.is_empty() {
+            return Err(format!("the route needs {} address lookup tables, which a legacy transaction can't use", response.address_lookup_table_addresses.len()).into());
+        }
+
+        Ok(JupiterSwapInstructions {
+            compute_budget: response.compute_budget_instructions.iter().map(JupiterInstruction::decode).collect::<Result<_, _>>()?,
+            setup: response.setup_instructions.iter().map(JupiterInstruction::decode).collect::<Result<_, _>>()?,
+            swap: response.swap_instruction.decode()?,
+            cleanup: response.cleanup_instruction.as_ref().map(JupiterInstruction::decode).transpose()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod mock {
+    use std::io::{self, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+    use base64::Engine;
+    use serde_json::{json, Value};
+    use solana_sdk::pubkey::Pubkey;
+
+    use crate::remote_signer::read_http_message;
+
+    /// A Jupiter API answering on an ephemeral port of 127.0.0.1 from a background thread, for
+    /// tests. Every route is one swap on one venue returning the output set with
+    /// [`MockJupiter::set_amount_out`], and its swap instruction is a call of `program_id` signed by
+    /// the user.
+    pub struct MockJupiter {
+        url: String,
+        state: Arc<Mutex<MockState>>,
+    }
+
+    struct MockState {
+        program_id: Pubkey,
+        amount_out: u64,
+        requests: Vec<String>,
+    }
+
+    impl MockJupiter {
+        pub fn spawn(program_id: Pubkey) -> io::Result<Self> {
+            let listener = TcpListener::bind("127.0.0.1:0")?;
+            let url = format!("http://{}/v6", listener.local_addr()?);
+            let state = Arc::new(Mutex::new(MockState { program_id, amount_out: 0, requests: Vec::new() }));
+            let served = state.clone();
+            thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    let Ok((start_line, body)) = read_http_message(&mut BufReader::new(&mut stream)) else {
+                        continue;
+                    };
+                    let (status, response) = served.lock().unwrap().answer(&start_line, &body);
+                    let body = response.to_string();
+                    let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+                }
+            });
+            Ok(MockJupiter { url, state })
+        }
+
+        /// What to pass to [`JupiterClient::new`].
+        pub fn url(&self) -> &str {
+            &self.url
+        }
+
+        pub fn set_amount_out(&self, amount_out: u64) {
+            self.state.lock().unwrap().amount_out = amount_out;
+        }
+
+        /// Start lines of the requests received so far.
+        pub fn requests(&self) -> Vec<String> {
+            self.state.lock().unwrap().requests.clone()
+        }
+    }
+
+    impl MockState {
+        fn answer(&mut self, start_line: &str, body: &[u8]) -> (&'static str, Value) {
+            self.requests.push(start_line.trim().to_string());
+            let target = start_line.split_whitespace().nth(1).unwrap_or_default();
+            let (path, query) = target.split_once('?').unwrap_or((target, ""));
+            let query = |name: &str| query.split('&').find_map(|pair| pair.strip_prefix(name)?.strip_prefix('=')).unwrap_or_default().to_string();
+
+            match path {
+                "/v6/quote" => {
+                    let amount = query("amount").parse::<u64>().unwrap_or_default();
+                    if amount == 0 || self.amount_out == 0 {
+                        return ("400 Bad Request", json!({ "error": "Could not find any route" }));
+                    }
+                    let slippage_bps = query("slippageBps").parse::<u64>().unwrap_or_default();
+                    let min_amount_out = self.amount_out as u128 * (10_000 - slippage_bps as u128) / 10_000;
+                    let swap_info = json!({
+                        "ammKey": Pubkey::new_from_array([1; 32]).to_string(),
+                        "label": "Whirlpool",
+                        "inputMint": query("inputMint"),
+                        "outputMint": query("outputMint"),
+                        "inAmount": amount.to_string(),
+                        "outAmount": self.amount_out.to_string(),
+                        "feeAmount": "0",
+                        "feeMint": query("inputMint"),
+                    });
+                    let quote = json!({
+                        "inputMint": query("inputMint"),
+                        "inAmount": amount.to_string(),
+                        "outputMint": query("outputMint"),
+                        "outAmount": self.amount_out.to_string(),
+                        "otherAmountThreshold": min_amount_out.to_string(),
+                        "swapMode": "ExactIn",
+                        "slippageBps": slippage_bps,
+                        "priceImpactPct": "0.001",
+                        "routePlan": [{ "swapInfo": swap_info, "percent": 100 }],
+                        "contextSlot": 1,
+                        "timeTaken": 0.01,
+                    });
+                    ("200 OK", quote)
+                }
+                "/v6/swap-instructions" => {
+                    let Ok(request) = serde_json::from_slice::<Value>(body) else {
+                        return ("400 Bad Request", json!({ "error": "invalid json" }));
+                    };
+                    let (Some(user), Some(amount_in)) = (request["userPublicKey"].as_str(), request["quoteResponse"]["inAmount"].as_str()) else {
+                        return ("400 Bad Request", json!({ "error": "userPublicKey and quoteResponse are required" }));
+                    };
+                    let instruction = |program_id: &Pubkey, data: &[u8]| {
+                        json!({
+                            "programId": program_id.to_string(),
+                            "accounts": [{ "pubkey": user, "isSigner": true, "isWritable": true }],
+                            "data": BASE64_STANDARD.encode(data),
+                        })
+                    };
+                    let amount_in = amount_in.parse::<u64>().unwrap_or_default();
+                    let response = json!({
+                        "computeBudgetInstructions": [instruction(&solana_sdk::compute_budget::id(), &[2, 0x40, 0x0d, 0x03, 0x00])],
+                        "setupInstructions": [],
+                        "swapInstruction": instruction(&self.program_id, &amount_in.to_le_bytes()),
+                        "cleanupInstruction": null,
+                        "addressLookupTableAddresses": [],
+                    });
+                    ("200 OK", response)
+                }
+                _ => ("404 Not Found", json!({ "error": format!("no route {}", path) })),
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_jupiter_client() {
+    use solana_sdk::signature::Signer;
+    use solana_sdk::signer::keypair::keypair_from_seed;
+
+    use crate::client::DEXClient;
+    use crate::jupiter::mock::MockJupiter;
+    use crate::rpc::InMemoryRpc;
+
+    let program_id = Pubkey::new_from_array([9; 32]);
+    let (mint_in, mint_out) = (Pubkey::new_from_array([2; 32]), Pubkey::new_from_array([3; 32]));
+    let user = keypair_from_seed(&[5; 32]).unwrap();
+    let mock = MockJupiter::spawn(program_id).unwrap();
+    let jupiter = JupiterClient::new(&format!("{}/", mock.url()));
+
+    // no route is an error, with Jupiter's reason
+    let err = jupiter.quote(&mint_in, &mint_out, 1_000_000, 50).await.unwrap_err();
+    assert!(err.to_string().contains("Could not find any route"));
+
+    mock.set_amount_out(2_000_000);
+    let quote = jupiter.quote(&mint_in, &mint_out, 1_000_000, 50).await.unwrap();
+    assert_eq!((quote.mint_in, quote.mint_out, quote.amount_in, quote.amount_out, quote.min_amount_out), (mint_in, mint_out, 1_000_000, 2_000_000, 1_990_000));
+    assert_eq!((quote.slippage_bps, quote.price_impact_pct), (50, Decimal::new(1, 3)));
+    assert_eq!(quote.labels(), vec!["Whirlpool"]);
+    assert_eq!(quote.route[0].percent, 100);
+    let requests = mock.requests();
+    assert!(requests[1].starts_with("GET /v6/quote?"));
+    assert!(requests[1].contains("asLegacyTransaction=true"));
+
+    let swap = jupiter.swap_instructions(&quote, &user.pubkey()).await.unwrap();
+    let instructions = swap.instructions();
+    assert_eq!(instructions.len(), 2);
+    assert_eq!(instructions[0].program_id, solana_sdk::compute_budget::id());
+    assert_eq!((instructions[1].program_id, instructions[1].data.clone()), (program_id, 1_000_000u64.to_le_bytes().to_vec()));
+    assert_eq!(instructions[1].accounts, vec![AccountMeta::new(user.pubkey(), true)]);
+
+    // sent like our own instructions
+    let client = DEXClient::with_rpc(InMemoryRpc::new());
+    client.send_instructions(&user, instructions.clone()).await.unwrap();
+    let sent = client.rpc().sent_transactions();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].message.instructions.len(), 2);
+    assert_eq!(sent[0].message.account_keys[sent[0].message.instructions[1].program_id_index as usize], program_id);
+}
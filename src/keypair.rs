@@ -0,0 +1,122 @@
+//! Loading signing keys from Solana CLI keypair files, base58 secret keys or the encrypted
+//! keystore, or connecting to a remote signer.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+
+use crate::config::ClusterConfig;
+use crate::keystore::{read_passphrase, Keystore};
+use crate::remote_signer::{RemoteSigner, SignerEndpoint};
+
+pub type KeypairError = Box<dyn std::error::Error>;
+
+pub type BoxedSigner = Box<dyn Signer + Send + Sync>;
+
+/// Bearer token sent to remote signers.
+pub const SIGNER_AUTH_TOKEN_ENV: &str = "SOLANA_DEX_SIGNER_TOKEN";
+
+/// Where `solana-keygen new` writes the default keypair.
+pub const DEFAULT_KEYPAIR_PATH: &str = "~/.config/solana/id.json";
+
+/// Base58 secret key, still accepted from .env files written for older versions.
+pub const PRIVATE_KEY_ENV: &str = "PRIVATE_KEY";
+
+/// Prefix of sources naming a key in the keystore, e.g. `keystore:trading`.
+pub const KEYSTORE_PREFIX: &str = "keystore:";
+
+/// Expands a leading `~` to `$HOME`.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var("HOME")) {
+        (Some(rest), Ok(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Reads a keypair file as written by `solana-keygen`: a JSON array of the 64 secret key bytes.
+pub fn read_keypair(path: impl AsRef<Path>) -> Result<Keypair, KeypairError> {
+    let path = path.as_ref();
+    read_keypair_file(path).map_err(|err| format!("{}: {}", path.display(), err).into())
+}
+
+/// A keypair from `source`, which is either the path of a keypair file or a base58 secret key.
+pub fn load_keypair(source: &str) -> Result<Keypair, KeypairError> {
+    let path = expand_tilde(source);
+    if path.exists() {
+        return read_keypair(path);
+    }
+
+    let bytes = bs58::decode(source.trim())
+        .into_vec()
+        .map_err(|_| format!("{} is neither a keypair file nor a base58 secret key", source))?;
+    Keypair::from_bytes(&bytes).map_err(|err| format!("invalid secret key: {}", err).into())
+}
+
+/// Unlocks `name` from the default keystore, with the passphrase from
+/// `SOLANA_DEX_KEYSTORE_PASSPHRASE` or the terminal.
+pub fn unlock_keystore_key(name: &str) -> Result<Keypair, KeypairError> {
+    let passphrase = read_passphrase(&format!("passphrase for key {}: ", name))?;
+    Keystore::open_default().unlock(name, &passphrase)
+}
+
+/// A signer from `source`: a keystore key for `keystore:NAME`, a remote signer for
+/// `http://host:port` or `unix:/path` (with the token from `SOLANA_DEX_SIGNER_TOKEN`),
+/// otherwise a keypair as in [`load_keypair`].
+pub fn load_signer(source: &str) -> Result<BoxedSigner, KeypairError> {
+    if let Some(name) = source.strip_prefix(KEYSTORE_PREFIX) {
+        return Ok(Box::new(unlock_keystore_key(name)?));
+    }
+    if SignerEndpoint::is_endpoint(source) {
+        let signer = RemoteSigner::connect_with_auth_token(source, env::var(SIGNER_AUTH_TOKEN_ENV).ok()).map_err(|err| format!("{}: {}", source, err))?;
+        return Ok(Box::new(signer));
+    }
+    Ok(Box::new(load_keypair(source)?))
+}
+
+/// The key trades are signed with, from the first of: `explicit` (e.g. `--keypair`), the
+/// profile's `keypair` (or `SOLANA_DEX_KEYPAIR`), `PRIVATE_KEY`, and `~/.config/solana/id.json`.
+pub fn load_authority(explicit: Option<&str>, profile: &ClusterConfig) -> Result<BoxedSigner, KeypairError> {
+    if let Some(source) = explicit.or(profile.keypair.as_deref()) {
+        return load_signer(source);
+    }
+    if let Ok(private_key) = env::var(PRIVATE_KEY_ENV) {
+        eprintln!("warning: using the unencrypted key in ${}, move it into the keystore with `keys import`", PRIVATE_KEY_ENV);
+        return Ok(Box::new(load_keypair(&private_key)?));
+    }
+    Ok(Box::new(read_keypair(expand_tilde(DEFAULT_KEYPAIR_PATH))?))
+}
+
+/// A separate fee payer from `explicit` (e.g. `--fee-payer`) or the profile's `fee_payer` (or
+/// `SOLANA_DEX_FEE_PAYER`). `None` means the authority pays.
+pub fn load_fee_payer(explicit: Option<&str>, profile: &ClusterConfig) -> Result<Option<BoxedSigner>, KeypairError> {
+    explicit.or(profile.fee_payer.as_deref()).map(load_signer).transpose()
+}
+
+#[test]
+fn test_load_keypair() {
+    use solana_sdk::signature::write_keypair_file;
+    use solana_sdk::signer::keypair::keypair_from_seed;
+
+    let keypair = keypair_from_seed(&[3; 32]).unwrap();
+    let path = env::temp_dir().join(format!("keypair_{}.json", std::process::id()));
+    write_keypair_file(&keypair, &path).unwrap();
+
+    let from_file = load_keypair(path.to_str().unwrap()).unwrap();
+    let from_base58 = load_keypair(&keypair.to_base58_string()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(from_file.pubkey(), keypair.pubkey());
+    assert_eq!(from_base58.pubkey(), keypair.pubkey());
+
+    assert!(load_keypair("/nonexistent/id.json").is_err());
+    assert!(load_keypair("3xyz").is_err());
+
+    let server = crate::remote_signer::LocalSigningServer::spawn_tcp(keypair.insecure_clone(), None).unwrap();
+    assert_eq!(load_signer(server.endpoint()).unwrap().pubkey(), keypair.pubkey());
+    assert_eq!(load_signer(&keypair.to_base58_string()).unwrap().pubkey(), keypair.pubkey());
+
+    if let Ok(home) = env::var("HOME") {
+        assert_eq!(expand_tilde(DEFAULT_KEYPAIR_PATH), Path::new(&home).join(".config/solana/id.json"));
+    }
+    assert_eq!(expand_tilde("/etc/id.json"), PathBuf::from("/etc/id.json"));
+}
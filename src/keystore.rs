@@ -0,0 +1,262 @@
+//! Passphrase-encrypted keypairs on disk, one JSON file per named key.
+//!
+//! The passphrase is stretched with scrypt into a 256-bit key, and the 64 secret key bytes are
+//! sealed with AES-256-GCM-SIV, authenticated together with the key's name and pubkey so a
+//! file can't be renamed or edited without unlocking failing.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use aes_gcm_siv::aead::rand_core::RngCore;
+use aes_gcm_siv::aead::{Aead, KeyInit, OsRng, Payload};
+use aes_gcm_siv::{Aes256GcmSiv, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+use crate::keypair::expand_tilde;
+
+pub type KeystoreError = Box<dyn std::error::Error>;
+
+/// Used when `SOLANA_DEX_KEYSTORE` is not set.
+pub const DEFAULT_KEYSTORE_DIR: &str = "~/.config/solana_dex/keystore";
+
+pub const KEYSTORE_DIR_ENV: &str = "SOLANA_DEX_KEYSTORE";
+
+/// Unlocks keys without a prompt, for unattended deployments.
+pub const KEYSTORE_PASSPHRASE_ENV: &str = "SOLANA_DEX_KEYSTORE_PASSPHRASE";
+
+/// scrypt cost for new files, 2^15 iterations take around 100ms.
+pub const DEFAULT_SCRYPT_LOG_N: u8 = 15;
+
+const KEYSTORE_VERSION: u32 = 1;
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// The file format of a stored key.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EncryptedKey {
+    pub version: u32,
+    pub name: String,
+    pub pubkey: String,
+    pub kdf: KdfParams,
+    pub cipher: CipherParams,
+    /// Base64 of the sealed secret key bytes.
+    pub ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KdfParams {
+    /// Always `scrypt`.
+    pub algorithm: String,
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+    /// Base64.
+    pub salt: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CipherParams {
+    /// Always `aes-256-gcm-siv`.
+    pub algorithm: String,
+    /// Base64.
+    pub nonce: String,
+}
+
+/// A stored key as listed, without unlocking it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeystoreEntry {
+    pub name: String,
+    pub pubkey: Pubkey,
+    pub path: PathBuf,
+}
+
+pub struct Keystore {
+    dir: PathBuf,
+    scrypt_log_n: u8,
+}
+
+impl Keystore {
+    pub fn open(dir: impl AsRef<Path>) -> Self {
+        Keystore { dir: dir.as_ref().to_path_buf(), scrypt_log_n: DEFAULT_SCRYPT_LOG_N }
+    }
+
+    /// `$SOLANA_DEX_KEYSTORE`, else `~/.config/solana_dex/keystore`.
+    pub fn open_default() -> Self {
+        let dir = env::var(KEYSTORE_DIR_ENV).unwrap_or_else(|_| DEFAULT_KEYSTORE_DIR.to_string());
+        Self::open(expand_tilde(&dir))
+    }
+
+    /// scrypt cost of keys imported from now on. Lower values are only meant for tests.
+    pub fn with_scrypt_log_n(mut self, log_n: u8) -> Self {
+        self.scrypt_log_n = log_n;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, KeystoreError> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("invalid key name {:?}, use letters, digits, - and _", name).into());
+        }
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+
+    /// Encrypts `keypair` under `passphrase` as `name`. Existing keys are never overwritten.
+    pub fn import(&self, name: &str, keypair: &Keypair, passphrase: &str) -> Result<KeystoreEntry, KeystoreError> {
+        let path = self.path(name)?;
+        if path.exists() {
+            return Err(format!("key {} already exists in {}", name, self.dir.display()).into());
+        }
+
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let kdf = KdfParams { algorithm: "scrypt".to_string(), log_n: self.scrypt_log_n, r: 8, p: 1, salt: BASE64_STANDARD.encode(salt) };
+        let pubkey = keypair.pubkey();
+
+        let cipher = cipher(&kdf, passphrase)?;
+        let aad = associated_data(name, &pubkey);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &keypair.to_bytes(), aad: &aad })
+            .map_err(|_| "encryption failed")?;
+
+        let encrypted = EncryptedKey {
+            version: KEYSTORE_VERSION,
+            name: name.to_string(),
+            pubkey: pubkey.to_string(),
+            kdf,
+            cipher: CipherParams { algorithm: "aes-256-gcm-siv".to_string(), nonce: BASE64_STANDARD.encode(nonce) },
+            ciphertext: BASE64_STANDARD.encode(ciphertext),
+        };
+        fs::create_dir_all(&self.dir)?;
+        write_private_file(&path, serde_json::to_string_pretty(&encrypted)?.as_bytes())?;
+        Ok(KeystoreEntry { name: name.to_string(), pubkey, path })
+    }
+
+    /// Decrypts the key called `name`.
+    pub fn unlock(&self, name: &str, passphrase: &str) -> Result<Keypair, KeystoreError> {
+        let encrypted = self.read(name)?;
+        if encrypted.version != KEYSTORE_VERSION || encrypted.kdf.algorithm != "scrypt" || encrypted.cipher.algorithm != "aes-256-gcm-siv" {
+            return Err(format!("key {} uses an unsupported keystore format", name).into());
+        }
+
+        let pubkey: Pubkey = encrypted.pubkey.parse()?;
+        let nonce = BASE64_STANDARD.decode(&encrypted.cipher.nonce)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(format!("key {} has a malformed nonce", name).into());
+        }
+        let cipher = cipher(&encrypted.kdf, passphrase)?;
+        let secret = cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &BASE64_STANDARD.decode(&encrypted.ciphertext)?, aad: &associated_data(&encrypted.name, &pubkey) })
+            .map_err(|_| format!("wrong passphrase for key {}, or the file was modified", name))?;
+
+        let keypair = Keypair::from_bytes(&secret)?;
+        if keypair.pubkey() != pubkey {
+            return Err(format!("key {} does not match its pubkey", name).into());
+        }
+        Ok(keypair)
+    }
+
+    /// Stored keys, by name.
+    pub fn list(&self) -> Result<Vec<KeystoreEntry>, KeystoreError> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for file in fs::read_dir(&self.dir)? {
+            let path = file?.path();
+            if path.extension().is_some_and(|extension| extension == "json") {
+                let encrypted: EncryptedKey = serde_json::from_str(&fs::read_to_string(&path)?).map_err(|err| format!("{}: {}", path.display(), err))?;
+                entries.push(KeystoreEntry { name: encrypted.name, pubkey: encrypted.pubkey.parse()?, path });
+            }
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn read(&self, name: &str) -> Result<EncryptedKey, KeystoreError> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            return Err(format!("no key {} in {}", name, self.dir.display()).into());
+        }
+        let encrypted: EncryptedKey = serde_json::from_str(&fs::read_to_string(&path)?).map_err(|err| format!("{}: {}", path.display(), err))?;
+        if encrypted.name != name {
+            return Err(format!("{} holds key {}, not {}", path.display(), encrypted.name, name).into());
+        }
+        Ok(encrypted)
+    }
+}
+
+/// `$SOLANA_DEX_KEYSTORE_PASSPHRASE`, otherwise asked for on the terminal.
+pub fn read_passphrase(prompt: &str) -> Result<String, KeystoreError> {
+    match env::var(KEYSTORE_PASSPHRASE_ENV) {
+        Ok(passphrase) => Ok(passphrase),
+        Err(_) => Ok(rpassword::prompt_password(prompt)?),
+    }
+}
+
+fn cipher(kdf: &KdfParams, passphrase: &str) -> Result<Aes256GcmSiv, KeystoreError> {
+    let params = scrypt::Params::new(kdf.log_n, kdf.r, kdf.p, 32).map_err(|err| format!("invalid scrypt parameters: {}", err))?;
+    let mut key = [0; 32];
+    scrypt::scrypt(passphrase.as_bytes(), &BASE64_STANDARD.decode(&kdf.salt)?, &params, &mut key).map_err(|err| format!("scrypt failed: {}", err))?;
+    let cipher = Aes256GcmSiv::new_from_slice(&key).map_err(|_| "invalid key length")?;
+    key.fill(0);
+    Ok(cipher)
+}
+
+fn associated_data(name: &str, pubkey: &Pubkey) -> Vec<u8> {
+    [name.as_bytes(), pubkey.as_ref()].concat()
+}
+
+/// Writes a file only the owner can read.
+fn write_private_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)
+}
+
+#[test]
+fn test_keystore() {
+    use solana_sdk::signer::keypair::keypair_from_seed;
+
+    let dir = env::temp_dir().join(format!("keystore_{}", std::process::id()));
+    let keystore = Keystore::open(&dir).with_scrypt_log_n(4);
+    assert!(keystore.list().unwrap().is_empty());
+
+    let trading = keypair_from_seed(&[7; 32]).unwrap();
+    let entry = keystore.import("trading", &trading, "correct horse").unwrap();
+    assert_eq!(entry.pubkey, trading.pubkey());
+    keystore.import("fees", &keypair_from_seed(&[8; 32]).unwrap(), "battery staple").unwrap();
+    assert!(keystore.import("trading", &trading, "correct horse").is_err());
+    assert!(keystore.import("../escape", &trading, "correct horse").is_err());
+
+    let names: Vec<String> = keystore.list().unwrap().into_iter().map(|entry| entry.name).collect();
+    assert_eq!(names, vec!["fees", "trading"]);
+
+    // the secret never hits the disk in the clear
+    let file = fs::read_to_string(&entry.path).unwrap();
+    assert!(!file.contains(&trading.to_base58_string()));
+    #[cfg(unix)]
+    assert_eq!(std::os::unix::fs::PermissionsExt::mode(&fs::metadata(&entry.path).unwrap().permissions()) & 0o777, 0o600);
+
+    assert_eq!(keystore.unlock("trading", "correct horse").unwrap().pubkey(), trading.pubkey());
+    assert!(keystore.unlock("trading", "battery staple").is_err());
+    assert!(keystore.unlock("missing", "correct horse").is_err());
+
+    // a copied file doesn't unlock under another name
+    fs::write(dir.join("copy.json"), file.replace("\"trading\"", "\"copy\"")).unwrap();
+    assert!(keystore.unlock("copy", "correct horse").is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,32 @@
+pub mod config;
+pub mod constant;
+pub mod keypair;
+pub mod keystore;
+pub mod remote_signer;
+pub mod offline;
+pub mod nonce;
+pub mod tick_array;
+pub mod token;
+pub mod swap_math;
+pub mod rpc;
+pub mod rpc_pool;
+pub mod sender;
+pub mod dex;
+pub mod whirlpool;
+pub mod raydium_clmm;
+pub mod raydium_amm;
+pub mod meteora_dlmm;
+pub mod phoenix;
+pub mod router;
+pub mod arbitrage;
+pub mod client;
+pub mod fixture;
+pub mod data_source;
+pub mod confirmation;
+pub mod settlement;
+#[cfg(feature = "geyser")]
+pub mod geyser;
+#[cfg(feature = "jito")]
+pub mod jito;
+#[cfg(feature = "jupiter")]
+pub mod jupiter;
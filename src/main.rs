@@ -1,387 +1,1178 @@
 // extern crate dotenv;
 
-use constant::{ORCA_WHIRLPOOL_PROGRAM_ID, USDC_ADDRESS, USDC_DECIMALS, WSOL_ADDRESS, WSOL_DECIMALS, WSOL_USDC_3000};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::{Args, Parser, Subcommand};
 use dotenv::dotenv;
-use tick_array::{calculate_token_b_amount, generate_oracle_pda, poolutil_get_tick_array_pubkeys_for_swap, pricemath_sqrt_price_x64_to_price, Whirlpool};
-use std::env;
-
-mod tick_array;
-mod constant;
-
-
-use anchor_lang::prelude::*;
-use solana_client::nonblocking::rpc_client::RpcClient as AsyncRpcClient;
-use solana_sdk::{
-    commitment_config::CommitmentConfig,
-    instruction::{AccountMeta, Instruction},
-    pubkey::Pubkey,
-    signature::{Keypair, Signer},
-    transaction::Transaction,
-};
+use rust_decimal::{Decimal, MathematicalOps};
+use serde_json::{json, Value};
+use solana_dex::arbitrage::{find_arbitrage, ArbitrageConfig};
+use solana_dex::client::DEXClient;
+use solana_dex::dex::{Pool, PoolQuote};
+use solana_dex::meteora_dlmm::MeteoraDlmmPool;
+use solana_dex::phoenix::PhoenixPool;
+use solana_dex::raydium_amm::RaydiumAmmPool;
+use solana_dex::raydium_clmm::RaydiumClmmPool;
+use solana_dex::router::{RoutePlan, Router};
+use solana_dex::whirlpool::WhirlpoolPool;
+use solana_dex::config::{ClusterConfig, Config, PoolConfig};
+#[cfg(feature = "jupiter")]
+use solana_dex::jupiter::JupiterClient;
+use solana_dex::keypair::{load_authority, load_fee_payer, load_keypair, BoxedSigner};
+use solana_dex::keystore::{read_passphrase, Keystore, KEYSTORE_PASSPHRASE_ENV};
+use solana_dex::nonce::NonceInfo;
+use solana_dex::offline::OfflineTransaction;
+use solana_dex::rpc::SolanaRpc;
+use solana_dex::tick_array::{pricemath_sqrt_price_x64_to_price, tickutil_get_start_tick_indexes_for_swap};
+use solana_dex::token::{raw_amount_to_ui, resolve_token, token_info_by_mint, ui_amount_to_raw, TokenInfo};
+use solana_sdk::commitment_config::CommitmentLevel;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{write_keypair_file, Keypair, Signature, Signer};
 use spl_associated_token_account::get_associated_token_address;
-use std::str::FromStr;
 
-pub struct DEXClient {
-    rpc_client: AsyncRpcClient,
-}
-
-#[derive(Default, AnchorSerialize, AnchorDeserialize)]
-pub struct SwapData {
-    pub amount: u64,
-    pub other_amount_threshold: u64,
-    pub sqrt_price_limit: u128,
-    pub amount_specified_is_input: bool,
-    pub a_to_b: bool,
-}
-
-#[derive(Default)]
-pub struct SwapTokenData {
-    pub amount: u64,
-    pub other_amount_threshold: u64,
-    pub sqrt_price_limit: u128,
-    pub amount_specified_is_input: bool,
-    pub token_in: solana_sdk::pubkey::Pubkey, 
-    pub token_out: solana_sdk::pubkey::Pubkey, 
-    pub token_in_decimals: i8, 
-    pub token_out_decimals: i8, 
-    pub slippage: u16,
-}
-
-impl SwapTokenData {
-    pub fn new(amount: u64, token_in: solana_sdk::pubkey::Pubkey, token_out: solana_sdk::pubkey::Pubkey, token_in_decimals: i8, token_out_decimals: i8, slippage: u16) -> Self {
-        SwapTokenData {
-            amount,
-            other_amount_threshold: 0,
-            sqrt_price_limit: 0,
-            amount_specified_is_input: true,
-            token_in,
-            token_out,
-            token_in_decimals,
-            token_out_decimals,
-            slippage,
-        }
-    }
+type CliResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Quote and swap on Orca Whirlpools, Raydium CLMM and AMM v4 pools, Meteora DLMM pairs and
+/// Phoenix markets.
+#[derive(Parser)]
+#[command(name = "solana_dex")]
+struct Cli {
+    /// Config file, defaults to $SOLANA_DEX_CONFIG, then ./solana_dex.toml, then the built-in one
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Cluster profile, defaults to $SOLANA_DEX_PROFILE, then the config's default_profile
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Print JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Signer keypair file, base58 secret key, keystore key (keystore:NAME) or remote signer
+    /// (http://host:port or unix:/path), defaults to the profile's keypair, then
+    /// $PRIVATE_KEY, then ~/.config/solana/id.json
+    #[arg(long, global = true)]
+    keypair: Option<String>,
+
+    /// Keypair file, keystore key or remote signer paying fees and rent, defaults to the
+    /// profile's fee_payer, then the signer
+    #[arg(long, global = true)]
+    fee_payer: Option<String>,
+
+    /// Signer pubkey, so transactions can be built with --dry-run or --sign-offline where the
+    /// key itself is not available
+    #[arg(long, global = true)]
+    authority: Option<Pubkey>,
+
+    #[command(subcommand)]
+    command: Command,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Simulate a swap, nothing is sent
+    Quote(SwapArgs),
+    /// Swap on a pool, or over the best route with --route
+    Swap {
+        #[command(flatten)]
+        swap: SwapArgs,
+
+        #[command(flatten)]
+        send: SendArgs,
+    },
+    /// Cycles through the configured pools that return more than they take, sized to their
+    /// most profitable input
+    Arbitrage {
+        #[command(flatten)]
+        arbitrage: ArbitrageArgs,
+
+        #[command(flatten)]
+        send: SendArgs,
+    },
+    /// Inspect whirlpools
+    #[command(subcommand)]
+    Pool(PoolCommand),
+    /// SOL and token balances of a wallet
+    Balances {
+        /// Wallet to inspect, defaults to the signer
+        #[arg(long)]
+        owner: Option<Pubkey>,
+
+        /// Symbols or mints, defaults to every token of the profile
+        tokens: Vec<String>,
+    },
+    /// Tick arrays a swap in either direction would cross
+    TickArrays {
+        /// Pool name from the config or whirlpool address
+        pool: String,
+
+        /// Arrays listed per direction
+        #[arg(long, default_value_t = 3)]
+        count: usize,
+    },
+    /// Create or close associated token accounts of the signer
+    #[command(subcommand)]
+    Ata(AtaCommand),
+    /// Sign a transaction written by --sign-offline, in place
+    Sign {
+        file: PathBuf,
+
+        /// Add a signature made elsewhere instead of signing with the signer, repeatable
+        #[arg(long, value_name = "PUBKEY=SIGNATURE")]
+        signature: Vec<String>,
+    },
+    /// Send a transaction written by --sign-offline once every signature is in
+    Send { file: PathBuf },
+    /// Health, latency and block height of the profile's RPC endpoints
+    Endpoints,
+    /// Durable nonce accounts, for transactions signed with --sign-offline --nonce that
+    /// don't expire
+    #[command(subcommand)]
+    Nonce(NonceCommand),
+    /// Manage the encrypted keystore ($SOLANA_DEX_KEYSTORE, defaults to
+    /// ~/.config/solana_dex/keystore)
+    #[command(subcommand)]
+    Keys(KeysCommand),
+}
 
+#[derive(Subcommand)]
+enum KeysCommand {
+    /// Encrypt a key under a passphrase, use it with --keypair keystore:NAME
+    Import {
+        name: String,
+
+        /// Keypair file or base58 secret key, asked for on the terminal when omitted
+        #[arg(long)]
+        from: Option<String>,
+    },
+    /// Decrypt a key, printed as a base58 secret key unless --output is given
+    Export {
+        name: String,
+
+        /// Write a Solana CLI keypair file instead
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Names and pubkeys of the stored keys
+    List,
+}
 
+#[derive(Subcommand)]
+enum PoolCommand {
+    /// Tokens, fee, liquidity and price of a whirlpool
+    Info {
+        /// Pool name from the config or whirlpool address
+        pool: String,
+    },
+}
 
-impl DEXClient {
-    pub fn new(rpc_url: &str) -> Self {
-        let rpc_client = AsyncRpcClient::new_with_commitment(rpc_url.to_string(), CommitmentConfig::confirmed());
-        DEXClient { rpc_client }
-    }
-
-    pub async fn orca_swap(
-        &self,
-        user: &Keypair,
-        whirlpool: &Pubkey,
-        token_authority: &Pubkey,
-        token_owner_account_a: &Pubkey,
-        token_vault_a: &Pubkey,
-        token_owner_account_b: &Pubkey,
-        token_vault_b: &Pubkey,
-        tick_array_0: &Pubkey,
-        tick_array_1: &Pubkey,
-        tick_array_2: &Pubkey,
-        oracle: &Pubkey,
-        amount: u64,
-        other_amount_threshold: u64,
-        sqrt_price_limit: u128,
-        amount_specified_is_input: bool,
-        a_to_b: bool,
-    ) -> std::result::Result<String, Box<dyn std::error::Error>> {
-        let orca_swap_program_id = Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID)?;
-        let token_program = spl_token::id();
-
-        let accounts = vec![
-            AccountMeta::new(token_program, false),
-            AccountMeta::new(*token_authority, true),
-            AccountMeta::new(*whirlpool, false),
-            AccountMeta::new(*token_owner_account_a, false),
-            AccountMeta::new(*token_vault_a, false),
-            AccountMeta::new(*token_owner_account_b, false),
-            AccountMeta::new(*token_vault_b, false),
-            AccountMeta::new(*tick_array_0, false),
-            AccountMeta::new(*tick_array_1, false),
-            AccountMeta::new(*tick_array_2, false),
-            AccountMeta::new(*oracle, false),
-        ];
-
-        let swap_data = SwapData {
-            amount,
-            other_amount_threshold,
-            sqrt_price_limit,
-            amount_specified_is_input,
-            a_to_b,
-        };
-
-        let data = {
-            let mut prefix = vec![248, 198, 158, 145, 225, 117, 135, 200];
-            prefix.extend(swap_data.try_to_vec()?);
-            prefix
-        };
-
-        let instruction = Instruction {
-            program_id: orca_swap_program_id,
-            accounts,
-            data,
-        };
-
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
+#[derive(Subcommand)]
+enum NonceCommand {
+    /// Create a nonce account advanced by the signer, paid for by the fee payer
+    Create,
+    /// Authority and current value of a nonce account
+    Show { address: Pubkey },
+    /// Move a nonce account on, voiding every transaction signed over its current value
+    Advance { address: Pubkey },
+}
 
-        println!("recent_blockhash: {}", recent_blockhash);
+#[derive(Subcommand)]
+enum AtaCommand {
+    Create {
+        /// Symbol or mint
+        token: String,
+
+        #[command(flatten)]
+        send: SendArgs,
+    },
+    /// Close an empty token account, or unwrap wrapped SOL
+    Close {
+        /// Symbol or mint
+        token: String,
+
+        #[command(flatten)]
+        send: SendArgs,
+    },
+}
 
-        let transaction = Transaction::new_signed_with_payer(
-            &[instruction],
-            Some(&user.pubkey()),
-            &[user],
-            recent_blockhash,
-        );
+#[derive(Args)]
+struct SendArgs {
+    /// Build and print the transaction without sending it
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Write the unsigned transaction to FILE for `sign` and `send` instead of sending it
+    #[arg(long, value_name = "FILE", conflicts_with = "dry_run")]
+    sign_offline: Option<PathBuf>,
+
+    /// Sign the offline transaction over this durable nonce account, so it stays valid until
+    /// sent instead of expiring after about a minute
+    #[arg(long, value_name = "ADDRESS", requires = "sign_offline")]
+    nonce: Option<Pubkey>,
+
+    /// processed, confirmed or finalized, defaults to the profile's commitment
+    #[arg(long)]
+    commitment: Option<CommitmentLevel>,
+
+    /// Send once and print every confirmation from the websocket as it arrives, instead of
+    /// rebroadcasting until confirmed
+    #[arg(long, conflicts_with_all = ["dry_run", "sign_offline"])]
+    track: bool,
+
+    /// Send as a Jito bundle through the profile's block engine, paying its tip
+    #[cfg(feature = "jito")]
+    #[arg(long, conflicts_with_all = ["dry_run", "sign_offline", "track"])]
+    jito: bool,
+}
 
-        let signature = self.rpc_client.send_and_confirm_transaction(&transaction).await?;
-        Ok(signature.to_string())
+impl SendArgs {
+    fn needs_signer(&self) -> bool {
+        !self.dry_run && self.sign_offline.is_none()
     }
+}
 
+#[derive(Args)]
+struct SwapArgs {
+    /// Amount of the input token, in whole tokens (e.g. 1.5)
+    amount: String,
 
-    pub async fn send_instructions(
-        &self,
-        user: &Keypair,
-        instructions: Vec<Instruction>,
-    ) -> std::result::Result<String, Box<dyn std::error::Error>> {
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
-        println!("recent_blockhash: {}", recent_blockhash);
-    
-        let transaction = Transaction::new_signed_with_payer(
-            &instructions,
-            Some(&user.pubkey()),
-            &[user],
-            recent_blockhash,
-        );
-    
-        let signature = self.rpc_client.send_and_confirm_transaction(&transaction).await?;
-        Ok(signature.to_string())
-    }
-
+    /// Input token, symbol or mint
+    token_in: String,
 
+    /// Output token, symbol or mint
+    token_out: String,
 
-    pub async fn get_or_create_associated_token_account(&self, user: &Keypair, mint: &Pubkey) -> std::result::Result<Pubkey, Box<dyn std::error::Error>> {
-        let associated_token_address = get_associated_token_address(&user.pubkey(), mint);
-        
-        match self.rpc_client.get_account(&associated_token_address).await {
-            Ok(_) => {
-                Ok(associated_token_address)
-            },
-            Err(_) => {
-                println!("Creating new associated token account");
-                self.create_associated_token_account(user, mint).await
-            }
-        }
-    }
+    /// Pool name from the config or whirlpool address, defaults to the configured pool of the pair
+    #[arg(long)]
+    pool: Option<String>,
 
-    async fn create_associated_token_account(&self, user: &Keypair, mint: &Pubkey) -> std::result::Result<Pubkey, Box<dyn std::error::Error>> {
-        let associated_token_address = get_associated_token_address(&user.pubkey(), mint);
-        
-        let instruction = spl_associated_token_account::instruction::create_associated_token_account(
-            &user.pubkey(),
-            &user.pubkey(),
-            mint,
-            &spl_token::id(),
-        );
+    /// Route through every configured pool, over one or two hops, split when that pays
+    #[arg(long, conflicts_with = "pool")]
+    route: bool,
 
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
+    /// Split over every whirlpool of the pair, one per tick spacing, in one transaction
+    #[arg(long, conflicts_with = "route")]
+    all_tiers: bool,
 
-        let transaction = Transaction::new_signed_with_payer(
-            &[instruction],
-            Some(&user.pubkey()),
-            &[user],
-            recent_blockhash,
-        );
+    /// Most routes a --route or --all-tiers trade is split over, defaults to 3 with --route
+    /// and every whirlpool of the pair with --all-tiers
+    #[arg(long)]
+    max_splits: Option<usize>,
 
-        self.rpc_client.send_and_confirm_transaction(&transaction).await?;
+    /// Least output accepted, in basis points below the quote
+    #[arg(long, default_value_t = 50)]
+    slippage_bps: u16,
 
-        Ok(associated_token_address)
-    }
+    /// Create missing tick arrays the trade needs, paid by the signer
+    #[arg(long)]
+    init_tick_arrays: bool,
 
-    pub async fn get_token_balance(&self, token_account: &Pubkey) -> std::result::Result<u64, Box<dyn std::error::Error>> {
-        let account = self.rpc_client.get_token_account_balance(token_account).await?;
-        Ok(account.amount.parse()?)
-    }
+    /// Set Jupiter's quote beside ours, or with swap trade Jupiter's route instead
+    #[cfg(feature = "jupiter")]
+    #[arg(long, conflicts_with_all = ["route", "all_tiers"])]
+    jupiter: bool,
 }
 
+#[derive(Args)]
+struct ArbitrageArgs {
+    /// Token the cycles start and end in, symbol or mint
+    #[arg(default_value = "SOL")]
+    token: String,
 
+    /// Most swaps in a cycle
+    #[arg(long, default_value_t = 3)]
+    max_hops: usize,
 
+    /// Largest input tried, in whole tokens, defaults to no limit
+    #[arg(long)]
+    max_amount: Option<String>,
 
-async fn build_orca_pool_swap_instruction(orca_client: &DEXClient, user: &Keypair, swap_token_data: &SwapTokenData, pool_address: &solana_sdk::pubkey::Pubkey, dex_address: &solana_sdk::pubkey::Pubkey, slippage: u16) -> std::result::Result<Instruction, Box<dyn std::error::Error>> {
- 
+    /// Least profit after network fees, in whole tokens, the transaction accepts
+    #[arg(long, default_value = "0")]
+    min_profit: String,
 
+    #[arg(long, default_value_t = 600_000)]
+    compute_unit_limit: u32,
 
-    let mut whirlpool_data = orca_client.rpc_client.get_account_data(pool_address).await.unwrap();
-    let whirlpool_data_slice = &mut whirlpool_data.as_mut_slice()[8..].as_ref();
-    
-    let whirlpool: Whirlpool = AnchorDeserialize::deserialize(whirlpool_data_slice).unwrap();
+    /// Priority fee in micro-lamports per compute unit
+    #[arg(long, default_value_t = 0)]
+    compute_unit_price: u64,
 
-    let a_to_b = if swap_token_data.token_in.eq(&solana_sdk::pubkey::Pubkey::try_from_slice(&whirlpool.token_mint_a.to_bytes()).unwrap()) {
-        true
-    } else {
-        false
-    };
-
-    // calcu price with rust_decimal crate (at client-side)
-    let token_in_price = pricemath_sqrt_price_x64_to_price(whirlpool.sqrt_price, swap_token_data.token_in_decimals, swap_token_data.token_out_decimals);
+    /// Trade the most profitable cycle in one transaction that fails below --min-profit
+    #[arg(long)]
+    execute: bool,
+}
 
-    println!("token_in_price : {}", token_in_price);
+/// Who signs, and the key itself unless the transaction is only built.
+struct Authority {
+    pubkey: Pubkey,
+    signer: Option<BoxedSigner>,
+}
 
+/// A command's result, printed as `text` or, with `--json`, as `json`.
+struct Output {
+    json: Value,
+    text: String,
+}
 
-    let token_out_amount_min = calculate_token_b_amount(&token_in_price, swap_token_data.amount, slippage, whirlpool.fee_rate, whirlpool.protocol_fee_rate);
+#[tokio::main]
+async fn main() -> CliResult<()> {
+    dotenv().ok();
 
-    println!("token_out_amount_min {:?}", token_out_amount_min);
+    let cli = Cli::parse();
+    let config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::load_default()?,
+    };
+    if let Command::Keys(command) = cli.command {
+        let output = keys(&Keystore::open_default(), command)?;
+        if cli.json {
+            println!("{}", serde_json::to_string_pretty(&output.json)?);
+        } else {
+            print!("{}", output.text);
+        }
+        return Ok(());
+    }
+    let profile = config.profile(cli.profile.as_deref())?;
+    let mut client = DEXClient::from_config(&profile)?;
+    if let Some(fee_payer) = load_fee_payer(cli.fee_payer.as_deref(), &profile)? {
+        client = client.with_fee_payer(fee_payer);
+    }
+    let load_user = || load_authority(cli.keypair.as_deref(), &profile);
+    let authority = |needs_signer: bool| -> CliResult<Authority> {
+        match cli.authority {
+            Some(pubkey) if !needs_signer => Ok(Authority { pubkey, signer: None }),
+            _ => {
+                let signer = load_user()?;
+                if cli.authority.is_some_and(|pubkey| pubkey != signer.pubkey()) {
+                    return Err(format!("--authority does not match the signer {}", signer.pubkey()).into());
+                }
+                Ok(Authority { pubkey: signer.pubkey(), signer: Some(signer) })
+            }
+        }
+    };
 
-    
-    let user_token_a_account = orca_client.get_or_create_associated_token_account(&user, &solana_sdk::pubkey::Pubkey::try_from_slice(&whirlpool.token_mint_a.to_bytes()).unwrap()).await?;
+    let output = match cli.command {
+        Command::Quote(swap) if swap.route || swap.all_tiers => route_quote(&profile, &client, &swap).await?.1,
+        Command::Quote(swap) => {
+            #[cfg_attr(not(feature = "jupiter"), allow(unused_variables))]
+            let ((_, quote, token_in, token_out), output) = quote(&profile, &client, &swap).await?;
+            #[cfg(feature = "jupiter")]
+            let output = if swap.jupiter { compare_jupiter(&profile, &quote, &token_in, &token_out, swap.slippage_bps, output).await? } else { output };
+            output
+        }
+        Command::Swap { swap, send } => swap_command(&profile, &client, &authority(send.needs_signer())?, &swap, &send).await?,
+        Command::Arbitrage { arbitrage: args, send } => {
+            let user = if args.execute { authority(send.needs_signer())? } else { authority(false)? };
+            arbitrage(&profile, &client, &user, &args, &send).await?
+        }
+        Command::Pool(PoolCommand::Info { pool }) => pool_info(&profile, &client, &pool).await?,
+        Command::Balances { owner, tokens } => {
+            let owner = match owner {
+                Some(owner) => owner,
+                None => authority(false)?.pubkey,
+            };
+            balances(&profile, &client, &owner, &tokens).await?
+        }
+        Command::TickArrays { pool, count } => tick_arrays(&profile, &client, &pool, count).await?,
+        Command::Ata(command) => {
+            let needs_signer = match &command {
+                AtaCommand::Create { send, .. } | AtaCommand::Close { send, .. } => send.needs_signer(),
+            };
+            ata(&profile, &client, &authority(needs_signer)?, command).await?
+        }
+        Command::Sign { file, signature } => {
+            let signer = if signature.is_empty() { Some(load_user()?) } else { None };
+            sign(&file, signer.as_deref(), &signature)?
+        }
+        Command::Send { file } => send(&client, &file).await?,
+        Command::Endpoints => endpoints(&client).await,
+        Command::Nonce(NonceCommand::Show { address }) => nonce_output(&client.get_nonce_info(&address).await?, "nonce account"),
+        Command::Nonce(command) => {
+            let user = authority(true)?;
+            let signer = user.signer.as_deref().ok_or("no signer loaded")?;
+            match command {
+                NonceCommand::Create => {
+                    let nonce_account = Keypair::new();
+                    nonce_output(&client.create_nonce_account(signer, &nonce_account, &user.pubkey).await?, "created nonce account")
+                }
+                NonceCommand::Advance { address } => nonce_output(&client.advance_nonce_account(signer, &address).await?, "advanced nonce account"),
+                NonceCommand::Show { .. } => unreachable!("needs no signer"),
+            }
+        }
+        Command::Keys(_) => unreachable!("handled before connecting"),
+    };
 
-    let user_token_b_account = orca_client.get_or_create_associated_token_account(&user, &solana_sdk::pubkey::Pubkey::try_from_slice(&whirlpool.token_mint_b.to_bytes()).unwrap()).await?;
-    
-   
-    let tick_arrays = poolutil_get_tick_array_pubkeys_for_swap(
-        whirlpool.tick_current_index,
-        whirlpool.tick_spacing,
-        a_to_b,
-        &dex_address,
-        &pool_address,
-    );
-    
-    let token_vault_a = solana_sdk::pubkey::Pubkey::try_from_slice(&whirlpool.token_vault_a.to_bytes()).unwrap();
-    let token_vault_b = solana_sdk::pubkey::Pubkey::try_from_slice(&whirlpool.token_vault_b.to_bytes()).unwrap();
-    let tick_array_0 = tick_arrays[0];
-    let tick_array_1 = tick_arrays[1];
-    let tick_array_2 = tick_arrays[2];
-    let (oracle, _) = generate_oracle_pda(pool_address, dex_address);
-    
-    let token_a_balance_before = orca_client.get_token_balance(&user_token_a_account).await?;
-    let token_b_balance_before = orca_client.get_token_balance(&user_token_b_account).await?;
-
-    if a_to_b {
-        println!("token_a_balance_before : {}", token_a_balance_before);
-        println!("token_b_balance_before : {}", token_b_balance_before);
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&output.json)?);
     } else {
-        println!("token_a_balance_before : {}", token_b_balance_before);
-        println!("token_b_balance_before : {}", token_a_balance_before);
+        print!("{}", output.text);
     }
+    Ok(())
+}
 
+/// A pool name from the profile, or a whirlpool address.
+fn resolve_pool(profile: &ClusterConfig, pool: &str) -> CliResult<Pubkey> {
+    match profile.pool(pool) {
+        Ok(pool) => pool.address(),
+        Err(_) => Pubkey::from_str(pool).map_err(|_| format!("{} is neither a configured pool nor an address", pool).into()),
+    }
+}
 
-    let orca_swap_program_id = solana_sdk::pubkey::Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
-    let token_program = spl_token::id();
-
-    
-
-    let accounts = vec![
-        AccountMeta::new(token_program, false),
-        AccountMeta::new(user.pubkey(), true),
-        AccountMeta::new(*pool_address, false),
-        AccountMeta::new(user_token_a_account, false),
-        AccountMeta::new(token_vault_a, false),
-        AccountMeta::new(user_token_b_account, false),
-        AccountMeta::new(token_vault_b, false),
-        AccountMeta::new(tick_array_0, false),
-        AccountMeta::new(tick_array_1, false),
-        AccountMeta::new(tick_array_2, false),
-        AccountMeta::new(oracle, false),
-    ];
-
-    let swap_data = SwapData {
-        amount: swap_token_data.amount,
-        other_amount_threshold: token_out_amount_min,
-        sqrt_price_limit: 0,
-        amount_specified_is_input: true,
-        a_to_b,
+/// The configured pool trading `token_in` against `token_out`.
+fn find_pool(profile: &ClusterConfig, token_in: &TokenInfo, token_out: &TokenInfo) -> CliResult<Pubkey> {
+    let mints = |pool: &PoolConfig| -> Option<(Pubkey, Pubkey)> {
+        Some((profile.token(&pool.token_a).ok()?.mint().ok()?, profile.token(&pool.token_b).ok()?.mint().ok()?))
     };
+    let pair = [(token_in.mint, token_out.mint), (token_out.mint, token_in.mint)];
+    match profile.pools.values().find(|pool| mints(pool).is_some_and(|mints| pair.contains(&mints))) {
+        Some(pool) => pool.address(),
+        None => Err(format!("no configured pool for {}/{}, pass --pool", token_in.label(), token_out.label()).into()),
+    }
+}
 
-    let data = {
-        let mut prefix = vec![248, 198, 158, 145, 225, 117, 135, 200];
-        prefix.extend(swap_data.try_to_vec()?);
-        prefix
+/// The pool at `pool_address`, loaded, of whichever venue's program owns it.
+async fn load_pool(profile: &ClusterConfig, client: &DEXClient, pool_address: &Pubkey, init_tick_arrays: bool) -> CliResult<Box<dyn Pool>> {
+    let owner = client.rpc().get_account(pool_address).await?.owner;
+    let mut pool: Box<dyn Pool> = if owner == profile.whirlpool_program_id()? {
+        Box::new(WhirlpoolPool::new(*pool_address, owner).with_initialize_tick_arrays(init_tick_arrays))
+    } else if owner == profile.raydium_clmm_program_id()? {
+        Box::new(RaydiumClmmPool::new(*pool_address, owner))
+    } else if owner == profile.raydium_amm_program_id()? {
+        Box::new(RaydiumAmmPool::new(*pool_address, owner))
+    } else if owner == profile.meteora_dlmm_program_id()? {
+        Box::new(MeteoraDlmmPool::new(*pool_address, owner))
+    } else if profile.phoenix_program_id()? == Some(owner) {
+        Box::new(PhoenixPool::new(*pool_address, owner))
+    } else {
+        return Err(format!("{} is owned by {}, which is not a supported dex program", pool_address, owner).into());
     };
+    client.load_pool(pool.as_mut()).await?;
+    Ok(pool)
+}
 
-    Ok(Instruction {
-        program_id: orca_swap_program_id,
-        accounts,
-        data,
-    })
+async fn quote(profile: &ClusterConfig, client: &DEXClient, args: &SwapArgs) -> CliResult<((Box<dyn Pool>, PoolQuote, TokenInfo, TokenInfo), Output)> {
+    let token_in = resolve_token(profile, client.rpc(), &args.token_in).await?;
+    let token_out = resolve_token(profile, client.rpc(), &args.token_out).await?;
+    let pool_address = match &args.pool {
+        Some(pool) => resolve_pool(profile, pool)?,
+        None => find_pool(profile, &token_in, &token_out)?,
+    };
+    let amount = ui_amount_to_raw(&args.amount, token_in.decimals)?;
 
+    let pool = load_pool(profile, client, &pool_address, args.init_tick_arrays).await?;
+    let a_to_b = pool.a_to_b(&token_in.mint)?;
+    let quote = pool.quote(amount, a_to_b)?;
+    if token_out.mint != quote.mint_out {
+        return Err(format!("pool {} does not trade {} for {}", pool_address, token_in.label(), token_out.label()).into());
+    }
+    let min_amount_out = quote.min_amount_out(args.slippage_bps);
+
+    // both prices in output tokens per input token
+    let (decimals_a, decimals_b) = if a_to_b { (token_in.decimals, token_out.decimals) } else { (token_out.decimals, token_in.decimals) };
+    let spot_price = pool.price()? * Decimal::TEN.powi((decimals_a - decimals_b) as i64);
+    let spot_price = if a_to_b { spot_price } else { Decimal::ONE / spot_price };
+    let amount_in_ui = Decimal::from_str(&raw_amount_to_ui(quote.amount_in, token_in.decimals))?;
+    let amount_out_ui = Decimal::from_str(&raw_amount_to_ui(quote.amount_out, token_out.decimals))?;
+    let price = if amount_in_ui.is_zero() { Decimal::ZERO } else { amount_out_ui / amount_in_ui };
+    let price_impact_pct = if spot_price.is_zero() { Decimal::ZERO } else { (Decimal::ONE - price / spot_price) * Decimal::ONE_HUNDRED };
+
+    let json = json!({
+        "pool": pool_address.to_string(),
+        "dex": pool.dex(),
+        "token_in": token_in.mint.to_string(),
+        "token_out": token_out.mint.to_string(),
+        "amount_in": quote.amount_in,
+        "amount_out": quote.amount_out,
+        "min_amount_out": min_amount_out,
+        "fee_amount": quote.fee_amount,
+        "price": price.round_dp(12).normalize().to_string(),
+        "spot_price": spot_price.round_dp(12).normalize().to_string(),
+        "price_impact_pct": price_impact_pct.round_dp(4).normalize().to_string(),
+        "legs": quote.legs,
+        "accounts_to_initialize": quote.accounts_to_initialize.iter().map(|pubkey| pubkey.to_string()).collect::<Vec<_>>(),
+    });
+    let mut text = format!(
+        "pool        {} ({})\nin          {} {}\nout         {} {}\nmin out     {} {} ({} bps slippage)\nfee         {} {}\nprice       {} (spot {}, impact {}%)\n",
+        pool_address,
+        pool.dex(),
+        raw_amount_to_ui(quote.amount_in, token_in.decimals),
+        token_in.label(),
+        raw_amount_to_ui(quote.amount_out, token_out.decimals),
+        token_out.label(),
+        raw_amount_to_ui(min_amount_out, token_out.decimals),
+        token_out.label(),
+        args.slippage_bps,
+        raw_amount_to_ui(quote.fee_amount, token_in.decimals),
+        token_in.label(),
+        price.round_dp(6).normalize(),
+        spot_price.round_dp(6).normalize(),
+        price_impact_pct.round_dp(4).normalize(),
+    );
+    if quote.legs > 1 {
+        text += &format!("legs        {}\n", quote.legs);
+    }
+    for pubkey in &quote.accounts_to_initialize {
+        text += &format!("initializes {}\n", pubkey);
+    }
 
+    Ok(((pool, quote, token_in, token_out), Output { json, text }))
 }
 
+/// Every configured pool, loaded, to route through.
+async fn load_router(profile: &ClusterConfig, client: &DEXClient, init_tick_arrays: bool) -> CliResult<Router> {
+    let mut pools = Vec::new();
+    for pool in profile.pools.values() {
+        pools.push(load_pool(profile, client, &pool.address()?, init_tick_arrays).await?);
+    }
+    Ok(Router::new(pools))
+}
 
-#[tokio::main]
-async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
-
-    dotenv().ok();
+/// Every whirlpool of the pair, found from the whirlpools config of `--pool` or of the
+/// configured pool of the pair.
+async fn load_whirlpool_tiers(profile: &ClusterConfig, client: &DEXClient, args: &SwapArgs, token_in: &TokenInfo, token_out: &TokenInfo) -> CliResult<Router> {
+    let pool_address = match &args.pool {
+        Some(pool) => resolve_pool(profile, pool)?,
+        None => find_pool(profile, token_in, token_out)?,
+    };
+    let whirlpools_config = Pubkey::new_from_array(client.get_whirlpool(&pool_address).await?.whirlpools_config.to_bytes());
+    let mut pools = Vec::new();
+    for address in client.find_whirlpools(&profile.whirlpool_program_id()?, &whirlpools_config, &token_in.mint, &token_out.mint).await? {
+        pools.push(load_pool(profile, client, &address, args.init_tick_arrays).await?);
+    }
+    Ok(Router::new(pools))
+}
 
+async fn route_quote(profile: &ClusterConfig, client: &DEXClient, args: &SwapArgs) -> CliResult<((Router, RoutePlan, TokenInfo, TokenInfo), Output)> {
+    let token_in = resolve_token(profile, client.rpc(), &args.token_in).await?;
+    let token_out = resolve_token(profile, client.rpc(), &args.token_out).await?;
+    let amount = ui_amount_to_raw(&args.amount, token_in.decimals)?;
 
+    let (router, max_splits) = if args.all_tiers {
+        let router = load_whirlpool_tiers(profile, client, args, &token_in, &token_out).await?;
+        let max_splits = args.max_splits.unwrap_or(router.pools().len());
+        (router, max_splits)
+    } else {
+        (load_router(profile, client, args.init_tick_arrays).await?, args.max_splits.unwrap_or(3))
+    };
+    let plan = router.best_route(&token_in.mint, &token_out.mint, amount, max_splits.max(1))?;
+    let min_amount_out = plan.min_amount_out(args.slippage_bps);
+
+    let mut routes = Vec::new();
+    let mut text = format!(
+        "in          {} {}\nout         {} {}\nmin out     {} {} ({} bps slippage)\n",
+        raw_amount_to_ui(plan.amount_in, token_in.decimals),
+        token_in.label(),
+        raw_amount_to_ui(plan.amount_out, token_out.decimals),
+        token_out.label(),
+        raw_amount_to_ui(min_amount_out, token_out.decimals),
+        token_out.label(),
+        args.slippage_bps,
+    );
+    for route in &plan.routes {
+        let mut hops = Vec::new();
+        text += &format!("route       {} {}\n", raw_amount_to_ui(route.amount_in(), token_in.decimals), token_in.label());
+        for hop in &route.hops {
+            let dex = router.pool(&hop.pool).map_or("", |pool| pool.dex());
+            hops.push(json!({
+                "pool": hop.pool.to_string(),
+                "dex": dex,
+                "token_in": hop.mint_in.to_string(),
+                "token_out": hop.mint_out.to_string(),
+                "amount_in": hop.amount_in,
+                "amount_out": hop.amount_out,
+                "fee_amount": hop.fee_amount,
+            }));
+            text += &format!("  {} ({}) {} -> {} of {}\n", hop.pool, dex, hop.amount_in, hop.amount_out, hop.mint_out);
+        }
+        routes.push(json!({ "amount_in": route.amount_in(), "amount_out": route.amount_out(), "hops": hops }));
+    }
+    let json = json!({
+        "token_in": token_in.mint.to_string(),
+        "token_out": token_out.mint.to_string(),
+        "amount_in": plan.amount_in,
+        "amount_out": plan.amount_out,
+        "min_amount_out": min_amount_out,
+        "routes": routes,
+    });
+
+    Ok(((router, plan, token_in, token_out), Output { json, text }))
+}
 
-    let private_key = env::vars()
-        .find(|(key, _)| key == "PRIVATE_KEY")
-        .map(|(_, value)| value)
-        .ok_or("PRIVATE_KEY not found")?;
+async fn swap_command(profile: &ClusterConfig, client: &DEXClient, user: &Authority, args: &SwapArgs, send: &SendArgs) -> CliResult<Output> {
+    #[cfg(feature = "jupiter")]
+    if args.jupiter {
+        // Jupiter wraps and unwraps SOL itself
+        let ((quote, instructions, token_in, token_out), output) = jupiter_swap(profile, client, user, args).await?;
+        return send_and_settle(client, user, instructions, send, &quote, &token_in, &token_out, output).await;
+    }
 
-    let user: Keypair = Keypair::from_base58_string(&private_key.as_str());
-    println!("Initialized user wallet: {}", user.pubkey());
+    let (mut instructions, quote, token_in, token_out, output) = if args.route || args.all_tiers {
+        let ((router, plan, token_in, token_out), output) = route_quote(profile, client, args).await?;
+        let instructions = client.build_route_instructions(&user.pubkey, &router, &plan, args.slippage_bps).await?;
+        // settlement only compares what went in and came out, whichever pools were in between
+        let quote = PoolQuote {
+            pool: plan.routes[0].hops[0].pool,
+            a_to_b: plan.routes[0].hops[0].a_to_b,
+            mint_in: plan.mint_in,
+            mint_out: plan.mint_out,
+            amount_in: plan.amount_in,
+            amount_out: plan.amount_out,
+            fee_amount: 0,
+            legs: plan.routes.iter().map(|route| route.hops.len()).sum(),
+            accounts_to_initialize: Vec::new(),
+        };
+        (instructions, quote, token_in, token_out, output)
+    } else {
+        let ((pool, quote, token_in, token_out), output) = quote(profile, client, args).await?;
+        let instructions = client.build_swap_instructions(&user.pubkey, pool.as_ref(), &quote, quote.min_amount_out(args.slippage_bps)).await?;
+        (instructions, quote, token_in, token_out, output)
+    };
 
-    let rpc_url = env::vars()
-    .find(|(key, _)| key == "RPC_URL")
-    .map(|(_, value)| value)
-    .ok_or("RPC_URL not found")?;
+    wrap_sol(&user.pubkey, &mut instructions, &token_in.mint, quote.amount_in)?;
+    send_and_settle(client, user, instructions, send, &quote, &token_in, &token_out, output).await
+}
 
+/// Adds Jupiter's quote for the same trade as `quote` to `output`, and how much more or less it
+/// gives.
+#[cfg(feature = "jupiter")]
+async fn compare_jupiter(profile: &ClusterConfig, quote: &PoolQuote, token_in: &TokenInfo, token_out: &TokenInfo, slippage_bps: u16, mut output: Output) -> CliResult<Output> {
+    let jupiter = JupiterClient::new(profile.jupiter_url()).quote(&quote.mint_in, &quote.mint_out, quote.amount_in, slippage_bps).await.map_err(|err| err.to_string())?;
+    let difference_bps = if quote.amount_out == 0 {
+        Decimal::ZERO
+    } else {
+        (Decimal::from(jupiter.amount_out) - Decimal::from(quote.amount_out)) / Decimal::from(quote.amount_out) * Decimal::from(10_000)
+    };
+    output.json["jupiter"] = json!({
+        "amount_in": jupiter.amount_in,
+        "amount_out": jupiter.amount_out,
+        "min_amount_out": jupiter.min_amount_out,
+        "price_impact_pct": jupiter.price_impact_pct.normalize().to_string(),
+        "route": jupiter.route.iter().map(|step| json!({ "amm": step.amm_key.to_string(), "label": step.label, "percent": step.percent })).collect::<Vec<_>>(),
+        "difference_bps": difference_bps.round_dp(2).normalize().to_string(),
+    });
+    output.text += &format!(
+        "jupiter     {} {} via {} ({} bps against ours)\n",
+        raw_amount_to_ui(jupiter.amount_out, token_out.decimals),
+        token_out.label(),
+        jupiter.labels().join(", "),
+        difference_bps.round_dp(2).normalize(),
+    );
+    if jupiter.amount_in != quote.amount_in {
+        output.text += &format!("  for       {} {}\n", raw_amount_to_ui(jupiter.amount_in, token_in.decimals), token_in.label());
+    }
+    Ok(output)
+}
 
-    // 准备兑换数据
+/// Jupiter's route for the trade of `args` and its instructions, with a quote summing it up.
+#[cfg(feature = "jupiter")]
+async fn jupiter_swap(profile: &ClusterConfig, client: &DEXClient, user: &Authority, args: &SwapArgs) -> CliResult<((PoolQuote, Vec<Instruction>, TokenInfo, TokenInfo), Output)> {
+    let token_in = resolve_token(profile, client.rpc(), &args.token_in).await?;
+    let token_out = resolve_token(profile, client.rpc(), &args.token_out).await?;
+    let amount = ui_amount_to_raw(&args.amount, token_in.decimals)?;
+
+    let jupiter = JupiterClient::new(profile.jupiter_url());
+    let route = jupiter.quote(&token_in.mint, &token_out.mint, amount, args.slippage_bps).await.map_err(|err| err.to_string())?;
+    let instructions = jupiter.swap_instructions(&route, &user.pubkey).await.map_err(|err| err.to_string())?.instructions();
+    let first_step = route.route.first().ok_or("jupiter returned an empty route")?;
+    let quote = PoolQuote {
+        pool: first_step.amm_key,
+        a_to_b: true,
+        mint_in: route.mint_in,
+        mint_out: route.mint_out,
+        amount_in: route.amount_in,
+        amount_out: route.amount_out,
+        fee_amount: 0,
+        legs: route.route.len(),
+        accounts_to_initialize: Vec::new(),
+    };
 
-    const POOL_ADDRESS: &str = WSOL_USDC_3000;
+    let json = json!({
+        "dex": "jupiter",
+        "token_in": token_in.mint.to_string(),
+        "token_out": token_out.mint.to_string(),
+        "amount_in": route.amount_in,
+        "amount_out": route.amount_out,
+        "min_amount_out": route.min_amount_out,
+        "price_impact_pct": route.price_impact_pct.normalize().to_string(),
+        "route": route.route.iter().map(|step| json!({ "amm": step.amm_key.to_string(), "label": step.label, "percent": step.percent })).collect::<Vec<_>>(),
+    });
+    let text = format!(
+        "jupiter     via {}\nin          {} {}\nout         {} {}\nmin out     {} {} ({} bps slippage)\n",
+        route.labels().join(", "),
+        raw_amount_to_ui(route.amount_in, token_in.decimals),
+        token_in.label(),
+        raw_amount_to_ui(route.amount_out, token_out.decimals),
+        token_out.label(),
+        raw_amount_to_ui(route.min_amount_out, token_out.decimals),
+        token_out.label(),
+        args.slippage_bps,
+    );
+    Ok(((quote, instructions, token_in, token_out), Output { json, text }))
+}
 
-    let amount = 3000_00; // token_in : usdc_amount
-    let slippage = 10;
+/// Sends the swap `quote` is for, unless asked not to, and adds how it settled to `output`.
+#[allow(clippy::too_many_arguments)]
+async fn send_and_settle(
+    client: &DEXClient,
+    user: &Authority,
+    instructions: Vec<Instruction>,
+    send: &SendArgs,
+    quote: &PoolQuote,
+    token_in: &TokenInfo,
+    token_out: &TokenInfo,
+    mut output: Output,
+) -> CliResult<Output> {
+    if let Some(signature) = send_or_print(client, user, instructions, send, &mut output).await? {
+        // the swap went through either way, so a settlement that can't be read isn't an error
+        match client.get_swap_settlement(&signature, &user.pubkey, quote).await {
+            Ok(settlement) => {
+                let ui = |amount: u64, decimals: u8| raw_amount_to_ui(amount, decimals as i8);
+                output.json["settlement"] = json!({
+                    "slot": settlement.slot,
+                    "amount_in": ui(settlement.amount_in, settlement.decimals_in).to_string(),
+                    "amount_out": ui(settlement.amount_out, settlement.decimals_out).to_string(),
+                    "amount_in_raw": settlement.amount_in,
+                    "amount_out_raw": settlement.amount_out,
+                    "fee_lamports": settlement.fee_lamports,
+                    "sol_change_lamports": settlement.sol_change,
+                    "effective_price": settlement.effective_price.to_string(),
+                    "quoted_price": settlement.quoted_price().to_string(),
+                    "slippage_bps": settlement.slippage_bps,
+                });
+                output.text += &format!("settled     slot {}\n", settlement.slot);
+                output.text += &format!("  paid      {} {}\n", ui(settlement.amount_in, settlement.decimals_in), token_in.label());
+                output.text += &format!("  received  {} {}\n", ui(settlement.amount_out, settlement.decimals_out), token_out.label());
+                output.text += &format!("  fee       {} SOL\n", ui(settlement.fee_lamports, 9));
+                output.text += &format!("  price     {} (quoted {})\n", settlement.effective_price.normalize(), settlement.quoted_price().normalize());
+                output.text += &format!("  slippage  {} bps\n", settlement.slippage_bps);
+            }
+            Err(err) => {
+                output.json["settlement"] = Value::Null;
+                output.text += &format!("settlement  unavailable: {}\n", err);
+            }
+        }
+    }
+    Ok(output)
+}
 
+async fn arbitrage(profile: &ClusterConfig, client: &DEXClient, user: &Authority, args: &ArbitrageArgs, send: &SendArgs) -> CliResult<Output> {
+    let token = resolve_token(profile, client.rpc(), &args.token).await?;
+    let config = ArbitrageConfig {
+        max_hops: args.max_hops,
+        max_amount_in: match &args.max_amount {
+            Some(amount) => ui_amount_to_raw(amount, token.decimals)?,
+            None => u64::MAX,
+        },
+        min_profit: ui_amount_to_raw(&args.min_profit, token.decimals)?,
+        compute_unit_limit: args.compute_unit_limit,
+        compute_unit_price: args.compute_unit_price,
+    };
+    let router = load_router(profile, client, false).await?;
+    let opportunities = find_arbitrage(router.pools(), &token.mint, &config);
+
+    let ui = |amount: u64| raw_amount_to_ui(amount, token.decimals);
+    let ui_signed = |amount: Option<i64>| match amount {
+        Some(amount) if amount < 0 => format!("-{}", ui(amount.unsigned_abs())),
+        Some(amount) => ui(amount as u64).to_string(),
+        None => "unknown".to_string(),
+    };
+    let mut json_opportunities = Vec::new();
+    let mut text = String::new();
+    if opportunities.is_empty() {
+        text += &format!("no cycle from {} gains after pool fees\n", token.label());
+    }
+    for opportunity in &opportunities {
+        let mut path = token.label();
+        for hop in &opportunity.hops {
+            let symbol = profile.tokens.iter().find(|(_, info)| info.mint().ok() == Some(hop.mint_out)).map(|(symbol, _)| symbol.clone());
+            path += &format!(" -> {}", symbol.unwrap_or_else(|| hop.mint_out.to_string()));
+        }
+        text += &format!(
+            "{}\n  in {}, out {}, gross {}, network fee {} lamports, net {} {}\n",
+            path,
+            ui(opportunity.amount_in),
+            ui(opportunity.amount_out),
+            ui(opportunity.gross_profit),
+            opportunity.network_fee_lamports,
+            ui_signed(opportunity.net_profit),
+            token.label(),
+        );
+        json_opportunities.push(json!({
+            "pools": opportunity.hops.iter().map(|hop| hop.pool.to_string()).collect::<Vec<_>>(),
+            "mints": opportunity.hops.iter().map(|hop| hop.mint_out.to_string()).collect::<Vec<_>>(),
+            "spot_rate": opportunity.cycle.rate,
+            "amount_in": opportunity.amount_in,
+            "amount_out": opportunity.amount_out,
+            "gross_profit": opportunity.gross_profit,
+            "network_fee_lamports": opportunity.network_fee_lamports,
+            "net_profit": opportunity.net_profit,
+        }));
+    }
+    let mut output = Output { json: json!({ "token": token.mint.to_string(), "opportunities": json_opportunities }), text };
 
-    let token_in = solana_sdk::pubkey::Pubkey::from_str(USDC_ADDRESS).unwrap();
-    let token_out = solana_sdk::pubkey::Pubkey::from_str(WSOL_ADDRESS).unwrap();
+    if args.execute {
+        let Some(opportunity) = opportunities.iter().find(|opportunity| opportunity.net_profit.is_some_and(|profit| profit > 0 && profit as u64 >= config.min_profit)) else {
+            return Err(format!("no cycle nets at least {} {}", ui(config.min_profit), token.label()).into());
+        };
+        let mut instructions = client.build_arbitrage_instructions(&user.pubkey, router.pools(), opportunity, &config).await?;
+        wrap_sol(&user.pubkey, &mut instructions, &token.mint, opportunity.amount_in)?;
+        send_or_print(client, user, instructions, send, &mut output).await?;
+    }
+    Ok(output)
+}
 
-    let pool_address = solana_sdk::pubkey::Pubkey::from_str(POOL_ADDRESS).unwrap();
-    let dex_address = solana_sdk::pubkey::Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
+/// Wraps `amount_in` SOL right before `instructions` swap it, when `mint_in` is wrapped SOL,
+/// and closes a wrapped SOL account they create so whatever it holds ends up back in the wallet.
+fn wrap_sol(user: &Pubkey, instructions: &mut Vec<Instruction>, mint_in: &Pubkey, amount_in: u64) -> CliResult<()> {
+    let native_mint = spl_token::native_mint::id();
+    let wsol_account = get_associated_token_address(user, &native_mint);
+    let creates_wsol_account = instructions
+        .iter()
+        .any(|instruction| instruction.program_id == spl_associated_token_account::id() && instruction.accounts[1].pubkey == wsol_account);
+    if *mint_in == native_mint {
+        let at = instructions
+            .iter()
+            .position(|instruction| instruction.program_id != spl_associated_token_account::id())
+            .unwrap_or(instructions.len());
+        instructions.splice(
+            at..at,
+            [
+                solana_sdk::system_instruction::transfer(user, &wsol_account, amount_in),
+                spl_token::instruction::sync_native(&spl_token::id(), &wsol_account)?,
+            ],
+        );
+    }
+    if creates_wsol_account {
+        instructions.push(spl_token::instruction::close_account(&spl_token::id(), &wsol_account, user, user, &[])?);
+    }
+    Ok(())
+}
 
-    let swap_token_data = SwapTokenData::new(amount, token_in, token_out, USDC_DECIMALS, WSOL_DECIMALS, slippage);
+/// Sends `instructions` unless asked not to, and returns the signature sent.
+async fn send_or_print(client: &DEXClient, user: &Authority, instructions: Vec<Instruction>, send: &SendArgs, output: &mut Output) -> CliResult<Option<Signature>> {
+    let dry_run = send.dry_run;
+    let summary: Vec<Value> = instructions
+        .iter()
+        .map(|instruction| json!({ "program_id": instruction.program_id.to_string(), "accounts": instruction.accounts.len(), "data_len": instruction.data.len() }))
+        .collect();
+    output.json["instructions"] = Value::from(summary);
+    output.json["dry_run"] = Value::from(dry_run);
+
+    if dry_run {
+        output.text += &format!("dry run, {} instructions not sent:\n", instructions.len());
+        for instruction in &instructions {
+            output.text += &format!("  {} ({} accounts, {} bytes)\n", instruction.program_id, instruction.accounts.len(), instruction.data.len());
+        }
+        return Ok(None);
+    }
 
+    if let Some(path) = &send.sign_offline {
+        let transaction = match &send.nonce {
+            Some(nonce_account) => client.build_offline_transaction_with_nonce(&user.pubkey, &instructions, nonce_account).await?,
+            None => client.build_offline_transaction(&user.pubkey, &instructions).await?,
+        };
+        transaction.save(path)?;
+        output.json["offline_transaction"] = Value::from(path.display().to_string());
+        output.json["missing_signers"] = Value::from(transaction.missing_signers());
+        output.text += &format!("unsigned transaction written to {}, to be signed by {}\n", path.display(), transaction.missing_signers().join(", "));
+        return Ok(None);
+    }
 
-    // 1. new 一个client
+    let signer = user.signer.as_deref().ok_or("no signer loaded")?;
+    let commitment = send.commitment.unwrap_or(client.commitment());
+    if send.track {
+        let (signature, mut events) = client.send_instructions_tracked(signer, instructions, commitment).await?;
+        output.json["signature"] = Value::from(signature.to_string());
+        output.text += &format!("signature   {}\n", signature);
+
+        let mut json_events = Vec::new();
+        let mut reached = None;
+        while let Some(event) = events.recv().await {
+            let status = event.err.as_ref().map_or("ok".to_string(), |err| format!("failed: {}", err));
+            output.text += &format!("{:<11} slot {} after {} ms, {}\n", format!("{:?}", event.commitment).to_lowercase(), event.slot, event.latency.as_millis(), status);
+            json_events.push(json!({
+                "commitment": format!("{:?}", event.commitment).to_lowercase(),
+                "slot": event.slot,
+                "latency_ms": event.latency.as_millis() as u64,
+                "err": event.err.as_ref().map(|err| err.to_string()),
+            }));
+            reached = Some(event.commitment);
+        }
+        output.json["confirmations"] = Value::from(json_events);
+        if reached != Some(commitment) {
+            return Err(format!("{} did not reach {:?} in time", signature, commitment).into());
+        }
+        return Ok(Some(signature));
+    }
 
+    #[cfg(feature = "jito")]
+    if send.jito {
+        let landed = client.send_bundle(signer, vec![instructions], commitment).await?;
+        output.json["signature"] = Value::from(landed.signatures[0].to_string());
+        output.json["bundle_id"] = Value::from(landed.bundle_id.clone());
+        output.json["fell_back"] = Value::from(landed.fell_back);
+        output.json["drop_reason"] = Value::from(landed.drop_reason.clone());
+        output.text += &format!("signature   {}\n", landed.signatures[0]);
+        output.text += &match (&landed.bundle_id, landed.fell_back) {
+            (_, true) => format!("bundle      dropped ({}), sent without it\n", landed.drop_reason.as_deref().unwrap_or("no reason given")),
+            (Some(bundle_id), false) => format!("bundle      {}\n", bundle_id),
+            (None, false) => "bundle      landed, id unknown\n".to_string(),
+        };
+        return Ok(Some(landed.signatures[0]));
+    }
 
-    let client = DEXClient::new(&rpc_url);
-    println!("Connected to Solana mainnet");
+    let signature = client.send_instructions_with_commitment(signer, instructions, commitment).await?;
+    output.json["signature"] = Value::from(signature.clone());
+    output.text += &format!("signature   {}\n", signature);
+    Ok(Some(Signature::from_str(&signature)?))
+}
 
+/// Adds `signatures` given as `PUBKEY=SIGNATURE` to the transaction in `path`, or signs it with
+/// `signer`.
+fn sign(path: &Path, signer: Option<&(dyn Signer + Send + Sync)>, signatures: &[String]) -> CliResult<Output> {
+    let mut transaction = OfflineTransaction::load(path)?;
+    if let Some(signer) = signer {
+        transaction.sign(signer)?;
+    }
+    for signature in signatures {
+        let (pubkey, signature) = signature.split_once('=').ok_or_else(|| format!("{} is not PUBKEY=SIGNATURE", signature))?;
+        transaction.add_signature(&Pubkey::from_str(pubkey)?, &Signature::from_str(signature)?)?;
+    }
+    transaction.save(path)?;
 
+    let missing_signers = transaction.missing_signers();
+    let text = if missing_signers.is_empty() {
+        format!("{} is fully signed\n", path.display())
+    } else {
+        format!("{} still needs signatures of {}\n", path.display(), missing_signers.join(", "))
+    };
+    Ok(Output { json: json!({ "file": path.display().to_string(), "missing_signers": missing_signers }), text })
+}
 
-    // 2. 用client拉取数据并且构建指令
+fn keys(keystore: &Keystore, command: KeysCommand) -> CliResult<Output> {
+    match command {
+        KeysCommand::Import { name, from } => {
+            let keypair = match from {
+                Some(source) => load_keypair(&source)?,
+                None => load_keypair(&rpassword::prompt_password("secret key (base58): ")?)?,
+            };
+            let passphrase = read_passphrase(&format!("new passphrase for key {}: ", name))?;
+            if std::env::var(KEYSTORE_PASSPHRASE_ENV).is_err() && rpassword::prompt_password("repeat passphrase: ")? != passphrase {
+                return Err("passphrases do not match".into());
+            }
+            if passphrase.is_empty() {
+                return Err("the passphrase must not be empty".into());
+            }
+            let entry = keystore.import(&name, &keypair, &passphrase)?;
+            let text = format!("imported {} as {} into {}\n", entry.pubkey, entry.name, entry.path.display());
+            Ok(Output { json: json!({ "name": entry.name, "pubkey": entry.pubkey.to_string(), "path": entry.path.display().to_string() }), text })
+        }
+        KeysCommand::Export { name, output } => {
+            let keypair: Keypair = keystore.unlock(&name, &read_passphrase(&format!("passphrase for key {}: ", name))?)?;
+            match output {
+                Some(path) => {
+                    if path.exists() {
+                        return Err(format!("{} already exists", path.display()).into());
+                    }
+                    write_keypair_file(&keypair, &path).map_err(|err| format!("{}: {}", path.display(), err))?;
+                    let text = format!("wrote {} to {}\n", keypair.pubkey(), path.display());
+                    Ok(Output { json: json!({ "name": name, "pubkey": keypair.pubkey().to_string(), "path": path.display().to_string() }), text })
+                }
+                None => {
+                    let secret_key = keypair.to_base58_string();
+                    Ok(Output { json: json!({ "name": name, "pubkey": keypair.pubkey().to_string(), "secret_key": secret_key }), text: format!("{}\n", secret_key) })
+                }
+            }
+        }
+        KeysCommand::List => {
+            let entries = keystore.list()?;
+            let json = entries.iter().map(|entry| json!({ "name": entry.name, "pubkey": entry.pubkey.to_string() })).collect::<Vec<_>>();
+            let mut text = String::new();
+            if entries.is_empty() {
+                text += &format!("no keys in {}\n", keystore.dir().display());
+            }
+            for entry in &entries {
+                text += &format!("{:<16} {}\n", entry.name, entry.pubkey);
+            }
+            Ok(Output { json: Value::from(json), text })
+        }
+    }
+}
 
-    let instruction = build_orca_pool_swap_instruction(&client, &user, &swap_token_data, &pool_address, &dex_address, slippage).await.expect("build instruction error");
-    println!(" build instruction finish ");
+async fn send(client: &DEXClient, path: &Path) -> CliResult<Output> {
+    let signature = client.send_offline_transaction(OfflineTransaction::load(path)?).await?;
+    Ok(Output { json: json!({ "signature": signature }), text: format!("signature   {}\n", signature) })
+}
 
+fn nonce_output(nonce: &NonceInfo, title: &str) -> Output {
+    Output {
+        json: json!({
+            "address": nonce.address.to_string(),
+            "authority": nonce.authority.to_string(),
+            "nonce": nonce.blockhash.to_string(),
+            "lamports_per_signature": nonce.lamports_per_signature,
+            "lamports": nonce.lamports,
+        }),
+        text: format!("{} {}\nauthority   {}\nnonce       {}\n", title, nonce.address, nonce.authority, nonce.blockhash),
+    }
+}
 
-    // let token_a_balance_after = client.get_token_balance(&user_token_a_account).await?;
-    // let token_b_balance_after = client.get_token_balance(&user_token_b_account).await?;
+async fn endpoints(client: &DEXClient) -> Output {
+    let statuses = client.rpc().check_health().await;
+    let json = statuses
+        .iter()
+        .map(|status| {
+            json!({
+                "url": status.url,
+                "healthy": status.healthy,
+                "latency_ms": status.latency.map(|latency| latency.as_millis() as u64),
+                "block_height": status.block_height,
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut text = String::new();
+    for status in &statuses {
+        let latency = status.latency.map_or("-".to_string(), |latency| format!("{}ms", latency.as_millis()));
+        let block_height = status.block_height.map_or("-".to_string(), |block_height| block_height.to_string());
+        text += &format!("{:<9} {:>7} {:>12}  {}\n", if status.healthy { "healthy" } else { "unhealthy" }, latency, block_height, status.url);
+    }
+    Output { json: Value::from(json), text }
+}
 
-    // if a_to_b {
-    //     println!("token_a_balance_after : {}", token_a_balance_before);
-    //     println!("token_a_balance_after : {}", token_b_balance_before);
-    // } else {
-    //     println!("token_a_balance_after : {}", token_b_balance_after);
-    //     println!("token_a_balance_after : {}", token_a_balance_after);
-    // }
+async fn pool_info(profile: &ClusterConfig, client: &DEXClient, pool: &str) -> CliResult<Output> {
+    let pool_address = resolve_pool(profile, pool)?;
+    let whirlpool = client.get_whirlpool(&pool_address).await?;
+    let token_a = token_info_by_mint(profile, client.rpc(), &Pubkey::new_from_array(whirlpool.token_mint_a.to_bytes())).await?;
+    let token_b = token_info_by_mint(profile, client.rpc(), &Pubkey::new_from_array(whirlpool.token_mint_b.to_bytes())).await?;
+    let vault_a = Pubkey::new_from_array(whirlpool.token_vault_a.to_bytes());
+    let vault_b = Pubkey::new_from_array(whirlpool.token_vault_b.to_bytes());
+    let vault_balances = client.get_token_balances(&[vault_a, vault_b]).await?;
+    let (vault_a_balance, vault_b_balance) = (vault_balances[0], vault_balances[1]);
+    let price = pricemath_sqrt_price_x64_to_price(whirlpool.sqrt_price, token_a.decimals, token_b.decimals);
+    let price = Decimal::from_str(&price)?.round_dp(12).normalize();
+
+    let json = json!({
+        "address": pool_address.to_string(),
+        "token_a": { "mint": token_a.mint.to_string(), "symbol": token_a.symbol, "decimals": token_a.decimals, "vault": vault_a.to_string(), "vault_balance": vault_a_balance },
+        "token_b": { "mint": token_b.mint.to_string(), "symbol": token_b.symbol, "decimals": token_b.decimals, "vault": vault_b.to_string(), "vault_balance": vault_b_balance },
+        "tick_spacing": whirlpool.tick_spacing,
+        "fee_rate": whirlpool.fee_rate,
+        "protocol_fee_rate": whirlpool.protocol_fee_rate,
+        "liquidity": whirlpool.liquidity.to_string(),
+        "sqrt_price": whirlpool.sqrt_price.to_string(),
+        "tick_current_index": whirlpool.tick_current_index,
+        "price": price.to_string(),
+    });
+    let vault_text = |balance: Option<u64>, token: &TokenInfo| balance.map_or("-".to_string(), |balance| format!("{} {}", raw_amount_to_ui(balance, token.decimals), token.label()));
+    let text = format!(
+        "address      {}\ntoken a      {} ({} decimals), vault {}\ntoken b      {} ({} decimals), vault {}\ntick spacing {}\nfee rate     {}%\nliquidity    {}\ncurrent tick {}\nprice        {} {} per {}\n",
+        pool_address,
+        token_a.label(),
+        token_a.decimals,
+        vault_text(vault_a_balance, &token_a),
+        token_b.label(),
+        token_b.decimals,
+        vault_text(vault_b_balance, &token_b),
+        whirlpool.tick_spacing,
+        Decimal::new(whirlpool.fee_rate as i64, 4).normalize(),
+        whirlpool.liquidity,
+        whirlpool.tick_current_index,
+        price,
+        token_b.label(),
+        token_a.label(),
+    );
+    Ok(Output { json, text })
+}
 
+async fn balances(profile: &ClusterConfig, client: &DEXClient, owner: &Pubkey, tokens: &[String]) -> CliResult<Output> {
+    let symbols: Vec<String> = if tokens.is_empty() { profile.tokens.keys().cloned().collect() } else { tokens.to_vec() };
+    let (_, accounts) = client.rpc().get_multiple_accounts(&[*owner]).await?;
+    let lamports = accounts.into_iter().flatten().next().map_or(0, |account| account.lamports);
+
+    let mut json_tokens = Vec::new();
+    let mut text = format!("owner  {}\nSOL    {}\n", owner, raw_amount_to_ui(lamports, 9));
+    for symbol in &symbols {
+        let token = resolve_token(profile, client.rpc(), symbol).await?;
+        let token_account = get_associated_token_address(owner, &token.mint);
+        let balance = client.get_token_balances(&[token_account]).await?[0];
+        json_tokens.push(json!({
+            "mint": token.mint.to_string(),
+            "symbol": token.symbol,
+            "token_account": token_account.to_string(),
+            "amount": balance,
+            "ui_amount": balance.map(|balance| raw_amount_to_ui(balance, token.decimals)),
+        }));
+        let balance_text = balance.map_or("no token account".to_string(), |balance| raw_amount_to_ui(balance, token.decimals));
+        text += &format!("{:<6} {}\n", token.label(), balance_text);
+    }
 
+    let json = json!({ "owner": owner.to_string(), "lamports": lamports, "tokens": json_tokens });
+    Ok(Output { json, text })
+}
 
+async fn tick_arrays(profile: &ClusterConfig, client: &DEXClient, pool: &str, count: usize) -> CliResult<Output> {
+    let pool_address = resolve_pool(profile, pool)?;
+    let dex_address = profile.whirlpool_program_id()?;
+    let whirlpool = client.get_whirlpool(&pool_address).await?;
+
+    let mut json = json!({ "pool": pool_address.to_string(), "tick_current_index": whirlpool.tick_current_index });
+    let mut text = format!("pool {}, current tick {}\n", pool_address, whirlpool.tick_current_index);
+    for (direction, a_to_b) in [("a_to_b", true), ("b_to_a", false)] {
+        let start_tick_indexes = tickutil_get_start_tick_indexes_for_swap(whirlpool.tick_current_index, whirlpool.tick_spacing, a_to_b, count);
+        let fetched = client.get_tick_arrays(&dex_address, &pool_address, &start_tick_indexes).await?;
+
+        text += &format!("{}:\n", direction);
+        let mut json_arrays = Vec::new();
+        for (start_tick_index, (pubkey, tick_array)) in start_tick_indexes.iter().zip(fetched) {
+            let initialized_ticks = tick_array.map(|tick_array| tick_array.ticks.iter().filter(|tick| tick.initialized).count());
+            json_arrays.push(json!({
+                "start_tick_index": start_tick_index,
+                "address": pubkey.to_string(),
+                "initialized": tick_array.is_some(),
+                "initialized_ticks": initialized_ticks,
+            }));
+            let state = initialized_ticks.map_or("not initialized".to_string(), |ticks| format!("{} initialized ticks", ticks));
+            text += &format!("  {:>8} {} {}\n", start_tick_index, pubkey, state);
+        }
+        json[direction] = Value::from(json_arrays);
+    }
+    Ok(Output { json, text })
+}
 
-    // 3. 用client发送数据
-    let _ = client.send_instructions( &user, vec![instruction]).await.expect("send instruction fail");
-    println!(" success send instruction ");
+async fn ata(profile: &ClusterConfig, client: &DEXClient, user: &Authority, command: AtaCommand) -> CliResult<Output> {
+    let (token, send, create) = match &command {
+        AtaCommand::Create { token, send } => (token, send, true),
+        AtaCommand::Close { token, send } => (token, send, false),
+    };
+    let token = resolve_token(profile, client.rpc(), token).await?;
+    let token_account = get_associated_token_address(&user.pubkey, &token.mint);
+    let balance = client.get_token_balances(&[token_account]).await?[0];
+
+    let instruction = if create {
+        if balance.is_some() {
+            let text = format!("{} account {} already exists\n", token.label(), token_account);
+            return Ok(Output { json: json!({ "token_account": token_account.to_string(), "created": false }), text });
+        }
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(&client.fee_payer_pubkey(&user.pubkey), &user.pubkey, &token.mint, &spl_token::id())
+    } else {
+        match balance {
+            None => return Err(format!("{} account {} does not exist", token.label(), token_account).into()),
+            Some(balance) if balance > 0 && token.mint != spl_token::native_mint::id() => {
+                return Err(format!("{} account {} still holds {}", token.label(), token_account, raw_amount_to_ui(balance, token.decimals)).into());
+            }
+            Some(_) => spl_token::instruction::close_account(&spl_token::id(), &token_account, &user.pubkey, &user.pubkey, &[])?,
+        }
+    };
 
-    Ok(())
+    let action = if create { "create" } else { "close" };
+    let mut output = Output {
+        json: json!({ "token_account": token_account.to_string(), "action": action }),
+        text: format!("{} {} account {}\n", action, token.label(), token_account),
+    };
+    send_or_print(client, user, vec![instruction], send, &mut output).await?;
+    Ok(output)
 }
@@ -0,0 +1,692 @@
+//! Meteora DLMM: `LbPair` and `BinArray` decoding, bin array PDAs, bin-by-bin swap simulation
+//! with the base and variable fee, `swap` instructions, and [`MeteoraDlmmPool`], the [`Pool`]
+//! over a pair and the bin arrays around its active bin.
+//!
+//! Liquidity sits in bins of a fixed price, `(1 + bin_step / 10000)^bin_id`, so a swap empties
+//! bins one after the other at their own price instead of moving along a curve. The variable
+//! fee grows with how many bins the price moved through recently, and decays with time, so
+//! quotes depend on the clock. Only bin arrays in the pair's own bitmap, 512 on each side of
+//! bin 0, are searched; the bitmap extension beyond is not read.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::constant::{BIN_ARRAY_DISCRIMINATOR, DLMM_BINS_PER_ARRAY, DLMM_BIN_ARRAY_BITMAP_SIZE, LB_PAIR_DISCRIMINATOR, METEORA_DLMM_SWAP_DISCRIMINATOR, TOKEN_2022_PROGRAM_ID};
+use crate::dex::{DexError, Pool, PoolQuote};
+
+/// Bin arrays with liquidity loaded on each side of the active bin.
+pub const MAX_DLMM_BIN_ARRAYS_PER_SWAP: usize = 4;
+
+// fee rates are in 1e-9
+const FEE_PRECISION: u128 = 1_000_000_000;
+const MAX_FEE_RATE: u128 = 100_000_000;
+const BASIS_POINT_MAX: i64 = 10_000;
+const SCALE_OFFSET: u32 = 64;
+const ONE: u128 = 1 << SCALE_OFFSET;
+// exponents from 2^19 on overflow the Q64.64 power
+const MAX_EXPONENTIAL: u32 = 0x80000;
+
+pub use layout::*;
+
+// the IDL derives only know anchor's own `Pubkey` by that name
+mod layout {
+    use anchor_lang::prelude::Pubkey;
+    use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+
+    use crate::constant::DLMM_BINS_PER_ARRAY_USIZE;
+
+    #[derive(Copy, Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct StaticParameters {
+        pub base_factor: u16,
+        // seconds within which a swap keeps the volatility reference
+        pub filter_period: u16,
+        // seconds after which the volatility reference drops to 0
+        pub decay_period: u16,
+        // share of the volatility accumulator kept as reference, in bps
+        pub reduction_factor: u16,
+        pub variable_fee_control: u32,
+        pub max_volatility_accumulator: u32,
+        pub min_bin_id: i32,
+        pub max_bin_id: i32,
+        pub protocol_share: u16,
+        pub base_fee_power_factor: u8,
+        pub padding: [u8; 5],
+    }
+
+    #[derive(Copy, Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct VariableParameters {
+        pub volatility_accumulator: u32,
+        pub volatility_reference: u32,
+        pub index_reference: i32,
+        pub padding: [u8; 4],
+        pub last_update_timestamp: i64,
+        pub padding1: [u8; 8],
+    }
+
+    #[derive(Copy, Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct ProtocolFee {
+        pub amount_x: u64,
+        pub amount_y: u64,
+    }
+
+    #[derive(Copy, Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct DlmmRewardInfo {
+        pub mint: Pubkey,
+        pub vault: Pubkey,
+        pub funder: Pubkey,
+        pub reward_duration: u64,
+        pub reward_duration_end: u64,
+        pub reward_rate: u128,
+        pub last_update_time: u64,
+        pub cumulative_seconds_with_empty_liquidity_reward: u64,
+    }
+
+    // #[account(zero_copy)], no padding between fields. Total 904 bytes
+    #[derive(Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct LbPair {
+        pub parameters: StaticParameters,
+        pub v_parameters: VariableParameters,
+        pub bump_seed: [u8; 1],
+        pub bin_step_seed: [u8; 2],
+        pub pair_type: u8,
+        pub active_id: i32,
+        // bps between the prices of neighbouring bins
+        pub bin_step: u16,
+        // 1: disabled
+        pub status: u8,
+        pub require_base_factor_seed: u8,
+        pub base_factor_seed: [u8; 2],
+        pub activation_type: u8,
+        pub creator_pool_on_off_control: u8,
+        pub token_x_mint: Pubkey,
+        pub token_y_mint: Pubkey,
+        pub reserve_x: Pubkey,
+        pub reserve_y: Pubkey,
+        pub protocol_fee: ProtocolFee,
+        pub padding1: [u8; 32],
+        pub reward_infos: [DlmmRewardInfo; 2],
+        pub oracle: Pubkey,
+        // bin arrays -512..512, bit 0 being -512
+        pub bin_array_bitmap: [u64; 16],
+        pub last_updated_at: i64,
+        pub padding2: [u8; 32],
+        pub pre_activation_swap_address: Pubkey,
+        pub base_key: Pubkey,
+        pub activation_point: u64,
+        pub pre_activation_duration: u64,
+        pub padding3: [u8; 8],
+        pub padding4: u64,
+        pub creator: Pubkey,
+        // 0: SPL Token, 1: Token-2022
+        pub token_mint_x_program_flag: u8,
+        pub token_mint_y_program_flag: u8,
+        pub reserved: [u8; 22],
+    }
+
+    #[derive(Copy, Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct Bin {
+        pub amount_x: u64,
+        pub amount_y: u64,
+        // Q64.64, may be unset on bins never traded in
+        pub price: u128,
+        pub liquidity_supply: u128,
+        pub reward_per_token_stored: [u128; 2],
+        pub fee_amount_x_per_token_stored: u128,
+        pub fee_amount_y_per_token_stored: u128,
+        pub amount_x_in: u128,
+        pub amount_y_in: u128,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct BinArray {
+        pub index: i64,
+        pub version: u8,
+        pub padding: [u8; 7],
+        pub lb_pair: Pubkey,
+        pub bins: [Bin; DLMM_BINS_PER_ARRAY_USIZE],
+    }
+}
+
+/// A decoded account owned by the DLMM program, boxed like `WhirlpoolAccount`.
+pub enum DlmmAccount {
+    LbPair(Box<LbPair>),
+    BinArray(Box<BinArray>),
+}
+
+/// Decodes raw account data (discriminator included). Returns `None` for the program's other
+/// account types.
+pub fn decode_dlmm_account(data: &[u8]) -> Option<DlmmAccount> {
+    if data.len() < 8 {
+        return None;
+    }
+    let (discriminator, mut body) = data.split_at(8);
+
+    if discriminator == LB_PAIR_DISCRIMINATOR {
+        LbPair::deserialize(&mut body).ok().map(|lb_pair| DlmmAccount::LbPair(Box::new(lb_pair)))
+    } else if discriminator == BIN_ARRAY_DISCRIMINATOR {
+        BinArray::deserialize(&mut body).ok().map(|bin_array| DlmmAccount::BinArray(Box::new(bin_array)))
+    } else {
+        None
+    }
+}
+
+/// Index of the bin array holding `bin_id`.
+pub fn bin_id_to_bin_array_index(bin_id: i32) -> i64 {
+    bin_id.div_euclid(DLMM_BINS_PER_ARRAY) as i64
+}
+
+/// Lowest and highest bin of bin array `index`.
+pub fn bin_array_bounds(index: i64) -> (i32, i32) {
+    let lower_bin_id = index as i32 * DLMM_BINS_PER_ARRAY;
+    (lower_bin_id, lower_bin_id + DLMM_BINS_PER_ARRAY - 1)
+}
+
+pub fn get_bin_array_address(program_id: &Pubkey, lb_pair: &Pubkey, index: i64) -> Pubkey {
+    let seeds = [b"bin_array".as_ref(), lb_pair.as_ref(), &index.to_le_bytes()];
+    Pubkey::find_program_address(&seeds, program_id).0
+}
+
+pub fn get_event_authority_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"__event_authority".as_ref()], program_id).0
+}
+
+/// Whether the pair's bitmap marks bin array `index` as holding liquidity. Arrays outside the
+/// bitmap count as empty.
+pub fn is_bin_array_initialized(lb_pair: &LbPair, index: i64) -> bool {
+    let bit = index + DLMM_BIN_ARRAY_BITMAP_SIZE as i64;
+    (0..2 * DLMM_BIN_ARRAY_BITMAP_SIZE as i64).contains(&bit) && lb_pair.bin_array_bitmap[bit as usize / 64] & (1 << (bit % 64)) != 0
+}
+
+/// Indexes of up to `count` bin arrays with liquidity a swap would go through, in swap
+/// direction, from the one holding the active bin.
+pub fn get_bin_array_indexes_for_swap(lb_pair: &LbPair, swap_for_y: bool, count: usize) -> Vec<i64> {
+    let bitmap_size = DLMM_BIN_ARRAY_BITMAP_SIZE as i64;
+    let mut indexes = Vec::with_capacity(count);
+    let mut index = bin_id_to_bin_array_index(lb_pair.active_id);
+    while indexes.len() < count && (-bitmap_size..bitmap_size).contains(&index) {
+        if is_bin_array_initialized(lb_pair, index) {
+            indexes.push(index);
+        }
+        index += if swap_for_y { -1 } else { 1 };
+    }
+    indexes
+}
+
+// Q64.64 `base^exp`, by squaring with the base kept below one, rounded like the program
+fn pow(base: u128, exp: i32) -> Option<u128> {
+    let mut invert = exp.is_negative();
+    let exp = exp.unsigned_abs();
+    if exp == 0 {
+        return Some(ONE);
+    }
+    if exp >= MAX_EXPONENTIAL {
+        return None;
+    }
+
+    let mut squared_base = base;
+    let mut result = ONE;
+    if squared_base >= result {
+        squared_base = u128::MAX.checked_div(squared_base)?;
+        invert = !invert;
+    }
+    for bit in 0..19 {
+        if exp & (1 << bit) != 0 {
+            result = result.checked_mul(squared_base)? >> SCALE_OFFSET;
+        }
+        squared_base = squared_base.checked_mul(squared_base)? >> SCALE_OFFSET;
+    }
+
+    if result == 0 {
+        return None;
+    }
+    if invert {
+        result = u128::MAX.checked_div(result)?;
+    }
+    Some(result)
+}
+
+/// Q64.64 price, raw Y per raw X, of bin `bin_id`.
+pub fn get_price_from_id(bin_id: i32, bin_step: u16) -> Result<u128, DexError> {
+    let bps = ((bin_step as u128) << SCALE_OFFSET) / BASIS_POINT_MAX as u128;
+    pow(ONE + bps, bin_id).ok_or_else(|| format!("price of bin {} overflows", bin_id).into())
+}
+
+/// Base fee plus the variable fee for the current volatility, in 1e-9, capped at 10%.
+pub fn get_total_fee_rate(parameters: &StaticParameters, v_parameters: &VariableParameters, bin_step: u16) -> u128 {
+    let base_fee_rate = parameters.base_factor as u128 * bin_step as u128 * 10 * 10u128.pow(parameters.base_fee_power_factor as u32);
+    let variable_fee_rate = if parameters.variable_fee_control > 0 {
+        let square_vfa_bin = (v_parameters.volatility_accumulator as u128 * bin_step as u128).pow(2);
+        (parameters.variable_fee_control as u128 * square_vfa_bin).div_ceil(100_000_000_000)
+    } else {
+        0
+    };
+    (base_fee_rate + variable_fee_rate).min(MAX_FEE_RATE)
+}
+
+// resets the references the way the program does before a swap at `now`
+fn update_references(parameters: &StaticParameters, v_parameters: &mut VariableParameters, active_id: i32, now: i64) {
+    let elapsed = now - v_parameters.last_update_timestamp;
+    if elapsed >= parameters.filter_period as i64 {
+        v_parameters.index_reference = active_id;
+        v_parameters.volatility_reference = if elapsed < parameters.decay_period as i64 {
+            (v_parameters.volatility_accumulator as i64 * parameters.reduction_factor as i64 / BASIS_POINT_MAX) as u32
+        } else {
+            0
+        };
+    }
+}
+
+fn update_volatility_accumulator(parameters: &StaticParameters, v_parameters: &mut VariableParameters, active_id: i32) {
+    let delta_id = (v_parameters.index_reference as i64 - active_id as i64).unsigned_abs();
+    let volatility_accumulator = v_parameters.volatility_reference as u64 + delta_id * BASIS_POINT_MAX as u64;
+    v_parameters.volatility_accumulator = volatility_accumulator.min(parameters.max_volatility_accumulator as u64) as u32;
+}
+
+/// Result of [`simulate_swap`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DlmmSwapSimulation {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+    pub end_active_id: i32,
+    /// Leading bin arrays of those passed in the swap went through.
+    pub bin_arrays_used: usize,
+}
+
+/// Simulates an exact-input swap at unix time `now`, X for Y when `swap_for_y`.
+///
+/// `bin_arrays` are arrays with liquidity in swap direction, see
+/// [`get_bin_array_indexes_for_swap`]. The swap stops when it runs past the last one, so
+/// `amount_in` may be a partial fill.
+pub fn simulate_swap(lb_pair: &LbPair, bin_arrays: &[&BinArray], amount_in: u64, swap_for_y: bool, now: i64) -> Result<DlmmSwapSimulation, DexError> {
+    let parameters = &lb_pair.parameters;
+    let mut v_parameters = lb_pair.v_parameters;
+    let mut active_id = lb_pair.active_id;
+    update_references(parameters, &mut v_parameters, active_id, now);
+
+    let mut amount_left = amount_in;
+    let mut amount_out: u64 = 0;
+    let mut fee_total: u64 = 0;
+    let mut array_position = 0;
+
+    'arrays: while let Some(bin_array) = bin_arrays.get(array_position) {
+        let (lower_bin_id, upper_bin_id) = bin_array_bounds(bin_array.index);
+        // the next array with liquidity is entered at its edge, skipping the empty ones between
+        if swap_for_y && active_id > upper_bin_id {
+            active_id = upper_bin_id;
+        } else if !swap_for_y && active_id < lower_bin_id {
+            active_id = lower_bin_id;
+        } else if !(lower_bin_id..=upper_bin_id).contains(&active_id) {
+            array_position += 1;
+            continue;
+        }
+
+        while (lower_bin_id..=upper_bin_id).contains(&active_id) {
+            update_volatility_accumulator(parameters, &mut v_parameters, active_id);
+            let bin = &bin_array.bins[(active_id - lower_bin_id) as usize];
+            let max_amount_out = if swap_for_y { bin.amount_y } else { bin.amount_x };
+            if max_amount_out > 0 {
+                let price = get_price_from_id(active_id, lb_pair.bin_step)?;
+                let fee_rate = get_total_fee_rate(parameters, &v_parameters, lb_pair.bin_step);
+                let (step_in, step_out, step_fee) = swap_in_bin(amount_left, max_amount_out, price, fee_rate, swap_for_y)?;
+                amount_left -= step_in;
+                amount_out += step_out;
+                fee_total += step_fee;
+            }
+            if amount_left == 0 {
+                break 'arrays;
+            }
+
+            active_id += if swap_for_y { -1 } else { 1 };
+            if !(parameters.min_bin_id..=parameters.max_bin_id).contains(&active_id) {
+                break 'arrays;
+            }
+        }
+        array_position += 1;
+    }
+
+    Ok(DlmmSwapSimulation {
+        amount_in: amount_in - amount_left,
+        amount_out,
+        fee_amount: fee_total,
+        end_active_id: active_id,
+        bin_arrays_used: (array_position + 1).min(bin_arrays.len()),
+    })
+}
+
+// (input with fee, output, fee) of swapping up to `amount_in` in one bin holding `max_amount_out`
+fn swap_in_bin(amount_in: u64, max_amount_out: u64, price: u128, fee_rate: u128, swap_for_y: bool) -> Result<(u64, u64, u64), DexError> {
+    let max_amount_in = if swap_for_y {
+        ((max_amount_out as u128) << SCALE_OFFSET).div_ceil(price)
+    } else {
+        (max_amount_out as u128 * price).div_ceil(ONE)
+    };
+    // the fee on top of the amount swapped
+    let max_fee = (max_amount_in * fee_rate).div_ceil(FEE_PRECISION - fee_rate);
+    let max_amount_in_with_fee = max_amount_in + max_fee;
+
+    if amount_in as u128 >= max_amount_in_with_fee {
+        return Ok((u64::try_from(max_amount_in_with_fee)?, max_amount_out, u64::try_from(max_fee)?));
+    }
+    // the fee out of the amount
+    let fee = (amount_in as u128 * fee_rate).div_ceil(FEE_PRECISION);
+    let amount_in_after_fee = amount_in as u128 - fee;
+    let amount_out = if swap_for_y { (amount_in_after_fee * price) >> SCALE_OFFSET } else { (amount_in_after_fee << SCALE_OFFSET) / price };
+    Ok((amount_in, (amount_out as u64).min(max_amount_out), fee as u64))
+}
+
+#[derive(Default, AnchorSerialize, AnchorDeserialize)]
+pub struct DlmmSwapData {
+    pub amount_in: u64,
+    pub min_amount_out: u64,
+}
+
+fn token_program(program_flag: u8) -> Result<Pubkey, DexError> {
+    match program_flag {
+        0 => Ok(spl_token::id()),
+        1 => Ok(Pubkey::from_str(TOKEN_2022_PROGRAM_ID)?),
+        other => Err(format!("unknown token program flag {}", other).into()),
+    }
+}
+
+/// DLMM `swap` without a host fee or bitmap extension; `bin_arrays` are in swap direction.
+#[allow(clippy::too_many_arguments)]
+pub fn dlmm_swap_instruction(
+    program_id: &Pubkey,
+    lb_pair_address: &Pubkey,
+    lb_pair: &LbPair,
+    user: &Pubkey,
+    user_token_in: &Pubkey,
+    user_token_out: &Pubkey,
+    bin_arrays: &[Pubkey],
+    swap_data: &DlmmSwapData,
+) -> Result<Instruction, DexError> {
+    let pubkey = |anchor_pubkey: &anchor_lang::prelude::Pubkey| Pubkey::new_from_array(anchor_pubkey.to_bytes());
+
+    // optional accounts left out are passed as the program itself
+    let mut accounts = vec![
+        AccountMeta::new(*lb_pair_address, false),
+        AccountMeta::new_readonly(*program_id, false),
+        AccountMeta::new(pubkey(&lb_pair.reserve_x), false),
+        AccountMeta::new(pubkey(&lb_pair.reserve_y), false),
+        AccountMeta::new(*user_token_in, false),
+        AccountMeta::new(*user_token_out, false),
+        AccountMeta::new_readonly(pubkey(&lb_pair.token_x_mint), false),
+        AccountMeta::new_readonly(pubkey(&lb_pair.token_y_mint), false),
+        AccountMeta::new(pubkey(&lb_pair.oracle), false),
+        AccountMeta::new_readonly(*program_id, false),
+        AccountMeta::new_readonly(*user, true),
+        AccountMeta::new_readonly(token_program(lb_pair.token_mint_x_program_flag)?, false),
+        AccountMeta::new_readonly(token_program(lb_pair.token_mint_y_program_flag)?, false),
+        AccountMeta::new_readonly(get_event_authority_address(program_id), false),
+        AccountMeta::new_readonly(*program_id, false),
+    ];
+    accounts.extend(bin_arrays.iter().map(|bin_array| AccountMeta::new(*bin_array, false)));
+
+    let data = {
+        let mut prefix = METEORA_DLMM_SWAP_DISCRIMINATOR.to_vec();
+        prefix.extend(swap_data.try_to_vec()?);
+        prefix
+    };
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// A Meteora DLMM pair and the bin arrays with liquidity a trade in either direction can reach.
+/// Token A is the pair's X, token B its Y.
+pub struct MeteoraDlmmPool {
+    address: Pubkey,
+    program_id: Pubkey,
+    lb_pair: Option<LbPair>,
+    /// By index, with their addresses.
+    bin_arrays: HashMap<i64, (Pubkey, BinArray)>,
+}
+
+impl MeteoraDlmmPool {
+    pub fn new(address: Pubkey, program_id: Pubkey) -> Self {
+        MeteoraDlmmPool {
+            address,
+            program_id,
+            lb_pair: None,
+            bin_arrays: HashMap::new(),
+        }
+    }
+
+    pub fn lb_pair(&self) -> Option<&LbPair> {
+        self.lb_pair.as_ref()
+    }
+
+    /// The simulation behind [`Pool::quote`] at unix time `now`, with the bin arrays it went
+    /// through and the one after.
+    pub fn simulate(&self, amount_in: u64, swap_for_y: bool, now: i64) -> Result<(DlmmSwapSimulation, Vec<Pubkey>), DexError> {
+        let lb_pair = self.lb_pair.as_ref().ok_or_else(|| format!("pair {} is not loaded", self.address))?;
+        if lb_pair.status == 1 {
+            return Err(format!("pair {} is disabled", self.address).into());
+        }
+
+        let (pubkeys, bin_arrays): (Vec<Pubkey>, Vec<&BinArray>) = get_bin_array_indexes_for_swap(lb_pair, swap_for_y, MAX_DLMM_BIN_ARRAYS_PER_SWAP)
+            .iter()
+            .map_while(|index| self.bin_arrays.get(index).map(|(pubkey, bin_array)| (*pubkey, bin_array)))
+            .unzip();
+        if bin_arrays.is_empty() {
+            return Err(format!("no bin arrays with liquidity to swap through in pair {}", self.address).into());
+        }
+
+        let simulation = simulate_swap(lb_pair, &bin_arrays, amount_in, swap_for_y, now)?;
+        if simulation.amount_in < amount_in {
+            return Err(format!("{} of {} could not be swapped within the loaded bin arrays", amount_in - simulation.amount_in, amount_in).into());
+        }
+        let used = (simulation.bin_arrays_used + 1).min(pubkeys.len());
+        Ok((simulation, pubkeys[..used].to_vec()))
+    }
+}
+
+fn unix_timestamp() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+impl Pool for MeteoraDlmmPool {
+    fn dex(&self) -> &'static str {
+        "meteora_dlmm"
+    }
+
+    fn address(&self) -> Pubkey {
+        self.address
+    }
+
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn mints(&self) -> Option<(Pubkey, Pubkey)> {
+        self.lb_pair
+            .as_ref()
+            .map(|lb_pair| (Pubkey::new_from_array(lb_pair.token_x_mint.to_bytes()), Pubkey::new_from_array(lb_pair.token_y_mint.to_bytes())))
+    }
+
+    fn accounts_to_watch(&self) -> Vec<Pubkey> {
+        let mut accounts = vec![self.address];
+        if let Some(lb_pair) = &self.lb_pair {
+            for swap_for_y in [true, false] {
+                for index in get_bin_array_indexes_for_swap(lb_pair, swap_for_y, MAX_DLMM_BIN_ARRAYS_PER_SWAP) {
+                    let pubkey = get_bin_array_address(&self.program_id, &self.address, index);
+                    if !accounts.contains(&pubkey) {
+                        accounts.push(pubkey);
+                    }
+                }
+            }
+        }
+        accounts
+    }
+
+    fn update(&mut self, pubkey: &Pubkey, account: Option<&Account>) -> Result<(), DexError> {
+        let decoded = account.and_then(|account| decode_dlmm_account(&account.data));
+        if *pubkey == self.address {
+            match decoded {
+                Some(DlmmAccount::LbPair(lb_pair)) => self.lb_pair = Some(*lb_pair),
+                _ => return Err(format!("{} is not a Meteora DLMM pair", pubkey).into()),
+            }
+        } else {
+            match decoded {
+                Some(DlmmAccount::BinArray(bin_array)) => {
+                    self.bin_arrays.insert(bin_array.index, (*pubkey, *bin_array));
+                }
+                _ => self.bin_arrays.retain(|_, (address, _)| address != pubkey),
+            }
+        }
+        Ok(())
+    }
+
+    fn price(&self) -> Result<Decimal, DexError> {
+        let lb_pair = self.lb_pair.as_ref().ok_or_else(|| format!("pair {} is not loaded", self.address))?;
+        let price = get_price_from_id(lb_pair.active_id, lb_pair.bin_step)?;
+        // Q64.64 to Q32.32, which fits the 96 bits of a decimal
+        let price = Decimal::from_u128(price >> 32).ok_or("price is out of range")?;
+        Ok(price / Decimal::from(1u64 << 32))
+    }
+
+    fn fee_rate(&self) -> Result<Decimal, DexError> {
+        let lb_pair = self.lb_pair.as_ref().ok_or_else(|| format!("pair {} is not loaded", self.address))?;
+        let fee_rate = get_total_fee_rate(&lb_pair.parameters, &lb_pair.v_parameters, lb_pair.bin_step);
+        Ok(Decimal::from(fee_rate as u64) / Decimal::from(FEE_PRECISION as u64))
+    }
+
+    fn quote(&self, amount_in: u64, a_to_b: bool) -> Result<PoolQuote, DexError> {
+        let (simulation, _) = self.simulate(amount_in, a_to_b, unix_timestamp())?;
+        let (mint_x, mint_y) = self.mints().ok_or("pair is not loaded")?;
+        Ok(PoolQuote {
+            pool: self.address,
+            a_to_b,
+            mint_in: if a_to_b { mint_x } else { mint_y },
+            mint_out: if a_to_b { mint_y } else { mint_x },
+            amount_in: simulation.amount_in,
+            amount_out: simulation.amount_out,
+            fee_amount: simulation.fee_amount,
+            legs: 1,
+            accounts_to_initialize: Vec::new(),
+        })
+    }
+
+    fn swap_instructions(&self, user: &Pubkey, _funder: &Pubkey, quote: &PoolQuote, min_amount_out: u64) -> Result<Vec<Instruction>, DexError> {
+        let (_, bin_arrays) = self.simulate(quote.amount_in, quote.a_to_b, unix_timestamp())?;
+        let lb_pair = self.lb_pair.as_ref().ok_or("pair is not loaded")?;
+        let swap_data = DlmmSwapData {
+            amount_in: quote.amount_in,
+            min_amount_out,
+        };
+        let instruction = dlmm_swap_instruction(
+            &self.program_id,
+            &self.address,
+            lb_pair,
+            user,
+            &get_associated_token_address(user, &quote.mint_in),
+            &get_associated_token_address(user, &quote.mint_out),
+            &bin_arrays,
+            &swap_data,
+        )?;
+        Ok(vec![instruction])
+    }
+}
+
+#[tokio::test]
+async fn test_meteora_dlmm_pool() {
+    use crate::client::DEXClient;
+    use crate::constant::{DLMM_BINS_PER_ARRAY_USIZE, METEORA_DLMM_PROGRAM_ID};
+    use crate::dex::anchor_pubkey;
+    use crate::rpc::InMemoryRpc;
+
+    let program_id = Pubkey::from_str(METEORA_DLMM_PROGRAM_ID).unwrap();
+    let [pair_address, mint_x, mint_y, user] = [1u8, 2, 3, 7].map(|byte| Pubkey::new_from_array([byte; 32]));
+    let now = 1_700_000_000;
+
+    // 0.1% bins around price 1, a 0.1% base fee, and a volatility reference of two bins
+    let mut lb_pair = LbPair {
+        parameters: StaticParameters {
+            base_factor: 10_000,
+            filter_period: 30,
+            decay_period: 600,
+            reduction_factor: 5_000,
+            variable_fee_control: 40_000,
+            max_volatility_accumulator: 350_000,
+            min_bin_id: -443_636,
+            max_bin_id: 443_636,
+            ..Default::default()
+        },
+        v_parameters: VariableParameters { volatility_reference: 20_000, last_update_timestamp: now - 10, ..Default::default() },
+        bin_step: 10,
+        token_x_mint: anchor_pubkey(&mint_x),
+        token_y_mint: anchor_pubkey(&mint_y),
+        ..Default::default()
+    };
+    lb_pair.bin_array_bitmap[511 / 64] |= 1 << (511 % 64);
+    lb_pair.bin_array_bitmap[512 / 64] |= 1;
+
+    let rpc = InMemoryRpc::new();
+    rpc.set_program_account(pair_address, program_id, &LB_PAIR_DISCRIMINATOR, &lb_pair.try_to_vec().unwrap());
+    for index in [-1, 0] {
+        let mut bin_array = BinArray { index, version: 1, padding: [0; 7], lb_pair: anchor_pubkey(&pair_address), bins: [Bin::default(); DLMM_BINS_PER_ARRAY_USIZE] };
+        let (lower_bin_id, _) = bin_array_bounds(index);
+        for bin_id in -2..=2 {
+            if let Some(bin) = bin_array.bins.get_mut((bin_id - lower_bin_id) as usize) {
+                bin.amount_x = if bin_id >= 0 { 1_000_000_000 } else { 0 };
+                bin.amount_y = if bin_id <= 0 { 1_000_000_000 } else { 0 };
+            }
+        }
+        rpc.set_program_account(get_bin_array_address(&program_id, &pair_address, index), program_id, &BIN_ARRAY_DISCRIMINATOR, &bin_array.try_to_vec().unwrap());
+    }
+    let client = DEXClient::with_rpc(rpc);
+
+    let mut pool = MeteoraDlmmPool::new(pair_address, program_id);
+    client.load_pool(&mut pool).await.unwrap();
+    assert_eq!(pool.accounts_to_watch().len(), 3);
+    assert_eq!(pool.mints(), Some((mint_x, mint_y)));
+    assert_eq!(pool.price().unwrap(), Decimal::ONE);
+    assert_eq!(pool.fee_rate().unwrap(), Decimal::new(1, 3));
+    assert_eq!(bin_id_to_bin_array_index(-1), -1);
+    assert_eq!(get_price_from_id(0, 10).unwrap(), ONE);
+    assert_eq!(get_price_from_id(1, 10).unwrap() >> 44, (ONE + ONE / 1000) >> 44);
+    assert_eq!(get_price_from_id(-1, 10).unwrap() >> 44, (ONE * 1000 / 1001) >> 44);
+
+    // within the active bin: the base fee plus the variable fee of 0.0016% for two bins of volatility
+    let (simulation, bin_arrays) = pool.simulate(500_000_000, true, now).unwrap();
+    assert_eq!((simulation.amount_out, simulation.fee_amount), (499_492_000, 508_000));
+    assert_eq!(bin_arrays.len(), 2);
+    // long after the last swap the reference has decayed, leaving the base fee
+    let (simulation, _) = pool.simulate(500_000_000, true, now + 3_600).unwrap();
+    assert_eq!((simulation.amount_out, simulation.fee_amount), (499_500_000, 500_000));
+
+    // emptying the active bin moves on to bin -1 in the next array, at a lower price
+    let (simulation, _) = pool.simulate(1_500_000_000, true, now + 3_600).unwrap();
+    assert_eq!((simulation.end_active_id, simulation.bin_arrays_used), (-1, 2));
+    assert!(simulation.amount_out > 1_490_000_000 && simulation.amount_out < 1_500_000_000);
+    assert!(pool.simulate(5_000_000_000, true, now).is_err());
+    assert!(pool.simulate(2_000_000_000, false, now).is_ok());
+
+    let quote = pool.quote(500_000_000, true).unwrap();
+    let instructions = pool.swap_instructions(&user, &user, &quote, quote.min_amount_out(50)).unwrap();
+    let instruction = &instructions[0];
+    assert_eq!(instruction.data[..8], METEORA_DLMM_SWAP_DISCRIMINATOR);
+    let swap_data = DlmmSwapData::try_from_slice(&instruction.data[8..]).unwrap();
+    assert_eq!((swap_data.amount_in, swap_data.min_amount_out), (500_000_000, quote.min_amount_out(50)));
+    // 15 fixed accounts and the two bin arrays
+    assert_eq!(instruction.accounts.len(), 17);
+    assert_eq!(instruction.accounts[15].pubkey, get_bin_array_address(&program_id, &pair_address, 0));
+    assert!(instruction.accounts[10].is_signer);
+
+    lb_pair.status = 1;
+    pool.update(&pair_address, Some(&InMemoryRpc::program_account(program_id, &LB_PAIR_DISCRIMINATOR, &lb_pair.try_to_vec().unwrap()))).unwrap();
+    assert!(pool.quote(500_000_000, true).is_err());
+}
@@ -0,0 +1,128 @@
+//! Durable nonces, for transactions signed long before they are sent.
+//!
+//! A transaction whose blockhash is the value stored in a nonce account, and whose first
+//! instruction advances that account, stays valid until it executes or the nonce is advanced
+//! some other way, instead of expiring after about a minute like a recent blockhash.
+
+use solana_sdk::account::Account;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::Message;
+use solana_sdk::nonce::state::{State, Versions};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use solana_sdk::system_instruction::{self, SystemInstruction};
+use solana_sdk::system_program;
+
+pub type NonceError = Box<dyn std::error::Error>;
+
+/// An initialized nonce account.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NonceInfo {
+    pub address: Pubkey,
+    /// Has to sign every advance.
+    pub authority: Pubkey,
+    /// The current nonce, signed as the blockhash of the next transaction using it.
+    pub blockhash: Hash,
+    pub lamports_per_signature: u64,
+    pub lamports: u64,
+}
+
+impl NonceInfo {
+    pub fn from_account(address: &Pubkey, account: &Account) -> Result<Self, NonceError> {
+        if account.owner != system_program::id() {
+            return Err(format!("{} is not a nonce account", address).into());
+        }
+        let versions: Versions = bincode::deserialize(&account.data).map_err(|_| format!("{} is not a nonce account", address))?;
+        match versions.state() {
+            State::Uninitialized => Err(format!("nonce account {} is not initialized", address).into()),
+            State::Initialized(data) => Ok(NonceInfo {
+                address: *address,
+                authority: data.authority,
+                blockhash: data.blockhash(),
+                lamports_per_signature: data.get_lamports_per_signature(),
+                lamports: account.lamports,
+            }),
+        }
+    }
+
+    /// A message running `instructions` after advancing this nonce, signed over its current
+    /// value.
+    pub fn message(&self, instructions: &[Instruction], payer: &Pubkey) -> Message {
+        let mut message = Message::new_with_nonce(instructions.to_vec(), Some(payer), &self.address, &self.authority);
+        message.recent_blockhash = self.blockhash;
+        message
+    }
+}
+
+/// Lamports a nonce account needs to be rent exempt.
+pub fn nonce_account_rent() -> u64 {
+    Rent::default().minimum_balance(State::size())
+}
+
+/// Creates `nonce_account`, funded by `payer`, and sets its authority.
+pub fn create_nonce_account_instructions(payer: &Pubkey, nonce_account: &Pubkey, authority: &Pubkey) -> Vec<Instruction> {
+    system_instruction::create_nonce_account(payer, nonce_account, authority, nonce_account_rent())
+}
+
+/// The nonce account `message` advances first, if it uses a durable nonce.
+pub fn durable_nonce_account(message: &Message) -> Option<Pubkey> {
+    let instruction = message.instructions.first()?;
+    if message.account_keys.get(instruction.program_id_index as usize) != Some(&system_program::id()) {
+        return None;
+    }
+    match bincode::deserialize(&instruction.data) {
+        Ok(SystemInstruction::AdvanceNonceAccount) => instruction.accounts.first().map(|index| message.account_keys[*index as usize]),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn test_durable_nonce() {
+    use solana_sdk::fee_calculator::FeeCalculator;
+    use solana_sdk::nonce::state::{Data, DurableNonce};
+    use solana_sdk::signature::Signer;
+    use solana_sdk::signer::keypair::keypair_from_seed;
+
+    use crate::client::DEXClient;
+    use crate::rpc::InMemoryRpc;
+    use crate::sender::SendError;
+
+    let user = keypair_from_seed(&[6; 32]).unwrap();
+    let nonce_address = Pubkey::new_from_array([3; 32]);
+    let nonce_account = |value: &Hash| {
+        let data = Data::new(user.pubkey(), DurableNonce::from_blockhash(value), FeeCalculator::new(5000).lamports_per_signature);
+        let data = bincode::serialize(&Versions::new(State::Initialized(data))).unwrap();
+        Account { lamports: nonce_account_rent(), data, owner: system_program::id(), executable: false, rent_epoch: 0 }
+    };
+    let rpc = InMemoryRpc::new();
+    rpc.set_account(nonce_address, nonce_account(&Hash::new_from_array([8; 32])));
+    let client = DEXClient::with_rpc(rpc);
+
+    let nonce = client.get_nonce_info(&nonce_address).await.unwrap();
+    assert_eq!((nonce.authority, nonce.lamports_per_signature), (user.pubkey(), 5000));
+    assert_eq!(nonce.blockhash, *DurableNonce::from_blockhash(&Hash::new_from_array([8; 32])).as_hash());
+    assert!(NonceInfo::from_account(&nonce_address, &Account::default()).is_err());
+
+    // signed over the nonce, advancing it first, and still sent fine long after
+    let transfer = solana_sdk::system_instruction::transfer(&user.pubkey(), &Pubkey::new_from_array([9; 32]), 1);
+    let mut offline = client.build_offline_transaction_with_nonce(&user.pubkey(), &[transfer], &nonce_address).await.unwrap();
+    offline.sign(&user).unwrap();
+    let message = offline.decode_message().unwrap();
+    assert_eq!((message.recent_blockhash, durable_nonce_account(&message)), (nonce.blockhash, Some(nonce_address)));
+    client.rpc().set_block_height(10_000);
+    let signature = client.send_offline_transaction(offline).await.unwrap();
+    assert_eq!(client.rpc().sent_transactions()[0].signatures[0].to_string(), signature);
+
+    // one that never lands is given up on once the nonce moved on without it
+    let transfer = solana_sdk::system_instruction::transfer(&user.pubkey(), &Pubkey::new_from_array([9; 32]), 2);
+    let mut offline = client.build_offline_transaction_with_nonce(&user.pubkey(), &[transfer], &nonce_address).await.unwrap();
+    offline.sign(&user).unwrap();
+    client.rpc().drop_next_transactions(1);
+    client.rpc().set_account(nonce_address, nonce_account(&Hash::new_from_array([9; 32])));
+    let err = client.send_offline_transaction(offline).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<SendError>(), Some(SendError::Expired { .. })));
+
+    assert_eq!(durable_nonce_account(&Message::new(&[], Some(&user.pubkey()))), None);
+    assert_eq!(create_nonce_account_instructions(&user.pubkey(), &nonce_address, &user.pubkey()).len(), 2);
+}
@@ -0,0 +1,134 @@
+//! Offline signing: a transaction message is written out as base64, signed elsewhere (e.g. on
+//! an air-gapped machine), and the signatures are brought back to assemble and send it.
+//!
+//! The message commits to a recent blockhash, so it has to be signed and sent within about a
+//! minute of being built.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Signature, Signer};
+use solana_sdk::transaction::Transaction;
+
+pub type OfflineError = Box<dyn std::error::Error>;
+
+/// An unsigned (or partially signed) transaction, as stored in the file passed around for
+/// signing.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OfflineTransaction {
+    /// Base64 of the serialized message, which is exactly what every signer signs.
+    pub message: String,
+    /// Keys that must sign, fee payer first.
+    pub signers: Vec<String>,
+    /// Base58 signatures collected so far, keyed by signer.
+    #[serde(default)]
+    pub signatures: BTreeMap<String, String>,
+}
+
+impl OfflineTransaction {
+    pub fn new(message: &Message) -> Self {
+        let signers = message.account_keys[..message.header.num_required_signatures as usize].iter().map(|signer| signer.to_string()).collect();
+        OfflineTransaction { message: BASE64_STANDARD.encode(message.serialize()), signers, signatures: BTreeMap::new() }
+    }
+
+    pub fn decode_message(&self) -> Result<Message, OfflineError> {
+        Ok(bincode::deserialize(&BASE64_STANDARD.decode(&self.message)?)?)
+    }
+
+    /// Adds the signature of `signer`, which must be one of the required signers and must
+    /// verify against the message.
+    pub fn add_signature(&mut self, signer: &Pubkey, signature: &Signature) -> Result<(), OfflineError> {
+        if !self.signers.contains(&signer.to_string()) {
+            return Err(format!("{} is not a signer of this transaction", signer).into());
+        }
+        if !signature.verify(signer.as_ref(), &BASE64_STANDARD.decode(&self.message)?) {
+            return Err(format!("signature of {} does not match the message", signer).into());
+        }
+        self.signatures.insert(signer.to_string(), signature.to_string());
+        Ok(())
+    }
+
+    /// Signs with `signer` and keeps the signature.
+    pub fn sign(&mut self, signer: &dyn Signer) -> Result<Signature, OfflineError> {
+        let signature = sign_message_base64(signer, &self.message)?;
+        self.add_signature(&signer.try_pubkey()?, &signature)?;
+        Ok(signature)
+    }
+
+    /// Required signers that haven't signed yet.
+    pub fn missing_signers(&self) -> Vec<String> {
+        self.signers.iter().filter(|signer| !self.signatures.contains_key(*signer)).cloned().collect()
+    }
+
+    /// The transaction with every signature in place, ready to send.
+    pub fn into_transaction(self) -> Result<Transaction, OfflineError> {
+        let missing = self.missing_signers();
+        if !missing.is_empty() {
+            return Err(format!("missing signatures of {}", missing.join(", ")).into());
+        }
+        let mut transaction = Transaction::new_unsigned(self.decode_message()?);
+        transaction.signatures = self.signers.iter().map(|signer| Signature::from_str(&self.signatures[signer])).collect::<Result<_, _>>()?;
+        transaction.verify()?;
+        Ok(transaction)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, OfflineError> {
+        let path = path.as_ref();
+        serde_json::from_str(&fs::read_to_string(path)?).map_err(|err| format!("{}: {}", path.display(), err).into())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), OfflineError> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Signs a base64 message as written in [`OfflineTransaction::message`].
+pub fn sign_message_base64(signer: &dyn Signer, message: &str) -> Result<Signature, OfflineError> {
+    Ok(signer.try_sign_message(&BASE64_STANDARD.decode(message.trim())?)?)
+}
+
+#[tokio::test]
+async fn test_offline_transaction() {
+    use solana_sdk::signer::keypair::keypair_from_seed;
+    use solana_sdk::system_instruction;
+
+    use crate::client::DEXClient;
+    use crate::rpc::InMemoryRpc;
+
+    let user = keypair_from_seed(&[5; 32]).unwrap();
+    let fee_payer = keypair_from_seed(&[6; 32]).unwrap();
+    let client = DEXClient::with_rpc(InMemoryRpc::new()).with_fee_payer(fee_payer.insecure_clone());
+
+    // the client only knows the user's pubkey, and signs as fee payer right away
+    let instruction = system_instruction::transfer(&user.pubkey(), &Pubkey::new_unique(), 1);
+    let unsigned = client.build_offline_transaction(&user.pubkey(), &[instruction]).await.unwrap();
+    assert_eq!(unsigned.signers, vec![fee_payer.pubkey().to_string(), user.pubkey().to_string()]);
+    assert_eq!(unsigned.missing_signers(), vec![user.pubkey().to_string()]);
+
+    // through a file, signed elsewhere
+    let path = std::env::temp_dir().join(format!("offline_{}.json", std::process::id()));
+    unsigned.save(&path).unwrap();
+    let mut signed = OfflineTransaction::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(signed, unsigned);
+    assert!(signed.clone().into_transaction().is_err());
+    let signature = sign_message_base64(&user, &signed.message).unwrap();
+    assert!(signed.add_signature(&fee_payer.pubkey(), &signature).is_err());
+    assert!(signed.add_signature(&Pubkey::new_unique(), &signature).is_err());
+    signed.add_signature(&user.pubkey(), &signature).unwrap();
+    assert!(signed.missing_signers().is_empty());
+
+    let sent = client.send_offline_transaction(signed).await.unwrap();
+    let transactions = client.rpc().sent_transactions();
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(transactions[0].signatures[0].to_string(), sent);
+    assert_eq!(transactions[0].signatures[1], signature);
+}
@@ -0,0 +1,466 @@
+//! Phoenix order books: market decoding, quotes walking the resting orders, and
+//! immediate-or-cancel `Swap` instructions, with [`PhoenixPool`] putting a market behind the
+//! same [`Pool`] interface as the AMMs so their liquidity can be compared for a pair.
+//!
+//! Token A is the market's base token, B its quote token. Amounts on the book are in lots:
+//! base lots of `base_lot_size` atoms, and quote lots of `quote_lot_size` atoms, with prices in
+//! ticks of `tick_size_in_quote_lots_per_base_unit`. The taker fee is charged in quote lots, on
+//! top of what a buyer pays and out of what a seller gets. Order expiry is not checked, so an
+//! order past its last valid slot still counts until it is cleaned up.
+
+use anchor_lang::AnchorSerialize;
+use rust_decimal::Decimal;
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::constant::{PHOENIX_MARKET_HEADER_SIZE, PHOENIX_SWAP_TAG};
+use crate::dex::{DexError, Pool, PoolQuote};
+
+// `MarketStatus::Active`, the only status takers can trade in
+const ACTIVE_MARKET_STATUS: u64 = 1;
+
+// the `FIFOMarket` fields ahead of the order trees, after 256 bytes of padding
+const MARKET_BODY_HEADER_SIZE: usize = 256 + 6 * 8;
+// sokoban red-black tree: root and padding, then the allocator's size, bump index and free list
+const TREE_HEADER_SIZE: usize = 32;
+// four registers (left, right, parent, color), `FIFOOrderId` and `FIFORestingOrder`
+const ORDER_NODE_SIZE: usize = 16 + 16 + 32;
+const LEFT_REGISTER: usize = 0;
+const RIGHT_REGISTER: usize = 1;
+
+// `OrderPacket::ImmediateOrCancel`
+const IMMEDIATE_OR_CANCEL_PACKET: u8 = 2;
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Option<Pubkey> {
+    Pubkey::try_from(data.get(offset..offset + 32)?).ok()
+}
+
+/// A resting order, keyed like on-chain by price and sequence number.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PhoenixOrder {
+    pub price_in_ticks: u64,
+    pub order_sequence_number: u64,
+    pub trader_index: u64,
+    pub num_base_lots: u64,
+    pub last_valid_slot: u64,
+    pub last_valid_unix_timestamp_in_seconds: u64,
+}
+
+/// The parts of a Phoenix market quotes and swaps need, with both sides of the book from the
+/// best price on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhoenixMarket {
+    pub status: u64,
+    pub base_mint: Pubkey,
+    pub base_vault: Pubkey,
+    pub base_lot_size: u64,
+    pub quote_mint: Pubkey,
+    pub quote_vault: Pubkey,
+    pub quote_lot_size: u64,
+    pub base_lots_per_base_unit: u64,
+    pub tick_size_in_quote_lots_per_base_unit: u64,
+    pub taker_fee_bps: u64,
+    pub bids: Vec<PhoenixOrder>,
+    pub asks: Vec<PhoenixOrder>,
+}
+
+// the orders of the tree at `offset`, walked from the root since freed nodes are not zeroed
+fn read_orders(data: &[u8], offset: usize, capacity: u64) -> Option<Vec<PhoenixOrder>> {
+    let root = read_u32(data, offset)?;
+    let nodes_offset = offset + TREE_HEADER_SIZE;
+    let mut orders = Vec::new();
+    let mut stack = vec![root];
+    while let Some(index) = stack.pop() {
+        if index == 0 {
+            continue;
+        }
+        // a corrupt tree could loop
+        if index as u64 > capacity || orders.len() as u64 >= capacity {
+            return None;
+        }
+        let node = nodes_offset + (index as usize - 1) * ORDER_NODE_SIZE;
+        stack.push(read_u32(data, node + 4 * LEFT_REGISTER)?);
+        stack.push(read_u32(data, node + 4 * RIGHT_REGISTER)?);
+        orders.push(PhoenixOrder {
+            price_in_ticks: read_u64(data, node + 16)?,
+            order_sequence_number: read_u64(data, node + 24)?,
+            trader_index: read_u64(data, node + 32)?,
+            num_base_lots: read_u64(data, node + 40)?,
+            last_valid_slot: read_u64(data, node + 48)?,
+            last_valid_unix_timestamp_in_seconds: read_u64(data, node + 56)?,
+        });
+    }
+    Some(orders)
+}
+
+impl PhoenixMarket {
+    /// Reads a market account: the `MarketHeader`, then the `FIFOMarket` with its bids and asks.
+    pub fn decode(data: &[u8]) -> Option<PhoenixMarket> {
+        if data.len() < PHOENIX_MARKET_HEADER_SIZE + MARKET_BODY_HEADER_SIZE {
+            return None;
+        }
+        let (bids_size, asks_size) = (read_u64(data, 16)?, read_u64(data, 24)?);
+        let body = PHOENIX_MARKET_HEADER_SIZE + 256;
+        let bids_offset = PHOENIX_MARKET_HEADER_SIZE + MARKET_BODY_HEADER_SIZE;
+        let asks_offset = bids_offset + TREE_HEADER_SIZE + bids_size as usize * ORDER_NODE_SIZE;
+
+        let mut bids = read_orders(data, bids_offset, bids_size)?;
+        let mut asks = read_orders(data, asks_offset, asks_size)?;
+        bids.sort_by_key(|order| std::cmp::Reverse(order.price_in_ticks));
+        asks.sort_by_key(|order| order.price_in_ticks);
+
+        Some(PhoenixMarket {
+            status: read_u64(data, 8)?,
+            base_mint: read_pubkey(data, 48)?,
+            base_vault: read_pubkey(data, 80)?,
+            base_lot_size: read_u64(data, 112)?,
+            quote_mint: read_pubkey(data, 128)?,
+            quote_vault: read_pubkey(data, 160)?,
+            quote_lot_size: read_u64(data, 192)?,
+            base_lots_per_base_unit: read_u64(data, body)?,
+            tick_size_in_quote_lots_per_base_unit: read_u64(data, body + 8)?,
+            taker_fee_bps: read_u64(data, body + 24)?,
+            bids,
+            asks,
+        })
+    }
+
+    // quote lots for `base_lots` at `price_in_ticks`
+    fn quote_lots(&self, price_in_ticks: u64, base_lots: u64, round_up: bool) -> u128 {
+        let numerator = price_in_ticks as u128 * self.tick_size_in_quote_lots_per_base_unit as u128 * base_lots as u128;
+        let denominator = self.base_lots_per_base_unit as u128;
+        if round_up {
+            numerator.div_ceil(denominator)
+        } else {
+            numerator / denominator
+        }
+    }
+
+    fn taker_fee(&self, quote_lots: u128) -> u128 {
+        (quote_lots * self.taker_fee_bps as u128).div_ceil(10_000)
+    }
+
+    /// Price of `price_in_ticks` in quote atoms per base atom.
+    pub fn ticks_to_price(&self, price_in_ticks: u64) -> Decimal {
+        Decimal::from(price_in_ticks) * Decimal::from(self.tick_size_in_quote_lots_per_base_unit) * Decimal::from(self.quote_lot_size)
+            / (Decimal::from(self.base_lots_per_base_unit) * Decimal::from(self.base_lot_size))
+    }
+
+    /// Fills an immediate-or-cancel order of `amount_in` atoms against the book: base sold into
+    /// the bids when `sell_base`, otherwise quote spent on the asks, fee included.
+    pub fn simulate_ioc(&self, amount_in: u64, sell_base: bool) -> Result<PhoenixFill, DexError> {
+        if self.base_lot_size == 0 || self.quote_lot_size == 0 || self.base_lots_per_base_unit == 0 || self.tick_size_in_quote_lots_per_base_unit == 0 {
+            return Err("market has a zero lot or tick size".into());
+        }
+        let mut base_lots: u64 = 0;
+        let mut quote_lots: u128 = 0;
+
+        if sell_base {
+            let mut base_lots_left = amount_in / self.base_lot_size;
+            for order in &self.bids {
+                if base_lots_left == 0 {
+                    break;
+                }
+                let fill = base_lots_left.min(order.num_base_lots);
+                quote_lots += self.quote_lots(order.price_in_ticks, fill, false);
+                base_lots += fill;
+                base_lots_left -= fill;
+            }
+        } else {
+            // the budget covers the fee as well
+            let budget = (amount_in / self.quote_lot_size) as u128;
+            let mut quote_lots_left = budget * 10_000 / (10_000 + self.taker_fee_bps as u128);
+            for order in &self.asks {
+                let lot_price = order.price_in_ticks as u128 * self.tick_size_in_quote_lots_per_base_unit as u128;
+                let affordable = (quote_lots_left * self.base_lots_per_base_unit as u128 / lot_price.max(1)) as u64;
+                let fill = affordable.min(order.num_base_lots);
+                if fill == 0 {
+                    break;
+                }
+                let cost = self.quote_lots(order.price_in_ticks, fill, true).min(quote_lots_left);
+                quote_lots += cost;
+                quote_lots_left -= cost;
+                base_lots += fill;
+            }
+        }
+
+        let fee_quote_lots = self.taker_fee(quote_lots);
+        let to_u64 = |lots: u128, lot_size: u64| u64::try_from(lots * lot_size as u128).map_err(|_| "amount overflows u64");
+        let (amount_in, amount_out) = if sell_base {
+            (base_lots * self.base_lot_size, to_u64(quote_lots - fee_quote_lots, self.quote_lot_size)?)
+        } else {
+            (to_u64(quote_lots + fee_quote_lots, self.quote_lot_size)?, base_lots * self.base_lot_size)
+        };
+        Ok(PhoenixFill {
+            base_lots,
+            quote_lots: quote_lots as u64,
+            fee_quote_lots: fee_quote_lots as u64,
+            amount_in,
+            amount_out,
+        })
+    }
+}
+
+/// Result of [`PhoenixMarket::simulate_ioc`]. `amount_in` is what the fill takes, which is
+/// short of the order when it is not a whole number of lots or the book runs out.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PhoenixFill {
+    pub base_lots: u64,
+    /// Matched, before the fee.
+    pub quote_lots: u64,
+    pub fee_quote_lots: u64,
+    pub amount_in: u64,
+    pub amount_out: u64,
+}
+
+/// `OrderPacket::ImmediateOrCancel`, after its variant byte.
+#[derive(Default, AnchorSerialize)]
+pub struct ImmediateOrCancelOrder {
+    /// 0: bid, buying base, 1: ask, selling base.
+    pub side: u8,
+    pub price_in_ticks: Option<u64>,
+    pub num_base_lots: u64,
+    pub num_quote_lots: u64,
+    pub min_base_lots_to_fill: u64,
+    pub min_quote_lots_to_fill: u64,
+    /// 0: abort, 1: cancel the resting order, 2: decrement the take.
+    pub self_trade_behavior: u8,
+    pub match_limit: Option<u64>,
+    pub client_order_id: u128,
+    pub use_only_deposited_funds: bool,
+    pub last_valid_slot: Option<u64>,
+    pub last_valid_unix_timestamp_in_seconds: Option<u64>,
+}
+
+pub fn get_log_authority_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"log".as_ref()], program_id).0
+}
+
+/// Phoenix `Swap` sending `order` from the trader's token accounts, with no seat needed.
+pub fn phoenix_swap_instruction(
+    program_id: &Pubkey,
+    market_address: &Pubkey,
+    market: &PhoenixMarket,
+    trader: &Pubkey,
+    order: &ImmediateOrCancelOrder,
+) -> Result<Instruction, DexError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*program_id, false),
+        AccountMeta::new_readonly(get_log_authority_address(program_id), false),
+        AccountMeta::new(*market_address, false),
+        AccountMeta::new_readonly(*trader, true),
+        AccountMeta::new(get_associated_token_address(trader, &market.base_mint), false),
+        AccountMeta::new(get_associated_token_address(trader, &market.quote_mint), false),
+        AccountMeta::new(market.base_vault, false),
+        AccountMeta::new(market.quote_vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    let data = {
+        let mut prefix = vec![PHOENIX_SWAP_TAG, IMMEDIATE_OR_CANCEL_PACKET];
+        prefix.extend(order.try_to_vec()?);
+        prefix
+    };
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// A Phoenix market as a [`Pool`]; quotes are immediate-or-cancel fills against the book.
+pub struct PhoenixPool {
+    address: Pubkey,
+    program_id: Pubkey,
+    market: Option<PhoenixMarket>,
+}
+
+impl PhoenixPool {
+    pub fn new(address: Pubkey, program_id: Pubkey) -> Self {
+        PhoenixPool { address, program_id, market: None }
+    }
+
+    pub fn market(&self) -> Option<&PhoenixMarket> {
+        self.market.as_ref()
+    }
+
+    fn loaded(&self) -> Result<&PhoenixMarket, DexError> {
+        self.market.as_ref().ok_or_else(|| format!("market {} is not loaded", self.address).into())
+    }
+
+    fn active(&self) -> Result<&PhoenixMarket, DexError> {
+        let market = self.loaded()?;
+        if market.status != ACTIVE_MARKET_STATUS {
+            return Err(format!("market {} does not take orders, status {}", self.address, market.status).into());
+        }
+        Ok(market)
+    }
+}
+
+impl Pool for PhoenixPool {
+    fn dex(&self) -> &'static str {
+        "phoenix"
+    }
+
+    fn address(&self) -> Pubkey {
+        self.address
+    }
+
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn mints(&self) -> Option<(Pubkey, Pubkey)> {
+        self.market.as_ref().map(|market| (market.base_mint, market.quote_mint))
+    }
+
+    fn accounts_to_watch(&self) -> Vec<Pubkey> {
+        vec![self.address]
+    }
+
+    fn update(&mut self, pubkey: &Pubkey, account: Option<&Account>) -> Result<(), DexError> {
+        if *pubkey == self.address {
+            self.market = Some(account.and_then(|account| PhoenixMarket::decode(&account.data)).ok_or_else(|| format!("{} is not a Phoenix market", pubkey))?);
+        }
+        Ok(())
+    }
+
+    /// Midpoint of the best bid and ask.
+    fn price(&self) -> Result<Decimal, DexError> {
+        let market = self.loaded()?;
+        match (market.bids.first(), market.asks.first()) {
+            (Some(bid), Some(ask)) => Ok((market.ticks_to_price(bid.price_in_ticks) + market.ticks_to_price(ask.price_in_ticks)) / Decimal::TWO),
+            _ => Err(format!("market {} has an empty side", self.address).into()),
+        }
+    }
+
+    fn fee_rate(&self) -> Result<Decimal, DexError> {
+        Ok(Decimal::new(self.loaded()?.taker_fee_bps as i64, 4))
+    }
+
+    fn quote(&self, amount_in: u64, a_to_b: bool) -> Result<PoolQuote, DexError> {
+        let market = self.active()?;
+        let fill = market.simulate_ioc(amount_in, a_to_b)?;
+        if fill.amount_out == 0 {
+            return Err(format!("the book of market {} does not fill {}", self.address, amount_in).into());
+        }
+        Ok(PoolQuote {
+            pool: self.address,
+            a_to_b,
+            mint_in: if a_to_b { market.base_mint } else { market.quote_mint },
+            mint_out: if a_to_b { market.quote_mint } else { market.base_mint },
+            amount_in: fill.amount_in,
+            amount_out: fill.amount_out,
+            fee_amount: fill.fee_quote_lots * market.quote_lot_size,
+            legs: 1,
+            accounts_to_initialize: Vec::new(),
+        })
+    }
+
+    fn swap_instructions(&self, user: &Pubkey, _funder: &Pubkey, quote: &PoolQuote, min_amount_out: u64) -> Result<Vec<Instruction>, DexError> {
+        let market = self.active()?;
+        let order = if quote.a_to_b {
+            ImmediateOrCancelOrder {
+                side: 1,
+                num_base_lots: quote.amount_in / market.base_lot_size,
+                min_quote_lots_to_fill: min_amount_out.div_ceil(market.quote_lot_size),
+                ..Default::default()
+            }
+        } else {
+            ImmediateOrCancelOrder {
+                side: 0,
+                num_quote_lots: quote.amount_in / market.quote_lot_size,
+                min_base_lots_to_fill: min_amount_out.div_ceil(market.base_lot_size),
+                ..Default::default()
+            }
+        };
+        Ok(vec![phoenix_swap_instruction(&self.program_id, &self.address, market, user, &order)?])
+    }
+}
+
+#[tokio::test]
+async fn test_phoenix_pool() {
+    use crate::client::DEXClient;
+    use crate::rpc::InMemoryRpc;
+
+    let program_id = Pubkey::new_from_array([9; 32]);
+    let [market_address, base_mint, quote_mint, base_vault, quote_vault, user] = [1u8, 2, 3, 4, 5, 7].map(|byte| Pubkey::new_from_array([byte; 32]));
+    let put = |data: &mut Vec<u8>, offset: usize, bytes: &[u8]| data[offset..offset + bytes.len()].copy_from_slice(bytes);
+
+    // base lots of 1000 atoms, 1000 to a base unit, and ticks of 1000 quote atoms per unit
+    let (bids_size, asks_size) = (4, 4);
+    let bids_offset = PHOENIX_MARKET_HEADER_SIZE + MARKET_BODY_HEADER_SIZE;
+    let asks_offset = bids_offset + TREE_HEADER_SIZE + bids_size * ORDER_NODE_SIZE;
+    let mut data = vec![0; asks_offset + TREE_HEADER_SIZE + asks_size * ORDER_NODE_SIZE];
+    put(&mut data, 8, &ACTIVE_MARKET_STATUS.to_le_bytes());
+    put(&mut data, 16, &(bids_size as u64).to_le_bytes());
+    put(&mut data, 24, &(asks_size as u64).to_le_bytes());
+    put(&mut data, 48, base_mint.as_ref());
+    put(&mut data, 80, base_vault.as_ref());
+    put(&mut data, 112, &1000u64.to_le_bytes());
+    put(&mut data, 128, quote_mint.as_ref());
+    put(&mut data, 160, quote_vault.as_ref());
+    put(&mut data, 192, &1u64.to_le_bytes());
+    let body = PHOENIX_MARKET_HEADER_SIZE + 256;
+    put(&mut data, body, &1000u64.to_le_bytes());
+    put(&mut data, body + 8, &1000u64.to_le_bytes());
+    put(&mut data, body + 24, &5u64.to_le_bytes());
+    // (node, left, right, price in ticks, base lots), the root first
+    let trees = [(bids_offset, vec![(2, 1, 3, 99, 20), (1, 0, 0, 98, 5), (3, 0, 0, 100, 10)]), (asks_offset, vec![(1, 0, 2, 101, 10), (2, 0, 0, 103, 50)])];
+    for (offset, nodes) in trees {
+        put(&mut data, offset, &(nodes[0].0 as u32).to_le_bytes());
+        for (index, left, right, price_in_ticks, num_base_lots) in nodes {
+            let node = offset + TREE_HEADER_SIZE + (index - 1) * ORDER_NODE_SIZE;
+            put(&mut data, node, &(left as u32).to_le_bytes());
+            put(&mut data, node + 4, &(right as u32).to_le_bytes());
+            put(&mut data, node + 16, &(price_in_ticks as u64).to_le_bytes());
+            put(&mut data, node + 40, &(num_base_lots as u64).to_le_bytes());
+        }
+    }
+
+    let rpc = InMemoryRpc::new();
+    rpc.set_program_account(market_address, program_id, &[], &data);
+    let client = DEXClient::with_rpc(rpc);
+    let mut pool = PhoenixPool::new(market_address, program_id);
+    client.load_pool(&mut pool).await.unwrap();
+
+    let market = pool.market().unwrap();
+    assert_eq!(market.bids.iter().map(|order| order.price_in_ticks).collect::<Vec<_>>(), vec![100, 99, 98]);
+    assert_eq!(market.asks.iter().map(|order| order.price_in_ticks).collect::<Vec<_>>(), vec![101, 103]);
+    assert_eq!(pool.mints(), Some((base_mint, quote_mint)));
+    assert_eq!(pool.price().unwrap(), Decimal::new(1005, 4));
+    assert_eq!(pool.fee_rate().unwrap(), Decimal::new(5, 4));
+
+    // selling 25 lots: 10 at 100 and 15 at 99, less the 5 bps fee rounded up
+    let quote = pool.quote(25_500, true).unwrap();
+    assert_eq!((quote.amount_in, quote.amount_out, quote.fee_amount), (25_000, 2_483, 2));
+
+    // buying with 2000, fee included: 10 lots at 101 and 9 at 103
+    let quote = pool.quote(2_000, false).unwrap();
+    assert_eq!((quote.amount_in, quote.amount_out, quote.fee_amount), (1_938, 19_000, 1));
+    assert_eq!(quote.mint_out, base_mint);
+
+    let instructions = pool.swap_instructions(&user, &user, &quote, 18_500).unwrap();
+    let instruction = &instructions[0];
+    assert_eq!(instruction.data[..2], [PHOENIX_SWAP_TAG, IMMEDIATE_OR_CANCEL_PACKET]);
+    let order = ImmediateOrCancelOrder { side: 0, num_quote_lots: 1_938, min_base_lots_to_fill: 19, ..Default::default() };
+    assert_eq!(instruction.data[2..], order.try_to_vec().unwrap());
+    assert_eq!(instruction.accounts.len(), 9);
+    assert_eq!(instruction.accounts[4].pubkey, get_associated_token_address(&user, &base_mint));
+    assert!(instruction.accounts[3].is_signer);
+
+    // the asks hold 60 lots
+    assert_eq!(pool.quote(10_000_000, false).unwrap().amount_out, 60_000);
+    assert!(pool.update(&market_address, None).is_err());
+}
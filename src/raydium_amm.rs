@@ -0,0 +1,495 @@
+//! Raydium AMM v4, the constant-product pools most long-tail tokens trade in: `AmmInfo`
+//! decoding, the serum/OpenBook market accounts its swaps take, `swap_base_in`/`swap_base_out`,
+//! and [`RaydiumAmmPool`], the [`Pool`] over a pool, its two vaults and its market.
+//!
+//! Reserves are the vault balances less the PnL the pool owes its owner. Pools have traded
+//! without resting orders on the market since the order book was switched off, so the open
+//! orders account is passed along but not read.
+
+use anchor_lang::AnchorDeserialize;
+use rust_decimal::Decimal;
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::constant::{RAYDIUM_AMM_INFO_SIZE, RAYDIUM_AMM_SWAP_BASE_IN_TAG, RAYDIUM_AMM_SWAP_BASE_OUT_TAG, SERUM_MARKET_STATE_SIZE};
+use crate::dex::{DexError, Pool, PoolQuote};
+
+// `AmmInfo::status` values swaps are allowed in: initialized, swap only, and waiting for
+// `pool_open_time`, which the program checks against its clock
+const SWAPPABLE_STATUSES: [u64; 3] = [1, 6, 7];
+
+pub use layout::*;
+
+// the IDL derives only know anchor's own `Pubkey` by that name
+mod layout {
+    use anchor_lang::prelude::Pubkey;
+    use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+
+    #[derive(Copy, Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct Fees {
+        pub min_separate_numerator: u64,
+        pub min_separate_denominator: u64,
+        pub trade_fee_numerator: u64,
+        pub trade_fee_denominator: u64,
+        pub pnl_numerator: u64,
+        pub pnl_denominator: u64,
+        // taken from the input of every swap, 25 / 10000 on most pools
+        pub swap_fee_numerator: u64,
+        pub swap_fee_denominator: u64,
+    }
+
+    #[derive(Copy, Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct OutPutData {
+        // owed to the pool owner, still sitting in the vaults
+        pub need_take_pnl_coin: u64,
+        pub need_take_pnl_pc: u64,
+        pub total_pnl_pc: u64,
+        pub total_pnl_coin: u64,
+        pub pool_open_time: u64,
+        pub punish_pc_amount: u64,
+        pub punish_coin_amount: u64,
+        pub orderbook_to_init_time: u64,
+        pub swap_coin_in_amount: u128,
+        pub swap_pc_out_amount: u128,
+        pub swap_acc_pc_fee: u64,
+        pub swap_pc_in_amount: u128,
+        pub swap_coin_out_amount: u128,
+        pub swap_acc_coin_fee: u64,
+    }
+
+    // #[repr(C)] without padding, so the fields follow each other like Borsh's. Total 752 bytes
+    #[derive(Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct AmmInfo {
+        pub status: u64,
+        // bump of the pool authority
+        pub nonce: u64,
+        pub order_num: u64,
+        pub depth: u64,
+        pub coin_decimals: u64,
+        pub pc_decimals: u64,
+        pub state: u64,
+        pub reset_flag: u64,
+        pub min_size: u64,
+        pub vol_max_cut_ratio: u64,
+        pub amount_wave_ratio: u64,
+        pub coin_lot_size: u64,
+        pub pc_lot_size: u64,
+        pub min_price_multiplier: u64,
+        pub max_price_multiplier: u64,
+        pub sys_decimal_value: u64,
+        pub fees: Fees,
+        pub out_put: OutPutData,
+        pub coin_vault: Pubkey,
+        pub pc_vault: Pubkey,
+        pub coin_vault_mint: Pubkey,
+        pub pc_vault_mint: Pubkey,
+        pub lp_mint: Pubkey,
+        pub open_orders: Pubkey,
+        pub market: Pubkey,
+        pub market_program: Pubkey,
+        pub target_orders: Pubkey,
+        pub padding1: [u64; 8],
+        pub amm_owner: Pubkey,
+        pub lp_amount: u64,
+        pub client_order_id: u64,
+        pub recent_epoch: u64,
+        pub padding2: u64,
+    }
+}
+
+/// Decodes an `AmmInfo` account, which has no discriminator, so only its size tells it apart.
+pub fn decode_amm_info(data: &[u8]) -> Option<AmmInfo> {
+    if data.len() != RAYDIUM_AMM_INFO_SIZE {
+        return None;
+    }
+    AmmInfo::try_from_slice(data).ok()
+}
+
+/// The accounts of a serum/OpenBook market a Raydium AMM swap has to pass.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SerumMarket {
+    pub address: Pubkey,
+    pub vault_signer_nonce: u64,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub event_queue: Pubkey,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+}
+
+impl SerumMarket {
+    /// Reads a `MarketState` account, `serum` padding included.
+    pub fn decode(address: &Pubkey, data: &[u8]) -> Option<SerumMarket> {
+        if data.len() < SERUM_MARKET_STATE_SIZE || &data[..5] != b"serum" {
+            return None;
+        }
+        let pubkey = |offset: usize| Pubkey::try_from(&data[offset..offset + 32]).ok();
+        Some(SerumMarket {
+            address: *address,
+            vault_signer_nonce: u64::from_le_bytes(data[45..53].try_into().ok()?),
+            coin_vault: pubkey(117)?,
+            pc_vault: pubkey(165)?,
+            event_queue: pubkey(253)?,
+            bids: pubkey(285)?,
+            asks: pubkey(317)?,
+        })
+    }
+
+    pub fn vault_signer(&self, market_program: &Pubkey) -> Result<Pubkey, DexError> {
+        Ok(Pubkey::create_program_address(&[self.address.as_ref(), &self.vault_signer_nonce.to_le_bytes()], market_program)?)
+    }
+}
+
+pub fn get_amm_authority(program_id: &Pubkey, nonce: u64) -> Result<Pubkey, DexError> {
+    let nonce = u8::try_from(nonce).map_err(|_| format!("amm authority nonce {} is out of range", nonce))?;
+    Ok(Pubkey::create_program_address(&[b"amm authority".as_ref(), &[nonce]], program_id)?)
+}
+
+/// Output of swapping `amount_in` into a pool holding `reserve_in` and `reserve_out`, and the
+/// fee kept, rounded the way the program does.
+pub fn swap_base_in_amount_out(amount_in: u64, reserve_in: u64, reserve_out: u64, fees: &Fees) -> Result<(u64, u64), DexError> {
+    if fees.swap_fee_denominator == 0 || fees.swap_fee_numerator >= fees.swap_fee_denominator {
+        return Err("invalid swap fee".into());
+    }
+    let fee = (amount_in as u128 * fees.swap_fee_numerator as u128).div_ceil(fees.swap_fee_denominator as u128);
+    let amount_in_less_fee = amount_in as u128 - fee;
+    let amount_out = reserve_out as u128 * amount_in_less_fee / (reserve_in as u128 + amount_in_less_fee);
+    Ok((amount_out as u64, fee as u64))
+}
+
+/// Input, fee included, buying exactly `amount_out`, and the fee, rounded the way the program
+/// does.
+pub fn swap_base_out_amount_in(amount_out: u64, reserve_in: u64, reserve_out: u64, fees: &Fees) -> Result<(u64, u64), DexError> {
+    if fees.swap_fee_denominator == 0 || fees.swap_fee_numerator >= fees.swap_fee_denominator {
+        return Err("invalid swap fee".into());
+    }
+    if amount_out >= reserve_out {
+        return Err(format!("the pool holds {}, not more than the {} asked for", reserve_out, amount_out).into());
+    }
+    let amount_in_before_fee = (reserve_in as u128 * amount_out as u128).div_ceil((reserve_out - amount_out) as u128);
+    let fee = (amount_in_before_fee * fees.swap_fee_numerator as u128).div_ceil((fees.swap_fee_denominator - fees.swap_fee_numerator) as u128);
+    let amount_in = u64::try_from(amount_in_before_fee + fee).map_err(|_| "input amount overflows u64")?;
+    Ok((amount_in, fee as u64))
+}
+
+/// Exact-input or exact-output swap, as the program's instruction data.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RaydiumAmmSwap {
+    BaseIn { amount_in: u64, minimum_amount_out: u64 },
+    BaseOut { max_amount_in: u64, amount_out: u64 },
+}
+
+impl RaydiumAmmSwap {
+    pub fn pack(&self) -> Vec<u8> {
+        let (tag, first, second) = match *self {
+            RaydiumAmmSwap::BaseIn { amount_in, minimum_amount_out } => (RAYDIUM_AMM_SWAP_BASE_IN_TAG, amount_in, minimum_amount_out),
+            RaydiumAmmSwap::BaseOut { max_amount_in, amount_out } => (RAYDIUM_AMM_SWAP_BASE_OUT_TAG, max_amount_in, amount_out),
+        };
+        [vec![tag], first.to_le_bytes().to_vec(), second.to_le_bytes().to_vec()].concat()
+    }
+}
+
+/// `swap_base_in` or `swap_base_out` out of `user_source` into `user_destination`; the program
+/// tells the direction from the source's mint.
+#[allow(clippy::too_many_arguments)]
+pub fn raydium_amm_swap_instruction(
+    program_id: &Pubkey,
+    amm_address: &Pubkey,
+    amm: &AmmInfo,
+    market: &SerumMarket,
+    user_source: &Pubkey,
+    user_destination: &Pubkey,
+    user_owner: &Pubkey,
+    swap: RaydiumAmmSwap,
+) -> Result<Instruction, DexError> {
+    let pubkey = |anchor_pubkey: &anchor_lang::prelude::Pubkey| Pubkey::new_from_array(anchor_pubkey.to_bytes());
+    let market_program = pubkey(&amm.market_program);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(*amm_address, false),
+        AccountMeta::new_readonly(get_amm_authority(program_id, amm.nonce)?, false),
+        AccountMeta::new(pubkey(&amm.open_orders), false),
+        AccountMeta::new(pubkey(&amm.target_orders), false),
+        AccountMeta::new(pubkey(&amm.coin_vault), false),
+        AccountMeta::new(pubkey(&amm.pc_vault), false),
+        AccountMeta::new_readonly(market_program, false),
+        AccountMeta::new(market.address, false),
+        AccountMeta::new(market.bids, false),
+        AccountMeta::new(market.asks, false),
+        AccountMeta::new(market.event_queue, false),
+        AccountMeta::new(market.coin_vault, false),
+        AccountMeta::new(market.pc_vault, false),
+        AccountMeta::new_readonly(market.vault_signer(&market_program)?, false),
+        AccountMeta::new(*user_source, false),
+        AccountMeta::new(*user_destination, false),
+        AccountMeta::new_readonly(*user_owner, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: swap.pack(),
+    })
+}
+
+/// A Raydium AMM v4 pool with its vault balances and market. Token A is the pool's coin,
+/// token B its pc.
+pub struct RaydiumAmmPool {
+    address: Pubkey,
+    program_id: Pubkey,
+    amm: Option<AmmInfo>,
+    coin_vault_amount: Option<u64>,
+    pc_vault_amount: Option<u64>,
+    market: Option<SerumMarket>,
+}
+
+impl RaydiumAmmPool {
+    pub fn new(address: Pubkey, program_id: Pubkey) -> Self {
+        RaydiumAmmPool {
+            address,
+            program_id,
+            amm: None,
+            coin_vault_amount: None,
+            pc_vault_amount: None,
+            market: None,
+        }
+    }
+
+    pub fn amm_info(&self) -> Option<&AmmInfo> {
+        self.amm.as_ref()
+    }
+
+    fn loaded(&self) -> Result<&AmmInfo, DexError> {
+        self.amm.as_ref().ok_or_else(|| format!("pool {} is not loaded", self.address).into())
+    }
+
+    /// Coin and pc the pool trades with, the vault balances less the owner's PnL.
+    pub fn reserves(&self) -> Result<(u64, u64), DexError> {
+        let amm = self.loaded()?;
+        let (Some(coin_vault_amount), Some(pc_vault_amount)) = (self.coin_vault_amount, self.pc_vault_amount) else {
+            return Err(format!("vaults of pool {} are not loaded", self.address).into());
+        };
+        Ok((
+            coin_vault_amount.saturating_sub(amm.out_put.need_take_pnl_coin),
+            pc_vault_amount.saturating_sub(amm.out_put.need_take_pnl_pc),
+        ))
+    }
+
+    fn tradable_reserves(&self, a_to_b: bool) -> Result<(u64, u64), DexError> {
+        let amm = self.loaded()?;
+        if !SWAPPABLE_STATUSES.contains(&amm.status) {
+            return Err(format!("pool {} does not allow swaps, status {}", self.address, amm.status).into());
+        }
+        let (coin, pc) = self.reserves()?;
+        if coin == 0 || pc == 0 {
+            return Err(format!("pool {} is empty", self.address).into());
+        }
+        Ok(if a_to_b { (coin, pc) } else { (pc, coin) })
+    }
+
+    fn pool_quote(&self, a_to_b: bool, amount_in: u64, amount_out: u64, fee_amount: u64) -> Result<PoolQuote, DexError> {
+        let (mint_a, mint_b) = self.mints().ok_or("pool is not loaded")?;
+        Ok(PoolQuote {
+            pool: self.address,
+            a_to_b,
+            mint_in: if a_to_b { mint_a } else { mint_b },
+            mint_out: if a_to_b { mint_b } else { mint_a },
+            amount_in,
+            amount_out,
+            fee_amount,
+            legs: 1,
+            accounts_to_initialize: Vec::new(),
+        })
+    }
+
+    /// Simulates buying exactly `amount_out`, `amount_in` of the quote being what it costs.
+    pub fn quote_exact_out(&self, amount_out: u64, a_to_b: bool) -> Result<PoolQuote, DexError> {
+        let (reserve_in, reserve_out) = self.tradable_reserves(a_to_b)?;
+        let (amount_in, fee_amount) = swap_base_out_amount_in(amount_out, reserve_in, reserve_out, &self.loaded()?.fees)?;
+        self.pool_quote(a_to_b, amount_in, amount_out, fee_amount)
+    }
+
+    /// `swap_base_out` buying `quote.amount_out` for at most `max_amount_in`.
+    pub fn swap_base_out_instructions(&self, user: &Pubkey, quote: &PoolQuote, max_amount_in: u64) -> Result<Vec<Instruction>, DexError> {
+        self.swap_instructions_for(user, quote, RaydiumAmmSwap::BaseOut { max_amount_in, amount_out: quote.amount_out })
+    }
+
+    fn swap_instructions_for(&self, user: &Pubkey, quote: &PoolQuote, swap: RaydiumAmmSwap) -> Result<Vec<Instruction>, DexError> {
+        let amm = self.loaded()?;
+        let market = self.market.as_ref().ok_or_else(|| format!("market of pool {} is not loaded", self.address))?;
+        let instruction = raydium_amm_swap_instruction(
+            &self.program_id,
+            &self.address,
+            amm,
+            market,
+            &get_associated_token_address(user, &quote.mint_in),
+            &get_associated_token_address(user, &quote.mint_out),
+            user,
+            swap,
+        )?;
+        Ok(vec![instruction])
+    }
+}
+
+impl Pool for RaydiumAmmPool {
+    fn dex(&self) -> &'static str {
+        "raydium_amm"
+    }
+
+    fn address(&self) -> Pubkey {
+        self.address
+    }
+
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn mints(&self) -> Option<(Pubkey, Pubkey)> {
+        self.amm
+            .as_ref()
+            .map(|amm| (Pubkey::new_from_array(amm.coin_vault_mint.to_bytes()), Pubkey::new_from_array(amm.pc_vault_mint.to_bytes())))
+    }
+
+    fn accounts_to_watch(&self) -> Vec<Pubkey> {
+        let mut accounts = vec![self.address];
+        if let Some(amm) = &self.amm {
+            accounts.extend([amm.coin_vault, amm.pc_vault, amm.market].map(|pubkey| Pubkey::new_from_array(pubkey.to_bytes())));
+        }
+        accounts
+    }
+
+    fn update(&mut self, pubkey: &Pubkey, account: Option<&Account>) -> Result<(), DexError> {
+        if *pubkey == self.address {
+            self.amm = Some(account.and_then(|account| decode_amm_info(&account.data)).ok_or_else(|| format!("{} is not a Raydium AMM pool", pubkey))?);
+            return Ok(());
+        }
+        let Some(amm) = &self.amm else {
+            return Ok(());
+        };
+        let vault_amount = || account.and_then(|account| spl_token::state::Account::unpack(&account.data).ok()).map(|token_account| token_account.amount);
+        if pubkey.to_bytes() == amm.coin_vault.to_bytes() {
+            self.coin_vault_amount = vault_amount();
+        } else if pubkey.to_bytes() == amm.pc_vault.to_bytes() {
+            self.pc_vault_amount = vault_amount();
+        } else if pubkey.to_bytes() == amm.market.to_bytes() {
+            self.market = account.and_then(|account| SerumMarket::decode(pubkey, &account.data));
+        }
+        Ok(())
+    }
+
+    fn price(&self) -> Result<Decimal, DexError> {
+        let (coin, pc) = self.reserves()?;
+        if coin == 0 {
+            return Err(format!("pool {} is empty", self.address).into());
+        }
+        Ok(Decimal::from(pc) / Decimal::from(coin))
+    }
+
+    fn fee_rate(&self) -> Result<Decimal, DexError> {
+        let fees = &self.loaded()?.fees;
+        if fees.swap_fee_denominator == 0 {
+            return Err(format!("pool {} has no swap fee denominator", self.address).into());
+        }
+        Ok(Decimal::from(fees.swap_fee_numerator) / Decimal::from(fees.swap_fee_denominator))
+    }
+
+    fn quote(&self, amount_in: u64, a_to_b: bool) -> Result<PoolQuote, DexError> {
+        let (reserve_in, reserve_out) = self.tradable_reserves(a_to_b)?;
+        let (amount_out, fee_amount) = swap_base_in_amount_out(amount_in, reserve_in, reserve_out, &self.loaded()?.fees)?;
+        self.pool_quote(a_to_b, amount_in, amount_out, fee_amount)
+    }
+
+    fn swap_instructions(&self, user: &Pubkey, _funder: &Pubkey, quote: &PoolQuote, min_amount_out: u64) -> Result<Vec<Instruction>, DexError> {
+        self.swap_instructions_for(user, quote, RaydiumAmmSwap::BaseIn { amount_in: quote.amount_in, minimum_amount_out: min_amount_out })
+    }
+}
+
+#[tokio::test]
+async fn test_raydium_amm_pool() {
+    use std::str::FromStr;
+
+    use anchor_lang::AnchorSerialize;
+    use spl_token::state::{Account as TokenAccount, AccountState};
+
+    use crate::client::DEXClient;
+    use crate::dex::anchor_pubkey;
+    use crate::rpc::InMemoryRpc;
+
+    let program_id = Pubkey::from_str(crate::constant::RAYDIUM_AMM_PROGRAM_ID).unwrap();
+    let market_program = Pubkey::new_from_array([9; 32]);
+    let [pool_address, coin_mint, pc_mint, coin_vault, pc_vault, market_address, bids, asks] = [1u8, 2, 3, 4, 5, 6, 7, 8].map(|byte| Pubkey::new_from_array([byte; 32]));
+    let user = Pubkey::new_from_array([20; 32]);
+
+    let amm = AmmInfo {
+        status: 6,
+        nonce: Pubkey::find_program_address(&[b"amm authority"], &program_id).1 as u64,
+        fees: Fees { swap_fee_numerator: 25, swap_fee_denominator: 10_000, ..Default::default() },
+        out_put: OutPutData { need_take_pnl_pc: 1_000_000, ..Default::default() },
+        coin_vault: anchor_pubkey(&coin_vault),
+        pc_vault: anchor_pubkey(&pc_vault),
+        coin_vault_mint: anchor_pubkey(&coin_mint),
+        pc_vault_mint: anchor_pubkey(&pc_mint),
+        market: anchor_pubkey(&market_address),
+        market_program: anchor_pubkey(&market_program),
+        ..Default::default()
+    };
+    let amm_data = amm.try_to_vec().unwrap();
+    assert_eq!(amm_data.len(), RAYDIUM_AMM_INFO_SIZE);
+
+    let vault_signer_nonce = (0u64..).find(|nonce| Pubkey::create_program_address(&[market_address.as_ref(), &nonce.to_le_bytes()], &market_program).is_ok()).unwrap();
+    let mut market_data = vec![0; SERUM_MARKET_STATE_SIZE];
+    market_data[..5].copy_from_slice(b"serum");
+    market_data[45..53].copy_from_slice(&vault_signer_nonce.to_le_bytes());
+    market_data[285..317].copy_from_slice(bids.as_ref());
+    market_data[317..349].copy_from_slice(asks.as_ref());
+
+    let rpc = InMemoryRpc::new();
+    rpc.set_program_account(pool_address, program_id, &[], &amm_data);
+    rpc.set_program_account(market_address, market_program, &[], &market_data);
+    // the pc vault also holds 1e6 of PnL not traded with
+    for (vault, mint, amount) in [(coin_vault, coin_mint, 1_000_000_000_000), (pc_vault, pc_mint, 2_000_001_000_000)] {
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount { mint, owner: pool_address, amount, state: AccountState::Initialized, ..Default::default() }.pack_into_slice(&mut data);
+        rpc.set_program_account(vault, spl_token::id(), &[], &data);
+    }
+    let client = DEXClient::with_rpc(rpc);
+
+    let mut pool = RaydiumAmmPool::new(pool_address, program_id);
+    client.load_pool(&mut pool).await.unwrap();
+    assert_eq!(pool.accounts_to_watch(), vec![pool_address, coin_vault, pc_vault, market_address]);
+    assert_eq!(pool.reserves().unwrap(), (1_000_000_000_000, 2_000_000_000_000));
+    assert_eq!(pool.price().unwrap(), Decimal::TWO);
+    assert_eq!(pool.fee_rate().unwrap(), Decimal::new(25, 4));
+    assert_eq!(pool.mints(), Some((coin_mint, pc_mint)));
+
+    // 0.25% fee off the input, then x * y = k
+    let quote = pool.quote(1_000_000_000, true).unwrap();
+    assert_eq!((quote.amount_out, quote.fee_amount, quote.mint_out), (1_993_011_970, 2_500_000, pc_mint));
+
+    // buying that output back costs at most the input it came from
+    let exact_out = pool.quote_exact_out(quote.amount_out, true).unwrap();
+    assert!(exact_out.amount_in <= 1_000_000_000 && pool.quote(exact_out.amount_in, true).unwrap().amount_out >= quote.amount_out);
+    assert!(pool.quote_exact_out(2_000_000_000_000, true).is_err());
+
+    let instructions = pool.swap_instructions(&user, &user, &quote, quote.min_amount_out(50)).unwrap();
+    let instruction = &instructions[0];
+    assert_eq!(instruction.accounts.len(), 18);
+    assert_eq!(instruction.data, RaydiumAmmSwap::BaseIn { amount_in: 1_000_000_000, minimum_amount_out: quote.min_amount_out(50) }.pack());
+    assert_eq!(instruction.data[0], RAYDIUM_AMM_SWAP_BASE_IN_TAG);
+    assert_eq!(instruction.accounts[2].pubkey, Pubkey::find_program_address(&[b"amm authority"], &program_id).0);
+    assert_eq!((instruction.accounts[9].pubkey, instruction.accounts[10].pubkey), (bids, asks));
+    assert_eq!(instruction.accounts[15].pubkey, get_associated_token_address(&user, &coin_mint));
+    assert!(instruction.accounts[17].is_signer);
+
+    let instructions = pool.swap_base_out_instructions(&user, &exact_out, exact_out.amount_in).unwrap();
+    assert_eq!(instructions[0].data[0], RAYDIUM_AMM_SWAP_BASE_OUT_TAG);
+
+    // withdraw only
+    let amm = AmmInfo { status: 3, ..pool.amm_info().unwrap().clone() };
+    pool.update(&pool_address, Some(&InMemoryRpc::program_account(program_id, &[], &amm.try_to_vec().unwrap()))).unwrap();
+    assert!(pool.quote(1_000_000_000, true).is_err());
+}
@@ -0,0 +1,752 @@
+//! Raydium concentrated liquidity (CLMM): account layouts, tick array PDAs and bitmaps, swap
+//! simulation and `swap_v2`, and [`RaydiumClmmPool`], the [`Pool`] over a pool, its fee
+//! config and the initialized tick arrays around its price.
+//!
+//! The swap math is the same Q64.64 concentrated liquidity math as Whirlpool's, so steps go
+//! through `swap_math`. Tick boundaries come from Raydium's own tick to sqrt price table,
+//! [`get_sqrt_price_at_tick`], which differs from Whirlpool's in the last bits.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+use rust_decimal::Decimal;
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::constant::{
+    MAX_TICK_INDEX, MEMO_PROGRAM_ID, MIN_TICK_INDEX, RAYDIUM_AMM_CONFIG_DISCRIMINATOR, RAYDIUM_POOL_STATE_DISCRIMINATOR, RAYDIUM_SWAP_V2_DISCRIMINATOR,
+    RAYDIUM_TICK_ARRAY_BITMAP_EXTENSION_DISCRIMINATOR, RAYDIUM_TICK_ARRAY_BITMAP_SIZE, RAYDIUM_TICK_ARRAY_SIZE,
+    RAYDIUM_TICK_ARRAY_STATE_DISCRIMINATOR, TOKEN_2022_PROGRAM_ID,
+};
+use crate::dex::{DexError, Pool, PoolQuote};
+use crate::swap_math::{swapmath_compute_swap, tickmath_tick_index_from_sqrt_price, SwapSimulation, MIN_SQRT_PRICE_X64};
+use crate::tick_array::pricemath_sqrt_price_x64_to_price;
+
+/// Raydium's upper sqrt price bound, a little above Whirlpool's.
+pub const RAYDIUM_MAX_SQRT_PRICE_X64: u128 = 79226673521066979257578248091;
+
+/// Initialized tick arrays loaded on each side of the price, and the most one swap crosses.
+pub const MAX_RAYDIUM_TICK_ARRAYS_PER_SWAP: usize = 6;
+
+// `PoolState::status` bit that stops swaps
+const SWAP_DISABLED_STATUS_BIT: u8 = 1 << 4;
+
+pub use layout::*;
+
+// the IDL derives only know anchor's own `Pubkey` by that name
+mod layout {
+    use anchor_lang::prelude::Pubkey;
+    use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+
+    use crate::constant::RAYDIUM_TICK_ARRAY_SIZE_USIZE;
+
+    #[derive(Copy, Clone, AnchorDeserialize, AnchorSerialize, Default, Debug, PartialEq)]
+    pub struct RaydiumRewardInfo {
+        pub reward_state: u8,
+        pub open_time: u64,
+        pub end_time: u64,
+        pub last_update_time: u64,
+        pub emissions_per_second_x64: u128,
+        pub reward_total_emissioned: u64,
+        pub reward_claimed: u64,
+        pub token_mint: Pubkey,
+        pub token_vault: Pubkey,
+        pub authority: Pubkey,
+        pub reward_growth_global_x64: u128,
+    }
+
+    // #[account(zero_copy(unsafe))], packed, so the fields follow each other like Borsh's
+    #[derive(Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct PoolState {
+        pub bump: [u8; 1],
+        pub amm_config: Pubkey,
+        pub owner: Pubkey,
+
+        pub token_mint_0: Pubkey,
+        pub token_mint_1: Pubkey,
+        pub token_vault_0: Pubkey,
+        pub token_vault_1: Pubkey,
+        pub observation_key: Pubkey,
+
+        pub mint_decimals_0: u8,
+        pub mint_decimals_1: u8,
+        pub tick_spacing: u16,
+
+        pub liquidity: u128,
+        // Q64.64
+        pub sqrt_price_x64: u128,
+        pub tick_current: i32,
+
+        pub padding3: u16,
+        pub padding4: u16,
+
+        pub fee_growth_global_0_x64: u128,
+        pub fee_growth_global_1_x64: u128,
+        pub protocol_fees_token_0: u64,
+        pub protocol_fees_token_1: u64,
+        pub swap_in_amount_token_0: u128,
+        pub swap_out_amount_token_1: u128,
+        pub swap_in_amount_token_1: u128,
+        pub swap_out_amount_token_0: u128,
+
+        // bit 4 set: swaps disabled
+        pub status: u8,
+        pub padding: [u8; 7],
+
+        pub reward_infos: [RaydiumRewardInfo; 3],
+
+        // tick arrays -512..512 away from tick 0, the rest are in the bitmap extension
+        pub tick_array_bitmap: [u64; 16],
+
+        pub total_fees_token_0: u64,
+        pub total_fees_claimed_token_0: u64,
+        pub total_fees_token_1: u64,
+        pub total_fees_claimed_token_1: u64,
+        pub fund_fees_token_0: u64,
+        pub fund_fees_token_1: u64,
+
+        pub open_time: u64,
+        pub recent_epoch: u64,
+
+        pub padding1: [u64; 24],
+        pub padding2: [u64; 32],
+    }
+
+    #[derive(Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct AmmConfig {
+        pub bump: u8,
+        pub index: u16,
+        pub owner: Pubkey,
+        pub protocol_fee_rate: u32,
+        // hundredths of a basis point, like Whirlpool's `fee_rate`
+        pub trade_fee_rate: u32,
+        pub tick_spacing: u16,
+        pub fund_fee_rate: u32,
+        pub padding_u32: u32,
+        pub fund_owner: Pubkey,
+        pub padding: [u64; 3],
+    }
+
+    #[derive(Copy, Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct TickState {
+        pub tick: i32,
+        pub liquidity_net: i128,
+        // zero for ticks no position starts or ends at
+        pub liquidity_gross: u128,
+        pub fee_growth_outside_0_x64: u128,
+        pub fee_growth_outside_1_x64: u128,
+        pub reward_growths_outside_x64: [u128; 3],
+        pub padding: [u32; 13],
+    }
+
+    impl TickState {
+        pub fn is_initialized(&self) -> bool {
+            self.liquidity_gross != 0
+        }
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct TickArrayState {
+        pub pool_id: Pubkey,
+        pub start_tick_index: i32,
+        pub ticks: [TickState; RAYDIUM_TICK_ARRAY_SIZE_USIZE],
+        pub initialized_tick_count: u8,
+        pub recent_epoch: u64,
+        pub padding: [u8; 107],
+    }
+
+    /// Which tick arrays beyond the pool's own bitmap are initialized, 14 bitmaps of 512 arrays
+    /// on each side.
+    #[derive(Clone, Default, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+    pub struct TickArrayBitmapExtension {
+        pub pool_id: Pubkey,
+        pub positive_tick_array_bitmap: [[u64; 8]; 14],
+        pub negative_tick_array_bitmap: [[u64; 8]; 14],
+    }
+}
+
+/// A decoded account owned by the Raydium CLMM program, boxed like `WhirlpoolAccount`.
+pub enum RaydiumClmmAccount {
+    PoolState(Box<PoolState>),
+    AmmConfig(Box<AmmConfig>),
+    TickArray(Box<TickArrayState>),
+    BitmapExtension(Box<TickArrayBitmapExtension>),
+}
+
+/// Decodes raw account data (discriminator included). Returns `None` for the program's other
+/// account types (positions, observations, ...).
+pub fn decode_raydium_clmm_account(data: &[u8]) -> Option<RaydiumClmmAccount> {
+    if data.len() < 8 {
+        return None;
+    }
+    let (discriminator, mut body) = data.split_at(8);
+
+    if discriminator == RAYDIUM_POOL_STATE_DISCRIMINATOR {
+        PoolState::deserialize(&mut body).ok().map(|pool| RaydiumClmmAccount::PoolState(Box::new(pool)))
+    } else if discriminator == RAYDIUM_AMM_CONFIG_DISCRIMINATOR {
+        AmmConfig::deserialize(&mut body).ok().map(|config| RaydiumClmmAccount::AmmConfig(Box::new(config)))
+    } else if discriminator == RAYDIUM_TICK_ARRAY_STATE_DISCRIMINATOR {
+        TickArrayState::deserialize(&mut body).ok().map(|tick_array| RaydiumClmmAccount::TickArray(Box::new(tick_array)))
+    } else if discriminator == RAYDIUM_TICK_ARRAY_BITMAP_EXTENSION_DISCRIMINATOR {
+        TickArrayBitmapExtension::deserialize(&mut body).ok().map(|extension| RaydiumClmmAccount::BitmapExtension(Box::new(extension)))
+    } else {
+        None
+    }
+}
+
+fn ticks_in_array(tick_spacing: u16) -> i32 {
+    RAYDIUM_TICK_ARRAY_SIZE * tick_spacing as i32
+}
+
+/// Start index of the tick array holding `tick_index`.
+pub fn get_tick_array_start_index(tick_index: i32, tick_spacing: u16) -> i32 {
+    tick_index.div_euclid(ticks_in_array(tick_spacing)) * ticks_in_array(tick_spacing)
+}
+
+pub fn get_tick_array_address(program_id: &Pubkey, pool: &Pubkey, start_tick_index: i32) -> Pubkey {
+    let seeds = [b"tick_array".as_ref(), pool.as_ref(), &start_tick_index.to_be_bytes()];
+    Pubkey::find_program_address(&seeds, program_id).0
+}
+
+pub fn get_bitmap_extension_address(program_id: &Pubkey, pool: &Pubkey) -> Pubkey {
+    let seeds = [b"pool_tick_array_bitmap_extension".as_ref(), pool.as_ref()];
+    Pubkey::find_program_address(&seeds, program_id).0
+}
+
+fn bit_is_set(words: &[u64], bit: i32) -> bool {
+    words[bit as usize / 64] & (1 << (bit % 64)) != 0
+}
+
+/// Whether the tick array starting at `start_tick_index` is initialized, going by the pool's
+/// bitmap and, further out, the extension (taken as empty when missing).
+pub fn is_tick_array_initialized(pool: &PoolState, extension: Option<&TickArrayBitmapExtension>, start_tick_index: i32) -> bool {
+    let ticks_in_array = ticks_in_array(pool.tick_spacing);
+    let ticks_in_bitmap = ticks_in_array * RAYDIUM_TICK_ARRAY_BITMAP_SIZE;
+    if (-ticks_in_bitmap..ticks_in_bitmap).contains(&start_tick_index) {
+        return bit_is_set(&pool.tick_array_bitmap, start_tick_index / ticks_in_array + RAYDIUM_TICK_ARRAY_BITMAP_SIZE);
+    }
+    let Some(extension) = extension else {
+        return false;
+    };
+
+    // bitmap n covers [(n + 1) * ticks_in_bitmap, (n + 2) * ticks_in_bitmap) on either side
+    let distance = start_tick_index.abs();
+    let (bitmaps, bitmap, bit) = if start_tick_index > 0 {
+        (&extension.positive_tick_array_bitmap, distance / ticks_in_bitmap - 1, distance % ticks_in_bitmap / ticks_in_array)
+    } else {
+        let bitmap = (distance - 1) / ticks_in_bitmap - 1;
+        (&extension.negative_tick_array_bitmap, bitmap, (start_tick_index + (bitmap + 2) * ticks_in_bitmap) / ticks_in_array)
+    };
+    bitmaps.get(bitmap as usize).is_some_and(|words| bit_is_set(words, bit))
+}
+
+/// Start indexes of up to `count` initialized tick arrays a swap would cross, in swap
+/// direction, from the one holding the current tick.
+pub fn get_initialized_tick_array_start_indexes(pool: &PoolState, extension: Option<&TickArrayBitmapExtension>, a_to_b: bool, count: usize) -> Vec<i32> {
+    let ticks_in_array = ticks_in_array(pool.tick_spacing);
+    let (min_start_tick_index, max_start_tick_index) = (
+        get_tick_array_start_index(MIN_TICK_INDEX, pool.tick_spacing),
+        get_tick_array_start_index(MAX_TICK_INDEX, pool.tick_spacing),
+    );
+
+    let mut start_tick_indexes = Vec::with_capacity(count);
+    let mut start_tick_index = get_tick_array_start_index(pool.tick_current, pool.tick_spacing);
+    while start_tick_indexes.len() < count && (min_start_tick_index..=max_start_tick_index).contains(&start_tick_index) {
+        if is_tick_array_initialized(pool, extension, start_tick_index) {
+            start_tick_indexes.push(start_tick_index);
+        }
+        start_tick_index += if a_to_b { -ticks_in_array } else { ticks_in_array };
+    }
+    start_tick_indexes
+}
+
+// the next initialized tick of `tick_array` in swap direction, strictly after `tick_index` going
+// b to a and at or before it going a to b; from the array's edge when the array lies ahead
+fn next_initialized_tick(tick_array: &TickArrayState, tick_index: i32, tick_spacing: u16, a_to_b: bool) -> Option<TickState> {
+    let start_tick_index = tick_array.start_tick_index;
+    let current_start_tick_index = get_tick_array_start_index(tick_index, tick_spacing);
+    let offset = if current_start_tick_index == start_tick_index {
+        (tick_index - start_tick_index) / tick_spacing as i32
+    } else if a_to_b && start_tick_index < current_start_tick_index {
+        RAYDIUM_TICK_ARRAY_SIZE - 1
+    } else if !a_to_b && start_tick_index > current_start_tick_index {
+        -1
+    } else {
+        return None;
+    };
+
+    let mut offsets: Box<dyn Iterator<Item = i32>> = if a_to_b { Box::new((0..=offset).rev()) } else { Box::new(offset + 1..RAYDIUM_TICK_ARRAY_SIZE) };
+    offsets.find_map(|offset| Some(tick_array.ticks[offset as usize]).filter(TickState::is_initialized))
+}
+
+/// `1/sqrt(1.0001)^(2^i)` in Q64.64 for bit `i` of a tick, as the program rounds them.
+const SQRT_PRICE_FACTORS_X64: [u128; 19] = [
+    0xfffcb933bd6fb800,
+    0xfff97272373d4000,
+    0xfff2e50f5f657000,
+    0xffe5caca7e10f000,
+    0xffcb9843d60f7000,
+    0xff973b41fa98e800,
+    0xff2ea16466c9b000,
+    0xfe5dee046a9a3800,
+    0xfcbe86c7900bb000,
+    0xf987a7253ac65800,
+    0xf3392b0822bb6000,
+    0xe7159475a2caf000,
+    0xd097f3bdfd2f2000,
+    0xa9f746462d9f8000,
+    0x70d869a156f31c00,
+    0x31be135f97ed3200,
+    0x9aa508b5b85a500,
+    0x5d6af8dedc582c,
+    0x2216e584f5fa,
+];
+
+/// The sqrt price at `tick` in Q64.64, bit for bit as the program's `get_sqrt_price_at_tick`.
+pub fn get_sqrt_price_at_tick(tick: i32) -> Result<u128, DexError> {
+    if !(MIN_TICK_INDEX..=MAX_TICK_INDEX).contains(&tick) {
+        return Err(format!("tick {} is out of range", tick).into());
+    }
+    let abs_tick = tick.unsigned_abs();
+
+    // the ratio stays at or below 2^64, so each product fits in a u128
+    let mut ratio: u128 = if abs_tick & 1 != 0 { SQRT_PRICE_FACTORS_X64[0] } else { 1 << 64 };
+    for (bit, factor) in SQRT_PRICE_FACTORS_X64.iter().enumerate().skip(1) {
+        if abs_tick & (1 << bit) != 0 {
+            ratio = (ratio * factor) >> 64;
+        }
+    }
+    if tick > 0 {
+        ratio = u128::MAX / ratio;
+    }
+    Ok(ratio)
+}
+
+/// The greatest tick whose [`get_sqrt_price_at_tick`] is at most `sqrt_price_x64`.
+pub fn get_tick_at_sqrt_price(sqrt_price_x64: u128) -> Result<i32, DexError> {
+    // Whirlpool's table is off by a few units at most, which moves the tick by one at most
+    let tick = tickmath_tick_index_from_sqrt_price(sqrt_price_x64);
+    if get_sqrt_price_at_tick(tick)? > sqrt_price_x64 {
+        Ok(tick - 1)
+    } else if tick < MAX_TICK_INDEX && get_sqrt_price_at_tick(tick + 1)? <= sqrt_price_x64 {
+        Ok(tick + 1)
+    } else {
+        Ok(tick)
+    }
+}
+
+/// Simulates an exact-input swap the way the program does.
+///
+/// `tick_arrays` are initialized arrays in swap direction, see
+/// [`get_initialized_tick_array_start_indexes`]. The swap stops early, like on-chain, at
+/// `sqrt_price_limit` (0 for no limit), and when it runs past the last array, so
+/// `amount_in`/`amount_out` may be a partial fill.
+pub fn simulate_swap(
+    pool: &PoolState,
+    trade_fee_rate: u32,
+    tick_arrays: &[TickArrayState],
+    amount: u64,
+    sqrt_price_limit: u128,
+    a_to_b: bool,
+) -> Result<SwapSimulation, DexError> {
+    let fee_rate = u16::try_from(trade_fee_rate).map_err(|_| format!("trade fee rate {} is out of range", trade_fee_rate))?;
+    let sqrt_price_limit = match sqrt_price_limit {
+        0 if a_to_b => MIN_SQRT_PRICE_X64 + 1,
+        0 => RAYDIUM_MAX_SQRT_PRICE_X64 - 1,
+        limit => limit,
+    };
+    if (a_to_b && sqrt_price_limit >= pool.sqrt_price_x64) || (!a_to_b && sqrt_price_limit <= pool.sqrt_price_x64) {
+        return Err("sqrt price limit is on the wrong side of the current price".into());
+    }
+
+    let mut amount_remaining = amount;
+    let mut amount_out: u64 = 0;
+    let mut fee_total: u64 = 0;
+    let mut curr_sqrt_price = pool.sqrt_price_x64;
+    let mut curr_tick_index = pool.tick_current;
+    let mut curr_liquidity = pool.liquidity;
+    let mut array_index = 0;
+
+    while amount_remaining > 0 && curr_sqrt_price != sqrt_price_limit {
+        let next_tick = loop {
+            let Some(tick_array) = tick_arrays.get(array_index) else {
+                break None;
+            };
+            match next_initialized_tick(tick_array, curr_tick_index, pool.tick_spacing, a_to_b) {
+                Some(tick) => break Some(tick),
+                None => array_index += 1,
+            }
+        };
+        // out of loaded liquidity
+        let Some(next_tick) = next_tick else {
+            break;
+        };
+
+        let next_tick_index = next_tick.tick.clamp(MIN_TICK_INDEX, MAX_TICK_INDEX);
+        let next_tick_sqrt_price = get_sqrt_price_at_tick(next_tick_index)?;
+        let sqrt_price_target = if a_to_b { next_tick_sqrt_price.max(sqrt_price_limit) } else { next_tick_sqrt_price.min(sqrt_price_limit) };
+
+        let step = swapmath_compute_swap(amount_remaining, fee_rate, curr_liquidity, curr_sqrt_price, sqrt_price_target, true, a_to_b)?;
+        amount_remaining -= step.amount_in + step.fee_amount;
+        amount_out += step.amount_out;
+        fee_total += step.fee_amount;
+
+        if step.next_sqrt_price == next_tick_sqrt_price {
+            let liquidity_net = if a_to_b { -next_tick.liquidity_net } else { next_tick.liquidity_net };
+            curr_liquidity = curr_liquidity.checked_add_signed(liquidity_net).ok_or("liquidity underflow while crossing tick")?;
+            curr_tick_index = if a_to_b { next_tick_index - 1 } else { next_tick_index };
+        } else if step.next_sqrt_price != curr_sqrt_price {
+            curr_tick_index = get_tick_at_sqrt_price(step.next_sqrt_price)?;
+        }
+        curr_sqrt_price = step.next_sqrt_price;
+    }
+
+    Ok(SwapSimulation {
+        amount_in: amount - amount_remaining,
+        amount_out,
+        fee_amount: fee_total,
+        end_sqrt_price: curr_sqrt_price,
+        end_tick_index: curr_tick_index,
+        end_liquidity: curr_liquidity,
+        tick_arrays_used: (array_index + 1).min(tick_arrays.len()),
+    })
+}
+
+#[derive(Default, AnchorSerialize, AnchorDeserialize)]
+pub struct SwapV2Data {
+    pub amount: u64,
+    pub other_amount_threshold: u64,
+    pub sqrt_price_limit_x64: u128,
+    pub is_base_input: bool,
+}
+
+/// Raydium CLMM `swap_v2`, which takes SPL Token and Token-2022 mints alike. The bitmap
+/// extension, when given, goes first among the tick arrays, which are in swap direction.
+#[allow(clippy::too_many_arguments)]
+pub fn swap_v2_instruction(
+    program_id: &Pubkey,
+    pool_address: &Pubkey,
+    pool: &PoolState,
+    payer: &Pubkey,
+    input_token_account: &Pubkey,
+    output_token_account: &Pubkey,
+    bitmap_extension: Option<&Pubkey>,
+    tick_arrays: &[Pubkey],
+    a_to_b: bool,
+    swap_data: &SwapV2Data,
+) -> Result<Instruction, DexError> {
+    let pubkey = |anchor_pubkey: &anchor_lang::prelude::Pubkey| Pubkey::new_from_array(anchor_pubkey.to_bytes());
+    let (input_vault, output_vault, input_mint, output_mint) = if a_to_b {
+        (&pool.token_vault_0, &pool.token_vault_1, &pool.token_mint_0, &pool.token_mint_1)
+    } else {
+        (&pool.token_vault_1, &pool.token_vault_0, &pool.token_mint_1, &pool.token_mint_0)
+    };
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*payer, true),
+        AccountMeta::new_readonly(pubkey(&pool.amm_config), false),
+        AccountMeta::new(*pool_address, false),
+        AccountMeta::new(*input_token_account, false),
+        AccountMeta::new(*output_token_account, false),
+        AccountMeta::new(pubkey(input_vault), false),
+        AccountMeta::new(pubkey(output_vault), false),
+        AccountMeta::new(pubkey(&pool.observation_key), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(Pubkey::from_str(TOKEN_2022_PROGRAM_ID)?, false),
+        AccountMeta::new_readonly(Pubkey::from_str(MEMO_PROGRAM_ID)?, false),
+        AccountMeta::new_readonly(pubkey(input_mint), false),
+        AccountMeta::new_readonly(pubkey(output_mint), false),
+    ];
+    accounts.extend(bitmap_extension.map(|extension| AccountMeta::new(*extension, false)));
+    accounts.extend(tick_arrays.iter().map(|tick_array| AccountMeta::new(*tick_array, false)));
+
+    let data = {
+        let mut prefix = RAYDIUM_SWAP_V2_DISCRIMINATOR.to_vec();
+        prefix.extend(swap_data.try_to_vec()?);
+        prefix
+    };
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// A Raydium CLMM pool, its fee config, bitmap extension and the initialized tick arrays a
+/// trade in either direction can reach.
+pub struct RaydiumClmmPool {
+    address: Pubkey,
+    program_id: Pubkey,
+    bitmap_extension_address: Pubkey,
+    pool: Option<PoolState>,
+    amm_config: Option<AmmConfig>,
+    bitmap_extension: Option<TickArrayBitmapExtension>,
+    /// By start tick index, with their addresses.
+    tick_arrays: HashMap<i32, (Pubkey, TickArrayState)>,
+}
+
+impl RaydiumClmmPool {
+    pub fn new(address: Pubkey, program_id: Pubkey) -> Self {
+        RaydiumClmmPool {
+            address,
+            program_id,
+            bitmap_extension_address: get_bitmap_extension_address(&program_id, &address),
+            pool: None,
+            amm_config: None,
+            bitmap_extension: None,
+            tick_arrays: HashMap::new(),
+        }
+    }
+
+    pub fn pool_state(&self) -> Option<&PoolState> {
+        self.pool.as_ref()
+    }
+
+    /// The simulation behind [`Pool::quote`], with the tick arrays it crosses.
+    pub fn simulate(&self, amount: u64, a_to_b: bool) -> Result<(SwapSimulation, Vec<Pubkey>), DexError> {
+        let pool = self.pool.as_ref().ok_or_else(|| format!("pool {} is not loaded", self.address))?;
+        let amm_config = self.amm_config.as_ref().ok_or_else(|| format!("fee config of pool {} is not loaded", self.address))?;
+        if pool.status & SWAP_DISABLED_STATUS_BIT != 0 {
+            return Err(format!("swaps are disabled in pool {}", self.address).into());
+        }
+
+        let (pubkeys, tick_arrays): (Vec<Pubkey>, Vec<TickArrayState>) =
+            get_initialized_tick_array_start_indexes(pool, self.bitmap_extension.as_ref(), a_to_b, MAX_RAYDIUM_TICK_ARRAYS_PER_SWAP)
+                .iter()
+                .map_while(|start_tick_index| self.tick_arrays.get(start_tick_index).copied())
+                .unzip();
+        if tick_arrays.is_empty() {
+            return Err(format!("no initialized tick arrays to swap through in pool {}", self.address).into());
+        }
+
+        let simulation = simulate_swap(pool, amm_config.trade_fee_rate, &tick_arrays, amount, 0, a_to_b)?;
+        if simulation.amount_in < amount {
+            return Err(format!("{} of {} could not be swapped within the loaded tick arrays", amount - simulation.amount_in, amount).into());
+        }
+        Ok((simulation, pubkeys))
+    }
+}
+
+impl Pool for RaydiumClmmPool {
+    fn dex(&self) -> &'static str {
+        "raydium_clmm"
+    }
+
+    fn address(&self) -> Pubkey {
+        self.address
+    }
+
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn mints(&self) -> Option<(Pubkey, Pubkey)> {
+        self.pool
+            .as_ref()
+            .map(|pool| (Pubkey::new_from_array(pool.token_mint_0.to_bytes()), Pubkey::new_from_array(pool.token_mint_1.to_bytes())))
+    }
+
+    fn accounts_to_watch(&self) -> Vec<Pubkey> {
+        let mut accounts = vec![self.address];
+        if let Some(pool) = &self.pool {
+            accounts.push(Pubkey::new_from_array(pool.amm_config.to_bytes()));
+            accounts.push(self.bitmap_extension_address);
+            for a_to_b in [true, false] {
+                for start_tick_index in get_initialized_tick_array_start_indexes(pool, self.bitmap_extension.as_ref(), a_to_b, MAX_RAYDIUM_TICK_ARRAYS_PER_SWAP) {
+                    let pubkey = get_tick_array_address(&self.program_id, &self.address, start_tick_index);
+                    if !accounts.contains(&pubkey) {
+                        accounts.push(pubkey);
+                    }
+                }
+            }
+        }
+        accounts
+    }
+
+    fn update(&mut self, pubkey: &Pubkey, account: Option<&Account>) -> Result<(), DexError> {
+        let decoded = account.and_then(|account| decode_raydium_clmm_account(&account.data));
+        if *pubkey == self.address {
+            match decoded {
+                Some(RaydiumClmmAccount::PoolState(pool)) => self.pool = Some(*pool),
+                _ => return Err(format!("{} is not a Raydium CLMM pool", pubkey).into()),
+            }
+        } else if *pubkey == self.bitmap_extension_address {
+            self.bitmap_extension = match decoded {
+                Some(RaydiumClmmAccount::BitmapExtension(extension)) => Some(*extension),
+                _ => None,
+            };
+        } else {
+            match decoded {
+                Some(RaydiumClmmAccount::AmmConfig(amm_config)) => self.amm_config = Some(*amm_config),
+                Some(RaydiumClmmAccount::TickArray(tick_array)) => {
+                    self.tick_arrays.insert(tick_array.start_tick_index, (*pubkey, *tick_array));
+                }
+                _ => self.tick_arrays.retain(|_, (address, _)| address != pubkey),
+            }
+        }
+        Ok(())
+    }
+
+    fn price(&self) -> Result<Decimal, DexError> {
+        let pool = self.pool.as_ref().ok_or_else(|| format!("pool {} is not loaded", self.address))?;
+        Ok(Decimal::from_str(&pricemath_sqrt_price_x64_to_price(pool.sqrt_price_x64, 0, 0))?)
+    }
+
+    fn fee_rate(&self) -> Result<Decimal, DexError> {
+        let amm_config = self.amm_config.as_ref().ok_or_else(|| format!("config of pool {} is not loaded", self.address))?;
+        Ok(Decimal::new(amm_config.trade_fee_rate as i64, 6))
+    }
+
+    fn quote(&self, amount_in: u64, a_to_b: bool) -> Result<PoolQuote, DexError> {
+        let (simulation, _) = self.simulate(amount_in, a_to_b)?;
+        let (mint_a, mint_b) = self.mints().ok_or("pool is not loaded")?;
+        Ok(PoolQuote {
+            pool: self.address,
+            a_to_b,
+            mint_in: if a_to_b { mint_a } else { mint_b },
+            mint_out: if a_to_b { mint_b } else { mint_a },
+            amount_in: simulation.amount_in,
+            amount_out: simulation.amount_out,
+            fee_amount: simulation.fee_amount,
+            legs: 1,
+            accounts_to_initialize: Vec::new(),
+        })
+    }
+
+    fn swap_instructions(&self, user: &Pubkey, _funder: &Pubkey, quote: &PoolQuote, min_amount_out: u64) -> Result<Vec<Instruction>, DexError> {
+        let (simulation, tick_arrays) = self.simulate(quote.amount_in, quote.a_to_b)?;
+        let pool = self.pool.as_ref().ok_or("pool is not loaded")?;
+        // one array more than simulated, in case the price moves before the swap lands
+        let tick_arrays = &tick_arrays[..(simulation.tick_arrays_used + 1).min(tick_arrays.len())];
+        let bitmap_extension = self.bitmap_extension.as_ref().map(|_| &self.bitmap_extension_address);
+
+        let swap_data = SwapV2Data {
+            amount: quote.amount_in,
+            other_amount_threshold: min_amount_out,
+            sqrt_price_limit_x64: 0,
+            is_base_input: true,
+        };
+        let instruction = swap_v2_instruction(
+            &self.program_id,
+            &self.address,
+            pool,
+            user,
+            &get_associated_token_address(user, &quote.mint_in),
+            &get_associated_token_address(user, &quote.mint_out),
+            bitmap_extension,
+            tick_arrays,
+            quote.a_to_b,
+            &swap_data,
+        )?;
+        Ok(vec![instruction])
+    }
+}
+
+#[tokio::test]
+async fn test_raydium_clmm_pool() {
+    use crate::client::DEXClient;
+    use crate::dex::anchor_pubkey;
+    use crate::rpc::InMemoryRpc;
+
+    let program_id = Pubkey::from_str(crate::constant::RAYDIUM_CLMM_PROGRAM_ID).unwrap();
+    let pool_address = Pubkey::new_from_array([1; 32]);
+    let (mint_0, mint_1, amm_config_address) = (Pubkey::new_from_array([2; 32]), Pubkey::new_from_array([3; 32]), Pubkey::new_from_array([4; 32]));
+    let user = Pubkey::new_from_array([7; 32]);
+
+    // price 1 inside two positions of 5e11 each, over ticks -600..600 and -1200..1200
+    let mut pool = PoolState {
+        amm_config: anchor_pubkey(&amm_config_address),
+        token_mint_0: anchor_pubkey(&mint_0),
+        token_mint_1: anchor_pubkey(&mint_1),
+        tick_spacing: 10,
+        liquidity: 1_000_000_000_000,
+        sqrt_price_x64: 1 << 64,
+        ..Default::default()
+    };
+    for start_tick_index in [-1200, -600, 600, 1200] {
+        let bit = start_tick_index / 600 + RAYDIUM_TICK_ARRAY_BITMAP_SIZE;
+        pool.tick_array_bitmap[bit as usize / 64] |= 1 << (bit % 64);
+    }
+    let amm_config = AmmConfig { trade_fee_rate: 3000, tick_spacing: 10, ..Default::default() };
+
+    let rpc = InMemoryRpc::new();
+    rpc.set_program_account(pool_address, program_id, &RAYDIUM_POOL_STATE_DISCRIMINATOR, &pool.try_to_vec().unwrap());
+    rpc.set_program_account(amm_config_address, program_id, &RAYDIUM_AMM_CONFIG_DISCRIMINATOR, &amm_config.try_to_vec().unwrap());
+    for (start_tick_index, liquidity_net) in [(-1200, 500_000_000_000), (-600, 500_000_000_000), (600, -500_000_000_000), (1200, -500_000_000_000)] {
+        let mut tick_array = TickArrayState {
+            pool_id: anchor_pubkey(&pool_address),
+            start_tick_index,
+            ticks: [TickState::default(); crate::constant::RAYDIUM_TICK_ARRAY_SIZE_USIZE],
+            initialized_tick_count: 1,
+            recent_epoch: 0,
+            padding: [0; 107],
+        };
+        tick_array.ticks[0] = TickState { tick: start_tick_index, liquidity_net, liquidity_gross: 500_000_000_000, ..Default::default() };
+        let address = get_tick_array_address(&program_id, &pool_address, start_tick_index);
+        rpc.set_program_account(address, program_id, &RAYDIUM_TICK_ARRAY_STATE_DISCRIMINATOR, &tick_array.try_to_vec().unwrap());
+    }
+    let client = DEXClient::with_rpc(rpc);
+
+    let mut raydium_pool = RaydiumClmmPool::new(pool_address, program_id);
+    client.load_pool(&mut raydium_pool).await.unwrap();
+    // the pool, its config, the missing bitmap extension and two arrays each way
+    assert_eq!(raydium_pool.accounts_to_watch().len(), 7);
+    assert_eq!(raydium_pool.mints(), Some((mint_0, mint_1)));
+    assert_eq!(raydium_pool.price().unwrap(), Decimal::ONE);
+    assert_eq!(raydium_pool.fee_rate().unwrap(), Decimal::new(3, 3));
+
+    // within the current range: 1e9 less the 0.3% fee at price 1, less the price impact
+    let quote = raydium_pool.quote(1_000_000_000, true).unwrap();
+    assert_eq!((quote.amount_in, quote.amount_out, quote.fee_amount), (1_000_000_000, 996_006_981, 3_000_000));
+    assert_eq!((quote.mint_in, quote.mint_out), (mint_0, mint_1));
+
+    // crossing tick -600 leaves the wide position only, running past -1200 empties the pool
+    let (simulation, tick_arrays) = raydium_pool.simulate(40_000_000_000, true).unwrap();
+    assert_eq!(simulation.end_liquidity, 500_000_000_000);
+    assert!((-1200..-600).contains(&simulation.end_tick_index));
+    assert_eq!(tick_arrays[0], get_tick_array_address(&program_id, &pool_address, -600));
+    assert!(raydium_pool.simulate(100_000_000_000, true).is_err());
+    assert!(raydium_pool.quote(1_000_000_000, false).unwrap().amount_out < 1_000_000_000);
+
+    let instructions = raydium_pool.swap_instructions(&user, &user, &quote, quote.min_amount_out(50)).unwrap();
+    assert_eq!(instructions.len(), 1);
+    let instruction = &instructions[0];
+    assert_eq!(instruction.data[..8], RAYDIUM_SWAP_V2_DISCRIMINATOR);
+    let swap_data = SwapV2Data::try_from_slice(&instruction.data[8..]).unwrap();
+    assert_eq!((swap_data.amount, swap_data.other_amount_threshold, swap_data.is_base_input), (1_000_000_000, quote.min_amount_out(50), true));
+    // 13 fixed accounts, the array the swap stays in and one more
+    assert_eq!(instruction.accounts.len(), 15);
+    assert_eq!(instruction.accounts[3].pubkey, get_associated_token_address(&user, &mint_0));
+    assert_eq!(instruction.accounts[11].pubkey, mint_0);
+
+    // the program's own tick table, exact at the bounds and a few units off Whirlpool's inside
+    assert_eq!(get_sqrt_price_at_tick(MIN_TICK_INDEX).unwrap(), MIN_SQRT_PRICE_X64);
+    assert_eq!(get_sqrt_price_at_tick(MAX_TICK_INDEX).unwrap(), RAYDIUM_MAX_SQRT_PRICE_X64);
+    assert_eq!(get_sqrt_price_at_tick(0).unwrap(), 1 << 64);
+    assert_eq!(get_sqrt_price_at_tick(-19662).unwrap(), 6_902_178_135_957_565_469);
+    assert_eq!(get_sqrt_price_at_tick(20000).unwrap(), 50_140_942_267_124_942_059);
+    assert!(get_sqrt_price_at_tick(MAX_TICK_INDEX + 1).is_err());
+    for tick in [MIN_TICK_INDEX + 1, -19662, -1, 0, 1, 20000, MAX_TICK_INDEX - 1] {
+        let sqrt_price = get_sqrt_price_at_tick(tick).unwrap();
+        assert_eq!(get_tick_at_sqrt_price(sqrt_price).unwrap(), tick);
+        assert_eq!(get_tick_at_sqrt_price(sqrt_price - 1).unwrap(), tick - 1);
+    }
+
+    // tick arrays beyond the pool's own bitmap are looked up in the extension
+    let mut extension = TickArrayBitmapExtension::default();
+    assert!(!is_tick_array_initialized(&pool, Some(&extension), 307_200));
+    extension.positive_tick_array_bitmap[0][0] = 1;
+    extension.negative_tick_array_bitmap[0][7] = 1 << 63;
+    assert!(is_tick_array_initialized(&pool, Some(&extension), 307_200));
+    assert!(is_tick_array_initialized(&pool, Some(&extension), -307_800));
+    assert!(!is_tick_array_initialized(&pool, None, 307_200));
+
+    // swaps disabled
+    pool.status = SWAP_DISABLED_STATUS_BIT;
+    raydium_pool.update(&pool_address, Some(&InMemoryRpc::program_account(program_id, &RAYDIUM_POOL_STATE_DISCRIMINATOR, &pool.try_to_vec().unwrap()))).unwrap();
+    assert!(raydium_pool.quote(1_000_000_000, true).is_err());
+}
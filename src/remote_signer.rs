@@ -0,0 +1,390 @@
+//! A [`Signer`] backed by a signing service, so the secret key never enters this process.
+//!
+//! The service speaks JSON over HTTP/1.1, on TCP (`http://host:port`) or on a Unix socket
+//! (`unix:/path/to/signer.sock`):
+//!
+//! - `GET /pubkey` answers `{"pubkey": "<base58>"}`
+//! - `POST /sign` with `{"pubkey": "<base58>", "message": "<base64>"}` answers
+//!   `{"signature": "<base58>"}`
+//!
+//! An optional bearer token is sent as `Authorization`. [`LocalSigningServer`] serves the same
+//! protocol around a local keypair, for tests and local development.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+use solana_sdk::signer::{Signer, SignerError};
+
+pub type RemoteSignerError = Box<dyn std::error::Error + Send + Sync>;
+
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SignerEndpoint {
+    /// `host:port`
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl FromStr for SignerEndpoint {
+    type Err = RemoteSignerError;
+
+    fn from_str(endpoint: &str) -> Result<Self, Self::Err> {
+        if let Some(address) = endpoint.strip_prefix("http://") {
+            Ok(SignerEndpoint::Tcp(address.trim_end_matches('/').to_string()))
+        } else if let Some(path) = endpoint.strip_prefix("unix:") {
+            Ok(SignerEndpoint::Unix(PathBuf::from(path)))
+        } else {
+            Err(format!("unsupported signer endpoint {}, expected http://host:port or unix:/path", endpoint).into())
+        }
+    }
+}
+
+trait Stream: Read + Write {}
+impl<T: Read + Write> Stream for T {}
+
+impl SignerEndpoint {
+    /// Whether `source` names a signing service rather than a key.
+    pub fn is_endpoint(source: &str) -> bool {
+        source.starts_with("http://") || source.starts_with("unix:")
+    }
+
+    fn connect(&self) -> io::Result<Box<dyn Stream>> {
+        match self {
+            SignerEndpoint::Tcp(address) => {
+                let stream = TcpStream::connect(address)?;
+                stream.set_read_timeout(Some(IO_TIMEOUT))?;
+                stream.set_write_timeout(Some(IO_TIMEOUT))?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(unix)]
+            SignerEndpoint::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(IO_TIMEOUT))?;
+                stream.set_write_timeout(Some(IO_TIMEOUT))?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(not(unix))]
+            SignerEndpoint::Unix(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "unix sockets are not supported on this platform")),
+        }
+    }
+}
+
+/// Signs by asking a signing service, see the module docs for the protocol.
+///
+/// Signing blocks the calling thread on the service's answer.
+pub struct RemoteSigner {
+    endpoint: SignerEndpoint,
+    auth_token: Option<String>,
+    pubkey: Pubkey,
+}
+
+impl RemoteSigner {
+    /// Connects to `endpoint` and asks it which key it signs with.
+    pub fn connect(endpoint: &str) -> Result<Self, RemoteSignerError> {
+        Self::connect_with_auth_token(endpoint, None)
+    }
+
+    pub fn connect_with_auth_token(endpoint: &str, auth_token: Option<String>) -> Result<Self, RemoteSignerError> {
+        let mut signer = RemoteSigner {
+            endpoint: endpoint.parse()?,
+            auth_token,
+            pubkey: Pubkey::default(),
+        };
+        let response = signer.request("GET", "/pubkey", None)?;
+        signer.pubkey = response["pubkey"].as_str().ok_or("signer answered without a pubkey")?.parse()?;
+        Ok(signer)
+    }
+
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value, RemoteSignerError> {
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n",
+            method,
+            path,
+            body.len()
+        );
+        if let Some(auth_token) = &self.auth_token {
+            request += &format!("Authorization: Bearer {}\r\n", auth_token);
+        }
+        request += "\r\n";
+        request += &body;
+
+        let mut stream = self.endpoint.connect()?;
+        stream.write_all(request.as_bytes())?;
+        stream.flush()?;
+
+        let (status, body) = read_http_message(&mut BufReader::new(stream))?;
+        let status_code = status.split_whitespace().nth(1).unwrap_or_default();
+        if status_code != "200" {
+            return Err(format!("signer answered {}: {}", status, String::from_utf8_lossy(&body)).into());
+        }
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let request = json!({ "pubkey": self.pubkey.to_string(), "message": BASE64_STANDARD.encode(message) });
+        let response = self.request("POST", "/sign", Some(&request)).map_err(|err| SignerError::Connection(err.to_string()))?;
+
+        let signature = response["signature"]
+            .as_str()
+            .and_then(|signature| Signature::from_str(signature).ok())
+            .ok_or_else(|| SignerError::Protocol("signer answered without a signature".to_string()))?;
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(SignerError::Protocol("signer returned a signature that does not verify".to_string()));
+        }
+        Ok(signature)
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+/// Reads a request or response: the start line, then a body framed by `Content-Length` or
+/// chunked transfer encoding. Without either a request has no body, and a response runs to the
+/// end of the stream.
+pub(crate) fn read_http_message(reader: &mut impl BufRead) -> io::Result<(String, Vec<u8>)> {
+    let mut start_line = String::new();
+    reader.read_line(&mut start_line)?;
+
+    let mut content_length = None;
+    let mut chunked = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = value.trim().parse::<usize>().ok(),
+            "transfer-encoding" => chunked = value.trim().eq_ignore_ascii_case("chunked"),
+            _ => {}
+        }
+    }
+
+    let mut body = Vec::new();
+    if chunked {
+        loop {
+            let mut size_line = String::new();
+            reader.read_line(&mut size_line)?;
+            let size = usize::from_str_radix(size_line.trim().split(';').next().unwrap_or_default(), 16)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid chunk size"))?;
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk)?;
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    } else if let Some(content_length) = content_length {
+        body.resize(content_length, 0);
+        reader.read_exact(&mut body)?;
+    } else if start_line.starts_with("HTTP/") {
+        reader.read_to_end(&mut body)?;
+    }
+    Ok((start_line.trim_end().to_string(), body))
+}
+
+/// Serves the signing protocol for a local keypair on a background thread, which runs until
+/// the process exits.
+pub struct LocalSigningServer {
+    endpoint: String,
+    signed: Arc<AtomicUsize>,
+}
+
+struct ServerState {
+    keypair: Keypair,
+    auth_token: Option<String>,
+    signed: Arc<AtomicUsize>,
+}
+
+impl LocalSigningServer {
+    /// Listens on an ephemeral port of 127.0.0.1.
+    pub fn spawn_tcp(keypair: Keypair, auth_token: Option<String>) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let endpoint = format!("http://{}", listener.local_addr()?);
+        let (state, signed) = ServerState::new(keypair, auth_token);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                state.serve(stream);
+            }
+        });
+        Ok(LocalSigningServer { endpoint, signed })
+    }
+
+    /// Listens on a Unix socket at `path`, replacing a stale socket file.
+    #[cfg(unix)]
+    pub fn spawn_unix(keypair: Keypair, auth_token: Option<String>, path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        let endpoint = format!("unix:{}", path.display());
+        let (state, signed) = ServerState::new(keypair, auth_token);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                state.serve(stream);
+            }
+        });
+        Ok(LocalSigningServer { endpoint, signed })
+    }
+
+    /// What to pass to [`RemoteSigner::connect`].
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Messages signed so far.
+    pub fn signed(&self) -> usize {
+        self.signed.load(Ordering::SeqCst)
+    }
+}
+
+impl ServerState {
+    fn new(keypair: Keypair, auth_token: Option<String>) -> (Self, Arc<AtomicUsize>) {
+        let signed = Arc::new(AtomicUsize::new(0));
+        (ServerState { keypair, auth_token, signed: signed.clone() }, signed)
+    }
+
+    fn serve(&self, stream: impl Read + Write) {
+        let mut reader = BufReader::new(stream);
+        let (status, body) = match self.read_request(&mut reader) {
+            Ok(response) => ("200 OK", response.to_string()),
+            Err((status, message)) => (status, json!({ "error": message }).to_string()),
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        let stream = reader.get_mut();
+        let _ = stream.write_all(response.as_bytes());
+        let _ = stream.flush();
+    }
+
+    fn read_request(&self, reader: &mut impl BufRead) -> Result<Value, (&'static str, String)> {
+        let bad_request = |message: String| ("400 Bad Request", message);
+
+        // headers are read again here for the token, the body by read_http_message
+        let mut head = String::new();
+        let mut authorization = None;
+        reader.read_line(&mut head).map_err(|err| bad_request(err.to_string()))?;
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).map_err(|err| bad_request(err.to_string()))? == 0 || header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "authorization" => authorization = Some(value.trim().to_string()),
+                    "content-length" => content_length = value.trim().parse().unwrap_or(0),
+                    _ => {}
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).map_err(|err| bad_request(err.to_string()))?;
+
+        if let Some(auth_token) = &self.auth_token {
+            if authorization.as_deref() != Some(format!("Bearer {}", auth_token).as_str()) {
+                return Err(("401 Unauthorized", "missing or wrong token".to_string()));
+            }
+        }
+
+        let mut request_line = head.split_whitespace();
+        match (request_line.next(), request_line.next()) {
+            (Some("GET"), Some("/pubkey")) => Ok(json!({ "pubkey": self.keypair.pubkey().to_string() })),
+            (Some("POST"), Some("/sign")) => {
+                let request: Value = serde_json::from_slice(&body).map_err(|err| bad_request(err.to_string()))?;
+                if request["pubkey"].as_str() != Some(self.keypair.pubkey().to_string().as_str()) {
+                    return Err(("403 Forbidden", "unknown pubkey".to_string()));
+                }
+                let message = request["message"]
+                    .as_str()
+                    .and_then(|message| BASE64_STANDARD.decode(message).ok())
+                    .ok_or_else(|| bad_request("message must be base64".to_string()))?;
+                self.signed.fetch_add(1, Ordering::SeqCst);
+                Ok(json!({ "signature": self.keypair.sign_message(&message).to_string() }))
+            }
+            _ => Err(("404 Not Found", format!("no route for {}", head.trim()))),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_remote_signer() {
+    use solana_sdk::signer::keypair::keypair_from_seed;
+
+    use crate::client::DEXClient;
+    use crate::rpc::InMemoryRpc;
+
+    let keypair = keypair_from_seed(&[9; 32]).unwrap();
+    let pubkey = keypair.pubkey();
+
+    let server = LocalSigningServer::spawn_tcp(keypair.insecure_clone(), Some("secret".to_string())).unwrap();
+    assert!(RemoteSigner::connect(server.endpoint()).is_err());
+    let signer = RemoteSigner::connect_with_auth_token(server.endpoint(), Some("secret".to_string())).unwrap();
+    assert_eq!(signer.pubkey(), pubkey);
+    assert_eq!(signer.sign_message(b"hello"), keypair.sign_message(b"hello"));
+
+    // the client signs and sends through the service like with a local key
+    let client = DEXClient::with_rpc(InMemoryRpc::new());
+    let instruction = solana_sdk::system_instruction::transfer(&pubkey, &Pubkey::new_unique(), 1);
+    client.send_instructions(&signer, vec![instruction]).await.unwrap();
+    assert_eq!(client.rpc().sent_transactions().len(), 1);
+    assert_eq!(server.signed(), 2);
+
+    #[cfg(unix)]
+    {
+        let path = std::env::temp_dir().join(format!("remote_signer_{}.sock", std::process::id()));
+        let server = LocalSigningServer::spawn_unix(keypair, None, &path).unwrap();
+        let signer = RemoteSigner::connect(server.endpoint()).unwrap();
+        assert_eq!(signer.pubkey(), pubkey);
+        assert!(signer.try_sign_message(b"hello").is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    assert!("ftp://signer".parse::<SignerEndpoint>().is_err());
+}
+
+#[test]
+fn test_read_http_message() {
+    use std::io::Cursor;
+
+    // a request without a length has no body, so the next one on the connection is left unread
+    let mut reader = Cursor::new(b"GET /quote?amount=1 HTTP/1.1\r\nHost: localhost\r\n\r\nGET /next HTTP/1.1\r\n\r\n".to_vec());
+    assert_eq!(read_http_message(&mut reader).unwrap(), ("GET /quote?amount=1 HTTP/1.1".to_string(), Vec::new()));
+    assert_eq!(read_http_message(&mut reader).unwrap().0, "GET /next HTTP/1.1");
+
+    // a response without one runs to the end of the stream
+    let mut reader = Cursor::new(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{\"ok\":true}".to_vec());
+    assert_eq!(read_http_message(&mut reader).unwrap(), ("HTTP/1.1 200 OK".to_string(), b"{\"ok\":true}".to_vec()));
+
+    let mut reader = Cursor::new(b"POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}extra".to_vec());
+    assert_eq!(read_http_message(&mut reader).unwrap().1, b"{}");
+    let mut reader = Cursor::new(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n{}\r\n0\r\n\r\n".to_vec());
+    assert_eq!(read_http_message(&mut reader).unwrap().1, b"{}");
+}
@@ -0,0 +1,283 @@
+//! Best-route search over loaded pools of any venue.
+//!
+//! [`Router::best_route`] quotes every path of one pool, or two pools through an intermediate
+//! token, and can split the amount across paths that share no pool, handing it out in steps
+//! to whichever path gives the most for the next step. The resulting [`RoutePlan`] becomes the
+//! swap instructions of one transaction.
+
+use std::collections::HashSet;
+
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::dex::{apply_slippage_bps, DexError, Pool, PoolQuote};
+
+/// Steps the amount is handed out in when splitting.
+pub const SPLIT_STEPS: u64 = 20;
+
+/// Paths, best first by their quote for the whole amount, considered for a split.
+const SPLIT_CANDIDATES: usize = 6;
+
+/// One path through one or two pools, with the quote of each hop in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Route {
+    pub hops: Vec<PoolQuote>,
+}
+
+impl Route {
+    pub fn amount_in(&self) -> u64 {
+        self.hops.first().map_or(0, |hop| hop.amount_in)
+    }
+
+    pub fn amount_out(&self) -> u64 {
+        self.hops.last().map_or(0, |hop| hop.amount_out)
+    }
+}
+
+/// The amount split over one or more routes from `mint_in` to `mint_out`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoutePlan {
+    pub mint_in: Pubkey,
+    pub mint_out: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub routes: Vec<Route>,
+}
+
+impl RoutePlan {
+    /// Every token the plan goes through, each once.
+    pub fn mints(&self) -> Vec<Pubkey> {
+        let mut mints = vec![self.mint_in, self.mint_out];
+        for hop in self.routes.iter().flat_map(|route| &route.hops) {
+            if !mints.contains(&hop.mint_out) {
+                mints.push(hop.mint_out);
+            }
+        }
+        mints
+    }
+
+    pub fn min_amount_out(&self, slippage_bps: u16) -> u64 {
+        apply_slippage_bps(self.amount_out, slippage_bps)
+    }
+
+    /// The swaps of every route for `user`, whose token accounts have to exist. Each hop of a
+    /// route accepts its quote less an even share of `slippage_bps`, so the route as a whole
+    /// gives up no more than `slippage_bps` and in one transaction they fail unless together
+    /// they return [`RoutePlan::min_amount_out`], give or take rounding.
+    ///
+    /// A second hop can only spend what the first one is sure to deliver, so it is quoted
+    /// again for the first hop's minimum output; whatever the first hop delivers above that
+    /// stays in the user's account of the intermediate token.
+    pub fn swap_instructions(&self, router: &Router, user: &Pubkey, funder: &Pubkey, slippage_bps: u16) -> Result<Vec<Instruction>, DexError> {
+        let mut instructions = Vec::new();
+        for route in &self.routes {
+            let mut amount_in = route.amount_in();
+            let hop_slippage_bps = slippage_bps / route.hops.len().max(1) as u16;
+            for hop in &route.hops {
+                let pool = router.pool(&hop.pool).ok_or_else(|| format!("pool {} is not in the router", hop.pool))?;
+                let quote = if amount_in == hop.amount_in { hop.clone() } else { pool.quote(amount_in, hop.a_to_b)? };
+                let min_amount_out = quote.min_amount_out(hop_slippage_bps);
+                instructions.extend(pool.swap_instructions(user, funder, &quote, min_amount_out)?);
+                amount_in = min_amount_out;
+            }
+        }
+        Ok(instructions)
+    }
+}
+
+/// Pools of any venue, loaded, to route through.
+pub struct Router {
+    pools: Vec<Box<dyn Pool>>,
+}
+
+impl Router {
+    pub fn new(pools: Vec<Box<dyn Pool>>) -> Self {
+        Router { pools }
+    }
+
+    pub fn pools(&self) -> &[Box<dyn Pool>] {
+        &self.pools
+    }
+
+    pub fn pool(&self, address: &Pubkey) -> Option<&dyn Pool> {
+        self.pools.iter().find(|pool| pool.address() == *address).map(|pool| pool.as_ref())
+    }
+
+    /// Paths from `mint_in` to `mint_out` as pool indexes: every pool trading the pair, then
+    /// every two pools joined by a third token.
+    pub fn paths(&self, mint_in: &Pubkey, mint_out: &Pubkey) -> Vec<Vec<usize>> {
+        let other_mint = |index: usize, mint: &Pubkey| -> Option<Pubkey> {
+            let (mint_a, mint_b) = self.pools[index].mints()?;
+            match (mint_a == *mint, mint_b == *mint) {
+                (true, _) => Some(mint_b),
+                (_, true) => Some(mint_a),
+                _ => None,
+            }
+        };
+
+        let mut paths = Vec::new();
+        if mint_in == mint_out {
+            return paths;
+        }
+        for first in 0..self.pools.len() {
+            let Some(middle) = other_mint(first, mint_in) else {
+                continue;
+            };
+            if middle == *mint_out {
+                paths.push(vec![first]);
+                continue;
+            }
+            for second in 0..self.pools.len() {
+                if second != first && other_mint(second, &middle) == Some(*mint_out) {
+                    paths.push(vec![first, second]);
+                }
+            }
+        }
+        paths
+    }
+
+    // the hops of `path` for all of `amount`, or `None` where a pool can't take it all
+    fn quote_path(&self, path: &[usize], mint_in: &Pubkey, amount: u64) -> Option<Route> {
+        let mut hops = Vec::with_capacity(path.len());
+        let (mut mint, mut amount) = (*mint_in, amount);
+        for &index in path {
+            let pool = &self.pools[index];
+            let quote = pool.quote(amount, pool.a_to_b(&mint).ok()?).ok()?;
+            if quote.amount_in < amount || quote.amount_out == 0 {
+                return None;
+            }
+            (mint, amount) = (quote.mint_out, quote.amount_out);
+            hops.push(quote);
+        }
+        Some(Route { hops })
+    }
+
+    /// The most `mint_out` for `amount` of `mint_in`, over up to `max_splits` routes (1 for no
+    /// split).
+    pub fn best_route(&self, mint_in: &Pubkey, mint_out: &Pubkey, amount: u64, max_splits: usize) -> Result<RoutePlan, DexError> {
+        let mut candidates: Vec<(Vec<usize>, Route)> =
+            self.paths(mint_in, mint_out).into_iter().filter_map(|path| self.quote_path(&path, mint_in, amount).map(|route| (path, route))).collect();
+        candidates.sort_by_key(|(_, route)| std::cmp::Reverse(route.amount_out()));
+        let Some(best) = candidates.first().map(|(_, route)| route.clone()) else {
+            return Err(format!("no pool or pair of pools takes {} of {} for {}", amount, mint_in, mint_out).into());
+        };
+
+        let mut routes = vec![best.clone()];
+        if max_splits > 1 && candidates.len() > 1 {
+            candidates.truncate(SPLIT_CANDIDATES);
+            let split = self.split(&candidates, mint_in, amount, max_splits);
+            if split.iter().map(Route::amount_out).sum::<u64>() > best.amount_out() {
+                routes = split;
+            }
+        }
+
+        Ok(RoutePlan {
+            mint_in: *mint_in,
+            mint_out: *mint_out,
+            amount_in: routes.iter().map(Route::amount_in).sum(),
+            amount_out: routes.iter().map(Route::amount_out).sum(),
+            routes,
+        })
+    }
+
+    // hands `amount` out in `SPLIT_STEPS` steps, each to the path gaining the most from it,
+    // among paths sharing no pool with another path in use
+    fn split(&self, candidates: &[(Vec<usize>, Route)], mint_in: &Pubkey, amount: u64, max_splits: usize) -> Vec<Route> {
+        let step = (amount / SPLIT_STEPS).max(1);
+        let mut allocated = vec![0u64; candidates.len()];
+        let mut routes: Vec<Option<Route>> = vec![None; candidates.len()];
+        let mut remaining = amount;
+
+        while remaining > 0 {
+            let size = if remaining < 2 * step { remaining } else { step };
+            let in_use: Vec<usize> = (0..candidates.len()).filter(|&index| allocated[index] > 0).collect();
+            let pools_in_use: HashSet<usize> = in_use.iter().flat_map(|&index| candidates[index].0.iter().copied()).collect();
+
+            let mut best: Option<(usize, Route, u64)> = None;
+            for (index, (path, _)) in candidates.iter().enumerate() {
+                let used = allocated[index] > 0;
+                if !used && (in_use.len() >= max_splits || path.iter().any(|pool| pools_in_use.contains(pool))) {
+                    continue;
+                }
+                let Some(route) = self.quote_path(path, mint_in, allocated[index] + size) else {
+                    continue;
+                };
+                let gain = route.amount_out() - routes[index].as_ref().map_or(0, Route::amount_out).min(route.amount_out());
+                if best.as_ref().is_none_or(|(_, _, best_gain)| gain > *best_gain) {
+                    best = Some((index, route, gain));
+                }
+            }
+
+            let Some((index, route, _)) = best else {
+                break;
+            };
+            allocated[index] += size;
+            routes[index] = Some(route);
+            remaining -= size;
+        }
+
+        // an amount that couldn't be placed in full leaves nothing to compare
+        if remaining > 0 {
+            return Vec::new();
+        }
+        routes.into_iter().flatten().collect()
+    }
+}
+
+#[test]
+fn test_best_route() {
+    use crate::dex::constant_product_pool as pool;
+
+    let [sol, usdc, bonk] = [1u8, 2, 3].map(|byte| Pubkey::new_from_array([byte; 32]));
+    let router = Router::new(vec![
+        pool(10, (sol, usdc), (1_000_000, 100_000_000)),
+        pool(11, (usdc, sol), (45_000_000, 500_000)),
+        pool(12, (sol, bonk), (1_000_000, 1_000_000_000)),
+        pool(13, (bonk, usdc), (1_000_000_000, 100_000_000)),
+    ]);
+    assert_eq!(router.paths(&sol, &usdc), vec![vec![0], vec![1], vec![2, 3]]);
+    assert_eq!(router.paths(&usdc, &usdc), Vec::<Vec<usize>>::new());
+    assert!(router.best_route(&sol, &Pubkey::new_unique(), 1_000, 3).is_err());
+
+    // a small trade goes through the deepest pool alone
+    let plan = router.best_route(&sol, &usdc, 1_000, 3).unwrap();
+    assert_eq!(plan.routes.len(), 1);
+    assert_eq!(plan.routes[0].hops[0].pool, Pubkey::new_from_array([10; 32]));
+    assert_eq!(plan.amount_out, router.pools()[0].quote(1_000, true).unwrap().amount_out);
+
+    // a big one is spread over every path, which beats the best of them alone
+    let amount = 200_000;
+    let single = router.best_route(&sol, &usdc, amount, 1).unwrap();
+    let plan = router.best_route(&sol, &usdc, amount, 3).unwrap();
+    assert_eq!(single.routes.len(), 1);
+    assert_eq!(plan.routes.len(), 3);
+    assert_eq!(plan.amount_in, amount);
+    assert_eq!(plan.routes.iter().map(Route::amount_in).sum::<u64>(), amount);
+    assert!(plan.amount_out > single.amount_out);
+    assert_eq!(plan.mints(), vec![sol, usdc, bonk]);
+    let two_hop = plan.routes.iter().find(|route| route.hops.len() == 2).unwrap();
+    assert_eq!((two_hop.hops[0].mint_out, two_hop.hops[1].mint_in), (bonk, bonk));
+    assert_eq!(two_hop.hops[1].amount_in, two_hop.hops[0].amount_out);
+
+    // paths can't share a pool
+    let shared = Router::new(vec![pool(10, (sol, usdc), (1_000_000, 100_000_000)), pool(12, (sol, bonk), (1_000_000, 1_000_000_000)), pool(13, (bonk, usdc), (1_000, 100))]);
+    let plan = shared.best_route(&sol, &usdc, amount, 3).unwrap();
+    assert_eq!(plan.routes.len(), 1);
+
+    // the second hop spends only the first hop's minimum output, and the two hops share the
+    // slippage so the plan's minimum still holds
+    let plan = router.best_route(&sol, &usdc, amount, 3).unwrap();
+    let instructions = plan.swap_instructions(&router, &Pubkey::new_unique(), &Pubkey::new_unique(), 100).unwrap();
+    assert_eq!(instructions.len(), 4);
+    let amounts = |instruction: &Instruction| (u64::from_le_bytes(instruction.data[..8].try_into().unwrap()), u64::from_le_bytes(instruction.data[8..].try_into().unwrap()));
+    let index = instructions.iter().position(|instruction| instruction.program_id == Pubkey::new_from_array([12; 32])).unwrap();
+    let (first_in, first_min) = amounts(&instructions[index]);
+    assert_eq!((first_in, first_min), (two_hop.hops[0].amount_in, two_hop.hops[0].min_amount_out(50)));
+    let (second_in, second_min) = amounts(&instructions[index + 1]);
+    assert_eq!(second_in, first_min);
+    assert_eq!(second_min, router.pools()[3].quote(first_min, true).unwrap().min_amount_out(50));
+    let last_hop_pools = plan.routes.iter().map(|route| route.hops.last().unwrap().pool).collect::<Vec<_>>();
+    let min_out = instructions.iter().filter(|instruction| last_hop_pools.contains(&instruction.program_id)).map(|instruction| amounts(instruction).1).sum::<u64>();
+    assert!(min_out >= plan.min_amount_out(100));
+    assert!(plan.min_amount_out(100) <= plan.amount_out);
+}
@@ -0,0 +1,457 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use async_trait::async_trait;
+use solana_client::nonblocking::rpc_client::RpcClient as AsyncRpcClient;
+use solana_client::rpc_response::RpcKeyedAccount;
+use solana_client::rpc_config::{RpcSendTransactionConfig, RpcTransactionConfig};
+use solana_client::rpc_request::RpcRequest;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use solana_sdk::hash::{hashv, Hash};
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiLoadedAddresses, UiTransactionEncoding, UiTransactionTokenBalance};
+
+pub type RpcError = Box<dyn std::error::Error>;
+
+/// Blocks a blockhash stays valid for after it is handed out.
+pub const BLOCKHASH_VALIDITY_BLOCKS: u64 = 150;
+
+/// Where a sent transaction stands.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignatureStatus {
+    pub slot: u64,
+    /// Set when the transaction was executed and failed.
+    pub err: Option<TransactionError>,
+    /// Highest commitment the transaction has reached.
+    pub commitment: CommitmentLevel,
+}
+
+/// What a confirmed transaction did, from `getTransaction`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionMeta {
+    pub slot: u64,
+    pub err: Option<TransactionError>,
+    /// Lamports paid by the first account.
+    pub fee: u64,
+    /// Static keys, then the writable and the readonly ones loaded from lookup tables, the
+    /// order balances are indexed in.
+    pub account_keys: Vec<Pubkey>,
+    pub pre_balances: Vec<u64>,
+    pub post_balances: Vec<u64>,
+    pub pre_token_balances: Vec<TokenBalance>,
+    pub post_token_balances: Vec<TokenBalance>,
+}
+
+/// A token account's balance before or after a transaction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenBalance {
+    pub account_index: usize,
+    pub mint: Pubkey,
+    pub owner: Option<Pubkey>,
+    pub amount: u64,
+    pub decimals: u8,
+}
+
+/// The RPC calls `DEXClient` depends on.
+///
+/// Implemented for the nonblocking `RpcClient`, and by [`InMemoryRpc`] for offline tests.
+#[async_trait]
+pub trait SolanaRpc: Send + Sync {
+    async fn get_account(&self, pubkey: &Pubkey) -> Result<Account, RpcError>;
+
+    async fn get_account_data(&self, pubkey: &Pubkey) -> Result<Vec<u8>, RpcError> {
+        Ok(self.get_account(pubkey).await?.data)
+    }
+
+    /// Fetches `pubkeys` in one call and returns the slot they were read at. Accounts that
+    /// don't exist are `None`.
+    async fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<(u64, Vec<Option<Account>>), RpcError>;
+
+    async fn get_latest_blockhash(&self) -> Result<Hash, RpcError>;
+
+    /// The latest blockhash and the last block height transactions using it can land at.
+    async fn get_latest_blockhash_with_last_valid_block_height(&self) -> Result<(Hash, u64), RpcError>;
+
+    async fn get_block_height(&self) -> Result<u64, RpcError>;
+
+    async fn send_and_confirm_transaction(&self, transaction: &Transaction) -> Result<Signature, RpcError>;
+
+    /// Submits `transaction` without waiting for it, leaving retries to the caller. Preflight
+    /// simulation is skipped when `skip_preflight` is set, as for rebroadcasts.
+    async fn send_transaction(&self, transaction: &Transaction, skip_preflight: bool) -> Result<Signature, RpcError>;
+
+    /// Statuses of `signatures` in order, `None` for transactions the cluster hasn't seen.
+    async fn get_signature_statuses(&self, signatures: &[Signature]) -> Result<Vec<Option<SignatureStatus>>, RpcError>;
+
+    /// The block height, then the statuses of `signatures` read after it from the same node.
+    /// A transaction still unknown past its last valid block height can then never land,
+    /// which a status read from a node lagging the height would not prove.
+    async fn poll_signature_statuses(&self, signatures: &[Signature]) -> Result<(u64, Vec<Option<SignatureStatus>>), RpcError> {
+        let block_height = self.get_block_height().await?;
+        Ok((block_height, self.get_signature_statuses(signatures).await?))
+    }
+
+    /// Raw token amount held by an SPL token account.
+    async fn get_token_account_balance(&self, token_account: &Pubkey) -> Result<u64, RpcError>;
+
+    /// `None` until the transaction is confirmed.
+    async fn get_transaction_meta(&self, signature: &Signature) -> Result<Option<TransactionMeta>, RpcError>;
+}
+
+#[async_trait]
+impl SolanaRpc for AsyncRpcClient {
+    async fn get_account(&self, pubkey: &Pubkey) -> Result<Account, RpcError> {
+        Ok(AsyncRpcClient::get_account(self, pubkey).await?)
+    }
+
+    async fn get_account_data(&self, pubkey: &Pubkey) -> Result<Vec<u8>, RpcError> {
+        Ok(AsyncRpcClient::get_account_data(self, pubkey).await?)
+    }
+
+    async fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<(u64, Vec<Option<Account>>), RpcError> {
+        let response = AsyncRpcClient::get_multiple_accounts_with_commitment(self, pubkeys, self.commitment()).await?;
+        Ok((response.context.slot, response.value))
+    }
+
+    async fn get_latest_blockhash(&self) -> Result<Hash, RpcError> {
+        Ok(AsyncRpcClient::get_latest_blockhash(self).await?)
+    }
+
+    async fn get_latest_blockhash_with_last_valid_block_height(&self) -> Result<(Hash, u64), RpcError> {
+        Ok(AsyncRpcClient::get_latest_blockhash_with_commitment(self, self.commitment()).await?)
+    }
+
+    async fn get_block_height(&self) -> Result<u64, RpcError> {
+        Ok(AsyncRpcClient::get_block_height(self).await?)
+    }
+
+    async fn send_and_confirm_transaction(&self, transaction: &Transaction) -> Result<Signature, RpcError> {
+        Ok(AsyncRpcClient::send_and_confirm_transaction(self, transaction).await?)
+    }
+
+    async fn send_transaction(&self, transaction: &Transaction, skip_preflight: bool) -> Result<Signature, RpcError> {
+        let config = RpcSendTransactionConfig {
+            skip_preflight,
+            preflight_commitment: Some(self.commitment().commitment),
+            // rebroadcasting is up to the caller
+            max_retries: Some(0),
+            ..RpcSendTransactionConfig::default()
+        };
+        Ok(AsyncRpcClient::send_transaction_with_config(self, transaction, config).await?)
+    }
+
+    async fn get_signature_statuses(&self, signatures: &[Signature]) -> Result<Vec<Option<SignatureStatus>>, RpcError> {
+        let statuses = AsyncRpcClient::get_signature_statuses(self, signatures).await?.value;
+        Ok(statuses
+            .into_iter()
+            .map(|status| {
+                status.map(|status| {
+                    let commitment = if status.confirmations.is_none() {
+                        CommitmentLevel::Finalized
+                    } else if status.satisfies_commitment(CommitmentConfig::confirmed()) {
+                        CommitmentLevel::Confirmed
+                    } else {
+                        CommitmentLevel::Processed
+                    };
+                    SignatureStatus { slot: status.slot, err: status.err, commitment }
+                })
+            })
+            .collect())
+    }
+
+    async fn get_token_account_balance(&self, token_account: &Pubkey) -> Result<u64, RpcError> {
+        let balance = AsyncRpcClient::get_token_account_balance(self, token_account).await?;
+        Ok(balance.amount.parse()?)
+    }
+
+    async fn get_transaction_meta(&self, signature: &Signature) -> Result<Option<TransactionMeta>, RpcError> {
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            // getTransaction doesn't serve processed
+            commitment: Some(if self.commitment().commitment == CommitmentLevel::Finalized { CommitmentConfig::finalized() } else { CommitmentConfig::confirmed() }),
+            max_supported_transaction_version: Some(0),
+        };
+        let transaction: Option<EncodedConfirmedTransactionWithStatusMeta> = self.send(RpcRequest::GetTransaction, serde_json::json!([signature.to_string(), config])).await?;
+        transaction.map(transaction_meta).transpose()
+    }
+}
+
+fn transaction_meta(transaction: EncodedConfirmedTransactionWithStatusMeta) -> Result<TransactionMeta, RpcError> {
+    let meta = transaction.transaction.meta.ok_or("transaction has no status meta")?;
+    let decoded = transaction.transaction.transaction.decode().ok_or("undecodable transaction")?;
+
+    let mut account_keys = decoded.message.static_account_keys().to_vec();
+    if let Some(loaded) = Option::<UiLoadedAddresses>::from(meta.loaded_addresses) {
+        for address in loaded.writable.iter().chain(&loaded.readonly) {
+            account_keys.push(address.parse()?);
+        }
+    }
+    let token_balances = |balances: Option<Vec<UiTransactionTokenBalance>>| -> Result<Vec<TokenBalance>, RpcError> {
+        balances
+            .unwrap_or_default()
+            .into_iter()
+            .map(|balance| {
+                Ok(TokenBalance {
+                    account_index: balance.account_index as usize,
+                    mint: balance.mint.parse()?,
+                    owner: Option::<String>::from(balance.owner).map(|owner| owner.parse()).transpose()?,
+                    amount: balance.ui_token_amount.amount.parse()?,
+                    decimals: balance.ui_token_amount.decimals,
+                })
+            })
+            .collect()
+    };
+
+    Ok(TransactionMeta {
+        slot: transaction.slot,
+        err: meta.err,
+        fee: meta.fee,
+        account_keys,
+        pre_balances: meta.pre_balances,
+        post_balances: meta.post_balances,
+        pre_token_balances: token_balances(meta.pre_token_balances.into())?,
+        post_token_balances: token_balances(meta.post_token_balances.into())?,
+    })
+}
+
+/// An account store standing in for a cluster.
+///
+/// Transactions are signature-checked and recorded but not executed, so account state only
+/// changes through [`InMemoryRpc::set_account`]. Sent transactions land right away at
+/// `finalized`, unless dropped or failed on purpose, or sent after their blockhash expired.
+pub struct InMemoryRpc {
+    accounts: RwLock<HashMap<Pubkey, Account>>,
+    slot: u64,
+    latest_blockhash: Hash,
+    sent_transactions: Mutex<Vec<Transaction>>,
+    chain: Mutex<InMemoryChain>,
+    unavailable: AtomicBool,
+}
+
+/// What `send_transaction` and the status calls see.
+#[derive(Default)]
+struct InMemoryChain {
+    block_height: u64,
+    block_height_step: u64,
+    /// Last valid block height of every blockhash handed out.
+    blockhashes: HashMap<Hash, u64>,
+    landed: HashMap<Signature, SignatureStatus>,
+    drop_next: usize,
+    send_errors: VecDeque<TransactionError>,
+    metas: HashMap<Signature, TransactionMeta>,
+}
+
+impl Default for InMemoryRpc {
+    fn default() -> Self {
+        InMemoryRpc {
+            accounts: RwLock::new(HashMap::new()),
+            slot: 0,
+            latest_blockhash: Hash::new_from_array([1; 32]),
+            sent_transactions: Mutex::new(Vec::new()),
+            chain: Mutex::new(InMemoryChain::default()),
+            unavailable: AtomicBool::new(false),
+        }
+    }
+}
+
+impl InMemoryRpc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Slot reported by `get_multiple_accounts`.
+    pub fn with_slot(mut self, slot: u64) -> Self {
+        self.slot = slot;
+        self
+    }
+
+    /// Loads every `*.json` account file in `dir`.
+    ///
+    /// Files use the keyed-account JSON written by `solana account --output json`, the same
+    /// format `solana-test-validator --account` accepts.
+    pub fn from_fixture_dir(dir: impl AsRef<Path>) -> Result<Self, RpcError> {
+        let rpc = Self::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "json") {
+                rpc.load_account_file(&path)?;
+            }
+        }
+        Ok(rpc)
+    }
+
+    pub fn load_account_file(&self, path: impl AsRef<Path>) -> Result<Pubkey, RpcError> {
+        let path = path.as_ref();
+        let keyed_account: RpcKeyedAccount = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let pubkey = keyed_account.pubkey.parse::<Pubkey>()?;
+        let account: Account = keyed_account
+            .account
+            .decode()
+            .ok_or_else(|| format!("{}: unsupported account data encoding", path.display()))?;
+        self.set_account(pubkey, account);
+        Ok(pubkey)
+    }
+
+    pub fn set_account(&self, pubkey: Pubkey, account: Account) {
+        self.accounts.write().unwrap().insert(pubkey, account);
+    }
+
+    /// A rent-free account owned by `owner` holding `discriminator` followed by `data`; pass an
+    /// empty discriminator for programs that don't prefix their accounts.
+    pub fn program_account(owner: Pubkey, discriminator: &[u8], data: &[u8]) -> Account {
+        Account { lamports: 1, data: [discriminator, data].concat(), owner, executable: false, rent_epoch: 0 }
+    }
+
+    pub fn set_program_account(&self, pubkey: Pubkey, owner: Pubkey, discriminator: &[u8], data: &[u8]) {
+        self.set_account(pubkey, Self::program_account(owner, discriminator, data));
+    }
+
+    /// Transactions passed to `send_and_confirm_transaction` or `send_transaction`, oldest
+    /// first, including rebroadcasts.
+    pub fn sent_transactions(&self) -> Vec<Transaction> {
+        self.sent_transactions.lock().unwrap().clone()
+    }
+
+    pub fn set_block_height(&self, block_height: u64) {
+        self.chain.lock().unwrap().block_height = block_height;
+    }
+
+    /// Advances the block height by `step` on every `get_block_height`, standing in for the
+    /// time that passes between polls.
+    pub fn with_block_height_step(self, step: u64) -> Self {
+        self.chain.lock().unwrap().block_height_step = step;
+        self
+    }
+
+    /// The next `count` transactions sent are accepted but never land.
+    pub fn drop_next_transactions(&self, count: usize) {
+        self.chain.lock().unwrap().drop_next += count;
+    }
+
+    /// The next `send_transaction` fails its preflight with `err`.
+    pub fn fail_next_send(&self, err: TransactionError) {
+        self.chain.lock().unwrap().send_errors.push_back(err);
+    }
+
+    /// While set, every call fails with a connection error, like a node that is down.
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.unavailable.store(unavailable, Ordering::SeqCst);
+    }
+
+    fn check_available(&self) -> Result<(), RpcError> {
+        if self.unavailable.load(Ordering::SeqCst) {
+            return Err(Box::new(std::io::Error::from(std::io::ErrorKind::ConnectionRefused)));
+        }
+        Ok(())
+    }
+
+    /// What `get_transaction_meta` returns for `signature`.
+    pub fn set_transaction_meta(&self, signature: Signature, meta: TransactionMeta) {
+        self.chain.lock().unwrap().metas.insert(signature, meta);
+    }
+
+    /// Records `transaction` as executed with `err`, as if it failed on chain.
+    pub fn land_failed(&self, transaction: &Transaction, err: TransactionError) {
+        let status = SignatureStatus { slot: self.slot, err: Some(err), commitment: CommitmentLevel::Finalized };
+        self.chain.lock().unwrap().landed.insert(transaction.signatures[0], status);
+    }
+}
+
+#[async_trait]
+impl SolanaRpc for InMemoryRpc {
+    async fn get_account(&self, pubkey: &Pubkey) -> Result<Account, RpcError> {
+        self.check_available()?;
+        self.accounts
+            .read()
+            .unwrap()
+            .get(pubkey)
+            .cloned()
+            .ok_or_else(|| format!("AccountNotFound: pubkey={}", pubkey).into())
+    }
+
+    async fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<(u64, Vec<Option<Account>>), RpcError> {
+        self.check_available()?;
+        let accounts = self.accounts.read().unwrap();
+        Ok((self.slot, pubkeys.iter().map(|pubkey| accounts.get(pubkey).cloned()).collect()))
+    }
+
+    async fn get_latest_blockhash(&self) -> Result<Hash, RpcError> {
+        self.check_available()?;
+        Ok(self.latest_blockhash)
+    }
+
+    async fn get_latest_blockhash_with_last_valid_block_height(&self) -> Result<(Hash, u64), RpcError> {
+        self.check_available()?;
+        let mut chain = self.chain.lock().unwrap();
+        let blockhash = hashv(&[self.latest_blockhash.as_ref(), &chain.block_height.to_le_bytes()]);
+        let last_valid_block_height = chain.block_height + BLOCKHASH_VALIDITY_BLOCKS;
+        chain.blockhashes.insert(blockhash, last_valid_block_height);
+        Ok((blockhash, last_valid_block_height))
+    }
+
+    async fn get_block_height(&self) -> Result<u64, RpcError> {
+        self.check_available()?;
+        let mut chain = self.chain.lock().unwrap();
+        let block_height = chain.block_height;
+        chain.block_height += chain.block_height_step;
+        Ok(block_height)
+    }
+
+    async fn send_and_confirm_transaction(&self, transaction: &Transaction) -> Result<Signature, RpcError> {
+        self.check_available()?;
+        transaction.verify()?;
+        self.sent_transactions.lock().unwrap().push(transaction.clone());
+        Ok(transaction.signatures[0])
+    }
+
+    async fn send_transaction(&self, transaction: &Transaction, skip_preflight: bool) -> Result<Signature, RpcError> {
+        self.check_available()?;
+        transaction.verify()?;
+        let signature = transaction.signatures[0];
+        let mut chain = self.chain.lock().unwrap();
+        if let Some(err) = chain.send_errors.pop_front() {
+            return Err(Box::new(err));
+        }
+        if !skip_preflight && chain.landed.contains_key(&signature) {
+            return Err(Box::new(TransactionError::AlreadyProcessed));
+        }
+        self.sent_transactions.lock().unwrap().push(transaction.clone());
+
+        // blockhashes not handed out by this rpc never expire
+        let expired = chain
+            .blockhashes
+            .get(&transaction.message.recent_blockhash)
+            .is_some_and(|last_valid_block_height| chain.block_height > *last_valid_block_height);
+        if chain.drop_next > 0 {
+            chain.drop_next -= 1;
+        } else if !expired {
+            let status = SignatureStatus { slot: self.slot, err: None, commitment: CommitmentLevel::Finalized };
+            chain.landed.entry(signature).or_insert(status);
+        }
+        Ok(signature)
+    }
+
+    async fn get_signature_statuses(&self, signatures: &[Signature]) -> Result<Vec<Option<SignatureStatus>>, RpcError> {
+        self.check_available()?;
+        let chain = self.chain.lock().unwrap();
+        Ok(signatures.iter().map(|signature| chain.landed.get(signature).cloned()).collect())
+    }
+
+    async fn get_token_account_balance(&self, token_account: &Pubkey) -> Result<u64, RpcError> {
+        self.check_available()?;
+        let account = self.get_account(token_account).await?;
+        Ok(spl_token::state::Account::unpack(&account.data)?.amount)
+    }
+
+    async fn get_transaction_meta(&self, signature: &Signature) -> Result<Option<TransactionMeta>, RpcError> {
+        self.check_available()?;
+        Ok(self.chain.lock().unwrap().metas.get(signature).cloned())
+    }
+}
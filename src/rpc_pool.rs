@@ -0,0 +1,522 @@
+//! Several RPC endpoints behind one [`SolanaRpc`], so a single flaky provider can't stall
+//! everything.
+//!
+//! Each call goes to the healthy endpoint with the lowest observed latency that has rate
+//! limit budget left, and fails over to the next one on connection errors, timeouts and
+//! other retryable errors. Errors that every node would return, like a failing preflight,
+//! are passed on right away. Status polls stick to the endpoint that handed out the last
+//! blockhash, and read the block height and statuses together, so a sender never compares
+//! one node's height with another node's view of its transaction.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
+use solana_client::client_error::ClientError;
+use solana_client::nonblocking::rpc_client::RpcClient as AsyncRpcClient;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::{Transaction, TransactionError};
+
+use crate::config::{ClusterConfig, RpcEndpointConfig};
+use crate::rpc::{RpcError, SignatureStatus, SolanaRpc, TransactionMeta};
+use crate::sender::{classify_error, ErrorClass};
+
+type RpcFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, RpcError>> + Send + 'a>>;
+
+#[derive(Clone, Debug)]
+pub struct RpcPoolOptions {
+    /// Failed calls in a row after which an endpoint is benched.
+    pub max_consecutive_failures: u32,
+    /// How long a benched endpoint is skipped.
+    pub unhealthy_cooldown: Duration,
+    /// Blocks an endpoint may trail the highest one seen by [`RpcPool::check_health`].
+    pub max_block_height_lag: u64,
+    /// Calls taking longer count as failed.
+    pub request_timeout: Duration,
+    /// Send transactions through every sending endpoint at once.
+    pub fan_out_sends: bool,
+}
+
+impl Default for RpcPoolOptions {
+    fn default() -> Self {
+        RpcPoolOptions {
+            max_consecutive_failures: 3,
+            unhealthy_cooldown: Duration::from_secs(10),
+            max_block_height_lag: 50,
+            request_timeout: Duration::from_secs(10),
+            fan_out_sends: false,
+        }
+    }
+}
+
+/// An endpoint's health as last seen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EndpointStatus {
+    pub url: String,
+    pub healthy: bool,
+    /// Moving average over successful calls.
+    pub latency: Option<Duration>,
+    /// As of the last health check.
+    pub block_height: Option<u64>,
+    pub requests: u64,
+    pub failures: u64,
+}
+
+struct RpcEndpoint<R> {
+    url: String,
+    rpc: R,
+    send: bool,
+    rate_limit: Option<Mutex<TokenBucket>>,
+    state: Mutex<EndpointState>,
+}
+
+#[derive(Default)]
+struct EndpointState {
+    latency: Option<Duration>,
+    consecutive_failures: u32,
+    unhealthy_until: Option<Instant>,
+    block_height: Option<u64>,
+    requests: u64,
+    failures: u64,
+}
+
+struct TokenBucket {
+    requests_per_second: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(requests_per_second: f64) -> Self {
+        TokenBucket { requests_per_second, tokens: requests_per_second.max(1.0), refilled_at: Instant::now() }
+    }
+
+    /// Takes a token, or tells how long until one is available.
+    fn try_acquire(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let burst = self.requests_per_second.max(1.0);
+        self.tokens = (self.tokens + now.duration_since(self.refilled_at).as_secs_f64() * self.requests_per_second).min(burst);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.requests_per_second))
+        }
+    }
+}
+
+impl<R> RpcEndpoint<R> {
+    fn try_acquire(&self) -> Result<(), Duration> {
+        self.rate_limit.as_ref().map_or(Ok(()), |rate_limit| rate_limit.lock().unwrap().try_acquire())
+    }
+
+    fn record_success(&self, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.requests += 1;
+        state.consecutive_failures = 0;
+        state.unhealthy_until = None;
+        // exponential moving average, a fifth of the new sample
+        state.latency = Some(state.latency.map_or(latency, |average| (average * 4 + latency) / 5));
+    }
+
+    fn record_failure(&self, options: &RpcPoolOptions) {
+        let mut state = self.state.lock().unwrap();
+        state.requests += 1;
+        state.failures += 1;
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= options.max_consecutive_failures {
+            state.unhealthy_until = Some(Instant::now() + options.unhealthy_cooldown);
+        }
+    }
+
+    fn status(&self, now: Instant) -> EndpointStatus {
+        let state = self.state.lock().unwrap();
+        EndpointStatus {
+            url: self.url.clone(),
+            healthy: state.unhealthy_until.is_none_or(|until| now >= until),
+            latency: state.latency,
+            block_height: state.block_height,
+            requests: state.requests,
+            failures: state.failures,
+        }
+    }
+}
+
+/// A [`SolanaRpc`] over several endpoints, see the module docs.
+pub struct RpcPool<R: SolanaRpc = AsyncRpcClient> {
+    endpoints: Vec<RpcEndpoint<R>>,
+    options: RpcPoolOptions,
+    /// Endpoint that handed out the last blockhash, preferred for status polls.
+    pinned: Mutex<Option<usize>>,
+}
+
+impl RpcPool {
+    /// A pool over `rpc_url` and the profile's `rpc_endpoints`.
+    pub fn from_config(config: &ClusterConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let commitment = config.commitment_config()?;
+        let options = RpcPoolOptions { fan_out_sends: config.fan_out_sends, ..RpcPoolOptions::default() };
+        Ok(Self::from_endpoints(&config.all_rpc_endpoints(), commitment, options))
+    }
+
+    pub fn from_endpoints(endpoints: &[RpcEndpointConfig], commitment: CommitmentConfig, options: RpcPoolOptions) -> Self {
+        endpoints.iter().fold(RpcPool::new(options), |pool, endpoint| {
+            let rpc = AsyncRpcClient::new_with_commitment(endpoint.url.clone(), commitment);
+            pool.with_endpoint(&endpoint.url, rpc, endpoint.requests_per_second, endpoint.send)
+        })
+    }
+}
+
+impl<R: SolanaRpc> RpcPool<R> {
+    pub fn new(options: RpcPoolOptions) -> Self {
+        RpcPool { endpoints: Vec::new(), options, pinned: Mutex::new(None) }
+    }
+
+    /// Adds an endpoint, rate limited to `requests_per_second` if given. Transactions only go
+    /// through endpoints with `send` set.
+    pub fn with_endpoint(mut self, url: &str, rpc: R, requests_per_second: Option<f64>, send: bool) -> Self {
+        self.endpoints.push(RpcEndpoint {
+            url: url.to_string(),
+            rpc,
+            send,
+            rate_limit: requests_per_second.map(|requests_per_second| Mutex::new(TokenBucket::new(requests_per_second))),
+            state: Mutex::new(EndpointState::default()),
+        });
+        self
+    }
+
+    /// The endpoint added `index`th.
+    pub fn endpoint(&self, index: usize) -> &R {
+        &self.endpoints[index].rpc
+    }
+
+    pub fn status(&self) -> Vec<EndpointStatus> {
+        let now = Instant::now();
+        self.endpoints.iter().map(|endpoint| endpoint.status(now)).collect()
+    }
+
+    /// Asks every endpoint for its block height, and benches the ones that fail or trail the
+    /// highest by more than `max_block_height_lag`.
+    pub async fn check_health(&self) -> Vec<EndpointStatus> {
+        let checks = self.endpoints.iter().map(|endpoint| async move {
+            let started = Instant::now();
+            match tokio::time::timeout(self.options.request_timeout, endpoint.rpc.get_block_height()).await {
+                Ok(Ok(block_height)) => {
+                    endpoint.record_success(started.elapsed());
+                    endpoint.state.lock().unwrap().block_height = Some(block_height);
+                }
+                _ => {
+                    // one failed check is enough to bench it
+                    let mut state = endpoint.state.lock().unwrap();
+                    state.failures += 1;
+                    state.consecutive_failures = state.consecutive_failures.max(self.options.max_consecutive_failures);
+                    state.unhealthy_until = Some(Instant::now() + self.options.unhealthy_cooldown);
+                }
+            }
+        });
+        futures::future::join_all(checks).await;
+
+        let highest = self.endpoints.iter().filter_map(|endpoint| endpoint.state.lock().unwrap().block_height).max();
+        for endpoint in &self.endpoints {
+            let mut state = endpoint.state.lock().unwrap();
+            if let (Some(highest), Some(block_height)) = (highest, state.block_height) {
+                if highest - block_height > self.options.max_block_height_lag {
+                    state.unhealthy_until = Some(Instant::now() + self.options.unhealthy_cooldown);
+                }
+            }
+        }
+        self.status()
+    }
+
+    /// Endpoint indexes to try in order: healthy ones by latency, untried first, then benched
+    /// ones as a last resort. A healthy `preferred` endpoint goes first.
+    fn candidates(&self, sending: bool, preferred: Option<usize>) -> Vec<usize> {
+        let now = Instant::now();
+        let mut candidates: Vec<usize> = (0..self.endpoints.len()).filter(|index| !sending || self.endpoints[*index].send).collect();
+        candidates.sort_by_key(|index| {
+            let state = self.endpoints[*index].state.lock().unwrap();
+            let unhealthy = state.unhealthy_until.is_some_and(|until| now < until);
+            (unhealthy, Some(*index) != preferred, state.latency.unwrap_or_default())
+        });
+        candidates
+    }
+
+    /// Runs `call` on the best endpoint, failing over while errors are retryable.
+    async fn call<'s, T>(&'s self, sending: bool, call: impl Fn(&'s R) -> RpcFuture<'s, T>) -> Result<T, RpcError> {
+        self.call_on(None, sending, call).await.map(|(value, _)| value)
+    }
+
+    /// Like `call`, trying `preferred` first, and telling which endpoint answered.
+    async fn call_on<'s, T>(&'s self, preferred: Option<usize>, sending: bool, call: impl Fn(&'s R) -> RpcFuture<'s, T>) -> Result<(T, usize), RpcError> {
+        let candidates = self.candidates(sending, preferred);
+        if candidates.is_empty() {
+            return Err("no rpc endpoint to use".into());
+        }
+
+        for (attempt, index) in candidates.iter().enumerate() {
+            let endpoint = &self.endpoints[*index];
+            let last = attempt + 1 == candidates.len();
+            // skip endpoints out of budget, unless none is left to try
+            if let Err(mut wait) = endpoint.try_acquire() {
+                if !last {
+                    continue;
+                }
+                loop {
+                    tokio::time::sleep(wait).await;
+                    match endpoint.try_acquire() {
+                        Ok(()) => break,
+                        Err(next) => wait = next,
+                    }
+                }
+            }
+
+            let started = Instant::now();
+            let result = match tokio::time::timeout(self.options.request_timeout, call(&endpoint.rpc)).await {
+                Ok(result) => result,
+                Err(_) => Err(Box::new(std::io::Error::new(std::io::ErrorKind::TimedOut, format!("{} timed out", endpoint.url))) as RpcError),
+            };
+            match result {
+                Ok(value) => {
+                    endpoint.record_success(started.elapsed());
+                    return Ok((value, *index));
+                }
+                Err(err) => {
+                    // a rejected transaction is still an answer, only retryable errors count against the endpoint
+                    let retryable = classify_error(err.as_ref()) == ErrorClass::Retryable;
+                    if retryable {
+                        endpoint.record_failure(&self.options);
+                    } else {
+                        endpoint.record_success(started.elapsed());
+                    }
+                    if last || !retryable {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+        unreachable!("the last candidate always returns")
+    }
+
+    /// Sends `transaction` through every sending endpoint at once. The first endpoint to
+    /// accept it wins; if none does, the most telling error is returned.
+    async fn fan_out_send(&self, transaction: &Transaction, skip_preflight: bool) -> Result<Signature, RpcError> {
+        let mut sends: FuturesUnordered<_> = self.candidates(true, None).into_iter().map(|index| async move {
+            let endpoint = &self.endpoints[index];
+            if let Err(wait) = endpoint.try_acquire() {
+                tokio::time::sleep(wait).await;
+            }
+            let started = Instant::now();
+            match tokio::time::timeout(self.options.request_timeout, endpoint.rpc.send_transaction(transaction, skip_preflight)).await {
+                Ok(Ok(signature)) => {
+                    endpoint.record_success(started.elapsed());
+                    Ok(signature)
+                }
+                Ok(Err(err)) => {
+                    let err = SendableError::from(err);
+                    match err {
+                        SendableError::Retryable(_) => endpoint.record_failure(&self.options),
+                        _ => endpoint.record_success(started.elapsed()),
+                    }
+                    Err(err)
+                }
+                Err(_) => {
+                    endpoint.record_failure(&self.options);
+                    Err(SendableError::Retryable(format!("{} timed out", endpoint.url)))
+                }
+            }
+        }).collect();
+
+        // sends still in flight are dropped once one endpoint has accepted the transaction
+        let mut errors = Vec::new();
+        while let Some(result) = sends.next().await {
+            match result {
+                Ok(signature) => return Ok(signature),
+                Err(err) => errors.push(err),
+            }
+        }
+        // a node that already has the transaction or rejected it says more than one that is down
+        errors.sort_by_key(|err| matches!(err, SendableError::Retryable(_)));
+        errors.into_iter().next().map_or_else(|| Err("no rpc endpoint to send through".into()), |err| Err(err.into()))
+    }
+}
+
+/// An RPC error reduced to what classifying it needs, so it can cross await points.
+enum SendableError {
+    Transaction(TransactionError),
+    Retryable(String),
+    Fatal(String),
+}
+
+impl From<RpcError> for SendableError {
+    fn from(err: RpcError) -> Self {
+        let transaction_error = err
+            .downcast_ref::<TransactionError>()
+            .cloned()
+            .or_else(|| err.downcast_ref::<ClientError>().and_then(ClientError::get_transaction_error));
+        match (transaction_error, classify_error(err.as_ref())) {
+            (Some(transaction_error), _) => SendableError::Transaction(transaction_error),
+            (None, ErrorClass::Retryable) => SendableError::Retryable(err.to_string()),
+            (None, _) => SendableError::Fatal(err.to_string()),
+        }
+    }
+}
+
+impl From<SendableError> for RpcError {
+    fn from(err: SendableError) -> Self {
+        match err {
+            SendableError::Transaction(transaction_error) => Box::new(transaction_error),
+            SendableError::Retryable(message) => Box::new(std::io::Error::other(message)),
+            SendableError::Fatal(message) => message.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: SolanaRpc> SolanaRpc for RpcPool<R> {
+    async fn get_account(&self, pubkey: &Pubkey) -> Result<Account, RpcError> {
+        self.call(false, |rpc| rpc.get_account(pubkey)).await
+    }
+
+    async fn get_account_data(&self, pubkey: &Pubkey) -> Result<Vec<u8>, RpcError> {
+        self.call(false, |rpc| rpc.get_account_data(pubkey)).await
+    }
+
+    async fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<(u64, Vec<Option<Account>>), RpcError> {
+        self.call(false, |rpc| rpc.get_multiple_accounts(pubkeys)).await
+    }
+
+    async fn get_latest_blockhash(&self) -> Result<Hash, RpcError> {
+        self.call(false, |rpc| rpc.get_latest_blockhash()).await
+    }
+
+    async fn get_latest_blockhash_with_last_valid_block_height(&self) -> Result<(Hash, u64), RpcError> {
+        let (blockhash, index) = self.call_on(None, false, |rpc| rpc.get_latest_blockhash_with_last_valid_block_height()).await?;
+        *self.pinned.lock().unwrap() = Some(index);
+        Ok(blockhash)
+    }
+
+    async fn get_block_height(&self) -> Result<u64, RpcError> {
+        self.call(false, |rpc| rpc.get_block_height()).await
+    }
+
+    async fn send_and_confirm_transaction(&self, transaction: &Transaction) -> Result<Signature, RpcError> {
+        self.call(true, |rpc| rpc.send_and_confirm_transaction(transaction)).await
+    }
+
+    async fn send_transaction(&self, transaction: &Transaction, skip_preflight: bool) -> Result<Signature, RpcError> {
+        if self.options.fan_out_sends {
+            return self.fan_out_send(transaction, skip_preflight).await;
+        }
+        self.call(true, |rpc| rpc.send_transaction(transaction, skip_preflight)).await
+    }
+
+    async fn get_signature_statuses(&self, signatures: &[Signature]) -> Result<Vec<Option<SignatureStatus>>, RpcError> {
+        self.call(false, |rpc| rpc.get_signature_statuses(signatures)).await
+    }
+
+    /// Served whole by one endpoint, the one the last blockhash came from while it is healthy.
+    async fn poll_signature_statuses(&self, signatures: &[Signature]) -> Result<(u64, Vec<Option<SignatureStatus>>), RpcError> {
+        let pinned = *self.pinned.lock().unwrap();
+        self.call_on(pinned, false, |rpc| rpc.poll_signature_statuses(signatures)).await.map(|(polled, _)| polled)
+    }
+
+    async fn get_token_account_balance(&self, token_account: &Pubkey) -> Result<u64, RpcError> {
+        self.call(false, |rpc| rpc.get_token_account_balance(token_account)).await
+    }
+
+    async fn get_transaction_meta(&self, signature: &Signature) -> Result<Option<TransactionMeta>, RpcError> {
+        self.call(false, |rpc| rpc.get_transaction_meta(signature)).await
+    }
+}
+
+#[tokio::test]
+async fn test_rpc_pool() {
+    use solana_sdk::signature::Signer;
+
+    use crate::rpc::InMemoryRpc;
+
+    let endpoint = |block_height: u64| {
+        let rpc = InMemoryRpc::new();
+        rpc.set_block_height(block_height);
+        rpc
+    };
+
+    // fails over while the first endpoint is down, and benches it after three failures
+    let pool = RpcPool::new(RpcPoolOptions::default()).with_endpoint("a", endpoint(10), None, true).with_endpoint("b", endpoint(1000), None, true);
+    pool.endpoint(0).set_unavailable(true);
+    for _ in 0..4 {
+        assert_eq!(pool.get_block_height().await.unwrap(), 1000);
+    }
+    let status = pool.status();
+    assert_eq!((status[0].healthy, status[0].requests, status[0].failures), (false, 3, 3));
+    assert_eq!((status[1].healthy, status[1].requests), (true, 4));
+
+    // back up but trailing by 990 blocks, still benched until it catches up
+    pool.endpoint(0).set_unavailable(false);
+    let status = pool.check_health().await;
+    assert_eq!((status[0].healthy, status[0].block_height), (false, Some(10)));
+    pool.endpoint(0).set_block_height(990);
+    assert!(pool.check_health().await.iter().all(|status| status.healthy));
+
+    // nothing left to fail over to
+    pool.endpoint(0).set_unavailable(true);
+    pool.endpoint(1).set_unavailable(true);
+    assert!(pool.get_block_height().await.is_err());
+
+    // an endpoint out of budget passes calls on to the next one
+    let pool = RpcPool::new(RpcPoolOptions::default()).with_endpoint("a", endpoint(1), Some(1.0), true).with_endpoint("b", endpoint(2), None, true);
+    assert_eq!(pool.get_block_height().await.unwrap(), 1);
+    assert_eq!(pool.get_block_height().await.unwrap(), 2);
+    assert_eq!(pool.status().iter().map(|status| status.requests).collect::<Vec<_>>(), vec![1, 1]);
+
+    // preflight failures come back without trying the next endpoint
+    let payer = solana_sdk::signer::keypair::keypair_from_seed(&[4; 32]).unwrap();
+    let instruction = solana_sdk::system_instruction::transfer(&payer.pubkey(), &payer.pubkey(), 1);
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], Hash::new_unique());
+    let pool = RpcPool::new(RpcPoolOptions::default()).with_endpoint("a", endpoint(0), None, true).with_endpoint("b", endpoint(0), None, true);
+    pool.endpoint(0).fail_next_send(TransactionError::InsufficientFundsForFee);
+    let err = pool.send_transaction(&transaction, false).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<TransactionError>(), Some(&TransactionError::InsufficientFundsForFee));
+    assert!(pool.endpoint(1).sent_transactions().is_empty());
+
+    // fanned out to every sending endpoint, one being down is fine
+    let options = RpcPoolOptions { fan_out_sends: true, ..RpcPoolOptions::default() };
+    let pool = RpcPool::new(options)
+        .with_endpoint("a", endpoint(0), None, true)
+        .with_endpoint("b", endpoint(0), None, true)
+        .with_endpoint("c", endpoint(0), None, true)
+        .with_endpoint("read only", endpoint(0), None, false);
+    pool.endpoint(0).set_unavailable(true);
+    assert_eq!(pool.send_transaction(&transaction, false).await.unwrap(), transaction.signatures[0]);
+    let sent: Vec<usize> = (0..4).map(|index| pool.endpoint(index).sent_transactions().len()).collect();
+    assert_eq!((sent[0], sent[3]), (0, 0));
+    assert!(sent[1] + sent[2] >= 1);
+    pool.endpoint(0).set_unavailable(false);
+
+    // a rejection wins over a connection error
+    pool.endpoint(2).set_unavailable(true);
+    pool.endpoint(0).fail_next_send(TransactionError::InsufficientFundsForFee);
+    pool.endpoint(1).fail_next_send(TransactionError::InsufficientFundsForFee);
+    let err = pool.send_transaction(&transaction, true).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<TransactionError>(), Some(&TransactionError::InsufficientFundsForFee));
+
+    // status polls stay on the endpoint the blockhash came from, even once another is faster
+    let pool = RpcPool::new(RpcPoolOptions::default()).with_endpoint("a", endpoint(10), None, true).with_endpoint("b", endpoint(1000), None, true);
+    pool.get_latest_blockhash_with_last_valid_block_height().await.unwrap();
+    pool.endpoints[0].state.lock().unwrap().latency = Some(Duration::from_secs(1));
+    assert!(pool.get_block_height().await.unwrap() >= 1000);
+    let (block_height, statuses) = pool.poll_signature_statuses(&transaction.signatures).await.unwrap();
+    assert!(block_height < 1000);
+    assert_eq!(statuses, vec![None]);
+
+    // and fail over whole when it goes down
+    pool.endpoint(0).set_unavailable(true);
+    assert!(pool.poll_signature_statuses(&transaction.signatures).await.unwrap().0 >= 1000);
+}
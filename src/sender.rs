@@ -0,0 +1,383 @@
+//! Sending a transaction until it lands: rebroadcast at an interval, and re-sign with a fresh
+//! blockhash only once the previous one has provably expired, so a trade never executes twice.
+
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_custom_error::{
+    JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY, JSON_RPC_SERVER_ERROR_SLOT_SKIPPED,
+};
+use solana_client::rpc_request::RpcError as ClientRpcError;
+use solana_sdk::commitment_config::CommitmentLevel;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::{Transaction, TransactionError};
+
+use crate::nonce::NonceInfo;
+use crate::rpc::{RpcError, SolanaRpc};
+
+/// What to do about a failed RPC call.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorClass {
+    /// Transient, the same transaction may be sent again.
+    Retryable,
+    /// Sending again can't help, e.g. a failing instruction or missing funds.
+    Fatal,
+    /// The cluster already has the transaction, keep polling its status.
+    AlreadyProcessed,
+}
+
+/// Sorts an RPC error, from either the nonblocking `RpcClient` or [`crate::rpc::InMemoryRpc`].
+pub fn classify_error(err: &(dyn Error + 'static)) -> ErrorClass {
+    if let Some(transaction_error) = err.downcast_ref::<TransactionError>() {
+        return classify_transaction_error(transaction_error);
+    }
+    if let Some(client_error) = err.downcast_ref::<ClientError>() {
+        if let Some(transaction_error) = client_error.get_transaction_error() {
+            return classify_transaction_error(&transaction_error);
+        }
+        return match client_error.kind() {
+            ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) | ClientErrorKind::Middleware(_) => ErrorClass::Retryable,
+            ClientErrorKind::RpcError(ClientRpcError::RpcRequestError(_)) => ErrorClass::Retryable,
+            ClientErrorKind::RpcError(ClientRpcError::RpcResponseError { code, .. })
+                if [JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY, JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE, JSON_RPC_SERVER_ERROR_SLOT_SKIPPED].contains(code) =>
+            {
+                ErrorClass::Retryable
+            }
+            _ => ErrorClass::Fatal,
+        };
+    }
+    if err.is::<std::io::Error>() {
+        return ErrorClass::Retryable;
+    }
+    ErrorClass::Fatal
+}
+
+pub fn classify_transaction_error(err: &TransactionError) -> ErrorClass {
+    match err {
+        TransactionError::AlreadyProcessed => ErrorClass::AlreadyProcessed,
+        // the node lags behind the blockhash, or the block is full or busy
+        TransactionError::BlockhashNotFound
+        | TransactionError::AccountInUse
+        | TransactionError::ClusterMaintenance
+        | TransactionError::WouldExceedMaxBlockCostLimit
+        | TransactionError::WouldExceedMaxAccountCostLimit
+        | TransactionError::WouldExceedMaxVoteCostLimit
+        | TransactionError::WouldExceedAccountDataBlockLimit
+        | TransactionError::ProgramExecutionTemporarilyRestricted { .. } => ErrorClass::Retryable,
+        _ => ErrorClass::Fatal,
+    }
+}
+
+#[derive(Debug)]
+pub enum SendError {
+    /// Nothing will land: the transaction failed preflight or on chain (then `signature` is
+    /// set), or couldn't be signed.
+    Fatal { message: String, transaction_error: Option<TransactionError>, signature: Option<Signature> },
+    /// Every blockhash expired without the transaction landing, so none of `signatures` can
+    /// execute anymore and sending again is safe.
+    Expired { signatures: Vec<Signature> },
+    /// The RPC node kept failing.
+    Unavailable(String),
+    /// A transaction on a durable nonce didn't land in time, but may still until the nonce is
+    /// advanced.
+    Pending { signature: Signature, nonce_account: Pubkey },
+}
+
+impl SendError {
+    /// Whether the trade can be attempted again from scratch.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, SendError::Fatal { .. } | SendError::Pending { .. })
+    }
+
+    fn fatal(err: RpcError) -> Self {
+        let transaction_error = err
+            .downcast_ref::<TransactionError>()
+            .cloned()
+            .or_else(|| err.downcast_ref::<ClientError>().and_then(ClientError::get_transaction_error));
+        SendError::Fatal { message: err.to_string(), transaction_error, signature: None }
+    }
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SendError::Fatal { message, signature: Some(signature), .. } => write!(f, "transaction {} failed: {}", signature, message),
+            SendError::Fatal { message, .. } => write!(f, "transaction failed: {}", message),
+            SendError::Expired { signatures } => write!(f, "blockhash expired after {} signing attempts, nothing landed", signatures.len()),
+            SendError::Unavailable(message) => write!(f, "rpc unavailable: {}", message),
+            SendError::Pending { signature, nonce_account } => {
+                write!(f, "transaction {} hasn't landed yet and stays valid until nonce {} is advanced", signature, nonce_account)
+            }
+        }
+    }
+}
+
+impl Error for SendError {}
+
+#[derive(Clone, Debug)]
+pub struct SendConfig {
+    /// How often the transaction is sent again while it hasn't landed.
+    pub rebroadcast_interval: Duration,
+    /// How often block height and status are checked.
+    pub poll_interval: Duration,
+    /// Commitment the transaction has to reach.
+    pub commitment: CommitmentLevel,
+    /// New blockhashes tried after the first one expired.
+    pub max_resigns: usize,
+    /// RPC failures in a row tolerated before giving up.
+    pub max_rpc_errors: usize,
+    /// How long a transaction on a durable nonce is rebroadcast.
+    pub durable_nonce_timeout: Duration,
+}
+
+impl Default for SendConfig {
+    fn default() -> Self {
+        SendConfig {
+            rebroadcast_interval: Duration::from_secs(2),
+            poll_interval: Duration::from_millis(400),
+            commitment: CommitmentLevel::Confirmed,
+            max_resigns: 2,
+            max_rpc_errors: 10,
+            durable_nonce_timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SentTransaction {
+    /// The signature that landed.
+    pub signature: Signature,
+    pub slot: u64,
+    /// Sends over all blockhashes, rebroadcasts included.
+    pub broadcasts: usize,
+    /// Times the transaction was signed again with a new blockhash.
+    pub resigns: usize,
+}
+
+pub struct TransactionSender<'a, R: SolanaRpc> {
+    rpc: &'a R,
+    config: SendConfig,
+}
+
+impl<'a, R: SolanaRpc> TransactionSender<'a, R> {
+    pub fn new(rpc: &'a R) -> Self {
+        Self::with_config(rpc, SendConfig::default())
+    }
+
+    pub fn with_config(rpc: &'a R, config: SendConfig) -> Self {
+        TransactionSender { rpc, config }
+    }
+
+    /// Sends the transaction `sign` builds for a blockhash until it reaches the configured
+    /// commitment. `sign` is called again only after the last blockhash expired without any
+    /// of the signed versions landing.
+    pub async fn send(&self, mut sign: impl FnMut(Hash) -> Result<Transaction, Box<dyn Error>>) -> Result<SentTransaction, SendError> {
+        let mut signatures = Vec::new();
+        let mut broadcasts = 0;
+        let mut rpc_errors = 0;
+
+        loop {
+            let (blockhash, last_valid_block_height) = match self.rpc.get_latest_blockhash_with_last_valid_block_height().await {
+                Ok(blockhash) => blockhash,
+                Err(err) => {
+                    self.retry_rpc_error(err, &mut rpc_errors).await?;
+                    continue;
+                }
+            };
+            let transaction = sign(blockhash).map_err(|err| SendError::Fatal { message: err.to_string(), transaction_error: None, signature: None })?;
+            signatures.push(transaction.signatures[0]);
+
+            let mut last_broadcast: Option<Instant> = None;
+            loop {
+                if last_broadcast.is_none_or(|at| at.elapsed() >= self.config.rebroadcast_interval) {
+                    // only the first send of each signed version is simulated
+                    if let Err(err) = self.rpc.send_transaction(&transaction, last_broadcast.is_some()).await {
+                        match classify_error(err.as_ref()) {
+                            ErrorClass::Fatal => return Err(SendError::fatal(err)),
+                            ErrorClass::Retryable | ErrorClass::AlreadyProcessed => {}
+                        }
+                    }
+                    broadcasts += 1;
+                    last_broadcast = Some(Instant::now());
+                }
+
+                // the height is read before the statuses, on one node: a transaction still
+                // unknown after its blockhash expired can never land
+                let (block_height, statuses) = match self.rpc.poll_signature_statuses(&signatures).await {
+                    Ok(polled) => {
+                        rpc_errors = 0;
+                        polled
+                    }
+                    Err(err) => {
+                        self.retry_rpc_error(err, &mut rpc_errors).await?;
+                        continue;
+                    }
+                };
+
+                let landed = signatures.iter().zip(statuses).find_map(|(signature, status)| status.map(|status| (*signature, status)));
+                if let Some((signature, status)) = &landed {
+                    if let Some(err) = &status.err {
+                        return Err(SendError::Fatal { message: err.to_string(), transaction_error: Some(err.clone()), signature: Some(*signature) });
+                    }
+                    if commitment_rank(status.commitment) >= commitment_rank(self.config.commitment) {
+                        return Ok(SentTransaction { signature: *signature, slot: status.slot, broadcasts, resigns: signatures.len() - 1 });
+                    }
+                } else if block_height > last_valid_block_height {
+                    if signatures.len() > self.config.max_resigns {
+                        return Err(SendError::Expired { signatures });
+                    }
+                    break;
+                }
+
+                tokio::time::sleep(self.config.poll_interval).await;
+            }
+        }
+    }
+
+    /// Sends `transaction`, signed over the durable nonce in `nonce_account`, until it reaches
+    /// the configured commitment. It can't be signed again, so it is rebroadcast until it
+    /// lands, the nonce moves on without it, or `durable_nonce_timeout` passes.
+    pub async fn send_durable(&self, transaction: &Transaction, nonce_account: &Pubkey) -> Result<SentTransaction, SendError> {
+        let signature = transaction.signatures[0];
+        let started = Instant::now();
+        let mut broadcasts = 0;
+        let mut rpc_errors = 0;
+        let mut last_broadcast: Option<Instant> = None;
+
+        loop {
+            if last_broadcast.is_none_or(|at| at.elapsed() >= self.config.rebroadcast_interval) {
+                if let Err(err) = self.rpc.send_transaction(transaction, last_broadcast.is_some()).await {
+                    match classify_error(err.as_ref()) {
+                        ErrorClass::Fatal => return Err(SendError::fatal(err)),
+                        ErrorClass::Retryable | ErrorClass::AlreadyProcessed => {}
+                    }
+                }
+                broadcasts += 1;
+                last_broadcast = Some(Instant::now());
+            }
+
+            // the nonce is read before the status: one that moved on while the transaction is
+            // still unknown was advanced by something else
+            let polled = match self.rpc.get_account(nonce_account).await {
+                Ok(account) => match NonceInfo::from_account(nonce_account, &account) {
+                    Ok(nonce) => self.rpc.get_signature_statuses(&[signature]).await.map(|statuses| (nonce, statuses)),
+                    Err(err) => return Err(SendError::Fatal { message: err.to_string(), transaction_error: None, signature: None }),
+                },
+                Err(err) => Err(err),
+            };
+            let (nonce, statuses) = match polled {
+                Ok(polled) => {
+                    rpc_errors = 0;
+                    polled
+                }
+                Err(err) => {
+                    self.retry_rpc_error(err, &mut rpc_errors).await?;
+                    continue;
+                }
+            };
+
+            if let Some(status) = statuses.into_iter().next().flatten() {
+                if let Some(err) = &status.err {
+                    return Err(SendError::Fatal { message: err.to_string(), transaction_error: Some(err.clone()), signature: Some(signature) });
+                }
+                if commitment_rank(status.commitment) >= commitment_rank(self.config.commitment) {
+                    return Ok(SentTransaction { signature, slot: status.slot, broadcasts, resigns: 0 });
+                }
+            } else if nonce.blockhash != transaction.message.recent_blockhash {
+                return Err(SendError::Expired { signatures: vec![signature] });
+            } else if started.elapsed() >= self.config.durable_nonce_timeout {
+                return Err(SendError::Pending { signature, nonce_account: *nonce_account });
+            }
+
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+
+    /// Sleeps before retrying after a retryable RPC error, or fails.
+    async fn retry_rpc_error(&self, err: RpcError, rpc_errors: &mut usize) -> Result<(), SendError> {
+        *rpc_errors += 1;
+        if classify_error(err.as_ref()) == ErrorClass::Fatal {
+            return Err(SendError::fatal(err));
+        }
+        if *rpc_errors > self.config.max_rpc_errors {
+            return Err(SendError::Unavailable(err.to_string()));
+        }
+        tokio::time::sleep(self.config.poll_interval).await;
+        Ok(())
+    }
+}
+
+pub(crate) fn commitment_rank(commitment: CommitmentLevel) -> u8 {
+    match commitment {
+        CommitmentLevel::Processed => 0,
+        CommitmentLevel::Confirmed => 1,
+        CommitmentLevel::Finalized => 2,
+    }
+}
+
+#[tokio::test]
+async fn test_transaction_sender() {
+    use solana_sdk::instruction::InstructionError;
+    use solana_sdk::signature::Signer;
+    use solana_sdk::signer::keypair::keypair_from_seed;
+    use solana_sdk::system_instruction;
+
+    use crate::rpc::InMemoryRpc;
+
+    let payer = keypair_from_seed(&[4; 32]).unwrap();
+    let instruction = system_instruction::transfer(&payer.pubkey(), &payer.pubkey(), 1);
+    let sign = |blockhash: Hash| -> Result<Transaction, Box<dyn Error>> {
+        Ok(Transaction::new_signed_with_payer(std::slice::from_ref(&instruction), Some(&payer.pubkey()), &[&payer], blockhash))
+    };
+    let config = SendConfig { rebroadcast_interval: Duration::ZERO, poll_interval: Duration::ZERO, ..SendConfig::default() };
+
+    // a dropped transaction is rebroadcast as is
+    let rpc = InMemoryRpc::new();
+    rpc.drop_next_transactions(1);
+    let sent = TransactionSender::with_config(&rpc, config.clone()).send(sign).await.unwrap();
+    assert_eq!((sent.broadcasts, sent.resigns), (2, 0));
+    assert!(rpc.sent_transactions().iter().all(|transaction| transaction.signatures[0] == sent.signature));
+
+    // signed again only once the blockhash expired, the old signature never lands
+    let rpc = InMemoryRpc::new().with_block_height_step(100);
+    rpc.drop_next_transactions(3);
+    let sent = TransactionSender::with_config(&rpc, config.clone()).send(sign).await.unwrap();
+    assert_eq!((sent.broadcasts, sent.resigns), (4, 1));
+    let first = rpc.sent_transactions()[0].signatures[0];
+    assert_ne!(first, sent.signature);
+    assert_eq!(rpc.get_signature_statuses(&[first]).await.unwrap(), vec![None]);
+
+    // until the resigns run out
+    let rpc = InMemoryRpc::new().with_block_height_step(100);
+    rpc.drop_next_transactions(usize::MAX);
+    let err = TransactionSender::with_config(&rpc, SendConfig { max_resigns: 1, ..config.clone() }).send(sign).await.unwrap_err();
+    assert!(matches!(&err, SendError::Expired { signatures } if signatures.len() == 2));
+    assert!(err.is_retryable());
+
+    // a busy account is retried, a failing instruction is not
+    let rpc = InMemoryRpc::new();
+    rpc.fail_next_send(TransactionError::AccountInUse);
+    let sent = TransactionSender::with_config(&rpc, config.clone()).send(sign).await.unwrap();
+    assert_eq!(sent.broadcasts, 2);
+    rpc.fail_next_send(TransactionError::InstructionError(0, InstructionError::Custom(6001)));
+    let err = TransactionSender::with_config(&rpc, config.clone()).send(sign).await.unwrap_err();
+    assert!(matches!(err, SendError::Fatal { transaction_error: Some(TransactionError::InstructionError(0, _)), .. }));
+    assert!(!err.is_retryable());
+
+    // executed and failed on chain
+    let rpc = InMemoryRpc::new();
+    let landed_failed = |blockhash: Hash| -> Result<Transaction, Box<dyn Error>> {
+        let transaction = sign(blockhash)?;
+        rpc.land_failed(&transaction, TransactionError::InsufficientFundsForRent { account_index: 1 });
+        Ok(transaction)
+    };
+    let err = TransactionSender::with_config(&rpc, config).send(landed_failed).await.unwrap_err();
+    assert!(matches!(err, SendError::Fatal { signature: Some(_), transaction_error: Some(TransactionError::InsufficientFundsForRent { .. }), .. }));
+
+    assert_eq!(classify_error(&std::io::Error::from(std::io::ErrorKind::TimedOut)), ErrorClass::Retryable);
+    assert_eq!(classify_transaction_error(&TransactionError::AlreadyProcessed), ErrorClass::AlreadyProcessed);
+}
@@ -0,0 +1,201 @@
+//! What a swap actually did, read back from its confirmed transaction.
+//!
+//! Amounts are the changes of the owner's token balances. Wrapped SOL accounts are usually
+//! opened and closed within the swap and leave no token balance behind, so for the native
+//! mint the owner's lamports count too, less the fee and the rent of accounts the transaction
+//! opened when the owner paid for them.
+
+use rust_decimal::Decimal;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::rpc::{TokenBalance, TransactionMeta};
+
+pub type SettlementError = Box<dyn std::error::Error>;
+
+/// Decimals of SOL and wrapped SOL.
+pub const NATIVE_DECIMALS: u8 = 9;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapSettlement {
+    pub signature: Signature,
+    pub slot: u64,
+    pub mint_in: Pubkey,
+    pub mint_out: Pubkey,
+    /// Raw amount that left the owner.
+    pub amount_in: u64,
+    /// Raw amount that reached the owner.
+    pub amount_out: u64,
+    pub decimals_in: u8,
+    pub decimals_out: u8,
+    /// Transaction fee in lamports, paid by the fee payer.
+    pub fee_lamports: u64,
+    /// Change of the owner's SOL balance, with fee, rent and wrapping.
+    pub sol_change: i64,
+    /// Output per input, in whole tokens.
+    pub effective_price: Decimal,
+    pub quoted_amount_in: u64,
+    pub quoted_amount_out: u64,
+    /// Output short of the quote in basis points, negative when the fill beat it.
+    pub slippage_bps: i64,
+}
+
+impl SwapSettlement {
+    /// Settles a swap of `mint_in` for `mint_out` by `owner`, quoted at `quoted_amount_in` for
+    /// `quoted_amount_out`.
+    pub fn from_meta(
+        signature: Signature,
+        meta: &TransactionMeta,
+        owner: &Pubkey,
+        mint_in: &Pubkey,
+        mint_out: &Pubkey,
+        quoted_amount_in: u64,
+        quoted_amount_out: u64,
+    ) -> Result<Self, SettlementError> {
+        if let Some(err) = &meta.err {
+            return Err(format!("transaction {} failed: {}", signature, err).into());
+        }
+
+        let (change_in, decimals_in) = owner_balance_change(meta, owner, mint_in);
+        let (change_out, decimals_out) = owner_balance_change(meta, owner, mint_out);
+        let amount_in = u64::try_from(-change_in).map_err(|_| format!("no {} left the owner's accounts", mint_in))?;
+        let amount_out = u64::try_from(change_out).map_err(|_| format!("no {} reached the owner's accounts", mint_out))?;
+        let decimals_in = decimals_in.ok_or_else(|| format!("transaction has no {} balances", mint_in))?;
+        let decimals_out = decimals_out.ok_or_else(|| format!("transaction has no {} balances", mint_out))?;
+        if amount_in == 0 {
+            return Err(format!("no {} left the owner's accounts", mint_in).into());
+        }
+
+        let effective_price = ui_amount(amount_out, decimals_out) / ui_amount(amount_in, decimals_in);
+        let slippage_bps = if quoted_amount_out == 0 {
+            0
+        } else {
+            ((quoted_amount_out as i128 - amount_out as i128) * 10_000 / quoted_amount_out as i128) as i64
+        };
+
+        Ok(SwapSettlement {
+            signature,
+            slot: meta.slot,
+            mint_in: *mint_in,
+            mint_out: *mint_out,
+            amount_in,
+            amount_out,
+            decimals_in,
+            decimals_out,
+            fee_lamports: meta.fee,
+            sol_change: lamport_change(meta, owner) as i64,
+            effective_price,
+            quoted_amount_in,
+            quoted_amount_out,
+            slippage_bps,
+        })
+    }
+
+    /// Output per input the quote promised, in whole tokens.
+    pub fn quoted_price(&self) -> Decimal {
+        if self.quoted_amount_in == 0 {
+            return Decimal::ZERO;
+        }
+        ui_amount(self.quoted_amount_out, self.decimals_out) / ui_amount(self.quoted_amount_in, self.decimals_in)
+    }
+}
+
+/// Net change of `owner`'s token balances of `mint`, with the mint's decimals if the
+/// transaction holds any of it.
+pub fn token_balance_change(meta: &TransactionMeta, owner: &Pubkey, mint: &Pubkey) -> (i128, Option<u8>) {
+    let owned = |balances: &[TokenBalance]| -> (i128, Option<u8>) {
+        balances
+            .iter()
+            .filter(|balance| balance.mint == *mint && balance.owner == Some(*owner))
+            .fold((0, None), |(total, _), balance| (total + balance.amount as i128, Some(balance.decimals)))
+    };
+    let (pre, pre_decimals) = owned(&meta.pre_token_balances);
+    let (post, post_decimals) = owned(&meta.post_token_balances);
+    (post - pre, post_decimals.or(pre_decimals))
+}
+
+/// Net lamport change of `account`.
+pub fn lamport_change(meta: &TransactionMeta, account: &Pubkey) -> i128 {
+    meta.account_keys
+        .iter()
+        .position(|key| key == account)
+        .map_or(0, |index| meta.post_balances[index] as i128 - meta.pre_balances[index] as i128)
+}
+
+/// [`token_balance_change`], plus the owner's lamports spent or received for the native mint.
+fn owner_balance_change(meta: &TransactionMeta, owner: &Pubkey, mint: &Pubkey) -> (i128, Option<u8>) {
+    let (change, decimals) = token_balance_change(meta, owner, mint);
+    if *mint != spl_token::native_mint::id() {
+        return (change, decimals);
+    }
+
+    let mut lamports = lamport_change(meta, owner);
+    if meta.account_keys.first() == Some(owner) {
+        lamports += meta.fee as i128;
+        // rent of accounts opened and left open, the wrapped lamports they hold are not rent
+        for index in 0..meta.account_keys.len() {
+            if meta.pre_balances[index] == 0 && meta.post_balances[index] > 0 {
+                let wrapped = meta
+                    .post_token_balances
+                    .iter()
+                    .find(|balance| balance.account_index == index && balance.mint == *mint)
+                    .map_or(0, |balance| balance.amount);
+                lamports += (meta.post_balances[index] - wrapped) as i128;
+            }
+        }
+    }
+    (change + lamports, Some(NATIVE_DECIMALS))
+}
+
+fn ui_amount(amount: u64, decimals: u8) -> Decimal {
+    Decimal::from(amount) / Decimal::from(10u64.pow(decimals as u32))
+}
+
+#[test]
+fn test_swap_settlement() {
+    use solana_sdk::transaction::TransactionError;
+
+    let owner = Pubkey::new_from_array([1; 32]);
+    let usdc = Pubkey::new_from_array([2; 32]);
+    let wsol = spl_token::native_mint::id();
+    let balance = |account_index: usize, mint: Pubkey, amount: u64, decimals: u8| TokenBalance { account_index, mint, owner: Some(owner), amount, decimals };
+    let vault_balance = |account_index: usize, mint: Pubkey, amount: u64| TokenBalance { account_index, mint, owner: Some(Pubkey::new_from_array([9; 32])), amount, decimals: 6 };
+
+    // 1 SOL wrapped into an account opened and closed by the swap, for 149.5 USDC into a new
+    // USDC account; quoted at 150
+    let rent = 2_039_280;
+    let meta = TransactionMeta {
+        slot: 77,
+        err: None,
+        fee: 5000,
+        account_keys: vec![owner, Pubkey::new_from_array([3; 32]), Pubkey::new_from_array([4; 32]), Pubkey::new_from_array([5; 32])],
+        pre_balances: vec![10_000_000_000, 0, 0, rent],
+        post_balances: vec![10_000_000_000 - 1_000_000_000 - 5000 - rent, 0, rent, rent],
+        pre_token_balances: vec![vault_balance(3, usdc, 1_000_000_000)],
+        post_token_balances: vec![balance(2, usdc, 149_500_000, 6), vault_balance(3, usdc, 1_000_000_000 - 149_500_000)],
+    };
+    let signature = Signature::from([1; 64]);
+    let settlement = SwapSettlement::from_meta(signature, &meta, &owner, &wsol, &usdc, 1_000_000_000, 150_000_000).unwrap();
+    assert_eq!((settlement.amount_in, settlement.amount_out, settlement.fee_lamports, settlement.slot), (1_000_000_000, 149_500_000, 5000, 77));
+    assert_eq!(settlement.effective_price, Decimal::new(1495, 1));
+    assert_eq!(settlement.quoted_price(), Decimal::from(150));
+    assert_eq!(settlement.slippage_bps, 33);
+    assert_eq!(settlement.sol_change, -1_000_005_000 - rent as i64);
+
+    // the other way, into an existing wrapped SOL account that stays open
+    let meta = TransactionMeta {
+        pre_balances: vec![1_000_000_000, rent, rent],
+        post_balances: vec![1_000_000_000 - 5000, rent + 7_000_000, rent],
+        account_keys: meta.account_keys[..3].to_vec(),
+        pre_token_balances: vec![balance(1, wsol, 0, 9), balance(2, usdc, 2_000_000, 6)],
+        post_token_balances: vec![balance(1, wsol, 7_000_000, 9), balance(2, usdc, 1_000_000, 6)],
+        ..meta
+    };
+    let settlement = SwapSettlement::from_meta(signature, &meta, &owner, &usdc, &wsol, 1_000_000, 6_000_000).unwrap();
+    assert_eq!((settlement.amount_in, settlement.amount_out, settlement.slippage_bps), (1_000_000, 7_000_000, -1666));
+    assert_eq!(settlement.effective_price, Decimal::new(7, 3));
+
+    let failed = TransactionMeta { err: Some(TransactionError::InsufficientFundsForFee), ..meta.clone() };
+    assert!(SwapSettlement::from_meta(signature, &failed, &owner, &usdc, &wsol, 1_000_000, 6_000_000).is_err());
+    assert!(SwapSettlement::from_meta(signature, &meta, &owner, &wsol, &usdc, 1_000_000, 6_000_000).is_err());
+}
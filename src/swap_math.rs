@@ -0,0 +1,494 @@
+//! Client-side port of the Whirlpool program's swap math, so quotes match on-chain results
+//! to the unit instead of going through a float price.
+
+use crate::constant::{MAX_TICK_INDEX, MIN_TICK_INDEX, TICK_ARRAY_SIZE, TICK_ARRAY_SIZE_USIZE};
+use crate::tick_array::{TickArray, Whirlpool};
+
+pub use u256::U256;
+
+// the macro expansion trips lints we can't fix from here
+#[allow(clippy::manual_div_ceil, clippy::assign_op_pattern)]
+mod u256 {
+    uint::construct_uint! {
+        pub struct U256(4);
+    }
+}
+
+pub const MIN_SQRT_PRICE_X64: u128 = 4295048016;
+pub const MAX_SQRT_PRICE_X64: u128 = 79226673515401279992447579055;
+
+// fee_rate is stored in hundredths of a basis point
+const FEE_RATE_MUL_VALUE: u128 = 1_000_000;
+
+type SwapMathResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+pub fn tickmath_sqrt_price_from_tick_index(tick: i32) -> u128 {
+    if tick >= 0 {
+        get_sqrt_price_positive_tick(tick)
+    } else {
+        get_sqrt_price_negative_tick(tick)
+    }
+}
+
+// Q96 intermediate, the result is shifted back to Q64.64
+fn get_sqrt_price_positive_tick(tick: i32) -> u128 {
+    const FACTORS: [u128; 18] = [
+        79236085330515764027303304731,
+        79244008939048815603706035061,
+        79259858533276714757314932305,
+        79291567232598584799939703904,
+        79355022692464371645785046466,
+        79482085999252804386437311141,
+        79736823300114093921829183326,
+        80248749790819932309965073892,
+        81282483887344747381513967011,
+        83390072131320151908154831281,
+        87770609709833776024991924138,
+        97234110755111693312479820773,
+        119332217159966728226237229890,
+        179736315981702064433883588727,
+        407748233172238350107850275304,
+        2098478828474011932436660412517,
+        55581415166113811149459800483533,
+        38992368544603139932233054999993551,
+    ];
+
+    let mut ratio = if tick & 1 != 0 {
+        U256::from(79232123823359799118286999567u128)
+    } else {
+        U256::from(79228162514264337593543950336u128)
+    };
+    for (bit, factor) in FACTORS.iter().enumerate() {
+        if tick & (2 << bit) != 0 {
+            ratio = (ratio * U256::from(*factor)) >> 96;
+        }
+    }
+    (ratio >> 32).as_u128()
+}
+
+fn get_sqrt_price_negative_tick(tick: i32) -> u128 {
+    const FACTORS: [u128; 18] = [
+        18444899583751176498,
+        18443055278223354162,
+        18439367220385604838,
+        18431993317065449817,
+        18417254355718160513,
+        18387811781193591352,
+        18329067761203520168,
+        18212142134806087854,
+        17980523815641551639,
+        17526086738831147013,
+        16651378430235024244,
+        15030750278693429944,
+        12247334978882834399,
+        8131365268884726200,
+        3584323654723342297,
+        696457651847595233,
+        26294789957452057,
+        37481735321082,
+    ];
+
+    let abs_tick = tick.abs();
+    let mut ratio: u128 = if abs_tick & 1 != 0 { 18445821805675392311 } else { 18446744073709551616 };
+    for (bit, factor) in FACTORS.iter().enumerate() {
+        if abs_tick & (2 << bit) != 0 {
+            ratio = ((U256::from(ratio) * U256::from(*factor)) >> 64).as_u128();
+        }
+    }
+    ratio
+}
+
+/// The greatest tick whose sqrt price is <= `sqrt_price_x64`.
+pub fn tickmath_tick_index_from_sqrt_price(sqrt_price_x64: u128) -> i32 {
+    let (mut low, mut high) = (MIN_TICK_INDEX, MAX_TICK_INDEX);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if tickmath_sqrt_price_from_tick_index(mid) <= sqrt_price_x64 {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
+fn div_round_up_if(numerator: U256, denominator: U256, round_up: bool) -> U256 {
+    let quotient = numerator / denominator;
+    if round_up && numerator % denominator != U256::zero() {
+        quotient + 1
+    } else {
+        quotient
+    }
+}
+
+fn to_u64(value: U256) -> SwapMathResult<u64> {
+    if value > U256::from(u64::MAX) {
+        return Err("token amount exceeds u64".into());
+    }
+    Ok(value.as_u64())
+}
+
+fn to_u128(value: U256) -> SwapMathResult<u128> {
+    if value > U256::from(u128::MAX) {
+        return Err("sqrt price exceeds u128".into());
+    }
+    Ok(value.as_u128())
+}
+
+pub fn swapmath_get_amount_delta_a(sqrt_price_0: u128, sqrt_price_1: u128, liquidity: u128, round_up: bool) -> SwapMathResult<u64> {
+    let (sqrt_price_lower, sqrt_price_upper) = (sqrt_price_0.min(sqrt_price_1), sqrt_price_0.max(sqrt_price_1));
+    let numerator = (U256::from(liquidity) * U256::from(sqrt_price_upper - sqrt_price_lower)) << 64;
+    let denominator = U256::from(sqrt_price_upper) * U256::from(sqrt_price_lower);
+    to_u64(div_round_up_if(numerator, denominator, round_up))
+}
+
+pub fn swapmath_get_amount_delta_b(sqrt_price_0: u128, sqrt_price_1: u128, liquidity: u128, round_up: bool) -> SwapMathResult<u64> {
+    let price_diff = sqrt_price_0.max(sqrt_price_1) - sqrt_price_0.min(sqrt_price_1);
+    let product = U256::from(liquidity) * U256::from(price_diff);
+    to_u64(div_round_up_if(product, U256::one() << 64, round_up))
+}
+
+fn get_next_sqrt_price_from_a_round_up(sqrt_price: u128, liquidity: u128, amount: u64, amount_specified_is_input: bool) -> SwapMathResult<u128> {
+    if amount == 0 {
+        return Ok(sqrt_price);
+    }
+    let product = U256::from(sqrt_price) * U256::from(amount);
+    let liquidity_x64 = U256::from(liquidity) << 64;
+    let denominator = if amount_specified_is_input {
+        liquidity_x64 + product
+    } else {
+        if product >= liquidity_x64 {
+            return Err("swap output exceeds available liquidity".into());
+        }
+        liquidity_x64 - product
+    };
+    let numerator = liquidity_x64.checked_mul(U256::from(sqrt_price)).ok_or("liquidity overflows the sqrt price math")?;
+    to_u128(div_round_up_if(numerator, denominator, true))
+}
+
+fn get_next_sqrt_price_from_b_round_down(sqrt_price: u128, liquidity: u128, amount: u64, amount_specified_is_input: bool) -> SwapMathResult<u128> {
+    let delta = to_u128(div_round_up_if(U256::from(amount) << 64, U256::from(liquidity), !amount_specified_is_input))?;
+    if amount_specified_is_input {
+        sqrt_price.checked_add(delta).ok_or_else(|| "sqrt price overflow".into())
+    } else {
+        sqrt_price.checked_sub(delta).ok_or_else(|| "swap output exceeds available liquidity".into())
+    }
+}
+
+fn get_next_sqrt_price(sqrt_price: u128, liquidity: u128, amount: u64, amount_specified_is_input: bool, a_to_b: bool) -> SwapMathResult<u128> {
+    if amount_specified_is_input == a_to_b {
+        get_next_sqrt_price_from_a_round_up(sqrt_price, liquidity, amount, amount_specified_is_input)
+    } else {
+        get_next_sqrt_price_from_b_round_down(sqrt_price, liquidity, amount, amount_specified_is_input)
+    }
+}
+
+// the side of the swap that `amount` is denominated in
+fn get_amount_fixed_delta(sqrt_price_current: u128, sqrt_price_target: u128, liquidity: u128, amount_specified_is_input: bool, a_to_b: bool) -> SwapMathResult<u64> {
+    if a_to_b == amount_specified_is_input {
+        swapmath_get_amount_delta_a(sqrt_price_current, sqrt_price_target, liquidity, amount_specified_is_input)
+    } else {
+        swapmath_get_amount_delta_b(sqrt_price_current, sqrt_price_target, liquidity, amount_specified_is_input)
+    }
+}
+
+fn get_amount_unfixed_delta(sqrt_price_current: u128, sqrt_price_target: u128, liquidity: u128, amount_specified_is_input: bool, a_to_b: bool) -> SwapMathResult<u64> {
+    if a_to_b == amount_specified_is_input {
+        swapmath_get_amount_delta_b(sqrt_price_current, sqrt_price_target, liquidity, !amount_specified_is_input)
+    } else {
+        swapmath_get_amount_delta_a(sqrt_price_current, sqrt_price_target, liquidity, !amount_specified_is_input)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SwapStepComputation {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub next_sqrt_price: u128,
+    pub fee_amount: u64,
+}
+
+/// One step of a swap within a single liquidity range, towards `sqrt_price_target`.
+pub fn swapmath_compute_swap(
+    amount_remaining: u64,
+    fee_rate: u16,
+    liquidity: u128,
+    sqrt_price_current: u128,
+    sqrt_price_target: u128,
+    amount_specified_is_input: bool,
+    a_to_b: bool,
+) -> SwapMathResult<SwapStepComputation> {
+    let mut amount_fixed_delta = get_amount_fixed_delta(sqrt_price_current, sqrt_price_target, liquidity, amount_specified_is_input, a_to_b)?;
+
+    let amount_calc = if amount_specified_is_input {
+        (amount_remaining as u128 * (FEE_RATE_MUL_VALUE - fee_rate as u128) / FEE_RATE_MUL_VALUE) as u64
+    } else {
+        amount_remaining
+    };
+
+    let next_sqrt_price = if amount_calc >= amount_fixed_delta {
+        sqrt_price_target
+    } else {
+        get_next_sqrt_price(sqrt_price_current, liquidity, amount_calc, amount_specified_is_input, a_to_b)?
+    };
+    let is_max_swap = next_sqrt_price == sqrt_price_target;
+
+    let amount_unfixed_delta = get_amount_unfixed_delta(sqrt_price_current, next_sqrt_price, liquidity, amount_specified_is_input, a_to_b)?;
+    if !is_max_swap {
+        amount_fixed_delta = get_amount_fixed_delta(sqrt_price_current, next_sqrt_price, liquidity, amount_specified_is_input, a_to_b)?;
+    }
+
+    let (amount_in, mut amount_out) = if amount_specified_is_input {
+        (amount_fixed_delta, amount_unfixed_delta)
+    } else {
+        (amount_unfixed_delta, amount_fixed_delta)
+    };
+    if !amount_specified_is_input && amount_out > amount_remaining {
+        amount_out = amount_remaining;
+    }
+
+    let fee_amount = if amount_specified_is_input && !is_max_swap {
+        amount_remaining - amount_in
+    } else {
+        let fee = U256::from(amount_in) * U256::from(fee_rate);
+        to_u64(div_round_up_if(fee, U256::from(FEE_RATE_MUL_VALUE - fee_rate as u128), true))?
+    };
+
+    Ok(SwapStepComputation {
+        amount_in,
+        amount_out,
+        next_sqrt_price,
+        fee_amount,
+    })
+}
+
+/// Next initialized tick at or below (a to b) / above (b to a) `tick_index`, searching
+/// `tick_arrays` in order from `array_index`. When no initialized tick is left the far
+/// boundary of the last array is returned so the swap can run up to it.
+fn get_next_initialized_tick_index(
+    tick_arrays: &[TickArray],
+    tick_index: i32,
+    tick_spacing: u16,
+    a_to_b: bool,
+    mut array_index: usize,
+) -> (usize, i32) {
+    let tick_spacing = tick_spacing as i32;
+    let ticks_in_array = TICK_ARRAY_SIZE * tick_spacing;
+    let mut search_index = tick_index;
+
+    loop {
+        let tick_array = &tick_arrays[array_index];
+        let start_tick_index = tick_array.start_tick_index;
+
+        let mut offset = (search_index - start_tick_index).div_euclid(tick_spacing);
+        if !a_to_b {
+            offset += 1;
+        }
+        while (0..TICK_ARRAY_SIZE).contains(&offset) {
+            if tick_array.ticks[offset as usize].initialized {
+                return (array_index, start_tick_index + offset * tick_spacing);
+            }
+            offset = if a_to_b { offset - 1 } else { offset + 1 };
+        }
+
+        if array_index + 1 == tick_arrays.len() {
+            let boundary = if a_to_b {
+                start_tick_index
+            } else {
+                start_tick_index + (TICK_ARRAY_SIZE - 1) * tick_spacing
+            };
+            return (array_index, boundary);
+        }
+        search_index = if a_to_b { start_tick_index - 1 } else { start_tick_index + ticks_in_array - 1 };
+        array_index += 1;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapSimulation {
+    /// Input token consumed, fees included.
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+    pub end_sqrt_price: u128,
+    pub end_tick_index: i32,
+    pub end_liquidity: u128,
+    /// Number of `tick_arrays` the swap touched.
+    pub tick_arrays_used: usize,
+}
+
+/// Simulates a swap against `whirlpool` the way the program does.
+///
+/// `tick_arrays` must be ordered in swap direction with the first one containing the current
+/// tick. The swap stops early, like on-chain, at `sqrt_price_limit` (0 for no limit) or at
+/// the far end of the last array, so `amount_in`/`amount_out` may be a partial fill.
+pub fn swapmath_simulate_swap(
+    whirlpool: &Whirlpool,
+    tick_arrays: &[TickArray],
+    amount: u64,
+    sqrt_price_limit: u128,
+    amount_specified_is_input: bool,
+    a_to_b: bool,
+) -> SwapMathResult<SwapSimulation> {
+    if tick_arrays.is_empty() {
+        return Err("no tick arrays to swap through".into());
+    }
+    let sqrt_price_limit = match sqrt_price_limit {
+        0 if a_to_b => MIN_SQRT_PRICE_X64,
+        0 => MAX_SQRT_PRICE_X64,
+        limit => limit,
+    };
+    if (a_to_b && sqrt_price_limit > whirlpool.sqrt_price) || (!a_to_b && sqrt_price_limit < whirlpool.sqrt_price) {
+        return Err("sqrt price limit is on the wrong side of the current price".into());
+    }
+
+    let mut amount_remaining = amount;
+    let mut amount_calculated: u64 = 0;
+    let mut fee_total: u64 = 0;
+    let mut curr_sqrt_price = whirlpool.sqrt_price;
+    let mut curr_tick_index = whirlpool.tick_current_index;
+    let mut curr_liquidity = whirlpool.liquidity;
+    let mut array_index = 0;
+
+    while amount_remaining > 0 && sqrt_price_limit != curr_sqrt_price {
+        let (next_array_index, next_tick_index) =
+            get_next_initialized_tick_index(tick_arrays, curr_tick_index, whirlpool.tick_spacing, a_to_b, array_index);
+        array_index = next_array_index;
+
+        let next_tick_sqrt_price = tickmath_sqrt_price_from_tick_index(next_tick_index);
+        let sqrt_price_target = if a_to_b {
+            next_tick_sqrt_price.max(sqrt_price_limit)
+        } else {
+            next_tick_sqrt_price.min(sqrt_price_limit)
+        };
+
+        let step = swapmath_compute_swap(
+            amount_remaining,
+            whirlpool.fee_rate,
+            curr_liquidity,
+            curr_sqrt_price,
+            sqrt_price_target,
+            amount_specified_is_input,
+            a_to_b,
+        )?;
+
+        if amount_specified_is_input {
+            amount_remaining -= step.amount_in + step.fee_amount;
+            amount_calculated += step.amount_out;
+        } else {
+            amount_remaining -= step.amount_out;
+            amount_calculated += step.amount_in + step.fee_amount;
+        }
+        fee_total += step.fee_amount;
+
+        if step.next_sqrt_price == next_tick_sqrt_price {
+            let tick_array = &tick_arrays[array_index];
+            let offset = ((next_tick_index - tick_array.start_tick_index) / whirlpool.tick_spacing as i32) as usize;
+            let tick = tick_array.ticks[offset.min(TICK_ARRAY_SIZE_USIZE - 1)];
+            if tick.initialized {
+                let liquidity_net = if a_to_b { -tick.liquidity_net } else { tick.liquidity_net };
+                curr_liquidity = curr_liquidity
+                    .checked_add_signed(liquidity_net)
+                    .ok_or("liquidity underflow while crossing tick")?;
+            }
+            curr_tick_index = if a_to_b { next_tick_index - 1 } else { next_tick_index };
+        } else if step.next_sqrt_price != curr_sqrt_price {
+            curr_tick_index = tickmath_tick_index_from_sqrt_price(step.next_sqrt_price);
+        }
+        curr_sqrt_price = step.next_sqrt_price;
+
+        // reached the far end of the last array without filling the order
+        if step.next_sqrt_price == next_tick_sqrt_price && array_index + 1 == tick_arrays.len() && is_last_array_boundary(&tick_arrays[array_index], next_tick_index, whirlpool.tick_spacing, a_to_b) {
+            break;
+        }
+    }
+
+    let (amount_in, amount_out) = if amount_specified_is_input {
+        (amount - amount_remaining, amount_calculated)
+    } else {
+        (amount_calculated, amount - amount_remaining)
+    };
+
+    Ok(SwapSimulation {
+        amount_in,
+        amount_out,
+        fee_amount: fee_total,
+        end_sqrt_price: curr_sqrt_price,
+        end_tick_index: curr_tick_index,
+        end_liquidity: curr_liquidity,
+        tick_arrays_used: tick_arrays_used(tick_arrays, curr_sqrt_price, whirlpool.tick_spacing),
+    })
+}
+
+// arrays up to and including the one holding the end price
+fn tick_arrays_used(tick_arrays: &[TickArray], end_sqrt_price: u128, tick_spacing: u16) -> usize {
+    let ticks_in_array = TICK_ARRAY_SIZE * tick_spacing as i32;
+    let end_tick_index = tickmath_tick_index_from_sqrt_price(end_sqrt_price);
+    tick_arrays
+        .iter()
+        .position(|tick_array| {
+            let start_tick_index = tick_array.start_tick_index;
+            (start_tick_index..start_tick_index + ticks_in_array).contains(&end_tick_index)
+        })
+        .map_or(tick_arrays.len(), |index| index + 1)
+}
+
+fn is_last_array_boundary(tick_array: &TickArray, tick_index: i32, tick_spacing: u16, a_to_b: bool) -> bool {
+    let start_tick_index = tick_array.start_tick_index;
+    if a_to_b {
+        tick_index == start_tick_index
+    } else {
+        tick_index == start_tick_index + (TICK_ARRAY_SIZE - 1) * tick_spacing as i32
+    }
+}
+
+#[test]
+fn test_swap_math() {
+    use crate::tick_array::Tick;
+
+    // tick math agrees with the program's boundary constants and round-trips
+    assert_eq!(tickmath_sqrt_price_from_tick_index(MIN_TICK_INDEX), MIN_SQRT_PRICE_X64);
+    assert_eq!(tickmath_sqrt_price_from_tick_index(MAX_TICK_INDEX), MAX_SQRT_PRICE_X64);
+    assert_eq!(tickmath_sqrt_price_from_tick_index(0), 1u128 << 64);
+    for tick in [-443636, -19662, -1, 0, 1, 64, 100_000] {
+        let sqrt_price = tickmath_sqrt_price_from_tick_index(tick);
+        assert_eq!(tickmath_tick_index_from_sqrt_price(sqrt_price), tick);
+        assert_eq!(tickmath_tick_index_from_sqrt_price(sqrt_price + 1), tick);
+    }
+
+    // a single in-range step: 1 SOL into a pool at tick 0 with no initialized ticks nearby
+    let whirlpool = Whirlpool {
+        tick_spacing: 64,
+        fee_rate: 3000,
+        liquidity: 1_000_000_000_000,
+        sqrt_price: 1u128 << 64,
+        tick_current_index: 0,
+        ..Default::default()
+    };
+    let tick_array = |start_tick_index: i32| TickArray {
+        start_tick_index,
+        ticks: [Tick::default(); TICK_ARRAY_SIZE_USIZE],
+        whirlpool: Default::default(),
+    };
+    let tick_arrays = [tick_array(0), tick_array(-5632), tick_array(-11264)];
+
+    let simulation = swapmath_simulate_swap(&whirlpool, &tick_arrays, 1_000_000_000, 0, true, true).unwrap();
+    assert_eq!(simulation.amount_in, 1_000_000_000);
+    assert_eq!(simulation.fee_amount, 3_000_000);
+    // 997_000_000 * L / (L + 997_000_000) at price 1
+    assert_eq!(simulation.amount_out, 996_006_981);
+    // tick 0 is the first tick of its array, any a to b trade moves into the next one
+    assert_eq!(simulation.tick_arrays_used, 2);
+
+    // a price limit turns the same order into a partial fill
+    let limit = tickmath_sqrt_price_from_tick_index(-10);
+    let partial = swapmath_simulate_swap(&whirlpool, &tick_arrays, 1_000_000_000, limit, true, true).unwrap();
+    assert_eq!(partial.end_sqrt_price, limit);
+    assert!(partial.amount_in < 1_000_000_000);
+    assert_eq!(partial.end_tick_index, -10);
+
+    // liquidity no real pool holds fails rather than overflows
+    assert!(get_next_sqrt_price_from_a_round_up(MAX_SQRT_PRICE_X64, u128::MAX, 1, true).is_err());
+    let whale = Whirlpool { liquidity: u128::MAX, sqrt_price: MAX_SQRT_PRICE_X64 - 1, tick_current_index: MAX_TICK_INDEX - 1, ..whirlpool };
+    assert!(swapmath_simulate_swap(&whale, &[tick_array(439_296)], 1_000, 0, true, true).is_err());
+}
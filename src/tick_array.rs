@@ -55,7 +55,7 @@ pub struct WhirlpoolRewardInfo {
 }
 
 // #[account]
-#[derive(Default, Debug, PartialEq, AnchorDeserialize)]
+#[derive(Default, Clone, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
 pub struct Whirlpool {
     pub whirlpools_config: Pubkey, // 32
     pub whirlpool_bump: [u8; 1],   // 1
@@ -148,14 +148,43 @@ pub fn calculate_token_b_amount(
     amount_b_with_slippage.floor().to_u64().unwrap_or(u64::MAX)
 }
 
+// Start index of the first and last tick arrays, those holding MIN_TICK_INDEX and MAX_TICK_INDEX
+pub fn tickutil_get_start_tick_index_bounds(tick_spacing: u16) -> (i32, i32) {
+    let ticks_in_array = TICK_ARRAY_SIZE * tick_spacing as i32;
+    (
+      div_floor(MIN_TICK_INDEX, ticks_in_array) * ticks_in_array,
+      div_floor(MAX_TICK_INDEX, ticks_in_array) * ticks_in_array,
+    )
+}
+
+/// Start index of the tick array `offset` arrays away from the one holding `tick_current_index`,
+/// clamped to the first/last array instead of running past the tick bounds.
 pub fn tickutil_get_start_tick_index(tick_current_index: i32, tick_spacing: u16, offset: i32) -> i32 {
     let ticks_in_array = TICK_ARRAY_SIZE * tick_spacing as i32;
     let real_index = div_floor(tick_current_index, ticks_in_array);
     let start_tick_index = (real_index + offset) * ticks_in_array;
-  
-    assert!(MIN_TICK_INDEX <= start_tick_index);
-    assert!(start_tick_index + ticks_in_array <= MAX_TICK_INDEX);
-    start_tick_index
+
+    let (min_start_tick_index, max_start_tick_index) = tickutil_get_start_tick_index_bounds(tick_spacing);
+    start_tick_index.clamp(min_start_tick_index, max_start_tick_index)
+}
+
+/// Start indexes of up to `count` consecutive tick arrays a swap would cross, in swap direction.
+///
+/// Like the program, a b to a swap starting on the last tick of an array begins in the next
+/// one. The sequence ends early at the tick bounds instead of repeating the last array.
+pub fn tickutil_get_start_tick_indexes_for_swap(tick_current_index: i32, tick_spacing: u16, a_to_b: bool, count: usize) -> Vec<i32> {
+    let shift = if a_to_b { 0 } else { tick_spacing as i32 };
+    let mut start_tick_indexes: Vec<i32> = Vec::with_capacity(count);
+
+    for i in 0..count as i32 {
+      let offset = if a_to_b { -i } else { i };
+      let start_tick_index = tickutil_get_start_tick_index(tick_current_index + shift, tick_spacing, offset);
+      if start_tick_indexes.last() == Some(&start_tick_index) {
+        break;
+      }
+      start_tick_indexes.push(start_tick_index);
+    }
+    start_tick_indexes
 }
 
 
@@ -178,14 +207,21 @@ pub fn poolutil_get_tick_array_pubkeys_for_swap(
     whirlpool_pubkey: &solana_sdk::pubkey::Pubkey,
   ) -> [solana_sdk::pubkey::Pubkey; 3] {
 
-    let mut offset = 0;
+    let start_tick_indexes = tickutil_get_start_tick_indexes_for_swap(tick_current_index, tick_spacing, a_to_b, 3);
+    poolutil_pad_tick_array_pubkeys(
+      &start_tick_indexes
+        .iter()
+        .map(|start_tick_index| pdautil_get_tick_array(program_id, whirlpool_pubkey, *start_tick_index))
+        .collect::<Vec<_>>(),
+    )
+}
+
+/// Fills the three swap tick array slots, repeating the last array when fewer are usable
+/// (tick bounds reached, or the next array is not initialized).
+pub fn poolutil_pad_tick_array_pubkeys(tick_array_pubkeys: &[solana_sdk::pubkey::Pubkey]) -> [solana_sdk::pubkey::Pubkey; 3] {
     let mut pubkeys: [solana_sdk::pubkey::Pubkey; 3] = Default::default();
-  
-    for i in 0..pubkeys.len() {
-      let start_tick_index = tickutil_get_start_tick_index(tick_current_index, tick_spacing, offset);
-      let tick_array_pubkey = pdautil_get_tick_array(program_id, whirlpool_pubkey, start_tick_index);
-      pubkeys[i] = tick_array_pubkey;
-      offset = if a_to_b { offset - 1 } else { offset + 1 };
+    for (i, pubkey) in pubkeys.iter_mut().enumerate() {
+      *pubkey = tick_array_pubkeys[i.min(tick_array_pubkeys.len() - 1)];
     }
     pubkeys
 }
@@ -200,38 +236,23 @@ pub fn generate_oracle_pda(whirlpool_pubkey: &solana_sdk::pubkey::Pubkey, dex_pr
 
 #[tokio::test]
 async fn test_tick_array() {
-    use std::env;
-    use dotenv::dotenv;
-
     use crate::constant::ORCA_WHIRLPOOL_PROGRAM_ID;
-    // use crate::constant::SOL_DECIMALS;
-    // use crate::constant::USDC_DECIMALS;
-    use solana_client::nonblocking::rpc_client::RpcClient as AsyncRpcClient;
-    use solana_sdk::commitment_config::CommitmentConfig;
+    use crate::fixture::PoolFixture;
+    use crate::rpc::SolanaRpc;
 
-    dotenv().ok();
-    let sol_usdc_whirlpool_address = solana_sdk::pubkey::Pubkey::from_str("LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo").unwrap();
+    // synthetic SOL/USDC state with tick spacing 4 at a made-up address, not a recording
+    let fixture = PoolFixture::load(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/whirlpool_synthetic_sol_usdc.json")).unwrap();
+    let sol_usdc_whirlpool_address = solana_sdk::pubkey::Pubkey::from_str(&fixture.whirlpool).unwrap();
+    let rpc_client = fixture.to_rpc().unwrap();
 
     let orca_whirlpool_program_id = solana_sdk::pubkey::Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
 
-    let rpc_url = env::vars()
-        .find(|(key, _)| key == "RPC_URL")
-        .map(|(_, value)| value)
-        .ok_or("RPC_URL not found").unwrap();
-
-    let rpc_client = AsyncRpcClient::new_with_commitment(rpc_url, 
-    CommitmentConfig::confirmed());
-
     let mut whirlpool_data = rpc_client.get_account_data(&sol_usdc_whirlpool_address).await.unwrap();
-    let whirlpool_data_slice = &mut whirlpool_data.as_mut_slice()[0..].as_ref();
+    let whirlpool_data_slice = &mut whirlpool_data.as_mut_slice()[8..].as_ref();
     
     
     let whirlpool: Whirlpool = AnchorDeserialize::deserialize(whirlpool_data_slice).unwrap();
-    println!("{:?}", whirlpool);
 
-    // calcu price with rust_decimal crate (at client-side)
-    // println!("whirlpool price {}", pricemath_sqrt_price_x64_to_price(whirlpool.sqrt_price, SOL_DECIMALS, USDC_DECIMALS));
-    
     let a_to_b = true;
     // get tickarray for swap
     let tick_arrays = poolutil_get_tick_array_pubkeys_for_swap(
@@ -241,9 +262,6 @@ async fn test_tick_array() {
         &orca_whirlpool_program_id,
         &sol_usdc_whirlpool_address,
     );
-    println!("tick_arrays[0] {}", tick_arrays[0].to_string());
-    println!("tick_arrays[1] {}", tick_arrays[1].to_string());
-    println!("tick_arrays[2] {}", tick_arrays[2].to_string());
 
     let ta0_data: &[u8] = &rpc_client.get_account_data(&tick_arrays[0]).await.unwrap();
     let ta1_data: &[u8] = &rpc_client.get_account_data(&tick_arrays[1]).await.unwrap();
@@ -259,15 +277,14 @@ async fn test_tick_array() {
     let ta1_start_tick_index = ta1.start_tick_index;
     let ta2_start_tick_index = ta2.start_tick_index;
 
-    println!("ta0 start_tick_index {:?}", ta0_start_tick_index);
-    println!("ta1 start_tick_index {}", ta1_start_tick_index);
-    println!("ta2 start_tick_index {}", ta2_start_tick_index);
+    let ticks_in_array = TICK_ARRAY_SIZE * whirlpool.tick_spacing as i32;
+    assert!(ta0_start_tick_index <= whirlpool.tick_current_index && whirlpool.tick_current_index < ta0_start_tick_index + ticks_in_array);
+    assert_eq!(ta1_start_tick_index, ta0_start_tick_index - ticks_in_array);
+    assert_eq!(ta2_start_tick_index, ta1_start_tick_index - ticks_in_array);
+    assert!([ta0.whirlpool, ta1.whirlpool, ta2.whirlpool].iter().all(|whirlpool| whirlpool.to_bytes() == sol_usdc_whirlpool_address.to_bytes()));
 
 
     let whirlpool = solana_sdk::pubkey::Pubkey::from_str("HJPjoWUrhoZzkNfRpHuieeFk9WcZWjwy6PBjZ81ngndJ").unwrap();
     let oracle_address = generate_oracle_pda(&whirlpool, &orca_whirlpool_program_id);
-
-    println!("oracle_address {:?}", oracle_address.0);
-
-
-}
\ No newline at end of file
+    assert!(!oracle_address.0.is_on_curve());
+}
@@ -0,0 +1,115 @@
+//! Token lookup by symbol or mint, and conversion between raw and human readable amounts.
+
+use std::str::FromStr;
+
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::config::ClusterConfig;
+use crate::rpc::SolanaRpc;
+
+pub type TokenError = Box<dyn std::error::Error>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenInfo {
+    /// Set when the mint is one of the profile's known tokens.
+    pub symbol: Option<String>,
+    pub mint: Pubkey,
+    pub decimals: i8,
+}
+
+impl TokenInfo {
+    /// The symbol if known, otherwise the mint address.
+    pub fn label(&self) -> String {
+        self.symbol.clone().unwrap_or_else(|| self.mint.to_string())
+    }
+}
+
+/// Resolves `symbol_or_mint` against the profile's tokens (symbols match case-insensitively).
+/// Mints the profile doesn't know are read from the chain for their decimals.
+pub async fn resolve_token<R: SolanaRpc>(profile: &ClusterConfig, rpc: &R, symbol_or_mint: &str) -> Result<TokenInfo, TokenError> {
+    if let Some((symbol, token)) = profile.tokens.iter().find(|(symbol, _)| symbol.eq_ignore_ascii_case(symbol_or_mint)) {
+        return Ok(TokenInfo { symbol: Some(symbol.clone()), mint: token.mint()?, decimals: token.decimals });
+    }
+
+    let mint = Pubkey::from_str(symbol_or_mint).map_err(|_| format!("{} is neither a known token symbol nor a mint address", symbol_or_mint))?;
+    token_info_by_mint(profile, rpc, &mint).await
+}
+
+/// Like [`resolve_token`] for a mint already known to be one.
+pub async fn token_info_by_mint<R: SolanaRpc>(profile: &ClusterConfig, rpc: &R, mint: &Pubkey) -> Result<TokenInfo, TokenError> {
+    if let Some((symbol, token)) = profile.token_by_mint(mint) {
+        return Ok(TokenInfo { symbol: Some(symbol.to_string()), mint: *mint, decimals: token.decimals });
+    }
+
+    let account = rpc.get_account(mint).await?;
+    let decimals = spl_token::state::Mint::unpack(&account.data).map_err(|_| format!("{} is not a token mint", mint))?.decimals;
+    Ok(TokenInfo { symbol: None, mint: *mint, decimals: decimals as i8 })
+}
+
+/// Parses a human readable amount such as `1.5` into base units of a token with `decimals`.
+pub fn ui_amount_to_raw(amount: &str, decimals: i8) -> Result<u64, TokenError> {
+    let scale = u32::try_from(decimals)
+        .ok()
+        .and_then(|decimals| 10u64.checked_pow(decimals))
+        .ok_or_else(|| format!("unsupported token decimals {}", decimals))?;
+    let ui_amount = Decimal::from_str(amount.trim()).map_err(|_| format!("invalid amount {}", amount))?;
+    if ui_amount.is_sign_negative() {
+        return Err(format!("amount {} is negative", amount).into());
+    }
+    if ui_amount.scale() > decimals as u32 {
+        return Err(format!("amount {} has more than {} decimals", amount, decimals).into());
+    }
+
+    let raw_amount = ui_amount
+        .checked_mul(Decimal::from(scale))
+        .and_then(|raw_amount| raw_amount.to_u64())
+        .ok_or_else(|| format!("amount {} is too large", amount))?;
+    Ok(raw_amount)
+}
+
+/// Base units of a token with `decimals` as a human readable amount, without trailing zeros.
+pub fn raw_amount_to_ui(amount: u64, decimals: i8) -> String {
+    Decimal::from_i128_with_scale(amount as i128, decimals as u32).normalize().to_string()
+}
+
+#[tokio::test]
+async fn test_resolve_token() {
+    use solana_sdk::account::Account;
+    use spl_token::state::Mint;
+
+    use crate::config::Config;
+    use crate::rpc::InMemoryRpc;
+
+    let mainnet = &Config::builtin().profiles["mainnet"];
+    let rpc = InMemoryRpc::new();
+
+    let usdc = resolve_token(mainnet, &rpc, "usdc").await.unwrap();
+    assert_eq!(usdc.symbol.as_deref(), Some("USDC"));
+    assert_eq!(usdc.decimals, 6);
+    let by_mint = resolve_token(mainnet, &rpc, &usdc.mint.to_string()).await.unwrap();
+    assert_eq!(by_mint, usdc);
+
+    // unknown mints are read from the chain
+    let bonk = Pubkey::new_unique();
+    let mut data = vec![0; Mint::LEN];
+    Mint { decimals: 5, is_initialized: true, ..Default::default() }.pack_into_slice(&mut data);
+    rpc.set_account(bonk, Account { lamports: 1_461_600, data, owner: spl_token::id(), executable: false, rent_epoch: 0 });
+    let token = resolve_token(mainnet, &rpc, &bonk.to_string()).await.unwrap();
+    assert_eq!((token.symbol, token.decimals), (None, 5));
+    assert!(resolve_token(mainnet, &rpc, "BONK").await.is_err());
+
+    assert_eq!(ui_amount_to_raw("1.5", 9).unwrap(), 1_500_000_000);
+    assert_eq!(ui_amount_to_raw("0.3", 6).unwrap(), 300_000);
+    assert_eq!(ui_amount_to_raw("42", 0).unwrap(), 42);
+    assert!(ui_amount_to_raw("0.0000001", 6).is_err());
+    assert!(ui_amount_to_raw("-1", 6).is_err());
+    assert!(ui_amount_to_raw("100000000000000", 9).is_err());
+    assert!(ui_amount_to_raw("1", 20).is_err());
+    assert!(ui_amount_to_raw("1", -1).is_err());
+    assert_eq!(raw_amount_to_ui(1_500_000_000, 9), "1.5");
+    assert_eq!(raw_amount_to_ui(300_000, 6), "0.3");
+    assert_eq!(raw_amount_to_ui(0, 6), "0");
+}
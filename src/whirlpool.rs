@@ -0,0 +1,631 @@
+//! Orca Whirlpools: swap instructions, trades split across tick arrays, and [`WhirlpoolPool`],
+//! the [`Pool`] over a whirlpool and the tick arrays around its price.
+
+use std::collections::HashMap;
+
+use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+use rust_decimal::Decimal;
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address;
+use std::str::FromStr;
+
+use crate::constant::{INITIALIZE_TICK_ARRAY_DISCRIMINATOR, TICK_ARRAY_SIZE, TICK_ARRAY_SIZE_USIZE, WHIRLPOOL_SWAP_DISCRIMINATOR};
+use crate::data_source::{decode_whirlpool_account, WhirlpoolAccount};
+use crate::dex::{apply_slippage_bps, DexError, Pool, PoolQuote};
+use crate::swap_math::{swapmath_simulate_swap, tickmath_sqrt_price_from_tick_index, SwapSimulation};
+use crate::tick_array::{generate_oracle_pda, pdautil_get_tick_array, poolutil_pad_tick_array_pubkeys, pricemath_sqrt_price_x64_to_price, tickutil_get_start_tick_indexes_for_swap, Tick, TickArray, Whirlpool};
+
+/// Tick arrays a single Whirlpool `swap` instruction can cross.
+pub const MAX_TICK_ARRAYS_PER_SWAP: usize = 3;
+
+/// Tick arrays fetched for one trade, so a trade is split into at most three swaps.
+pub const MAX_TICK_ARRAYS_PER_TRADE: usize = 9;
+
+#[derive(Default, AnchorSerialize, AnchorDeserialize)]
+pub struct SwapData {
+    pub amount: u64,
+    pub other_amount_threshold: u64,
+    pub sqrt_price_limit: u128,
+    pub amount_specified_is_input: bool,
+    pub a_to_b: bool,
+}
+
+#[derive(Default, AnchorSerialize, AnchorDeserialize)]
+pub struct InitializeTickArrayData {
+    pub start_tick_index: i32,
+}
+
+/// Address of the whirlpool of `whirlpools_config` trading `mint_a` against `mint_b` at
+/// `tick_spacing`.
+pub fn get_whirlpool_address(program_id: &Pubkey, whirlpools_config: &Pubkey, mint_a: &Pubkey, mint_b: &Pubkey, tick_spacing: u16) -> Pubkey {
+    let seeds = [b"whirlpool".as_ref(), whirlpools_config.as_ref(), mint_a.as_ref(), mint_b.as_ref(), &tick_spacing.to_le_bytes()];
+    Pubkey::find_program_address(&seeds, program_id).0
+}
+
+/// Whirlpool `initialize_tick_array` for the array of `whirlpool_pubkey` starting at
+/// `start_tick_index`. `funder` signs and pays the rent (~0.07 SOL).
+pub fn build_initialize_tick_array_instruction(
+    program_id: &Pubkey,
+    whirlpool_pubkey: &Pubkey,
+    funder: &Pubkey,
+    start_tick_index: i32,
+) -> std::result::Result<Instruction, Box<dyn std::error::Error>> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*whirlpool_pubkey, false),
+        AccountMeta::new(*funder, true),
+        AccountMeta::new(pdautil_get_tick_array(program_id, whirlpool_pubkey, start_tick_index), false),
+        AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+    ];
+
+    let data = {
+        let mut prefix = INITIALIZE_TICK_ARRAY_DISCRIMINATOR.to_vec();
+        prefix.extend(InitializeTickArrayData { start_tick_index }.try_to_vec()?);
+        prefix
+    };
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+// what an uninitialized tick array behaves like: no initialized ticks
+fn empty_tick_array(whirlpool_pubkey: &Pubkey, start_tick_index: i32) -> TickArray {
+    TickArray {
+        start_tick_index,
+        ticks: [Tick::default(); TICK_ARRAY_SIZE_USIZE],
+        whirlpool: anchor_lang::prelude::Pubkey::new_from_array(whirlpool_pubkey.to_bytes()),
+    }
+}
+
+/// One `swap` instruction of a trade that was split to stay within three tick arrays.
+pub struct SwapLeg {
+    pub amount: u64,
+    pub other_amount_threshold: u64,
+    /// 0 for the last leg, otherwise the far boundary of the leg's third tick array.
+    pub sqrt_price_limit: u128,
+    pub tick_arrays: [Pubkey; 3],
+    pub simulation: SwapSimulation,
+}
+
+/// Splits an exact-input swap of `amount` into legs that each cross at most
+/// `MAX_TICK_ARRAYS_PER_SWAP` of `tick_arrays`, simulating each leg from where the previous
+/// one ends. `tick_arrays` is the sequence from `DEXClient::get_tick_arrays_for_swap`, and
+/// each leg accepts `slippage_bps` less than its simulated output.
+pub fn plan_orca_swap_legs(
+    whirlpool: &Whirlpool,
+    tick_arrays: &[(Pubkey, TickArray)],
+    amount: u64,
+    a_to_b: bool,
+    slippage_bps: u16,
+) -> std::result::Result<Vec<SwapLeg>, Box<dyn std::error::Error>> {
+    let mut state = whirlpool.clone();
+    let mut amount_remaining = amount;
+    let mut legs = Vec::new();
+
+    while amount_remaining > 0 {
+        let window: Vec<&(Pubkey, TickArray)> = tickutil_get_start_tick_indexes_for_swap(state.tick_current_index, state.tick_spacing, a_to_b, MAX_TICK_ARRAYS_PER_SWAP)
+            .into_iter()
+            .map_while(|start_tick_index| tick_arrays.iter().find(|(_, tick_array)| { tick_array.start_tick_index } == start_tick_index))
+            .collect();
+        if window.is_empty() {
+            return Err(format!("{} of {} could not be swapped within the initialized tick arrays", amount_remaining, amount).into());
+        }
+
+        let window_tick_arrays: Vec<TickArray> = window.iter().map(|(_, tick_array)| *tick_array).collect();
+        let simulation = swapmath_simulate_swap(&state, &window_tick_arrays, amount_remaining, 0, true, a_to_b)?;
+        if simulation.amount_in == 0 {
+            return Err(format!("{} of {} could not be swapped within the initialized tick arrays", amount_remaining, amount).into());
+        }
+
+        let is_last_leg = simulation.amount_in == amount_remaining;
+        let sqrt_price_limit = if is_last_leg {
+            0
+        } else {
+            let last_start_tick_index = window_tick_arrays[window_tick_arrays.len() - 1].start_tick_index;
+            let boundary_tick_index = if a_to_b {
+                last_start_tick_index
+            } else {
+                last_start_tick_index + (TICK_ARRAY_SIZE - 1) * state.tick_spacing as i32
+            };
+            tickmath_sqrt_price_from_tick_index(boundary_tick_index)
+        };
+
+        let window_pubkeys: Vec<Pubkey> = window.iter().map(|(pubkey, _)| *pubkey).collect();
+        legs.push(SwapLeg {
+            amount: simulation.amount_in,
+            other_amount_threshold: apply_slippage_bps(simulation.amount_out, slippage_bps),
+            sqrt_price_limit,
+            tick_arrays: poolutil_pad_tick_array_pubkeys(&window_pubkeys),
+            simulation: simulation.clone(),
+        });
+
+        amount_remaining -= simulation.amount_in;
+        state.sqrt_price = simulation.end_sqrt_price;
+        state.tick_current_index = simulation.end_tick_index;
+        state.liquidity = simulation.end_liquidity;
+    }
+
+    Ok(legs)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn whirlpool_swap_instruction(
+    program_id: &Pubkey,
+    token_authority: &Pubkey,
+    whirlpool_pubkey: &Pubkey,
+    whirlpool: &Whirlpool,
+    token_owner_account_a: &Pubkey,
+    token_owner_account_b: &Pubkey,
+    tick_arrays: &[Pubkey; 3],
+    swap_data: &SwapData,
+) -> std::result::Result<Instruction, Box<dyn std::error::Error>> {
+    let (oracle, _) = generate_oracle_pda(whirlpool_pubkey, program_id);
+
+    let accounts = vec![
+        AccountMeta::new(spl_token::id(), false),
+        AccountMeta::new(*token_authority, true),
+        AccountMeta::new(*whirlpool_pubkey, false),
+        AccountMeta::new(*token_owner_account_a, false),
+        AccountMeta::new(Pubkey::new_from_array(whirlpool.token_vault_a.to_bytes()), false),
+        AccountMeta::new(*token_owner_account_b, false),
+        AccountMeta::new(Pubkey::new_from_array(whirlpool.token_vault_b.to_bytes()), false),
+        AccountMeta::new(tick_arrays[0], false),
+        AccountMeta::new(tick_arrays[1], false),
+        AccountMeta::new(tick_arrays[2], false),
+        AccountMeta::new(oracle, false),
+    ];
+
+    let data = {
+        let mut prefix = WHIRLPOOL_SWAP_DISCRIMINATOR.to_vec();
+        prefix.extend(swap_data.try_to_vec()?);
+        prefix
+    };
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// An exact-input Whirlpool swap, simulated against the pool's current tick arrays.
+pub struct OrcaSwapQuote {
+    pub whirlpool: Whirlpool,
+    pub a_to_b: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// Least output accepted across all legs.
+    pub other_amount_threshold: u64,
+    pub fee_amount: u64,
+    pub end_sqrt_price: u128,
+    pub legs: Vec<SwapLeg>,
+    /// Missing tick arrays the trade reaches, created before swapping.
+    pub tick_arrays_to_initialize: Vec<i32>,
+}
+
+/// Quotes swapping `amount` on `whirlpool`, split into legs of at most three tick arrays.
+/// `tick_arrays` are the arrays from the current one on in swap direction, by start tick
+/// index, `None` where not initialized. `slippage_bps` sets each leg's minimum output.
+///
+/// With `initialize_tick_arrays`, missing tick arrays are treated as empty and the ones the
+/// trade reaches are listed in `tick_arrays_to_initialize`. Otherwise the trade must fit in
+/// the arrays that already exist.
+pub fn quote_whirlpool_swap(
+    pool_address: &Pubkey,
+    whirlpool: &Whirlpool,
+    tick_arrays: &[(i32, Pubkey, Option<TickArray>)],
+    amount: u64,
+    a_to_b: bool,
+    slippage_bps: u16,
+    initialize_tick_arrays: bool,
+) -> Result<OrcaSwapQuote, DexError> {
+    let mut missing_tick_arrays = Vec::new();
+    let tick_arrays: Vec<(Pubkey, TickArray)> = if initialize_tick_arrays {
+        tick_arrays
+            .iter()
+            .map(|(start_tick_index, pubkey, tick_array)| {
+                let tick_array = tick_array.unwrap_or_else(|| {
+                    missing_tick_arrays.push((*pubkey, *start_tick_index));
+                    empty_tick_array(pool_address, *start_tick_index)
+                });
+                (*pubkey, tick_array)
+            })
+            .collect()
+    } else {
+        let existing: Vec<(Pubkey, TickArray)> = tick_arrays.iter().map_while(|(_, pubkey, tick_array)| tick_array.map(|tick_array| (*pubkey, tick_array))).collect();
+        match tick_arrays.first() {
+            Some((_, current, None)) => return Err(format!("tick array {} holding the current tick is not initialized", current).into()),
+            _ => existing,
+        }
+    };
+
+    let mut legs = plan_orca_swap_legs(whirlpool, &tick_arrays, amount, a_to_b, slippage_bps)?;
+
+    // only create the missing arrays a leg reaches, the rest are swapped for padding
+    let is_missing = |pubkey: &Pubkey| missing_tick_arrays.iter().any(|(missing, _)| missing == pubkey);
+    let mut tick_arrays_to_initialize = Vec::new();
+    for leg in &mut legs {
+        let used = leg.simulation.tick_arrays_used.clamp(1, MAX_TICK_ARRAYS_PER_SWAP);
+        for pubkey in &leg.tick_arrays[..used] {
+            if let Some((_, start_tick_index)) = missing_tick_arrays.iter().find(|(missing, _)| missing == pubkey) {
+                if !tick_arrays_to_initialize.contains(start_tick_index) {
+                    tick_arrays_to_initialize.push(*start_tick_index);
+                }
+            }
+        }
+        if leg.tick_arrays[used..].iter().any(is_missing) {
+            leg.tick_arrays = poolutil_pad_tick_array_pubkeys(&leg.tick_arrays[..used]);
+        }
+    }
+
+    Ok(OrcaSwapQuote {
+        a_to_b,
+        amount_in: legs.iter().map(|leg| leg.amount).sum(),
+        amount_out: legs.iter().map(|leg| leg.simulation.amount_out).sum(),
+        other_amount_threshold: legs.iter().map(|leg| leg.other_amount_threshold).sum(),
+        fee_amount: legs.iter().map(|leg| leg.simulation.fee_amount).sum(),
+        end_sqrt_price: legs.last().map_or(whirlpool.sqrt_price, |leg| leg.simulation.end_sqrt_price),
+        whirlpool: whirlpool.clone(),
+        legs,
+        tick_arrays_to_initialize,
+    })
+}
+
+/// Instructions executing `quote` for `user` out of and into their associated token accounts:
+/// creating the tick arrays the quote needs, paid by `funder`, then one swap per leg.
+pub fn whirlpool_swap_instructions(
+    program_id: &Pubkey,
+    pool_address: &Pubkey,
+    user: &Pubkey,
+    funder: &Pubkey,
+    quote: &OrcaSwapQuote,
+) -> Result<Vec<Instruction>, DexError> {
+    let token_owner_account_a = get_associated_token_address(user, &Pubkey::new_from_array(quote.whirlpool.token_mint_a.to_bytes()));
+    let token_owner_account_b = get_associated_token_address(user, &Pubkey::new_from_array(quote.whirlpool.token_mint_b.to_bytes()));
+
+    let mut instructions = Vec::with_capacity(quote.tick_arrays_to_initialize.len() + quote.legs.len());
+    for start_tick_index in &quote.tick_arrays_to_initialize {
+        instructions.push(build_initialize_tick_array_instruction(program_id, pool_address, funder, *start_tick_index)?);
+    }
+    for leg in &quote.legs {
+        let swap_data = SwapData {
+            amount: leg.amount,
+            other_amount_threshold: leg.other_amount_threshold,
+            sqrt_price_limit: leg.sqrt_price_limit,
+            amount_specified_is_input: true,
+            a_to_b: quote.a_to_b,
+        };
+        instructions.push(whirlpool_swap_instruction(program_id, user, pool_address, &quote.whirlpool, &token_owner_account_a, &token_owner_account_b, &leg.tick_arrays, &swap_data)?);
+    }
+    Ok(instructions)
+}
+
+/// A whirlpool and the tick arrays a trade in either direction can reach.
+pub struct WhirlpoolPool {
+    address: Pubkey,
+    program_id: Pubkey,
+    whirlpool: Option<Whirlpool>,
+    /// By address, `None` for arrays that are not initialized.
+    tick_arrays: HashMap<Pubkey, Option<TickArray>>,
+    initialize_tick_arrays: bool,
+}
+
+impl WhirlpoolPool {
+    pub fn new(address: Pubkey, program_id: Pubkey) -> Self {
+        WhirlpoolPool {
+            address,
+            program_id,
+            whirlpool: None,
+            tick_arrays: HashMap::new(),
+            initialize_tick_arrays: false,
+        }
+    }
+
+    /// Quote through missing tick arrays as if they were empty, and create the ones a swap
+    /// reaches, see [`quote_whirlpool_swap`].
+    pub fn with_initialize_tick_arrays(mut self, initialize_tick_arrays: bool) -> Self {
+        self.initialize_tick_arrays = initialize_tick_arrays;
+        self
+    }
+
+    pub fn whirlpool(&self) -> Option<&Whirlpool> {
+        self.whirlpool.as_ref()
+    }
+
+    /// The leg-by-leg quote behind [`Pool::quote`].
+    pub fn quote_swap(&self, amount: u64, a_to_b: bool, slippage_bps: u16) -> Result<OrcaSwapQuote, DexError> {
+        let whirlpool = self.loaded()?;
+        let tick_arrays: Vec<(i32, Pubkey, Option<TickArray>)> = self
+            .start_tick_indexes(whirlpool, a_to_b)
+            .into_iter()
+            .map(|start_tick_index| {
+                let pubkey = pdautil_get_tick_array(&self.program_id, &self.address, start_tick_index);
+                (start_tick_index, pubkey, self.tick_arrays.get(&pubkey).copied().flatten())
+            })
+            .collect();
+        quote_whirlpool_swap(&self.address, whirlpool, &tick_arrays, amount, a_to_b, slippage_bps, self.initialize_tick_arrays)
+    }
+
+    fn loaded(&self) -> Result<&Whirlpool, DexError> {
+        self.whirlpool.as_ref().ok_or_else(|| format!("pool {} is not loaded", self.address).into())
+    }
+
+    fn start_tick_indexes(&self, whirlpool: &Whirlpool, a_to_b: bool) -> Vec<i32> {
+        tickutil_get_start_tick_indexes_for_swap(whirlpool.tick_current_index, whirlpool.tick_spacing, a_to_b, MAX_TICK_ARRAYS_PER_TRADE)
+    }
+}
+
+impl Pool for WhirlpoolPool {
+    fn dex(&self) -> &'static str {
+        "whirlpool"
+    }
+
+    fn address(&self) -> Pubkey {
+        self.address
+    }
+
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn mints(&self) -> Option<(Pubkey, Pubkey)> {
+        self.whirlpool
+            .as_ref()
+            .map(|whirlpool| (Pubkey::new_from_array(whirlpool.token_mint_a.to_bytes()), Pubkey::new_from_array(whirlpool.token_mint_b.to_bytes())))
+    }
+
+    fn accounts_to_watch(&self) -> Vec<Pubkey> {
+        let mut accounts = vec![self.address];
+        if let Some(whirlpool) = &self.whirlpool {
+            for a_to_b in [true, false] {
+                for start_tick_index in self.start_tick_indexes(whirlpool, a_to_b) {
+                    let pubkey = pdautil_get_tick_array(&self.program_id, &self.address, start_tick_index);
+                    if !accounts.contains(&pubkey) {
+                        accounts.push(pubkey);
+                    }
+                }
+            }
+        }
+        accounts
+    }
+
+    fn update(&mut self, pubkey: &Pubkey, account: Option<&Account>) -> Result<(), DexError> {
+        let decoded = account.and_then(|account| decode_whirlpool_account(&account.data));
+        if *pubkey == self.address {
+            match decoded {
+                Some(WhirlpoolAccount::Whirlpool(whirlpool)) => self.whirlpool = Some(*whirlpool),
+                _ => return Err(format!("{} is not a whirlpool", pubkey).into()),
+            }
+        } else {
+            match decoded {
+                Some(WhirlpoolAccount::TickArray(tick_array)) => self.tick_arrays.insert(*pubkey, Some(*tick_array)),
+                _ => self.tick_arrays.insert(*pubkey, None),
+            };
+        }
+        Ok(())
+    }
+
+    fn price(&self) -> Result<Decimal, DexError> {
+        Ok(Decimal::from_str(&pricemath_sqrt_price_x64_to_price(self.loaded()?.sqrt_price, 0, 0))?)
+    }
+
+    fn fee_rate(&self) -> Result<Decimal, DexError> {
+        // hundredths of a basis point
+        Ok(Decimal::new(self.loaded()?.fee_rate as i64, 6))
+    }
+
+    fn quote(&self, amount_in: u64, a_to_b: bool) -> Result<PoolQuote, DexError> {
+        let quote = self.quote_swap(amount_in, a_to_b, 0)?;
+        let (mint_a, mint_b) = self.mints().ok_or("pool is not loaded")?;
+        Ok(PoolQuote {
+            pool: self.address,
+            a_to_b,
+            mint_in: if a_to_b { mint_a } else { mint_b },
+            mint_out: if a_to_b { mint_b } else { mint_a },
+            amount_in: quote.amount_in,
+            amount_out: quote.amount_out,
+            fee_amount: quote.fee_amount,
+            legs: quote.legs.len(),
+            accounts_to_initialize: quote
+                .tick_arrays_to_initialize
+                .iter()
+                .map(|start_tick_index| pdautil_get_tick_array(&self.program_id, &self.address, *start_tick_index))
+                .collect(),
+        })
+    }
+
+    fn swap_instructions(&self, user: &Pubkey, funder: &Pubkey, quote: &PoolQuote, min_amount_out: u64) -> Result<Vec<Instruction>, DexError> {
+        let mut orca_quote = self.quote_swap(quote.amount_in, quote.a_to_b, 0)?;
+        // each leg keeps its share of the output the whole trade may fall short by
+        for leg in &mut orca_quote.legs {
+            leg.other_amount_threshold = (leg.simulation.amount_out as u128 * min_amount_out as u128 / orca_quote.amount_out.max(1) as u128) as u64;
+        }
+        whirlpool_swap_instructions(&self.program_id, &self.address, user, funder, &orca_quote)
+    }
+}
+
+#[test]
+fn test_plan_orca_swap_legs() {
+    use crate::constant::ORCA_WHIRLPOOL_PROGRAM_ID;
+
+    let program_id = Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
+    let pool_address = Pubkey::new_unique();
+    let whirlpool = Whirlpool {
+        tick_spacing: 64,
+        fee_rate: 3000,
+        liquidity: 10_000_000_000,
+        sqrt_price: 1u128 << 64,
+        tick_current_index: 0,
+        ..Default::default()
+    };
+    let tick_arrays: Vec<(Pubkey, TickArray)> = [0, -5632, -11264, -16896, -22528]
+        .into_iter()
+        .map(|start_tick_index| {
+            (pdautil_get_tick_array(&program_id, &pool_address, start_tick_index), empty_tick_array(&pool_address, start_tick_index))
+        })
+        .collect();
+
+    // a small trade stays within the first three arrays
+    let legs = plan_orca_swap_legs(&whirlpool, &tick_arrays, 1_000_000, true, 1000).unwrap();
+    assert_eq!(legs.len(), 1);
+    assert_eq!(legs[0].amount, 1_000_000);
+    assert_eq!(legs[0].sqrt_price_limit, 0);
+    assert_eq!(legs[0].other_amount_threshold, legs[0].simulation.amount_out * 90 / 100);
+    assert_eq!(apply_slippage_bps(10_000, 25), 9_975);
+
+    // pushing the price below tick -11264 needs the fourth array, so the trade is split there
+    let legs = plan_orca_swap_legs(&whirlpool, &tick_arrays, 10_000_000_000, true, 1000).unwrap();
+    assert_eq!(legs.len(), 2);
+    assert_eq!(legs.iter().map(|leg| leg.amount).sum::<u64>(), 10_000_000_000);
+    assert_eq!(legs[0].tick_arrays, [tick_arrays[0].0, tick_arrays[1].0, tick_arrays[2].0]);
+    assert_eq!(legs[0].sqrt_price_limit, tickmath_sqrt_price_from_tick_index(-11264));
+    assert_eq!(legs[0].simulation.end_tick_index, -11265);
+    assert_eq!(legs[1].tick_arrays, [tick_arrays[3].0, tick_arrays[4].0, tick_arrays[4].0]);
+    assert_eq!(legs[1].sqrt_price_limit, 0);
+
+    // more than every initialized array can absorb
+    assert!(plan_orca_swap_legs(&whirlpool, &tick_arrays, 1_000_000_000_000, true, 1000).is_err());
+}
+
+#[tokio::test]
+async fn test_whirlpool_pool() {
+    use crate::client::{quote_orca_pool_swap, DEXClient};
+    use crate::config::Config;
+    use crate::rpc::InMemoryRpc;
+
+    let fixture_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/wsol_usdc_3000");
+    let client = DEXClient::with_rpc(InMemoryRpc::from_fixture_dir(fixture_dir).unwrap());
+    let mainnet = &Config::builtin().profiles["mainnet"];
+    let pool_address = mainnet.pool("WSOL_USDC_3000").unwrap().address().unwrap();
+    let program_id = mainnet.whirlpool_program_id().unwrap();
+    let (wsol, usdc) = (mainnet.token("SOL").unwrap().mint().unwrap(), mainnet.token("USDC").unwrap().mint().unwrap());
+    let user = Pubkey::new_from_array([7; 32]);
+
+    let mut pool = WhirlpoolPool::new(pool_address, program_id);
+    assert_eq!(pool.accounts_to_watch(), vec![pool_address]);
+    assert!(pool.quote(1_000, true).is_err());
+    client.load_pool(&mut pool).await.unwrap();
+
+    // the pool and nine arrays each way, sharing the current one
+    let watched = pool.accounts_to_watch();
+    assert_eq!(watched.len(), 18);
+    assert!(watched.contains(&pdautil_get_tick_array(&program_id, &pool_address, -22528)));
+    assert_eq!(pool.mints(), Some((wsol, usdc)));
+    assert_eq!(pool.price().unwrap().round_dp(2), Decimal::new(14, 2));
+    assert_eq!(pool.fee_rate().unwrap(), Decimal::new(3, 3));
+    assert!(!pool.a_to_b(&usdc).unwrap());
+    assert!(pool.a_to_b(&user).is_err());
+
+    // the same quote the RPC-backed path gives
+    let quote = pool.quote(300_000, false).unwrap();
+    let orca_quote = quote_orca_pool_swap(&client, &pool_address, &program_id, &usdc, 300_000, 0, false).await.unwrap();
+    assert_eq!((quote.amount_in, quote.amount_out, quote.fee_amount), (orca_quote.amount_in, orca_quote.amount_out, orca_quote.fee_amount));
+    assert_eq!((quote.mint_in, quote.mint_out, quote.legs), (usdc, wsol, 1));
+    assert!(quote.accounts_to_initialize.is_empty());
+
+    let instructions = pool.swap_instructions(&user, &user, &quote, quote.min_amount_out(50)).unwrap();
+    assert_eq!(instructions.len(), 1);
+    let swap_data = SwapData::try_from_slice(&instructions[0].data[8..]).unwrap();
+    assert_eq!((swap_data.amount, swap_data.other_amount_threshold, swap_data.a_to_b), (300_000, apply_slippage_bps(quote.amount_out, 50), false));
+    assert_eq!(instructions[0].data[..8], WHIRLPOOL_SWAP_DISCRIMINATOR);
+
+    // the client adds the token accounts the user lacks
+    let instructions = client.build_swap_instructions(&user, &pool, &quote, quote.min_amount_out(50)).await.unwrap();
+    assert_eq!(instructions.len(), 3);
+
+    // an update raising the price of SOL makes it buy less
+    let mut whirlpool = pool.whirlpool().unwrap().clone();
+    whirlpool.sqrt_price = whirlpool.sqrt_price / 100 * 101;
+    whirlpool.tick_current_index = crate::swap_math::tickmath_tick_index_from_sqrt_price(whirlpool.sqrt_price);
+    let mut data = crate::constant::WHIRLPOOL_DISCRIMINATOR.to_vec();
+    data.extend(whirlpool.try_to_vec().unwrap());
+    let account = Account { lamports: 1, data, owner: program_id, executable: false, rent_epoch: 0 };
+    pool.update(&pool_address, Some(&account)).unwrap();
+    assert!(pool.quote(300_000, false).unwrap().amount_out < quote.amount_out);
+    assert!(pool.update(&pool_address, None).is_err());
+}
+
+#[tokio::test]
+async fn test_whirlpool_fee_tiers() {
+    use crate::client::DEXClient;
+    use crate::constant::{ORCA_WHIRLPOOL_PROGRAM_ID, TICK_ARRAY_DISCRIMINATOR, WHIRLPOOL_DISCRIMINATOR};
+    use crate::dex::anchor_pubkey;
+    use crate::router::Router;
+    use crate::rpc::InMemoryRpc;
+    use crate::tick_array::tickutil_get_start_tick_index;
+
+    let program_id = Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
+    let [mint_a, mint_b, whirlpools_config] = [1u8, 2, 3].map(|byte| Pubkey::new_from_array([byte; 32]));
+
+    // a deep 0.3% pool and a shallower 0.05% one at the same price, without initialized ticks
+    let rpc = InMemoryRpc::new();
+    for (tick_spacing, fee_rate, liquidity) in [(64u16, 3000u16, 10_000_000_000u128), (8, 500, 2_000_000_000)] {
+        let address = get_whirlpool_address(&program_id, &whirlpools_config, &mint_a, &mint_b, tick_spacing);
+        let whirlpool = Whirlpool {
+            whirlpools_config: anchor_pubkey(&whirlpools_config),
+            tick_spacing,
+            fee_rate,
+            liquidity,
+            sqrt_price: 1u128 << 64,
+            tick_current_index: 0,
+            token_mint_a: anchor_pubkey(&mint_a),
+            token_mint_b: anchor_pubkey(&mint_b),
+            ..Default::default()
+        };
+        rpc.set_program_account(address, program_id, &WHIRLPOOL_DISCRIMINATOR, &whirlpool.try_to_vec().unwrap());
+        for offset in -3..=3 {
+            let start_tick_index = tickutil_get_start_tick_index(0, tick_spacing, offset);
+            let mut data = start_tick_index.to_le_bytes().to_vec();
+            data.extend(vec![0; TICK_ARRAY_SIZE_USIZE * 113]);
+            data.extend(address.to_bytes());
+            rpc.set_program_account(pdautil_get_tick_array(&program_id, &address, start_tick_index), program_id, &TICK_ARRAY_DISCRIMINATOR, &data);
+        }
+    }
+    // not a whirlpool, whatever its address
+    rpc.set_program_account(get_whirlpool_address(&program_id, &whirlpools_config, &mint_a, &mint_b, 1), Pubkey::new_unique(), &[], &[0; 8]);
+    let client = DEXClient::with_rpc(rpc);
+
+    let addresses = client.find_whirlpools(&program_id, &whirlpools_config, &mint_b, &mint_a).await.unwrap();
+    assert_eq!(addresses.len(), 2);
+    assert_eq!(addresses[0], get_whirlpool_address(&program_id, &whirlpools_config, &mint_a, &mint_b, 8));
+    let mut pools: Vec<Box<dyn Pool>> = Vec::new();
+    for address in addresses {
+        let mut pool = WhirlpoolPool::new(address, program_id);
+        client.load_pool(&mut pool).await.unwrap();
+        pools.push(Box::new(pool));
+    }
+    let router = Router::new(pools);
+
+    // a small order takes the cheaper tier alone, a big one is spread over both
+    let small = router.best_route(&mint_a, &mint_b, 1_000_000, 2).unwrap();
+    assert_eq!(small.routes.len(), 1);
+    assert_eq!(small.routes[0].hops[0].pool, router.pools()[0].address());
+    let amount = 200_000_000;
+    let plan = router.best_route(&mint_a, &mint_b, amount, 2).unwrap();
+    assert_eq!(plan.routes.len(), 2);
+    assert_eq!(plan.amount_in, amount);
+    for pool in router.pools() {
+        assert!(plan.amount_out > pool.quote(amount, true).unwrap().amount_out);
+    }
+
+    // one swap per tier, whose minimums together come to the plan's
+    let user = Pubkey::new_from_array([7; 32]);
+    let instructions = client.build_route_instructions(&user, &router, &plan, 50).await.unwrap();
+    let swaps: Vec<SwapData> = instructions
+        .iter()
+        .filter(|instruction| instruction.program_id == program_id)
+        .map(|instruction| SwapData::try_from_slice(&instruction.data[8..]).unwrap())
+        .collect();
+    assert_eq!(swaps.len(), 2);
+    assert_eq!(swaps.iter().map(|swap| swap.amount).sum::<u64>(), amount);
+    let min_amount_out: u64 = swaps.iter().map(|swap| swap.other_amount_threshold).sum();
+    assert!(plan.min_amount_out(50) - min_amount_out <= 2);
+
+    // token accounts that can't be looked up aren't taken for missing
+    client.rpc().set_unavailable(true);
+    assert!(client.build_route_instructions(&user, &router, &plan, 50).await.is_err());
+}